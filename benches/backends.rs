@@ -0,0 +1,40 @@
+// 用语料库里的程序比较树遍历求值器和字节码虚拟机
+// 解析和宏展开放在计时之外，虚拟机这一侧的时间包括编译
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use implement_parser::ast::traits::AsNode;
+use implement_parser::corpus::programs;
+use implement_parser::evaluator::environment::Environment;
+use implement_parser::evaluator::eval::eval;
+use implement_parser::evaluator::macro_expansion::{define_macros, expand_macro};
+use implement_parser::sync::{Rc, RefCell};
+use implement_parser::vm;
+
+fn backends(c: &mut Criterion) {
+    let mut group = c.benchmark_group("backends");
+    for program in programs() {
+        let mut parsed = program.parse().unwrap();
+        let macro_env = Rc::new(RefCell::new(Environment::new()));
+        define_macros(&mut parsed, Rc::clone(&macro_env));
+        expand_macro(&mut parsed, macro_env).unwrap();
+
+        group.bench_with_input(
+            BenchmarkId::new("eval", program.name),
+            &parsed,
+            |b, parsed| {
+                b.iter(|| eval(parsed.as_node(), Rc::new(RefCell::new(Environment::new()))))
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("vm", program.name),
+            &parsed,
+            |b, parsed| {
+                b.iter(|| vm::run(parsed, Rc::new(RefCell::new(Environment::new()))).unwrap())
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, backends);
+criterion_main!(benches);
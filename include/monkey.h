@@ -0,0 +1,29 @@
+/* 嵌入 Monkey 解释器的 C 接口，对应 src/capi.rs，编译时需要打开 capi 功能 */
+#ifndef MONKEY_H
+#define MONKEY_H
+
+#include <stdbool.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct MonkeyInterpreter MonkeyInterpreter;
+typedef struct MonkeyResult MonkeyResult;
+
+/* 标准库已经加载，puts 写到标准输出 */
+MonkeyInterpreter *monkey_new(void);
+void monkey_free(MonkeyInterpreter *interpreter);
+
+/* 多次调用共用同一个根环境，返回的结果用 monkey_result_free 释放 */
+MonkeyResult *monkey_eval(MonkeyInterpreter *interpreter, const char *source);
+bool monkey_result_is_error(const MonkeyResult *result);
+/* 返回的字符串归结果所有，不需要单独释放 */
+const char *monkey_result_to_string(const MonkeyResult *result);
+void monkey_result_free(MonkeyResult *result);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
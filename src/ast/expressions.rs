@@ -1,23 +1,190 @@
 use super::traits::AsNode;
 use crate::ast::statements::BlockStatement;
-use crate::ast::traits::{Expression, Node};
+use crate::ast::traits::{impl_display_for_node, Node};
+use crate::ast::walk::NodeRef;
+use crate::errors::{runtime_error, EvalResult, MessageId};
 use crate::evaluator::environment::Environment;
 use crate::evaluator::eval::{
-    apply_function, eval, eval_expressions, eval_hash_literal, eval_identifier,
-    eval_index_expression, eval_infix_expression, eval_prefix_expression, is_error, is_truthy,
+    apply_function, eval_expressions, eval_hash_literal, eval_identifier, eval_index_expression,
+    eval_infix_expression, eval_node, eval_prefix_expression, is_truthy,
 };
-use crate::evaluator::object::{self, Array, Function, Macro, StringObject};
+use crate::evaluator::object::{self, Array, Function, Identity, Macro, StringObject, Value};
+use crate::lexer::Span;
 use crate::quote::quote;
+use crate::sync::{Rc, RefCell};
 use crate::token::Token;
-use by_address::ByAddress;
-use std::collections::HashMap;
-use std::{cell::RefCell, rc::Rc};
+use std::ops::Deref;
+
+// 所有的表达式节点，用 match 处理时编译器会检查有没有漏掉的种类
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Expr {
+    Identifier(Identifier),
+    Integer(IntegerLiteral),
+    Boolean(Boolean),
+    String(StringLiteral),
+    Prefix(PrefixExpression),
+    Infix(InfixExpression),
+    If(IfExpression),
+    Function(FunctionLiteral),
+    Call(CallExpression),
+    Array(ArrayLiteral),
+    Index(IndexExpression),
+    Hash(HashLiteral),
+    Macro(MacroLiteral),
+    Quote(QuoteExpression),
+    Unquote(UnquoteExpression),
+    UnquoteSplice(UnquoteSpliceExpression),
+}
+
+// 解引用成具体节点的 dyn Node，string()、span() 和 downcast_ref 这些方法可以直接用
+impl Deref for Expr {
+    type Target = dyn Node;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Expr::Identifier(node) => node,
+            Expr::Integer(node) => node,
+            Expr::Boolean(node) => node,
+            Expr::String(node) => node,
+            Expr::Prefix(node) => node,
+            Expr::Infix(node) => node,
+            Expr::If(node) => node,
+            Expr::Function(node) => node,
+            Expr::Call(node) => node,
+            Expr::Array(node) => node,
+            Expr::Index(node) => node,
+            Expr::Hash(node) => node,
+            Expr::Macro(node) => node,
+            Expr::Quote(node) => node,
+            Expr::Unquote(node) => node,
+            Expr::UnquoteSplice(node) => node,
+        }
+    }
+}
+
+impl From<Identifier> for Expr {
+    fn from(node: Identifier) -> Self {
+        Expr::Identifier(node)
+    }
+}
+
+impl From<IntegerLiteral> for Expr {
+    fn from(node: IntegerLiteral) -> Self {
+        Expr::Integer(node)
+    }
+}
+
+impl From<Boolean> for Expr {
+    fn from(node: Boolean) -> Self {
+        Expr::Boolean(node)
+    }
+}
+
+impl From<StringLiteral> for Expr {
+    fn from(node: StringLiteral) -> Self {
+        Expr::String(node)
+    }
+}
+
+impl From<PrefixExpression> for Expr {
+    fn from(node: PrefixExpression) -> Self {
+        Expr::Prefix(node)
+    }
+}
+
+impl From<InfixExpression> for Expr {
+    fn from(node: InfixExpression) -> Self {
+        Expr::Infix(node)
+    }
+}
+
+impl From<IfExpression> for Expr {
+    fn from(node: IfExpression) -> Self {
+        Expr::If(node)
+    }
+}
+
+impl From<FunctionLiteral> for Expr {
+    fn from(node: FunctionLiteral) -> Self {
+        Expr::Function(node)
+    }
+}
+
+impl From<CallExpression> for Expr {
+    fn from(node: CallExpression) -> Self {
+        Expr::Call(node)
+    }
+}
+
+impl From<ArrayLiteral> for Expr {
+    fn from(node: ArrayLiteral) -> Self {
+        Expr::Array(node)
+    }
+}
+
+impl From<IndexExpression> for Expr {
+    fn from(node: IndexExpression) -> Self {
+        Expr::Index(node)
+    }
+}
+
+impl From<HashLiteral> for Expr {
+    fn from(node: HashLiteral) -> Self {
+        Expr::Hash(node)
+    }
+}
+
+impl From<MacroLiteral> for Expr {
+    fn from(node: MacroLiteral) -> Self {
+        Expr::Macro(node)
+    }
+}
+
+impl From<QuoteExpression> for Expr {
+    fn from(node: QuoteExpression) -> Self {
+        Expr::Quote(node)
+    }
+}
+
+impl From<UnquoteExpression> for Expr {
+    fn from(node: UnquoteExpression) -> Self {
+        Expr::Unquote(node)
+    }
+}
+
+impl From<UnquoteSpliceExpression> for Expr {
+    fn from(node: UnquoteSpliceExpression) -> Self {
+        Expr::UnquoteSplice(node)
+    }
+}
+
+impl_display_for_node!(
+    Expr,
+    Identifier,
+    IntegerLiteral,
+    Boolean,
+    StringLiteral,
+    PrefixExpression,
+    InfixExpression,
+    IfExpression,
+    FunctionLiteral,
+    CallExpression,
+    ArrayLiteral,
+    IndexExpression,
+    HashLiteral,
+    MacroLiteral,
+    QuoteExpression,
+    UnquoteExpression,
+    UnquoteSpliceExpression,
+);
 
 // 标识符
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Identifier {
     pub token: Token,
-    pub value: String,
+    pub value: Rc<str>,
 }
 
 impl Node for Identifier {
@@ -26,20 +193,29 @@ impl Node for Identifier {
     }
 
     fn string(&self) -> String {
-        self.value.clone()
+        self.value.to_string()
     }
 
-    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        eval_identifier(self, environment)
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
     }
-}
 
-impl Expression for Identifier {
-    fn expression_node(&self) {}
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Identifier(self)
+    }
+
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
+        eval_identifier(self, environment)
+    }
 }
 
 // 整数字面量
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IntegerLiteral {
     pub token: Token,
     pub value: i64,
@@ -54,16 +230,27 @@ impl Node for IntegerLiteral {
         self.value.to_string()
     }
 
-    fn eval_to_object(&self, _environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        Box::new(object::Integer { value: self.value })
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
     }
-}
 
-impl Expression for IntegerLiteral {
-    fn expression_node(&self) {}
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Integer(self)
+    }
+
+    fn eval_to_object(&self, _environment: Rc<RefCell<Environment>>) -> EvalResult {
+        Ok(Rc::new(Value::Integer(object::Integer {
+            value: self.value,
+        })))
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Boolean {
     pub token: Token,
     pub value: bool,
@@ -78,23 +265,30 @@ impl Node for Boolean {
         self.value.to_string()
     }
 
-    fn eval_to_object(&self, _environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        if self.value {
-            Box::new(object::Boolean::True)
-        } else {
-            Box::new(object::Boolean::False)
-        }
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
+    }
+
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Boolean(self)
     }
-}
 
-impl Expression for Boolean {
-    fn expression_node(&self) {}
+    fn eval_to_object(&self, _environment: Rc<RefCell<Environment>>) -> EvalResult {
+        Ok(Rc::new(Value::Boolean(object::Boolean::from_native_bool(
+            self.value,
+        ))))
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IfExpression {
     pub token: Token,
-    pub condition: Box<dyn Expression>,
+    pub condition: Box<Expr>,
     pub consequence: BlockStatement,
     pub alternative: Option<BlockStatement>,
 }
@@ -117,27 +311,44 @@ impl Node for IfExpression {
         result
     }
 
-    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        let condition = eval(self.condition.as_node(), environment.clone());
-        if is_error(condition.as_ref()) {
-            return condition;
-        }
+    fn span(&self) -> Span {
+        Span::cover(
+            [
+                self.token.span,
+                self.condition.span(),
+                self.consequence.span(),
+            ]
+            .into_iter()
+            .chain(
+                self.alternative
+                    .as_ref()
+                    .map(|alternative| alternative.span()),
+            ),
+        )
+    }
+
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
+    }
 
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::If(self)
+    }
+
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
+        let condition = eval_node(self.condition.as_node(), environment.clone())?;
         if is_truthy(condition.as_ref()) {
-            eval(self.consequence.as_node(), environment)
+            eval_node(self.consequence.as_node(), environment)
         } else if let Some(alternative) = &self.alternative {
-            eval(alternative.as_node(), environment)
+            eval_node(alternative.as_node(), environment)
         } else {
-            Box::new(object::Null)
+            Ok(Rc::new(Value::Null(object::Null)))
         }
     }
 }
 
-impl Expression for IfExpression {
-    fn expression_node(&self) {}
-}
-
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FunctionLiteral {
     pub token: Token,
     pub parameters: Vec<Identifier>, // 这里是一个函数定义，因此只能是 Identifier
@@ -164,24 +375,37 @@ impl Node for FunctionLiteral {
         )
     }
 
-    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        Box::new(Function {
+    fn span(&self) -> Span {
+        Span::cover([self.token.span, self.body.span()])
+    }
+
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
+    }
+
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Function(self)
+    }
+
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
+        Ok(Rc::new(Value::Function(Function {
             parameters: self.parameters.clone(),
             body: self.body.clone(),
             env: environment,
-        })
+            identity: Identity::fresh(),
+        })))
     }
 }
 
-impl Expression for FunctionLiteral {
-    fn expression_node(&self) {}
-}
-
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CallExpression {
     pub token: Token, // '(' 词法单元
-    pub function: Box<dyn Expression>,
-    pub arguments: Vec<Box<dyn Expression>>,
+    pub function: Box<Expr>,
+    pub arguments: Vec<Expr>,
+    // 结尾的 `)` 的区间
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub closing: Span,
 }
 
 impl Node for CallExpression {
@@ -199,38 +423,36 @@ impl Node for CallExpression {
         format!("{}({})", self.function.string(), args)
     }
 
-    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        if self.function.token_literal() == "quote" {
-            if let Some(first) = self.arguments.first() {
-                let first = dyn_clone::clone_box(first.as_ref());
-                return quote(&mut first.as_boxed_node(), Rc::clone(&environment));
-            } else {
-                return Box::new(object::Error {
-                    message: "`quote` needs to be called with one argument".to_owned(),
-                });
-            }
-        }
-        let func = eval(self.function.as_node(), environment.clone());
-        if is_error(func.as_ref()) {
-            return func;
-        }
-        let mut params = eval_expressions(&self.arguments, environment);
-        if params.len() == 1 && is_error(params.first().unwrap().as_ref()) {
-            return params.swap_remove(0);
-        }
-        apply_function(func.as_ref(), &params)
+    fn span(&self) -> Span {
+        Span::cover(
+            [self.function.span(), self.token.span]
+                .into_iter()
+                .chain(self.arguments.iter().map(|argument| argument.span()))
+                .chain([self.closing]),
+        )
+    }
+
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
+    }
+
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Call(self)
     }
-}
 
-impl Expression for CallExpression {
-    fn expression_node(&self) {}
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
+        let func = eval_node(self.function.as_node(), environment.clone())?;
+        let params = eval_expressions(&self.arguments, Rc::clone(&environment))?;
+        apply_function(func.as_ref(), &params, environment)
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PrefixExpression {
     pub token: Token, // 前置的 token
-    pub operator: String,
-    pub right: Box<dyn Expression>,
+    pub operator: Rc<str>,
+    pub right: Box<Expr>,
 }
 
 impl Node for PrefixExpression {
@@ -242,25 +464,31 @@ impl Node for PrefixExpression {
         format!("({}{})", self.operator, self.right.string())
     }
 
-    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        let right = eval(self.right.as_node(), environment);
-        if is_error(right.as_ref()) {
-            return right;
-        }
-        eval_prefix_expression(&self.operator, right.as_ref())
+    fn span(&self) -> Span {
+        Span::cover([self.token.span, self.right.span()])
+    }
+
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
     }
-}
 
-impl Expression for PrefixExpression {
-    fn expression_node(&self) {}
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Prefix(self)
+    }
+
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
+        let right = eval_node(self.right.as_node(), environment)?;
+        eval_prefix_expression(&self.operator, right.as_ref())
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InfixExpression {
     pub token: Token, // 中间的 token
-    pub left: Box<dyn Expression>,
-    pub operator: String,
-    pub right: Box<dyn Expression>,
+    pub left: Box<Expr>,
+    pub operator: Rc<str>,
+    pub right: Box<Expr>,
 }
 
 impl Node for InfixExpression {
@@ -277,24 +505,27 @@ impl Node for InfixExpression {
         )
     }
 
-    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        let left = eval(self.left.as_node(), environment.clone());
-        if is_error(left.as_ref()) {
-            return left;
-        }
-        let right = eval(self.right.as_node(), environment);
-        if is_error(right.as_ref()) {
-            return right;
-        }
-        eval_infix_expression(left.as_ref(), &self.operator, right.as_ref())
+    fn span(&self) -> Span {
+        Span::cover([self.left.span(), self.token.span, self.right.span()])
+    }
+
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
     }
-}
 
-impl Expression for InfixExpression {
-    fn expression_node(&self) {}
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Infix(self)
+    }
+
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
+        let left = eval_node(self.left.as_node(), environment.clone())?;
+        let right = eval_node(self.right.as_node(), environment)?;
+        eval_infix_expression(left.as_ref(), &self.operator, right.as_ref())
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StringLiteral {
     pub token: Token,
     pub value: String,
@@ -309,21 +540,33 @@ impl Node for StringLiteral {
         &self.token.literal
     }
 
-    fn eval_to_object(&self, _environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        Box::new(StringObject {
-            value: self.value.clone(),
-        })
+    fn span(&self) -> Span {
+        self.token.span
+    }
+
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
     }
-}
 
-impl Expression for StringLiteral {
-    fn expression_node(&self) {}
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::String(self)
+    }
+
+    fn eval_to_object(&self, _environment: Rc<RefCell<Environment>>) -> EvalResult {
+        Ok(Rc::new(Value::String(StringObject {
+            value: self.value.clone(),
+        })))
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ArrayLiteral {
     pub token: Token, // [ 词法单元
-    pub elements: Vec<Box<dyn Expression>>,
+    pub elements: Vec<Expr>,
+    // 结尾的 `]` 的区间
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub closing: Span,
 }
 
 impl Node for ArrayLiteral {
@@ -341,30 +584,41 @@ impl Node for ArrayLiteral {
         &self.token.literal
     }
 
-    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        let elements = eval_expressions(&self.elements, environment);
-        if elements.len() == 1
-            && matches!(
-                elements.get(0).unwrap().object_type(),
-                object::ObjectType::Error
-            )
-        {
-            return dyn_clone::clone_box(elements[0].as_ref());
-        }
+    fn span(&self) -> Span {
+        Span::cover(
+            [self.token.span]
+                .into_iter()
+                .chain(self.elements.iter().map(|element| element.span()))
+                .chain([self.closing]),
+        )
+    }
 
-        Box::new(Array { elements })
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
+    }
+
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Array(self)
     }
-}
 
-impl Expression for ArrayLiteral {
-    fn expression_node(&self) {}
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
+        let elements = eval_expressions(&self.elements, environment)?;
+        Ok(Rc::new(Value::Array(Array {
+            elements,
+            frozen: false,
+        })))
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IndexExpression {
     pub token: Token,
-    pub left: Box<dyn Expression>,
-    pub index: Box<dyn Expression>,
+    pub left: Box<Expr>,
+    pub index: Box<Expr>,
+    // 结尾的 `]` 的区间
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub closing: Span,
 }
 
 impl Node for IndexExpression {
@@ -376,27 +630,39 @@ impl Node for IndexExpression {
         &self.token.literal
     }
 
-    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        let left = eval(self.left.as_node(), Rc::clone(&environment));
-        if is_error(left.as_ref()) {
-            return left;
-        }
-        let index = eval(self.index.as_node(), environment);
-        if is_error(index.as_ref()) {
-            return index;
-        }
-        eval_index_expression(left.as_ref(), index.as_ref())
+    fn span(&self) -> Span {
+        Span::cover([
+            self.left.span(),
+            self.token.span,
+            self.index.span(),
+            self.closing,
+        ])
+    }
+
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
     }
-}
 
-impl Expression for IndexExpression {
-    fn expression_node(&self) {}
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Index(self)
+    }
+
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
+        let left = eval_node(self.left.as_node(), Rc::clone(&environment))?;
+        let index = eval_node(self.index.as_node(), environment)?;
+        eval_index_expression(left.as_ref(), index.as_ref())
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HashLiteral {
     pub token: Token,
-    pub pairs: HashMap<ByAddress<Box<dyn Expression>>, Box<dyn Expression>>,
+    // 按源码里出现的顺序保存键值对
+    pub pairs: Vec<(Expr, Expr)>,
+    // 结尾的 `}` 的区间
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub closing: Span,
 }
 
 impl Node for HashLiteral {
@@ -414,16 +680,32 @@ impl Node for HashLiteral {
         &self.token.literal
     }
 
-    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        eval_hash_literal(self, environment)
+    fn span(&self) -> Span {
+        Span::cover(
+            [self.token.span].into_iter().chain(
+                self.pairs
+                    .iter()
+                    .flat_map(|(key, value)| [key.span(), value.span()])
+                    .chain([self.closing]),
+            ),
+        )
     }
-}
 
-impl Expression for HashLiteral {
-    fn expression_node(&self) {}
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
+    }
+
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Hash(self)
+    }
+
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
+        eval_hash_literal(self, environment)
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MacroLiteral {
     pub token: Token,
     pub parameters: Vec<Identifier>,
@@ -445,15 +727,135 @@ impl Node for MacroLiteral {
         format!("{}({}){}", self.token_literal(), params, self.body.string())
     }
 
-    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        Box::new(Macro {
+    fn span(&self) -> Span {
+        Span::cover([self.token.span, self.body.span()])
+    }
+
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
+    }
+
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Macro(self)
+    }
+
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
+        Ok(Rc::new(Value::Macro(Macro {
             parameters: self.parameters.clone(),
             body: self.body.clone(),
             env: environment,
-        })
+            identity: Identity::fresh(),
+        })))
     }
 }
 
-impl Expression for MacroLiteral {
-    fn expression_node(&self) {}
+// quote(expression)，求值得到没有求值的表达式，其中的 unquote 先被替换成求值的结果
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuoteExpression {
+    pub token: Token,
+    pub expression: Box<Expr>,
+    // 结尾的 `)` 的区间
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub closing: Span,
+}
+
+impl Node for QuoteExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn string(&self) -> String {
+        format!("{}({})", self.token_literal(), self.expression.string())
+    }
+
+    fn span(&self) -> Span {
+        Span::cover([self.token.span, self.expression.span(), self.closing])
+    }
+
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
+    }
+
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Quote(self)
+    }
+
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
+        quote(&self.expression, environment)
+    }
+}
+
+// unquote(expression)，只在 quote 里面有意义，由 quote 在求值时替换掉
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnquoteExpression {
+    pub token: Token,
+    pub expression: Box<Expr>,
+    // 结尾的 `)` 的区间
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub closing: Span,
+}
+
+impl Node for UnquoteExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn string(&self) -> String {
+        format!("{}({})", self.token_literal(), self.expression.string())
+    }
+
+    fn span(&self) -> Span {
+        Span::cover([self.token.span, self.expression.span(), self.closing])
+    }
+
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
+    }
+
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Unquote(self)
+    }
+
+    fn eval_to_object(&self, _environment: Rc<RefCell<Environment>>) -> EvalResult {
+        Err(runtime_error(MessageId::UnquoteOutsideQuote, &[&self.token_literal()]).into())
+    }
+}
+
+// unquote_splice(expression)，在 quote 里的调用参数或者数组元素的位置上展开成多个表达式
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnquoteSpliceExpression {
+    pub token: Token,
+    pub expression: Box<Expr>,
+    // 结尾的 `)` 的区间
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub closing: Span,
+}
+
+impl Node for UnquoteSpliceExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn string(&self) -> String {
+        format!("{}({})", self.token_literal(), self.expression.string())
+    }
+
+    fn span(&self) -> Span {
+        Span::cover([self.token.span, self.expression.span(), self.closing])
+    }
+
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
+    }
+
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::UnquoteSplice(self)
+    }
+
+    fn eval_to_object(&self, _environment: Rc<RefCell<Environment>>) -> EvalResult {
+        Err(runtime_error(MessageId::UnquoteOutsideQuote, &[&self.token_literal()]).into())
+    }
 }
@@ -0,0 +1,111 @@
+// 给工具用的节点编号：按先序遍历（walk::children 的顺序）给每个节点一个 NodeId，Program 自己是 0
+// 编号只取决于树的形状，同样的源码每次解析都得到同样的编号
+// 分析器、调试器和编辑器之间传编号就行，不用拿着树里的引用，需要节点本身时用 find 找回来
+use std::fmt::{self, Display};
+
+use super::program::Program;
+use super::traits::Node;
+use super::walk::{children, node_kind};
+use crate::lexer::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub usize);
+
+impl Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeInfo {
+    // 和 walk::node_kind 一样的类型名
+    pub kind: &'static str,
+    pub span: Span,
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NodeTable {
+    nodes: Vec<NodeInfo>,
+}
+
+impl NodeTable {
+    pub fn new(program: &Program) -> Self {
+        let mut table = NodeTable::default();
+        table.add(program, None);
+        table
+    }
+
+    fn add(&mut self, node: &dyn Node, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(NodeInfo {
+            kind: node_kind(node),
+            span: node.span(),
+            parent,
+            children: vec![],
+        });
+        let ids = children(node)
+            .into_iter()
+            .map(|child| self.add(child, Some(id)))
+            .collect();
+        self.nodes[id.0].children = ids;
+        id
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&NodeInfo> {
+        self.nodes.get(id.0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // 按编号的顺序，也就是源码的顺序
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &NodeInfo)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(index, info)| (NodeId(index), info))
+    }
+
+    // 从父节点一直到 Program，不包括 id 自己
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(self.get(id).and_then(|info| info.parent), |parent| {
+            self.get(*parent).and_then(|info| info.parent)
+        })
+    }
+
+    // 包含 offset 这个字节位置的最内层节点，编辑器的悬停和跳转用
+    // 宏展开生成的节点没有位置，不会被找到
+    pub fn node_at(&self, offset: usize) -> Option<NodeId> {
+        let contains = |id: &NodeId| {
+            let span = self.nodes[id.0].span;
+            span.start <= offset && offset < span.end
+        };
+        let mut found = Some(NodeId(0)).filter(contains)?;
+        while let Some(child) = self.nodes[found.0].children.iter().copied().find(contains) {
+            found = child;
+        }
+        Some(found)
+    }
+}
+
+// 按编号找回树里的节点，program 要和建表时是同一棵树
+pub fn find(program: &Program, id: NodeId) -> Option<&dyn Node> {
+    fn visit<'a>(node: &'a dyn Node, id: NodeId, next: &mut usize) -> Option<&'a dyn Node> {
+        if *next == id.0 {
+            return Some(node);
+        }
+        *next += 1;
+        children(node)
+            .into_iter()
+            .find_map(|child| visit(child, id, next))
+    }
+    visit(program, id, &mut 0)
+}
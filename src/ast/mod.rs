@@ -1,5 +1,7 @@
 pub mod expressions;
+pub mod ids;
 pub mod modify;
 pub mod program;
 pub mod statements;
 pub mod traits;
+pub mod walk;
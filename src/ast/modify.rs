@@ -1,139 +1,226 @@
-use std::collections::HashMap;
-
 use super::{
-    expressions::{
-        ArrayLiteral, Boolean, CallExpression, FunctionLiteral, HashLiteral, Identifier,
-        IfExpression, IndexExpression, InfixExpression, IntegerLiteral, MacroLiteral,
-        PrefixExpression, StringLiteral,
-    },
+    expressions::{Expr, Identifier},
     program::Program,
-    statements::{BlockStatement, ExpressionStatement, LetStatement, ReturnStatement},
-    traits::{AsNode, Expression, Node, Statement},
+    statements::{BlockStatement, Stmt},
 };
 
-// rust 里面没有办法把一个 &dyn Trait 转换为另一个，因为 &dyn Trait 里面存放的 vtable 没办法替换
-// 我开始想的是直接替换，就都没有返回值，但调用 modify 的时候没有办法在 modifier 这个参数里面用一个新的 node 替换旧的参数 node
-pub fn modify(
-    node: &mut dyn Node,
-    modifier: &impl Fn(Box<dyn Node>) -> Box<dyn Node>,
-) -> Box<dyn Node> {
-    if let Some(program) = node.downcast_mut::<Program>() {
-        for statement in program.statements.iter_mut() {
-            *statement = node_to_statement_helper(modify(statement.as_mut_node(), modifier));
-        }
-    } else if let Some(expression_statement) = node.downcast_mut::<ExpressionStatement>() {
-        expression_statement.expression = node_to_expression_helper(modify(
-            expression_statement.expression.as_mut_node(),
-            modifier,
-        ));
-    } else if let Some(block_statement) = node.downcast_mut::<BlockStatement>() {
-        for statement in block_statement.statements.iter_mut() {
-            *statement = node_to_statement_helper(modify(statement.as_mut_node(), modifier));
-        }
-    } else if let Some(return_statement) = node.downcast_mut::<ReturnStatement>() {
-        return_statement.return_value = node_to_expression_helper(modify(
-            return_statement.return_value.as_mut_node(),
-            modifier,
-        ));
-    } else if let Some(let_statement) = node.downcast_mut::<LetStatement>() {
-        let_statement.value =
-            node_to_expression_helper(modify(let_statement.value.as_mut_node(), modifier));
-    } else if let Some(infix_expresssion) = node.downcast_mut::<InfixExpression>() {
-        infix_expresssion.left =
-            node_to_expression_helper(modify(infix_expresssion.left.as_mut_node(), modifier));
-        infix_expresssion.right =
-            node_to_expression_helper(modify(infix_expresssion.right.as_mut_node(), modifier));
-    } else if let Some(prefix_expression) = node.downcast_mut::<PrefixExpression>() {
-        prefix_expression.right =
-            node_to_expression_helper(modify(prefix_expression.right.as_mut_node(), modifier));
-    } else if let Some(index_expresssion) = node.downcast_mut::<IndexExpression>() {
-        index_expresssion.left =
-            node_to_expression_helper(modify(index_expresssion.left.as_mut_node(), modifier));
-        index_expresssion.index =
-            node_to_expression_helper(modify(index_expresssion.index.as_mut_node(), modifier));
-    } else if let Some(if_expression) = node.downcast_mut::<IfExpression>() {
-        if_expression.condition =
-            node_to_expression_helper(modify(if_expression.condition.as_mut_node(), modifier));
-        modify(if_expression.consequence.as_mut_node(), modifier)
-            .downcast::<BlockStatement>()
-            .map_err(|_| "Shouldn't happen")
-            .unwrap();
-        if let Some(alternative) = if_expression.alternative.as_mut() {
-            if_expression.alternative = Some(
-                *modify(alternative.as_mut_node(), modifier)
-                    .downcast::<BlockStatement>()
-                    .map_err(|_| "Shouldn't happen")
-                    .unwrap(),
-            );
-        }
-    } else if let Some(function_literal) = node.downcast_mut::<FunctionLiteral>() {
-        for ident in function_literal.parameters.iter_mut() {
-            *ident = *modify(ident.as_mut_node(), modifier)
-                .downcast::<Identifier>()
-                .map_err(|_| "Shouldn't happen")
-                .unwrap();
-        }
-        function_literal.body = *modify(function_literal.body.as_mut_node(), modifier)
-            .downcast::<BlockStatement>()
-            .map_err(|_| "Shouldn't happen")
-            .unwrap();
-    } else if let Some(array_literal) = node.downcast_mut::<ArrayLiteral>() {
-        for element in array_literal.elements.iter_mut() {
-            modify(element.as_mut_node(), modifier);
-        }
-    } else if let Some(hash_literal) = node.downcast_mut::<HashLiteral>() {
-        let mut new_pairs = HashMap::new();
-        for (key, value) in hash_literal.pairs.iter() {
-            let mut new_key = dyn_clone::clone(&**key);
-            modify(new_key.as_mut_node(), modifier);
-            let mut new_value = dyn_clone::clone_box(value.as_ref());
-            modify(new_value.as_mut_node(), modifier);
-            new_pairs.insert(by_address::ByAddress(new_key), new_value);
-        }
-        hash_literal.pairs = new_pairs;
+// 不改动原来的树，返回经过 transformer 处理的新树
+// transformer 的调用顺序和 modify 相同，它原样返回的节点在新树里和原来的结构完全一样
+pub fn transform<N: Transform>(node: &N, transformer: &impl Fn(Expr) -> Expr) -> N {
+    node.transform(transformer)
+}
+
+pub trait Transform: Clone {
+    fn transform(&self, transformer: &impl Fn(Expr) -> Expr) -> Self;
+}
+
+impl Transform for Expr {
+    fn transform(&self, transformer: &impl Fn(Expr) -> Expr) -> Self {
+        modify(self.clone(), transformer)
+    }
+}
+
+impl Transform for Stmt {
+    fn transform(&self, transformer: &impl Fn(Expr) -> Expr) -> Self {
+        modify_statement(self.clone(), transformer)
     }
-    let node = dyn_clone::clone_box(node);
-    modifier(node)
-}
-
-fn node_to_statement_helper(node: Box<dyn Node>) -> Box<dyn Statement> {
-    if let Some(let_statement) = node.downcast_ref::<LetStatement>() {
-        dyn_clone::clone_box(let_statement)
-    } else if let Some(return_statement) = node.downcast_ref::<ReturnStatement>() {
-        dyn_clone::clone_box(return_statement)
-    } else if let Some(expression_statement) = node.downcast_ref::<ExpressionStatement>() {
-        dyn_clone::clone_box(expression_statement)
-    } else {
-        dyn_clone::clone_box(node.downcast_ref::<BlockStatement>().unwrap())
+}
+
+impl Transform for BlockStatement {
+    fn transform(&self, transformer: &impl Fn(Expr) -> Expr) -> Self {
+        Modifier(transformer).fold_block(self.clone())
+    }
+}
+
+impl Transform for Program {
+    fn transform(&self, transformer: &impl Fn(Expr) -> Expr) -> Self {
+        let mut program = self.clone();
+        modify_program(&mut program, transformer);
+        program
     }
 }
 
-fn node_to_expression_helper(node: Box<dyn Node>) -> Box<dyn Expression> {
-    if let Some(ident) = node.downcast_ref::<Identifier>() {
-        dyn_clone::clone_box(ident)
-    } else if let Some(integer) = node.downcast_ref::<IntegerLiteral>() {
-        dyn_clone::clone_box(integer)
-    } else if let Some(boolean) = node.downcast_ref::<Boolean>() {
-        dyn_clone::clone_box(boolean)
-    } else if let Some(if_exp) = node.downcast_ref::<IfExpression>() {
-        dyn_clone::clone_box(if_exp)
-    } else if let Some(func) = node.downcast_ref::<FunctionLiteral>() {
-        dyn_clone::clone_box(func)
-    } else if let Some(call) = node.downcast_ref::<CallExpression>() {
-        dyn_clone::clone_box(call)
-    } else if let Some(infix) = node.downcast_ref::<InfixExpression>() {
-        dyn_clone::clone_box(infix)
-    } else if let Some(prefix) = node.downcast_ref::<PrefixExpression>() {
-        dyn_clone::clone_box(prefix)
-    } else if let Some(str) = node.downcast_ref::<StringLiteral>() {
-        dyn_clone::clone_box(str)
-    } else if let Some(arr) = node.downcast_ref::<ArrayLiteral>() {
-        dyn_clone::clone_box(arr)
-    } else if let Some(hash) = node.downcast_ref::<HashLiteral>() {
-        dyn_clone::clone_box(hash)
-    } else if let Some(macro_literal) = node.downcast_ref::<MacroLiteral>() {
-        dyn_clone::clone_box(macro_literal)
-    } else {
-        dyn_clone::clone_box(node.downcast_ref::<IndexExpression>().unwrap())
+// 按值重建语法树，默认的实现原样进入每个子节点，quote、unquote 和宏字面量里面也进去
+// 只需要在某些节点上换一种做法时覆盖对应的方法，其余的节点交给同名的函数继续往下走
+pub trait Fold {
+    fn fold_expression(&mut self, expression: Expr) -> Expr {
+        fold_expression(self, expression)
+    }
+
+    fn fold_statement(&mut self, statement: Stmt) -> Stmt {
+        fold_statement(self, statement)
+    }
+
+    fn fold_block(&mut self, block: BlockStatement) -> BlockStatement {
+        fold_block(self, block)
+    }
+
+    // 引入绑定的标识符：let 的名字、函数和宏的参数
+    fn fold_binding(&mut self, identifier: Identifier) -> Identifier {
+        identifier
     }
 }
+
+pub fn fold_expression<F: Fold + ?Sized>(folder: &mut F, expression: Expr) -> Expr {
+    match expression {
+        Expr::Prefix(mut prefix) => {
+            prefix.right = fold_boxed(folder, prefix.right);
+            Expr::Prefix(prefix)
+        }
+        Expr::Infix(mut infix) => {
+            infix.left = fold_boxed(folder, infix.left);
+            infix.right = fold_boxed(folder, infix.right);
+            Expr::Infix(infix)
+        }
+        Expr::Index(mut index) => {
+            index.left = fold_boxed(folder, index.left);
+            index.index = fold_boxed(folder, index.index);
+            Expr::Index(index)
+        }
+        Expr::If(mut if_expression) => {
+            if_expression.condition = fold_boxed(folder, if_expression.condition);
+            if_expression.consequence = folder.fold_block(if_expression.consequence);
+            if_expression.alternative = if_expression
+                .alternative
+                .map(|alternative| folder.fold_block(alternative));
+            Expr::If(if_expression)
+        }
+        Expr::Function(mut function) => {
+            function.parameters = fold_bindings(folder, function.parameters);
+            function.body = folder.fold_block(function.body);
+            Expr::Function(function)
+        }
+        Expr::Macro(mut macro_literal) => {
+            macro_literal.parameters = fold_bindings(folder, macro_literal.parameters);
+            macro_literal.body = folder.fold_block(macro_literal.body);
+            Expr::Macro(macro_literal)
+        }
+        Expr::Call(mut call) => {
+            call.function = fold_boxed(folder, call.function);
+            call.arguments = fold_expressions(folder, call.arguments);
+            Expr::Call(call)
+        }
+        Expr::Array(mut array) => {
+            array.elements = fold_expressions(folder, array.elements);
+            Expr::Array(array)
+        }
+        Expr::Hash(mut hash) => {
+            hash.pairs = hash
+                .pairs
+                .into_iter()
+                .map(|(key, value)| (folder.fold_expression(key), folder.fold_expression(value)))
+                .collect();
+            Expr::Hash(hash)
+        }
+        Expr::Quote(mut quote) => {
+            quote.expression = fold_boxed(folder, quote.expression);
+            Expr::Quote(quote)
+        }
+        Expr::Unquote(mut unquote) => {
+            unquote.expression = fold_boxed(folder, unquote.expression);
+            Expr::Unquote(unquote)
+        }
+        Expr::UnquoteSplice(mut splice) => {
+            splice.expression = fold_boxed(folder, splice.expression);
+            Expr::UnquoteSplice(splice)
+        }
+        expression @ (Expr::Identifier(_)
+        | Expr::Integer(_)
+        | Expr::Boolean(_)
+        | Expr::String(_)) => expression,
+    }
+}
+
+pub fn fold_statement<F: Fold + ?Sized>(folder: &mut F, statement: Stmt) -> Stmt {
+    match statement {
+        Stmt::Let(mut let_statement) => {
+            let_statement.name = folder.fold_binding(let_statement.name);
+            let_statement.value = fold_boxed(folder, let_statement.value);
+            Stmt::Let(let_statement)
+        }
+        Stmt::Return(mut return_statement) => {
+            return_statement.return_value = fold_boxed(folder, return_statement.return_value);
+            Stmt::Return(return_statement)
+        }
+        Stmt::Expression(mut expression_statement) => {
+            expression_statement.expression = fold_boxed(folder, expression_statement.expression);
+            Stmt::Expression(expression_statement)
+        }
+        Stmt::Block(block) => Stmt::Block(folder.fold_block(block)),
+    }
+}
+
+pub fn fold_block<F: Fold + ?Sized>(folder: &mut F, mut block: BlockStatement) -> BlockStatement {
+    block.statements = fold_statements(folder, block.statements);
+    block
+}
+
+pub fn fold_statements<F: Fold + ?Sized>(folder: &mut F, statements: Vec<Stmt>) -> Vec<Stmt> {
+    statements
+        .into_iter()
+        .map(|statement| folder.fold_statement(statement))
+        .collect()
+}
+
+pub fn fold_expressions<F: Fold + ?Sized>(folder: &mut F, expressions: Vec<Expr>) -> Vec<Expr> {
+    expressions
+        .into_iter()
+        .map(|expression| folder.fold_expression(expression))
+        .collect()
+}
+
+fn fold_boxed<F: Fold + ?Sized>(folder: &mut F, expression: Box<Expr>) -> Box<Expr> {
+    Box::new(folder.fold_expression(*expression))
+}
+
+fn fold_bindings<F: Fold + ?Sized>(
+    folder: &mut F,
+    identifiers: Vec<Identifier>,
+) -> Vec<Identifier> {
+    identifiers
+        .into_iter()
+        .map(|identifier| folder.fold_binding(identifier))
+        .collect()
+}
+
+// modify 的 modifier 包装成 Fold
+struct Modifier<'a, M>(&'a M);
+
+impl<M: Fn(Expr) -> Expr> Fold for Modifier<'_, M> {
+    // quote、unquote、unquote_splice 和宏字面量里的代码要等到它们求值时才处理，不进去
+    fn fold_expression(&mut self, expression: Expr) -> Expr {
+        let expression = match expression {
+            expression @ (Expr::Macro(_)
+            | Expr::Quote(_)
+            | Expr::Unquote(_)
+            | Expr::UnquoteSplice(_)) => expression,
+            expression => fold_expression(self, expression),
+        };
+        (self.0)(expression)
+    }
+
+    // let 的名字和参数只能是标识符，modifier 把它换成别的表达式时保留原来的名字
+    fn fold_binding(&mut self, identifier: Identifier) -> Identifier {
+        match (self.0)(Expr::Identifier(identifier.clone())) {
+            Expr::Identifier(identifier) => identifier,
+            _ => identifier,
+        }
+    }
+}
+
+// 先处理子表达式，再把处理过的表达式交给 modifier，由它决定是否换成新的表达式
+// 节点按值传进传出，整棵树只移动不复制；手上只有引用时用 transform
+pub fn modify(expression: Expr, modifier: &impl Fn(Expr) -> Expr) -> Expr {
+    Modifier(modifier).fold_expression(expression)
+}
+
+pub fn modify_statement(statement: Stmt, modifier: &impl Fn(Expr) -> Expr) -> Stmt {
+    Modifier(modifier).fold_statement(statement)
+}
+
+pub fn modify_program(program: &mut Program, modifier: &impl Fn(Expr) -> Expr) {
+    program.statements = fold_statements(
+        &mut Modifier(modifier),
+        std::mem::take(&mut program.statements),
+    );
+}
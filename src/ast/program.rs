@@ -1,14 +1,51 @@
-use crate::ast::traits::{Node, Statement};
+use crate::ast::ids::NodeTable;
+use crate::ast::statements::Stmt;
+use crate::ast::traits::{impl_display_for_node, Node};
+use crate::ast::walk::NodeRef;
+use crate::errors::EvalResult;
 use crate::evaluator::environment::Environment;
 use crate::evaluator::eval::eval_program;
-use crate::evaluator::object::Object;
-use std::{cell::RefCell, rc::Rc};
+use crate::lexer::Span;
+use crate::sexpr;
+use crate::sync::{Rc, RefCell};
+use crate::token::Token;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Program {
-    pub statements: Vec<Box<dyn Statement>>,
+    pub statements: Vec<Stmt>,
+    // 解析时的原始源码，手动构造的 Program 没有源码
+    pub source: Option<Rc<str>>,
 }
 
+impl Program {
+    // 空白或者只有注释的输入解析出来没有任何语句
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    // 缩进的 S 表达式，显示每个节点的类型和区间
+    pub fn to_sexpr(&self) -> String {
+        sexpr::to_sexpr(self)
+    }
+
+    // 每个节点的编号、区间和父子关系，见 ids 模块
+    pub fn node_table(&self) -> NodeTable {
+        NodeTable::new(self)
+    }
+
+    // 根据 span 取回对应的源码片段
+    pub fn source_slice(&self, span: Span) -> Option<&str> {
+        self.source().and_then(|source| span.slice(source))
+    }
+}
+
+impl_display_for_node!(Program);
+
 impl Node for Program {
     fn token_literal(&self) -> &str {
         self.statements
@@ -24,7 +61,19 @@ impl Node for Program {
         out
     }
 
-    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> Box<dyn Object> {
+    fn span(&self) -> Span {
+        Span::cover(self.statements.iter().map(|statement| statement.span()))
+    }
+
+    fn token(&self) -> Option<&Token> {
+        None
+    }
+
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Program(self)
+    }
+
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
         eval_program(self, environment)
     }
 }
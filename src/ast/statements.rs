@@ -1,16 +1,81 @@
-use crate::ast::expressions::Identifier;
-use crate::ast::traits::{Expression, Node, Statement};
+use crate::ast::expressions::{Expr, Identifier};
+use crate::ast::traits::{impl_display_for_node, Node};
+use crate::ast::walk::NodeRef;
+use crate::errors::EvalResult;
 use crate::evaluator::environment::Environment;
-use crate::evaluator::eval::{eval, eval_block_statement, is_error};
-use crate::evaluator::object;
+use crate::evaluator::eval::{eval_block_statement, eval_node};
+use crate::evaluator::object::{self, Value};
+use crate::lexer::Span;
+use crate::sync::{Rc, RefCell};
 use crate::token::Token;
-use std::{cell::RefCell, rc::Rc};
+use std::ops::Deref;
+
+// 所有的语句节点
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Stmt {
+    Let(LetStatement),
+    Return(ReturnStatement),
+    Expression(ExpressionStatement),
+    Block(BlockStatement),
+}
+
+impl Deref for Stmt {
+    type Target = dyn Node;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Stmt::Let(node) => node,
+            Stmt::Return(node) => node,
+            Stmt::Expression(node) => node,
+            Stmt::Block(node) => node,
+        }
+    }
+}
+
+impl From<LetStatement> for Stmt {
+    fn from(node: LetStatement) -> Self {
+        Stmt::Let(node)
+    }
+}
 
-#[derive(Clone)]
+impl From<ReturnStatement> for Stmt {
+    fn from(node: ReturnStatement) -> Self {
+        Stmt::Return(node)
+    }
+}
+
+impl From<ExpressionStatement> for Stmt {
+    fn from(node: ExpressionStatement) -> Self {
+        Stmt::Expression(node)
+    }
+}
+
+impl From<BlockStatement> for Stmt {
+    fn from(node: BlockStatement) -> Self {
+        Stmt::Block(node)
+    }
+}
+
+impl_display_for_node!(
+    Stmt,
+    LetStatement,
+    ReturnStatement,
+    ExpressionStatement,
+    BlockStatement,
+);
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LetStatement {
     pub token: Token,
     pub name: Identifier,
-    pub value: Box<dyn Expression>,
+    // `let x: int = 5;` 里的 int，只给工具展示用，求值时忽略
+    pub type_annotation: Option<Identifier>,
+    pub value: Box<Expr>,
+    // 结尾的分号的区间，没有分号时为空
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub closing: Span,
 }
 
 impl Node for LetStatement {
@@ -20,35 +85,48 @@ impl Node for LetStatement {
 
     fn string(&self) -> String {
         let mut out = String::new();
-        out.push_str(&format!(
-            "{} {} = {};",
-            self.token_literal(),
-            self.name.string(),
-            self.value.string()
-        ));
+        out.push_str(&format!("{} {}", self.token_literal(), self.name.string()));
+        if let Some(type_annotation) = self.type_annotation.as_ref() {
+            out.push_str(&format!(": {}", type_annotation.string()));
+        }
+        out.push_str(&format!(" = {};", self.value.string()));
         out
     }
 
-    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        let value = eval(self.value.as_node(), environment.clone());
-        if is_error(value.as_ref()) {
-            return value;
-        }
-        environment
+    fn span(&self) -> Span {
+        Span::cover([
+            self.token.span,
+            self.name.span(),
+            self.value.span(),
+            self.closing,
+        ])
+    }
+
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
+    }
+
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Let(self)
+    }
+
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
+        let value = eval_node(self.value.as_node(), environment.clone())?;
+        Ok(environment
             .borrow_mut()
             .set(self.name.value.clone(), value)
-            .unwrap_or(Box::new(object::Null))
+            .unwrap_or(Rc::new(Value::Null(object::Null))))
     }
 }
 
-impl Statement for LetStatement {
-    fn statement_node(&self) {}
-}
-
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReturnStatement {
     pub token: Token,
-    pub return_value: Box<dyn Expression>,
+    pub return_value: Box<Expr>,
+    // 结尾的分号的区间，没有分号时为空
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub closing: Span,
 }
 
 impl Node for ReturnStatement {
@@ -66,23 +144,32 @@ impl Node for ReturnStatement {
         out
     }
 
-    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        let value = eval(self.return_value.as_node(), environment);
-        if is_error(value.as_ref()) {
-            return value;
-        }
-        Box::new(object::ReturnValue { value })
+    fn span(&self) -> Span {
+        Span::cover([self.token.span, self.return_value.span(), self.closing])
     }
-}
 
-impl Statement for ReturnStatement {
-    fn statement_node(&self) {}
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
+    }
+
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Return(self)
+    }
+
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
+        let value = eval_node(self.return_value.as_node(), environment)?;
+        Ok(Rc::new(Value::ReturnValue(object::ReturnValue { value })))
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ExpressionStatement {
     pub token: Token,
-    pub expression: Box<dyn Expression>,
+    pub expression: Box<Expr>,
+    // 结尾的分号的区间，没有分号时为空
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub closing: Span,
 }
 
 impl Node for ExpressionStatement {
@@ -94,23 +181,31 @@ impl Node for ExpressionStatement {
         self.expression.string()
     }
 
-    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        eval(self.expression.as_node(), environment)
+    fn span(&self) -> Span {
+        Span::cover([self.token.span, self.expression.span(), self.closing])
     }
-}
 
-impl Statement for ExpressionStatement {
-    fn statement_node(&self) {}
-}
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
+    }
+
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Expression(self)
+    }
 
-impl Expression for ExpressionStatement {
-    fn expression_node(&self) {}
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
+        eval_node(self.expression.as_node(), environment)
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlockStatement {
     pub token: Token, // '{' 词法单元
-    pub statements: Vec<Box<dyn Statement>>,
+    pub statements: Vec<Stmt>,
+    // 结尾的 `}` 的区间，块没有闭合时为空
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub closing: Span,
 }
 
 impl Node for BlockStatement {
@@ -126,11 +221,24 @@ impl Node for BlockStatement {
         result
     }
 
-    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> Box<dyn object::Object> {
-        eval_block_statement(self, environment)
+    fn span(&self) -> Span {
+        Span::cover(
+            [self.token.span]
+                .into_iter()
+                .chain(self.statements.iter().map(|statement| statement.span()))
+                .chain([self.closing]),
+        )
     }
-}
 
-impl Statement for BlockStatement {
-    fn statement_node(&self) {}
+    fn token(&self) -> Option<&Token> {
+        Some(&self.token)
+    }
+
+    fn node_ref(&self) -> NodeRef<'_> {
+        NodeRef::Block(self)
+    }
+
+    fn eval_to_object(&self, environment: Rc<RefCell<Environment>>) -> EvalResult {
+        eval_block_statement(self, environment)
+    }
 }
@@ -1,8 +1,11 @@
+use super::walk::NodeRef;
+use crate::errors::EvalResult;
 use crate::evaluator::environment::Environment;
-use crate::evaluator::object::Object;
+use crate::lexer::Span;
+use crate::sync::{Rc, RefCell};
+use crate::token::Token;
 use downcast_rs::{impl_downcast, Downcast};
 use dyn_clone::DynClone;
-use std::{cell::RefCell, rc::Rc};
 
 // trait used to upcasting, https://stackoverflow.com/questions/28632968/why-doesnt-rust-support-trait-object-upcasting
 pub trait AsNode {
@@ -33,25 +36,33 @@ pub trait Node: AsNode + DynClone + Downcast {
     // 从节点反向打印出本来的代码
     fn string(&self) -> String;
 
+    // 节点连同子节点在源码里覆盖的区间，包括结尾的括号和分号，宏展开生成的节点没有区间
+    fn span(&self) -> Span;
+
+    // 节点自己的词法单元，运行时错误用它的行列号定位，Program 没有
+    fn token(&self) -> Option<&Token>;
+
+    // 节点的具体类型，遍历 AST 时用 match 处理，见 walk::NodeRef
+    fn node_ref(&self) -> NodeRef<'_>;
+
     // 这里还不能使用 &'static mut, 这种引用全局只能有一个，就没法继续传递了
-    fn eval_to_object(&self, _environment: Rc<RefCell<Environment>>) -> Box<dyn Object>;
+    fn eval_to_object(&self, _environment: Rc<RefCell<Environment>>) -> EvalResult;
 }
 
 impl_downcast!(Node);
 dyn_clone::clone_trait_object!(Node);
 
-// 语句
-pub trait Statement: Node + Downcast {
-    fn statement_node(&self);
-}
-
-impl_downcast!(Statement);
-dyn_clone::clone_trait_object!(Statement);
-
-// 表达式
-pub trait Expression: Node + Downcast {
-    fn expression_node(&self);
+// 节点的 Display 就是 string() 还原出来的代码，可以直接用在 format! 和断言信息里
+macro_rules! impl_display_for_node {
+    ($($node:ty),* $(,)?) => {
+        $(
+            impl std::fmt::Display for $node {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    f.write_str(&self.string())
+                }
+            }
+        )*
+    };
 }
 
-impl_downcast!(Expression);
-dyn_clone::clone_trait_object!(Expression);
+pub(crate) use impl_display_for_node;
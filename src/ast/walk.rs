@@ -0,0 +1,157 @@
+use super::expressions::{
+    ArrayLiteral, Boolean, CallExpression, FunctionLiteral, HashLiteral, Identifier, IfExpression,
+    IndexExpression, InfixExpression, IntegerLiteral, MacroLiteral, PrefixExpression,
+    QuoteExpression, StringLiteral, UnquoteExpression, UnquoteSpliceExpression,
+};
+use super::program::Program;
+use super::statements::{BlockStatement, ExpressionStatement, LetStatement, ReturnStatement};
+use super::traits::{AsNode, Node};
+
+// 节点的具体类型，由 Node::node_ref 返回，变体和 Stmt、Expr 一一对应，另外加上 Program
+// 遍历 dyn Node 的工具都对它做 match，新增节点种类时编译器会指出每个漏掉的地方
+#[derive(Clone, Copy)]
+pub enum NodeRef<'a> {
+    Program(&'a Program),
+    Let(&'a LetStatement),
+    Return(&'a ReturnStatement),
+    Expression(&'a ExpressionStatement),
+    Block(&'a BlockStatement),
+    Identifier(&'a Identifier),
+    Integer(&'a IntegerLiteral),
+    Boolean(&'a Boolean),
+    String(&'a StringLiteral),
+    Prefix(&'a PrefixExpression),
+    Infix(&'a InfixExpression),
+    If(&'a IfExpression),
+    Function(&'a FunctionLiteral),
+    Call(&'a CallExpression),
+    Array(&'a ArrayLiteral),
+    Index(&'a IndexExpression),
+    Hash(&'a HashLiteral),
+    Macro(&'a MacroLiteral),
+    Quote(&'a QuoteExpression),
+    Unquote(&'a UnquoteExpression),
+    UnquoteSplice(&'a UnquoteSpliceExpression),
+}
+
+// 按源码顺序返回一个节点的直接子节点，只读遍历 AST 的工具（转译、导出 DOT 等）都基于它
+pub fn children(node: &dyn Node) -> Vec<&dyn Node> {
+    match node.node_ref() {
+        NodeRef::Program(program) => program.statements.iter().map(|s| s.as_node()).collect(),
+        NodeRef::Block(block) => block.statements.iter().map(|s| s.as_node()).collect(),
+        NodeRef::Let(let_statement) => {
+            vec![let_statement.name.as_node(), let_statement.value.as_node()]
+        }
+        NodeRef::Return(return_statement) => vec![return_statement.return_value.as_node()],
+        NodeRef::Expression(expression_statement) => {
+            vec![expression_statement.expression.as_node()]
+        }
+        NodeRef::Prefix(prefix) => vec![prefix.right.as_node()],
+        NodeRef::Infix(infix) => vec![infix.left.as_node(), infix.right.as_node()],
+        NodeRef::If(if_expression) => {
+            let mut nodes = vec![
+                if_expression.condition.as_node(),
+                if_expression.consequence.as_node(),
+            ];
+            if let Some(alternative) = if_expression.alternative.as_ref() {
+                nodes.push(alternative.as_node());
+            }
+            nodes
+        }
+        NodeRef::Function(function) => {
+            let mut nodes = function
+                .parameters
+                .iter()
+                .map(|parameter| parameter.as_node())
+                .collect::<Vec<_>>();
+            nodes.push(function.body.as_node());
+            nodes
+        }
+        NodeRef::Macro(macro_literal) => {
+            let mut nodes = macro_literal
+                .parameters
+                .iter()
+                .map(|parameter| parameter.as_node())
+                .collect::<Vec<_>>();
+            nodes.push(macro_literal.body.as_node());
+            nodes
+        }
+        NodeRef::Call(call) => {
+            let mut nodes = vec![call.function.as_node()];
+            nodes.extend(call.arguments.iter().map(|argument| argument.as_node()));
+            nodes
+        }
+        NodeRef::Array(array) => array.elements.iter().map(|e| e.as_node()).collect(),
+        NodeRef::Index(index) => vec![index.left.as_node(), index.index.as_node()],
+        NodeRef::Quote(quote) => vec![quote.expression.as_node()],
+        NodeRef::Unquote(unquote) => vec![unquote.expression.as_node()],
+        NodeRef::UnquoteSplice(splice) => vec![splice.expression.as_node()],
+        NodeRef::Hash(hash) => hash
+            .pairs
+            .iter()
+            .flat_map(|(key, value)| [key.as_node(), value.as_node()])
+            .collect(),
+        NodeRef::Identifier(_) | NodeRef::Integer(_) | NodeRef::Boolean(_) | NodeRef::String(_) => {
+            vec![]
+        }
+    }
+}
+
+// 节点的类型名，用于调试输出
+pub fn node_kind(node: &dyn Node) -> &'static str {
+    match node.node_ref() {
+        NodeRef::Program(_) => "Program",
+        NodeRef::Let(_) => "LetStatement",
+        NodeRef::Return(_) => "ReturnStatement",
+        NodeRef::Expression(_) => "ExpressionStatement",
+        NodeRef::Block(_) => "BlockStatement",
+        NodeRef::Identifier(_) => "Identifier",
+        NodeRef::Integer(_) => "IntegerLiteral",
+        NodeRef::Boolean(_) => "Boolean",
+        NodeRef::String(_) => "StringLiteral",
+        NodeRef::Prefix(_) => "PrefixExpression",
+        NodeRef::Infix(_) => "InfixExpression",
+        NodeRef::If(_) => "IfExpression",
+        NodeRef::Function(_) => "FunctionLiteral",
+        NodeRef::Call(_) => "CallExpression",
+        NodeRef::Array(_) => "ArrayLiteral",
+        NodeRef::Index(_) => "IndexExpression",
+        NodeRef::Hash(_) => "HashLiteral",
+        NodeRef::Macro(_) => "MacroLiteral",
+        NodeRef::Quote(_) => "QuoteExpression",
+        NodeRef::Unquote(_) => "UnquoteExpression",
+        NodeRef::UnquoteSplice(_) => "UnquoteSpliceExpression",
+    }
+}
+
+// 叶子节点的值、运算符和 let 绑定的名字，调试输出时跟在类型名后面
+pub fn node_detail(node: &dyn Node) -> Option<String> {
+    match node.node_ref() {
+        NodeRef::Identifier(identifier) => Some(identifier.value.to_string()),
+        NodeRef::Integer(integer) => Some(integer.value.to_string()),
+        NodeRef::Boolean(boolean) => Some(boolean.value.to_string()),
+        NodeRef::String(string) => Some(format!("{:?}", string.value)),
+        NodeRef::Prefix(prefix) => Some(prefix.operator.to_string()),
+        NodeRef::Infix(infix) => Some(infix.operator.to_string()),
+        NodeRef::Let(let_statement) => Some(match let_statement.type_annotation.as_ref() {
+            Some(type_annotation) => {
+                format!("{}: {}", let_statement.name.value, type_annotation.value)
+            }
+            None => let_statement.name.value.to_string(),
+        }),
+        NodeRef::Program(_)
+        | NodeRef::Return(_)
+        | NodeRef::Expression(_)
+        | NodeRef::Block(_)
+        | NodeRef::If(_)
+        | NodeRef::Function(_)
+        | NodeRef::Call(_)
+        | NodeRef::Array(_)
+        | NodeRef::Index(_)
+        | NodeRef::Hash(_)
+        | NodeRef::Macro(_)
+        | NodeRef::Quote(_)
+        | NodeRef::Unquote(_)
+        | NodeRef::UnquoteSplice(_) => None,
+    }
+}
@@ -0,0 +1,80 @@
+// 给 C 和其他能调用 C 函数的语言嵌入用的接口，声明在 include/monkey.h
+// monkey_new 创建的解释器用 monkey_free 释放，monkey_eval 返回的结果用 monkey_result_free 释放
+// 传进来的指针必须是这里创建、还没有释放的，字符串必须以 NUL 结尾，空指针会被忽略或者当作错误
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+
+use crate::interpreter::Interpreter;
+
+// 对 C 来说是不透明的类型，只通过指针使用
+pub struct MonkeyInterpreter(Interpreter);
+
+pub struct MonkeyResult {
+    text: CString,
+    is_error: bool,
+}
+
+impl MonkeyResult {
+    fn new(text: String, is_error: bool) -> *mut MonkeyResult {
+        // C 字符串里不能有 NUL，Monkey 的字符串里却可以有
+        let text = CString::new(text.replace('\0', "\\0")).unwrap_or_default();
+        Box::into_raw(Box::new(MonkeyResult { text, is_error }))
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn monkey_new() -> *mut MonkeyInterpreter {
+    Box::into_raw(Box::new(MonkeyInterpreter(Interpreter::new())))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn monkey_free(interpreter: *mut MonkeyInterpreter) {
+    if !interpreter.is_null() {
+        drop(Box::from_raw(interpreter));
+    }
+}
+
+// 解释器为空时返回空指针，源码不是 UTF-8 时返回错误结果
+#[no_mangle]
+pub unsafe extern "C" fn monkey_eval(
+    interpreter: *mut MonkeyInterpreter,
+    source: *const c_char,
+) -> *mut MonkeyResult {
+    let Some(MonkeyInterpreter(interpreter)) = interpreter.as_mut() else {
+        return ptr::null_mut();
+    };
+    if source.is_null() {
+        return MonkeyResult::new("source is null".to_owned(), true);
+    }
+    let source = match CStr::from_ptr(source).to_str() {
+        Ok(source) => source,
+        Err(error) => return MonkeyResult::new(error.to_string(), true),
+    };
+    match interpreter.eval_str(source) {
+        Ok(value) => MonkeyResult::new(value.inspect(), false),
+        Err(error) => MonkeyResult::new(error.to_string(), true),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn monkey_result_is_error(result: *const MonkeyResult) -> bool {
+    result.as_ref().is_none_or(|result| result.is_error)
+}
+
+// 求值得到的值或者错误信息，返回的字符串归结果所有，monkey_result_free 之后不能再用
+#[no_mangle]
+pub unsafe extern "C" fn monkey_result_to_string(result: *const MonkeyResult) -> *const c_char {
+    match result.as_ref() {
+        Some(result) => result.text.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn monkey_result_free(result: *mut MonkeyResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
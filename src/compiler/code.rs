@@ -0,0 +1,175 @@
+// 字节码的格式：一个字节的操作码，后面跟着定长的大端操作数
+use std::fmt::Write;
+
+use crate::lexer::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Opcode {
+    Constant,
+    Pop,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    True,
+    False,
+    Null,
+    Equal,
+    NotEqual,
+    GreaterThan,
+    LessThan,
+    Minus,
+    Bang,
+    JumpNotTruthy,
+    Jump,
+    GetGlobal,
+    SetGlobal,
+    GetLocal,
+    SetLocal,
+    GetFree,
+    // 编译时找不到的名字，运行时再到根环境和内置函数里查找，操作数是名字在常量池里的位置
+    GetName,
+    // 操作数是内置函数在 Bytecode::builtins 里的位置
+    GetBuiltin,
+    Array,
+    Hash,
+    Index,
+    Call,
+    ReturnValue,
+    // 常量池里的函数和捕获的自由变量个数
+    Closure,
+    CurrentClosure,
+}
+
+const OPCODES: [Opcode; 31] = [
+    Opcode::Constant,
+    Opcode::Pop,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Null,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::GreaterThan,
+    Opcode::LessThan,
+    Opcode::Minus,
+    Opcode::Bang,
+    Opcode::JumpNotTruthy,
+    Opcode::Jump,
+    Opcode::GetGlobal,
+    Opcode::SetGlobal,
+    Opcode::GetLocal,
+    Opcode::SetLocal,
+    Opcode::GetFree,
+    Opcode::GetName,
+    Opcode::GetBuiltin,
+    Opcode::Array,
+    Opcode::Hash,
+    Opcode::Index,
+    Opcode::Call,
+    Opcode::ReturnValue,
+    Opcode::Closure,
+    Opcode::CurrentClosure,
+];
+
+impl Opcode {
+    pub fn from_byte(byte: u8) -> Option<Opcode> {
+        OPCODES.get(usize::from(byte)).copied()
+    }
+
+    // 每个操作数占的字节数
+    pub fn operand_widths(self) -> &'static [usize] {
+        match self {
+            Opcode::Constant
+            | Opcode::JumpNotTruthy
+            | Opcode::Jump
+            | Opcode::GetGlobal
+            | Opcode::SetGlobal
+            | Opcode::GetName
+            | Opcode::GetBuiltin
+            | Opcode::Array
+            | Opcode::Hash => &[2],
+            Opcode::GetLocal | Opcode::SetLocal | Opcode::GetFree | Opcode::Call => &[1],
+            Opcode::Closure => &[2, 1],
+            _ => &[],
+        }
+    }
+
+    // 指令的总长度，包括操作码本身
+    pub fn width(self) -> usize {
+        1 + self.operand_widths().iter().sum::<usize>()
+    }
+}
+
+// 操作数放不下时返回 None
+pub fn make(op: Opcode, operands: &[usize]) -> Option<Vec<u8>> {
+    let mut instruction = Vec::with_capacity(op.width());
+    instruction.push(op as u8);
+    for (operand, width) in operands.iter().zip(op.operand_widths()) {
+        match width {
+            2 => instruction.extend(u16::try_from(*operand).ok()?.to_be_bytes()),
+            _ => instruction.push(u8::try_from(*operand).ok()?),
+        }
+    }
+    Some(instruction)
+}
+
+pub fn read_u16(instructions: &[u8], offset: usize) -> usize {
+    usize::from(u16::from_be_bytes([
+        instructions[offset],
+        instructions[offset + 1],
+    ]))
+}
+
+// 返回操作数和它们一共占的字节数
+pub fn read_operands(op: Opcode, instructions: &[u8]) -> (Vec<usize>, usize) {
+    let mut offset = 0;
+    let mut operands = vec![];
+    for width in op.operand_widths() {
+        operands.push(match width {
+            2 => read_u16(instructions, offset),
+            _ => usize::from(instructions[offset]),
+        });
+        offset += width;
+    }
+    (operands, offset)
+}
+
+// 每行一条指令，前面是指令的偏移，调试和测试用
+// 未知的操作码和不完整的操作数也输出一行，不会越界
+pub fn disassemble(instructions: &[u8]) -> String {
+    let mut out = String::new();
+    let mut offset = 0;
+    while offset < instructions.len() {
+        let Some(op) = Opcode::from_byte(instructions[offset]) else {
+            writeln!(out, "{:04} unknown opcode {}", offset, instructions[offset]).unwrap();
+            offset += 1;
+            continue;
+        };
+        if offset + op.width() > instructions.len() {
+            writeln!(out, "{:04} {:?} truncated operand", offset, op).unwrap();
+            break;
+        }
+        let (operands, read) = read_operands(op, &instructions[offset + 1..]);
+        write!(out, "{:04} {:?}", offset, op).unwrap();
+        for operand in operands {
+            write!(out, " {}", operand).unwrap();
+        }
+        out.push('\n');
+        offset += 1 + read;
+    }
+    out
+}
+
+// 可能出错的指令对应的源码位置，运行时错误按它填写行列号
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub offset: usize,
+    pub span: Span,
+    pub line: usize,
+    pub column: usize,
+}
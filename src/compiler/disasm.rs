@@ -0,0 +1,99 @@
+// 给人看的字节码清单：最外层的代码在前，常量池里的函数按编号排在后面
+// 每条指令后面的注释写出操作数指向的常量、全局变量名、内置函数名和跳转目标，以及出错时报告的源码位置
+use std::fmt::Write;
+
+use super::code::{read_operands, Opcode};
+use super::Bytecode;
+use crate::evaluator::object::{CompiledFunction, Value};
+use crate::sync::Rc;
+
+pub fn disassemble_bytecode(bytecode: &Bytecode) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "== main (globals {}, constants {}) ==",
+        bytecode.globals.len(),
+        bytecode.constants.len()
+    )
+    .unwrap();
+    out.push_str(&disassemble_function(&bytecode.main, bytecode));
+    for (index, constant) in bytecode.constants.iter().enumerate() {
+        let Value::CompiledFunction(function) = constant.as_ref() else {
+            continue;
+        };
+        writeln!(
+            out,
+            "\n== function {} (params {}, locals {}) ==",
+            index, function.num_parameters, function.num_locals
+        )
+        .unwrap();
+        out.push_str(&disassemble_function(function, bytecode));
+    }
+    out
+}
+
+// 操作数的含义要到 bytecode 的常量池和名字表里查
+pub fn disassemble_function(function: &CompiledFunction, bytecode: &Bytecode) -> String {
+    let code = function.instructions.as_slice();
+    let mut out = String::new();
+    let mut offset = 0;
+    while offset < code.len() {
+        let Some(op) = Opcode::from_byte(code[offset]) else {
+            writeln!(out, "{:04} unknown opcode {}", offset, code[offset]).unwrap();
+            offset += 1;
+            continue;
+        };
+        let (operands, read) = read_operands(op, &code[offset + 1..]);
+        let mut instruction = format!("{:?}", op);
+        for operand in operands.iter() {
+            write!(instruction, " {}", operand).unwrap();
+        }
+        let mut notes = describe(op, &operands, bytecode);
+        if let Ok(index) = function
+            .locations
+            .binary_search_by_key(&offset, |location| location.offset)
+        {
+            let location = function.locations[index];
+            notes.push(format!("line {}, col {}", location.line, location.column));
+        }
+        if notes.is_empty() {
+            writeln!(out, "{:04} {}", offset, instruction).unwrap();
+        } else {
+            writeln!(
+                out,
+                "{:04} {:<20} ; {}",
+                offset,
+                instruction,
+                notes.join(", ")
+            )
+            .unwrap();
+        }
+        offset += 1 + read;
+    }
+    out
+}
+
+fn describe(op: Opcode, operands: &[usize], bytecode: &Bytecode) -> Vec<String> {
+    let name = |names: &[Rc<str>], index: usize| {
+        names
+            .get(index)
+            .map_or_else(|| "?".to_owned(), |name| name.to_string())
+    };
+    match op {
+        Opcode::Constant | Opcode::GetName => vec![constant(bytecode, operands[0])],
+        Opcode::GetGlobal | Opcode::SetGlobal => vec![name(&bytecode.globals, operands[0])],
+        Opcode::GetBuiltin => vec![name(&bytecode.builtins, operands[0])],
+        Opcode::Jump | Opcode::JumpNotTruthy => vec![format!("-> {:04}", operands[0])],
+        Opcode::Closure => vec![format!("function {}, {} free", operands[0], operands[1])],
+        _ => vec![],
+    }
+}
+
+fn constant(bytecode: &Bytecode, index: usize) -> String {
+    match bytecode.constants.get(index).map(AsRef::as_ref) {
+        Some(Value::String(string)) => format!("{:?}", string.value),
+        Some(Value::CompiledFunction(_)) => format!("function {}", index),
+        Some(value) => value.inspect(),
+        None => "?".to_owned(),
+    }
+}
@@ -0,0 +1,447 @@
+// .monkeyc 文件格式：魔数和版本号，后面依次是全局变量名、内置函数名、常量池和最外层的代码
+// 整数都按大端存放，字符串和字节串前面是 u32 的长度
+// 被 quote 的代码存成格式化之后的源码，读取时重新解析
+use std::collections::HashMap;
+
+use super::code::{read_operands, Location, Opcode};
+use super::{Bytecode, CompileError};
+use crate::ast::expressions::Expr;
+use crate::ast::statements::Stmt;
+use crate::errors::{message, MessageId};
+use crate::evaluator::object::{CompiledFunction, Integer, Quote, StringObject, Value};
+use crate::formatter::{format_expression, FormatOptions};
+use crate::lexer::{Lexer, Span};
+use crate::parser::Parser;
+use crate::sync::Rc;
+
+pub const EXTENSION: &str = "monkeyc";
+pub const MAGIC: &[u8; 4] = b"MKYC";
+// 格式有不兼容的改动时加一
+pub const VERSION: u16 = 1;
+
+const TAG_INTEGER: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_QUOTE: u8 = 2;
+const TAG_FUNCTION: u8 = 3;
+
+// 常量池里只能有编译器生成的几种常量
+pub fn encode(bytecode: &Bytecode) -> Result<Vec<u8>, CompileError> {
+    let mut out = Writer::default();
+    out.bytes.extend(MAGIC);
+    out.bytes.extend(VERSION.to_be_bytes());
+    out.names(&bytecode.globals)?;
+    out.names(&bytecode.builtins)?;
+    out.len(bytecode.constants.len())?;
+    for constant in bytecode.constants.iter() {
+        match constant.as_ref() {
+            Value::Integer(integer) => {
+                out.bytes.push(TAG_INTEGER);
+                out.bytes.extend(integer.value.to_be_bytes());
+            }
+            Value::String(string) => {
+                out.bytes.push(TAG_STRING);
+                out.str(&string.value)?;
+            }
+            Value::Quote(quote) => {
+                out.bytes.push(TAG_QUOTE);
+                out.str(&format_expression(&quote.node, &FormatOptions::default()))?;
+            }
+            Value::CompiledFunction(function) => {
+                out.bytes.push(TAG_FUNCTION);
+                out.function(function)?;
+            }
+            other => {
+                return Err(CompileError::at(
+                    message(
+                        MessageId::NotSupportedByCompiler,
+                        &[&format!("{} constant", other.object_type())],
+                    ),
+                    None,
+                ))
+            }
+        }
+    }
+    out.function(&bytecode.main)?;
+    Ok(out.bytes)
+}
+
+// 读取时检查操作码、常量和变量的编号、跳转目标、栈的深度和自由变量的编号，
+// 通过检查的文件在虚拟机里执行不会越界
+pub fn decode(bytes: &[u8]) -> Result<Bytecode, CompileError> {
+    let mut reader = Reader { bytes, offset: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(invalid("missing magic header"));
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(CompileError::at(
+            message(MessageId::BytecodeVersionMismatch, &[&version, &VERSION]),
+            None,
+        ));
+    }
+    let globals = reader.names()?;
+    let builtins = reader.names()?;
+    let count = reader.len()?;
+    let mut constants = Vec::with_capacity(count.min(bytes.len()));
+    for _ in 0..count {
+        let constant = match reader.u8()? {
+            TAG_INTEGER => Value::Integer(Integer {
+                value: i64::from_be_bytes(reader.array()?),
+            }),
+            TAG_STRING => Value::String(StringObject {
+                value: reader.string()?,
+            }),
+            TAG_QUOTE => Value::Quote(Quote {
+                node: parse_expression(&reader.string()?)?,
+            }),
+            TAG_FUNCTION => Value::CompiledFunction(Rc::new(reader.function()?)),
+            tag => return Err(invalid(&format!("unknown constant tag {}", tag))),
+        };
+        constants.push(Rc::new(constant));
+    }
+    let main = reader.function()?;
+    if reader.offset != bytes.len() {
+        return Err(invalid("trailing bytes"));
+    }
+    let bytecode = Bytecode {
+        main: Rc::new(main),
+        constants,
+        globals,
+        builtins,
+    };
+    // 最外层的代码没有常量编号，也没有自由变量
+    let mut functions = vec![(None, Rc::clone(&bytecode.main))];
+    for (index, constant) in bytecode.constants.iter().enumerate() {
+        if let Value::CompiledFunction(function) = constant.as_ref() {
+            functions.push((Some(index), Rc::clone(function)));
+        }
+    }
+    let decoded = functions
+        .iter()
+        .map(|(_, function)| verify(function, &bytecode))
+        .collect::<Result<Vec<_>, _>>()?;
+    // 函数捕获的自由变量个数由创建它的 Closure 指令决定
+    let mut free_counts = HashMap::new();
+    for instruction in decoded.iter().flatten() {
+        if instruction.op != Opcode::Closure {
+            continue;
+        }
+        let count = instruction.operands[1];
+        if free_counts
+            .insert(instruction.operands[0], count)
+            .is_some_and(|previous| previous != count)
+        {
+            return Err(invalid(&format!(
+                "closure with a different number of free variables at {:04}",
+                instruction.offset
+            )));
+        }
+    }
+    for ((index, function), instructions) in functions.iter().zip(decoded.iter()) {
+        let free = index
+            .and_then(|index| free_counts.get(&index).copied())
+            .unwrap_or(0);
+        verify_stack(instructions, function.instructions.len(), free)?;
+    }
+    Ok(bytecode)
+}
+
+fn invalid(reason: &str) -> CompileError {
+    CompileError::at(message(MessageId::InvalidBytecodeFile, &[&reason]), None)
+}
+
+fn parse_expression(source: &str) -> Result<Expr, CompileError> {
+    let program = Parser::new(Lexer::new(source.to_owned()))
+        .parse()
+        .map_err(|_| invalid("quoted code does not parse"))?;
+    match program.statements.into_iter().next() {
+        Some(Stmt::Expression(statement)) => Ok(*statement.expression),
+        _ => Err(invalid("quoted code is not an expression")),
+    }
+}
+
+// verify 解码出来的一条指令
+struct Instruction {
+    offset: usize,
+    op: Opcode,
+    operands: Vec<usize>,
+}
+
+// 检查操作码、常量和变量的编号以及跳转目标，返回解码出来的指令
+fn verify(
+    function: &CompiledFunction,
+    bytecode: &Bytecode,
+) -> Result<Vec<Instruction>, CompileError> {
+    let code = function.instructions.as_slice();
+    let mut offset = 0;
+    let mut targets = vec![];
+    let mut instructions: Vec<Instruction> = vec![];
+    while offset < code.len() {
+        let op = Opcode::from_byte(code[offset])
+            .ok_or_else(|| invalid(&format!("unknown opcode {}", code[offset])))?;
+        if offset + op.width() > code.len() {
+            return Err(invalid("truncated instruction"));
+        }
+        let (operands, _) = read_operands(op, &code[offset + 1..]);
+        let in_range = match op {
+            Opcode::Constant | Opcode::Closure => operands[0] < bytecode.constants.len(),
+            Opcode::GetName => matches!(
+                bytecode.constants.get(operands[0]).map(Rc::as_ref),
+                Some(Value::String(_))
+            ),
+            Opcode::GetGlobal | Opcode::SetGlobal => operands[0] < bytecode.globals.len(),
+            Opcode::GetBuiltin => operands[0] < bytecode.builtins.len(),
+            Opcode::GetLocal | Opcode::SetLocal => {
+                operands[0] < function.num_locals.max(function.num_parameters)
+            }
+            // 键和值成对出现
+            Opcode::Hash => operands[0] % 2 == 0,
+            Opcode::Jump | Opcode::JumpNotTruthy => {
+                targets.push(operands[0]);
+                true
+            }
+            _ => true,
+        };
+        if !in_range {
+            return Err(invalid(&format!("operand out of range at {:04}", offset)));
+        }
+        if op == Opcode::Closure
+            && !matches!(
+                bytecode.constants[operands[0]].as_ref(),
+                Value::CompiledFunction(_)
+            )
+        {
+            return Err(invalid(&format!(
+                "closure of a non-function at {:04}",
+                offset
+            )));
+        }
+        instructions.push(Instruction {
+            offset,
+            op,
+            operands,
+        });
+        offset += op.width();
+    }
+    // 跳转只能落在指令的开头或者代码的结尾
+    for target in targets {
+        if target != code.len()
+            && instructions
+                .binary_search_by_key(&target, |instruction| instruction.offset)
+                .is_err()
+        {
+            return Err(invalid(&format!("jump into an instruction: {:04}", target)));
+        }
+    }
+    Ok(instructions)
+}
+
+// 沿着每条执行路径计算栈上临时值的个数，不能弹出不存在的值，
+// 同一条指令从不同的路径到达时个数要相同，GetFree 不能超出函数捕获的自由变量
+fn verify_stack(instructions: &[Instruction], len: usize, free: usize) -> Result<(), CompileError> {
+    let mut heights = vec![None; instructions.len()];
+    let mut pending = vec![(0, 0usize)];
+    while let Some((index, height)) = pending.pop() {
+        // 走到代码的结尾，虚拟机会报告函数没有返回值
+        let Some(instruction) = instructions.get(index) else {
+            continue;
+        };
+        let offset = instruction.offset;
+        match heights[index] {
+            Some(known) if known == height => continue,
+            Some(_) => {
+                return Err(invalid(&format!(
+                    "inconsistent stack height at {:04}",
+                    offset
+                )))
+            }
+            None => heights[index] = Some(height),
+        }
+        if instruction.op == Opcode::GetFree && instruction.operands[0] >= free {
+            return Err(invalid(&format!(
+                "free variable out of range at {:04}",
+                offset
+            )));
+        }
+        let (pops, pushes) = stack_effect(instruction);
+        let height = height
+            .checked_sub(pops)
+            .ok_or_else(|| invalid(&format!("stack underflow at {:04}", offset)))?
+            + pushes;
+        let target = |target: usize| {
+            if target == len {
+                instructions.len()
+            } else {
+                instructions.partition_point(|instruction| instruction.offset < target)
+            }
+        };
+        match instruction.op {
+            Opcode::ReturnValue => {}
+            Opcode::Jump => pending.push((target(instruction.operands[0]), height)),
+            Opcode::JumpNotTruthy => {
+                pending.push((index + 1, height));
+                pending.push((target(instruction.operands[0]), height));
+            }
+            _ => pending.push((index + 1, height)),
+        }
+    }
+    Ok(())
+}
+
+// 指令从栈上弹出和压入的值的个数
+fn stack_effect(instruction: &Instruction) -> (usize, usize) {
+    match instruction.op {
+        Opcode::Constant
+        | Opcode::True
+        | Opcode::False
+        | Opcode::Null
+        | Opcode::GetGlobal
+        | Opcode::GetLocal
+        | Opcode::GetFree
+        | Opcode::GetName
+        | Opcode::GetBuiltin
+        | Opcode::CurrentClosure => (0, 1),
+        Opcode::Pop
+        | Opcode::SetGlobal
+        | Opcode::SetLocal
+        | Opcode::JumpNotTruthy
+        | Opcode::ReturnValue => (1, 0),
+        Opcode::Add
+        | Opcode::Sub
+        | Opcode::Mul
+        | Opcode::Div
+        | Opcode::Equal
+        | Opcode::NotEqual
+        | Opcode::GreaterThan
+        | Opcode::LessThan
+        | Opcode::Index => (2, 1),
+        Opcode::Minus | Opcode::Bang => (1, 1),
+        Opcode::Jump => (0, 0),
+        Opcode::Array | Opcode::Hash => (instruction.operands[0], 1),
+        // 被调用的函数和参数换成返回值
+        Opcode::Call => (instruction.operands[0] + 1, 1),
+        Opcode::Closure => (instruction.operands[1], 1),
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    // 长度和编号都存成 u32，放不下时报错，而不是截断成一个读不回来的文件
+    fn len(&mut self, len: usize) -> Result<(), CompileError> {
+        let len = u32::try_from(len)
+            .map_err(|_| CompileError::at(message(MessageId::LengthTooLarge, &[&len]), None))?;
+        self.bytes.extend(len.to_be_bytes());
+        Ok(())
+    }
+
+    fn str(&mut self, value: &str) -> Result<(), CompileError> {
+        self.len(value.len())?;
+        self.bytes.extend(value.as_bytes());
+        Ok(())
+    }
+
+    fn names(&mut self, names: &[Rc<str>]) -> Result<(), CompileError> {
+        self.len(names.len())?;
+        for name in names {
+            self.str(name)?;
+        }
+        Ok(())
+    }
+
+    fn function(&mut self, function: &CompiledFunction) -> Result<(), CompileError> {
+        self.len(function.num_locals)?;
+        self.len(function.num_parameters)?;
+        self.str(&function.literal)?;
+        self.len(function.instructions.len())?;
+        self.bytes.extend(&function.instructions);
+        self.len(function.locations.len())?;
+        for location in function.locations.iter() {
+            for value in [
+                location.offset,
+                location.span.start,
+                location.span.end,
+                location.line,
+                location.column,
+            ] {
+                self.len(value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CompileError> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| invalid("unexpected end of file"))?;
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], CompileError> {
+        Ok(self.take(N)?.try_into().expect("took exactly N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, CompileError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, CompileError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn len(&mut self) -> Result<usize, CompileError> {
+        Ok(u32::from_be_bytes(self.array()?) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, CompileError> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| invalid("string is not UTF-8"))
+    }
+
+    fn names(&mut self) -> Result<Vec<Rc<str>>, CompileError> {
+        let count = self.len()?;
+        (0..count).map(|_| Ok(Rc::from(self.string()?))).collect()
+    }
+
+    fn function(&mut self) -> Result<CompiledFunction, CompileError> {
+        let num_locals = self.len()?;
+        let num_parameters = self.len()?;
+        let literal = self.string()?;
+        let len = self.len()?;
+        let instructions = self.take(len)?.to_vec();
+        let count = self.len()?;
+        let mut locations = Vec::with_capacity(count.min(self.bytes.len()));
+        for _ in 0..count {
+            locations.push(Location {
+                offset: self.len()?,
+                span: Span {
+                    start: self.len()?,
+                    end: self.len()?,
+                },
+                line: self.len()?,
+                column: self.len()?,
+            });
+        }
+        Ok(CompiledFunction {
+            instructions,
+            locations,
+            num_locals,
+            num_parameters,
+            literal,
+        })
+    }
+}
@@ -0,0 +1,488 @@
+// 另一个后端：把宏展开之后的 AST 编译成字节码，交给 vm 模块里的栈式虚拟机执行
+// 运算的结果和错误信息都和树遍历求值器一致，见 vm 模块
+pub mod code;
+pub mod disasm;
+pub mod file;
+pub mod symbol_table;
+
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+
+use self::code::{make, Location, Opcode};
+use self::symbol_table::{Symbol, SymbolScope, SymbolTable};
+use crate::ast::expressions::{CallExpression, Expr, FunctionLiteral, Identifier, QuoteExpression};
+use crate::ast::program::Program;
+use crate::ast::statements::Stmt;
+use crate::ast::traits::{AsNode, Node};
+use crate::ast::walk::{children, NodeRef};
+use crate::errors::{message, MessageId};
+use crate::evaluator::builtins::BuiltinRegistry;
+use crate::evaluator::object::{CompiledFunction, Integer, Quote, StringObject, Value};
+use crate::lexer::Span;
+use crate::sync::Rc;
+
+// 编译的结果：最外层的代码、到目前为止的整个常量池、全局变量名和内置函数名
+#[derive(Debug, Clone)]
+pub struct Bytecode {
+    pub main: Rc<CompiledFunction>,
+    pub constants: Vec<Rc<Value>>,
+    pub globals: Vec<Rc<str>>,
+    pub builtins: Vec<Rc<str>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub message: String,
+    pub span: Span,
+    // 从 1 开始的行列号，行号为 0 表示位置未知
+    pub line: usize,
+    pub column: usize,
+}
+
+impl CompileError {
+    fn at(message: String, node: Option<&dyn Node>) -> Self {
+        let (line, column) = node
+            .and_then(|node| node.token())
+            .map_or((0, 0), |token| (token.line, token.column));
+        Self {
+            message,
+            span: node.map(|node| node.span()).unwrap_or_default(),
+            line,
+            column,
+        }
+    }
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(
+                f,
+                "{} at line {}, col {}",
+                self.message, self.line, self.column
+            )
+        }
+    }
+}
+
+// 宏要在编译之前展开
+pub fn compile(program: &Program) -> Result<Bytecode, CompileError> {
+    Compiler::new().compile(program)
+}
+
+// 一个正在编译的函数体
+#[derive(Default)]
+struct CompilationScope {
+    instructions: Vec<u8>,
+    locations: Vec<Location>,
+}
+
+// 符号表和常量池在多次 compile 之间保留，REPL 的每一行都能看到之前定义的全局变量
+#[derive(Default)]
+pub struct Compiler {
+    constants: Vec<Rc<Value>>,
+    symbol_table: SymbolTable,
+    scopes: Vec<CompilationScope>,
+    builtins: Vec<Rc<str>>,
+}
+
+impl Compiler {
+    // 默认的内置函数在编译时就确定下来，其他找不到的名字留到运行时到环境里查找
+    pub fn new() -> Self {
+        Compiler::default().with_builtins(&BuiltinRegistry::new())
+    }
+
+    // 宿主程序注册的函数也要传进来，否则会编译成按名字查找
+    pub fn with_builtins(mut self, builtins: &BuiltinRegistry) -> Self {
+        for info in builtins.list() {
+            let name = Rc::<str>::from(info.name.as_str());
+            if self.builtins.contains(&name) {
+                continue;
+            }
+            self.symbol_table.define_builtin(self.builtins.len(), &name);
+            self.builtins.push(name);
+        }
+        self
+    }
+
+    pub fn compile(&mut self, program: &Program) -> Result<Bytecode, CompileError> {
+        // 顶层的 let 先全部定义好，函数体里可以引用后面才定义的全局变量
+        let mut names = vec![];
+        collect_lets(&program.statements, &mut names);
+        for name in names.iter() {
+            self.symbol_table.define(name);
+        }
+
+        self.scopes.push(CompilationScope::default());
+        let result = self
+            .block(&program.statements)
+            .and_then(|()| self.emit(Opcode::ReturnValue, &[], None));
+        let scope = self.scopes.pop().expect("main scope pushed above");
+        result?;
+        Ok(Bytecode {
+            main: Rc::new(CompiledFunction {
+                instructions: scope.instructions,
+                locations: scope.locations,
+                num_locals: 0,
+                num_parameters: 0,
+                literal: String::new(),
+            }),
+            constants: self.constants.clone(),
+            globals: self.symbol_table.global_names(),
+            builtins: self.builtins.clone(),
+        })
+    }
+
+    // 编译一串语句，最后一条语句的值留在栈上，没有值时是 null
+    fn block(&mut self, statements: &[Stmt]) -> Result<(), CompileError> {
+        let Some((last, init)) = statements.split_last() else {
+            return self.emit(Opcode::Null, &[], None);
+        };
+        for statement in init {
+            self.statement(statement)?;
+        }
+        match last {
+            Stmt::Expression(expression_statement) => {
+                self.expression(&expression_statement.expression)
+            }
+            Stmt::Block(block) => self.block(&block.statements),
+            statement => {
+                self.statement(statement)?;
+                self.emit(Opcode::Null, &[], None)
+            }
+        }
+    }
+
+    // 语句执行完不在栈上留下值
+    fn statement(&mut self, statement: &Stmt) -> Result<(), CompileError> {
+        match statement {
+            Stmt::Let(let_statement) => {
+                match let_statement.value.as_ref() {
+                    Expr::Function(function) => {
+                        self.function(function, Some(&let_statement.name.value))?
+                    }
+                    value => self.expression(value)?,
+                }
+                // 局部变量在值算完之后才定义，`let x = x + 1` 里右边的 x 还是外层的
+                let symbol = self.symbol_table.define(&let_statement.name.value);
+                match symbol.scope {
+                    SymbolScope::Global => self.emit(Opcode::SetGlobal, &[symbol.index], None),
+                    _ => self.emit(Opcode::SetLocal, &[symbol.index], None),
+                }
+            }
+            Stmt::Return(return_statement) => {
+                self.expression(&return_statement.return_value)?;
+                self.emit(Opcode::ReturnValue, &[], None)
+            }
+            Stmt::Expression(expression_statement) => {
+                self.expression(&expression_statement.expression)?;
+                self.emit(Opcode::Pop, &[], None)
+            }
+            Stmt::Block(block) => {
+                for statement in block.statements.iter() {
+                    self.statement(statement)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn expression(&mut self, expression: &Expr) -> Result<(), CompileError> {
+        let node = expression.as_node();
+        match expression {
+            Expr::Identifier(identifier) => self.identifier(identifier),
+            Expr::Integer(integer) => {
+                let index = self.add_constant(Value::Integer(Integer {
+                    value: integer.value,
+                }));
+                self.emit(Opcode::Constant, &[index], None)
+            }
+            Expr::Boolean(boolean) => {
+                let op = if boolean.value {
+                    Opcode::True
+                } else {
+                    Opcode::False
+                };
+                self.emit(op, &[], None)
+            }
+            Expr::String(string) => {
+                let index = self.add_constant(Value::String(StringObject {
+                    value: string.value.to_string(),
+                }));
+                self.emit(Opcode::Constant, &[index], None)
+            }
+            Expr::Prefix(prefix) => {
+                self.expression(&prefix.right)?;
+                let op = match prefix.operator.as_ref() {
+                    "-" => Opcode::Minus,
+                    "!" => Opcode::Bang,
+                    operator => return Err(unsupported(&format!("operator {}", operator), node)),
+                };
+                self.emit(op, &[], Some(node))
+            }
+            Expr::Infix(infix) => {
+                self.expression(&infix.left)?;
+                self.expression(&infix.right)?;
+                let op = match infix.operator.as_ref() {
+                    "+" => Opcode::Add,
+                    "-" => Opcode::Sub,
+                    "*" => Opcode::Mul,
+                    "/" => Opcode::Div,
+                    "==" => Opcode::Equal,
+                    "!=" => Opcode::NotEqual,
+                    ">" => Opcode::GreaterThan,
+                    "<" => Opcode::LessThan,
+                    operator => return Err(unsupported(&format!("operator {}", operator), node)),
+                };
+                self.emit(op, &[], Some(node))
+            }
+            Expr::If(if_expression) => {
+                self.expression(&if_expression.condition)?;
+                let jump_not_truthy = self.emit_jump(Opcode::JumpNotTruthy)?;
+                self.block(&if_expression.consequence.statements)?;
+                let jump = self.emit_jump(Opcode::Jump)?;
+                self.patch_jump(jump_not_truthy, node)?;
+                match if_expression.alternative.as_ref() {
+                    Some(alternative) => self.block(&alternative.statements)?,
+                    None => self.emit(Opcode::Null, &[], None)?,
+                }
+                self.patch_jump(jump, node)
+            }
+            Expr::Function(function) => self.function(function, None),
+            Expr::Macro(_) => Err(unsupported("macro", node)),
+            Expr::Call(call) => self.call(call),
+            Expr::Quote(quote) => self.quote(quote),
+            Expr::Unquote(_) | Expr::UnquoteSplice(_) => Err(CompileError::at(
+                message(MessageId::UnquoteOutsideQuote, &[&node.token_literal()]),
+                Some(node),
+            )),
+            Expr::Array(array) => {
+                for element in array.elements.iter() {
+                    self.expression(element)?;
+                }
+                self.emit(Opcode::Array, &[array.elements.len()], Some(node))
+            }
+            Expr::Index(index) => {
+                self.expression(&index.left)?;
+                self.expression(&index.index)?;
+                self.emit(Opcode::Index, &[], Some(node))
+            }
+            Expr::Hash(hash) => {
+                for (key, value) in hash.pairs.iter() {
+                    self.expression(key)?;
+                    self.expression(value)?;
+                }
+                self.emit(Opcode::Hash, &[hash.pairs.len() * 2], Some(node))
+            }
+        }
+    }
+
+    fn identifier(&mut self, identifier: &Identifier) -> Result<(), CompileError> {
+        match self.symbol_table.resolve(&identifier.value) {
+            Some(symbol) => self.load_symbol(&symbol, identifier),
+            None => {
+                let index = self.add_constant(Value::String(StringObject {
+                    value: identifier.value.to_string(),
+                }));
+                self.emit(Opcode::GetName, &[index], Some(identifier))
+            }
+        }
+    }
+
+    fn load_symbol(&mut self, symbol: &Symbol, node: &dyn Node) -> Result<(), CompileError> {
+        match symbol.scope {
+            SymbolScope::Global => self.emit(Opcode::GetGlobal, &[symbol.index], Some(node)),
+            SymbolScope::Local => self.emit(Opcode::GetLocal, &[symbol.index], Some(node)),
+            SymbolScope::Free => self.emit(Opcode::GetFree, &[symbol.index], Some(node)),
+            SymbolScope::Function => self.emit(Opcode::CurrentClosure, &[], Some(node)),
+            SymbolScope::Builtin => self.emit(Opcode::GetBuiltin, &[symbol.index], Some(node)),
+        }
+    }
+
+    // name 是 let 绑定的名字，函数体里用它递归调用自己
+    fn function(
+        &mut self,
+        function: &FunctionLiteral,
+        name: Option<&Rc<str>>,
+    ) -> Result<(), CompileError> {
+        let node = function.as_node();
+        self.symbol_table = SymbolTable::new_enclosed(std::mem::take(&mut self.symbol_table));
+        self.scopes.push(CompilationScope::default());
+        if let Some(name) = name {
+            self.symbol_table.define_function_name(name);
+        }
+        for parameter in function.parameters.iter() {
+            self.symbol_table.define(&parameter.value);
+        }
+        let result = self
+            .block(&function.body.statements)
+            .and_then(|()| self.emit(Opcode::ReturnValue, &[], None));
+
+        // 出错时也要回到外层，REPL 会继续用这个编译器
+        let scope = self.scopes.pop().expect("function scope pushed above");
+        let num_locals = self.symbol_table.num_definitions();
+        let free_symbols = self.symbol_table.free_symbols().to_vec();
+        self.symbol_table = std::mem::take(&mut self.symbol_table)
+            .into_outer()
+            .expect("function symbol table is enclosed");
+        result?;
+
+        if function.parameters.len() > usize::from(u8::MAX) {
+            return Err(too_large(
+                Opcode::Call,
+                function.parameters.len(),
+                Some(node),
+            ));
+        }
+        for symbol in free_symbols.iter() {
+            self.load_symbol(symbol, node)?;
+        }
+        let index = self.add_constant(Value::CompiledFunction(Rc::new(CompiledFunction {
+            instructions: scope.instructions,
+            locations: scope.locations,
+            num_locals,
+            num_parameters: function.parameters.len(),
+            literal: format!(
+                "fn ({}) {{\n{}\n}}",
+                function
+                    .parameters
+                    .iter()
+                    .map(|p| p.string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                function.body.string()
+            ),
+        })));
+        self.emit(Opcode::Closure, &[index, free_symbols.len()], Some(node))
+    }
+
+    // 被 quote 的代码直接放进常量池
+    fn quote(&mut self, quote: &QuoteExpression) -> Result<(), CompileError> {
+        let node = quote.as_node();
+        if contains_unquote(quote.expression.as_node()) {
+            return Err(unsupported("unquote", quote.expression.as_node()));
+        }
+        let index = self.add_constant(Value::Quote(Quote {
+            node: (*quote.expression).clone(),
+        }));
+        self.emit(Opcode::Constant, &[index], Some(node))
+    }
+
+    fn call(&mut self, call: &CallExpression) -> Result<(), CompileError> {
+        let node = call.as_node();
+        self.expression(&call.function)?;
+        for argument in call.arguments.iter() {
+            self.expression(argument)?;
+        }
+        self.emit(Opcode::Call, &[call.arguments.len()], Some(node))
+    }
+
+    fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(Rc::new(value));
+        self.constants.len() - 1
+    }
+
+    // node 是可能在运行时出错的指令对应的节点
+    fn emit(
+        &mut self,
+        op: Opcode,
+        operands: &[usize],
+        node: Option<&dyn Node>,
+    ) -> Result<(), CompileError> {
+        let Some(instruction) = make(op, operands) else {
+            let operand = operands.iter().max().copied().unwrap_or_default();
+            return Err(too_large(op, operand, node));
+        };
+        let scope = self.scopes.last_mut().expect("a scope is always pushed");
+        if let Some(node) = node {
+            let (line, column) = node
+                .token()
+                .map_or((0, 0), |token| (token.line, token.column));
+            scope.locations.push(Location {
+                offset: scope.instructions.len(),
+                span: node.span(),
+                line,
+                column,
+            });
+        }
+        scope.instructions.extend(instruction);
+        Ok(())
+    }
+
+    // 先写一个占位的跳转目标，返回指令的位置
+    fn emit_jump(&mut self, op: Opcode) -> Result<usize, CompileError> {
+        let position = self.current_instructions().len();
+        self.emit(op, &[0], None)?;
+        Ok(position)
+    }
+
+    // 跳到当前位置
+    fn patch_jump(&mut self, position: usize, node: &dyn Node) -> Result<(), CompileError> {
+        let target = self.current_instructions().len();
+        let Ok(target) = u16::try_from(target) else {
+            return Err(too_large(Opcode::Jump, target, Some(node)));
+        };
+        self.scopes
+            .last_mut()
+            .expect("a scope is always pushed")
+            .instructions[position + 1..position + 3]
+            .copy_from_slice(&target.to_be_bytes());
+        Ok(())
+    }
+
+    fn current_instructions(&self) -> &[u8] {
+        &self
+            .scopes
+            .last()
+            .expect("a scope is always pushed")
+            .instructions
+    }
+}
+
+fn unsupported(what: &str, node: &dyn Node) -> CompileError {
+    CompileError::at(
+        message(MessageId::NotSupportedByCompiler, &[&what]),
+        Some(node),
+    )
+}
+
+fn too_large(op: Opcode, operand: usize, node: Option<&dyn Node>) -> CompileError {
+    CompileError::at(
+        message(
+            MessageId::OperandTooLarge,
+            &[&format!("{:?}", op), &operand],
+        ),
+        node,
+    )
+}
+
+fn contains_unquote(node: &dyn Node) -> bool {
+    matches!(
+        node.node_ref(),
+        NodeRef::Unquote(_) | NodeRef::UnquoteSplice(_)
+    ) || children(node).into_iter().any(contains_unquote)
+}
+
+// 顶层 let 的名字，按出现的顺序，if 的代码块不产生新的作用域，不进入函数
+fn collect_lets(statements: &[Stmt], names: &mut Vec<Rc<str>>) {
+    let mut seen = names.iter().cloned().collect::<HashSet<_>>();
+    let mut pending = statements
+        .iter()
+        .rev()
+        .map(|statement| statement.as_node())
+        .collect::<Vec<_>>();
+    while let Some(node) = pending.pop() {
+        match node.node_ref() {
+            NodeRef::Function(_) | NodeRef::Macro(_) => continue,
+            NodeRef::Let(let_statement) if seen.insert(Rc::clone(&let_statement.name.value)) => {
+                names.push(Rc::clone(&let_statement.name.value));
+            }
+            _ => {}
+        }
+        let mut nested = children(node);
+        nested.reverse();
+        pending.extend(nested);
+    }
+}
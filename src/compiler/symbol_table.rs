@@ -0,0 +1,143 @@
+use crate::sync::Rc;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolScope {
+    Global,
+    Local,
+    // 外层函数的局部变量，创建闭包时复制进来
+    Free,
+    // 正在定义的函数自己的名字，用来递归调用
+    Function,
+    // 内置函数，编号是它在 Bytecode::builtins 里的位置
+    Builtin,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: Rc<str>,
+    pub scope: SymbolScope,
+    pub index: usize,
+}
+
+// 每个函数一张表，最外层是全局变量
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    outer: Option<Box<SymbolTable>>,
+    store: HashMap<Rc<str>, Symbol>,
+    num_definitions: usize,
+    free_symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    pub fn new_enclosed(outer: SymbolTable) -> Self {
+        SymbolTable {
+            outer: Some(Box::new(outer)),
+            ..SymbolTable::default()
+        }
+    }
+
+    // 回到外层的表，最外层返回 None
+    pub fn into_outer(self) -> Option<SymbolTable> {
+        self.outer.map(|outer| *outer)
+    }
+
+    pub fn is_global(&self) -> bool {
+        self.outer.is_none()
+    }
+
+    // 全局变量重新 let 时沿用原来的位置，之前定义的函数引用它时能看到新的值
+    // 局部变量每次 let 都占一个新的位置，之前创建的闭包复制走的还是旧的值
+    pub fn define(&mut self, name: &Rc<str>) -> Symbol {
+        if self.is_global() {
+            if let Some(symbol) = self.store.get(name) {
+                if symbol.scope == SymbolScope::Global {
+                    return symbol.clone();
+                }
+            }
+        }
+        let symbol = Symbol {
+            name: Rc::clone(name),
+            scope: if self.is_global() {
+                SymbolScope::Global
+            } else {
+                SymbolScope::Local
+            },
+            index: self.num_definitions,
+        };
+        self.num_definitions += 1;
+        self.store.insert(Rc::clone(name), symbol.clone());
+        symbol
+    }
+
+    // 同名的全局变量之后会覆盖它，和求值器先查环境再查内置函数一样
+    pub fn define_builtin(&mut self, index: usize, name: &Rc<str>) -> Symbol {
+        let symbol = Symbol {
+            name: Rc::clone(name),
+            scope: SymbolScope::Builtin,
+            index,
+        };
+        self.store.insert(Rc::clone(name), symbol.clone());
+        symbol
+    }
+
+    pub fn define_function_name(&mut self, name: &Rc<str>) -> Symbol {
+        let symbol = Symbol {
+            name: Rc::clone(name),
+            scope: SymbolScope::Function,
+            index: 0,
+        };
+        self.store.insert(Rc::clone(name), symbol.clone());
+        symbol
+    }
+
+    // 外层函数的局部变量会变成这一层的自由变量
+    pub fn resolve(&mut self, name: &str) -> Option<Symbol> {
+        if let Some(symbol) = self.store.get(name) {
+            return Some(symbol.clone());
+        }
+        let symbol = self.outer.as_mut()?.resolve(name)?;
+        if matches!(symbol.scope, SymbolScope::Global | SymbolScope::Builtin) {
+            return Some(symbol);
+        }
+        Some(self.define_free(symbol))
+    }
+
+    fn define_free(&mut self, original: Symbol) -> Symbol {
+        let symbol = Symbol {
+            name: Rc::clone(&original.name),
+            scope: SymbolScope::Free,
+            index: self.free_symbols.len(),
+        };
+        self.free_symbols.push(original);
+        self.store.insert(Rc::clone(&symbol.name), symbol.clone());
+        symbol
+    }
+
+    pub fn num_definitions(&self) -> usize {
+        self.num_definitions
+    }
+
+    // 自由变量在外层表里的符号，按自由变量的编号排列
+    pub fn free_symbols(&self) -> &[Symbol] {
+        &self.free_symbols
+    }
+
+    // 按位置排列的全局变量名，运行时报错用
+    pub fn global_names(&self) -> Vec<Rc<str>> {
+        let mut symbols = self
+            .store
+            .values()
+            .filter(|symbol| matches!(symbol.scope, SymbolScope::Global))
+            .collect::<Vec<_>>();
+        symbols.sort_by_key(|symbol| symbol.index);
+        symbols
+            .into_iter()
+            .map(|symbol| Rc::clone(&symbol.name))
+            .collect()
+    }
+}
@@ -0,0 +1,70 @@
+// 一组有代表性的 Monkey 程序，基准测试、fuzz 的种子和不同后端之间的一致性检查都从这里取输入
+// 源码在编译期嵌入，不依赖运行时的工作目录
+use crate::ast::program::Program;
+use crate::errors::ParseError;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Workload {
+    Strings,
+    Recursion,
+    Hashes,
+    Macros,
+}
+
+#[derive(Debug)]
+pub struct CorpusProgram {
+    pub name: &'static str,
+    pub workload: Workload,
+    pub source: &'static str,
+    // 程序最后一个表达式求值结果的 inspect()
+    pub expected: &'static str,
+}
+
+impl CorpusProgram {
+    pub fn parse(&self) -> Result<Program, Vec<ParseError>> {
+        Parser::new(Lexer::new(self.source.to_owned())).parse()
+    }
+}
+
+static CORPUS: [CorpusProgram; 4] = [
+    CorpusProgram {
+        name: "strings",
+        workload: Workload::Strings,
+        source: include_str!("../corpus/strings.mky"),
+        expected: "177",
+    },
+    CorpusProgram {
+        name: "recursion",
+        workload: Workload::Recursion,
+        source: include_str!("../corpus/recursion.mky"),
+        expected: "[987, 20100, 9]",
+    },
+    CorpusProgram {
+        name: "hashes",
+        workload: Workload::Hashes,
+        source: include_str!("../corpus/hashes.mky"),
+        expected: "[31415926, 2, 162, 81]",
+    },
+    CorpusProgram {
+        name: "macros",
+        workload: Workload::Macros,
+        source: include_str!("../corpus/macros.mky"),
+        expected: "[right, [3, 3], 49, 0, 10, 7]",
+    },
+];
+
+pub fn programs() -> &'static [CorpusProgram] {
+    &CORPUS
+}
+
+pub fn find(name: &str) -> Option<&'static CorpusProgram> {
+    CORPUS.iter().find(|program| program.name == name)
+}
+
+pub fn by_workload(workload: Workload) -> impl Iterator<Item = &'static CorpusProgram> {
+    CORPUS
+        .iter()
+        .filter(move |program| program.workload == workload)
+}
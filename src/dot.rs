@@ -0,0 +1,137 @@
+// 导出 Graphviz 的 DOT 格式，用来查看 AST 和运行时的环境链
+use std::fmt::Write;
+
+use crate::ast::expressions::Identifier;
+use crate::ast::traits::Node;
+use crate::ast::walk::{children, node_detail, node_kind};
+use crate::evaluator::environment::Environment;
+use crate::evaluator::object::Value;
+use crate::sync::{Rc, RefCell};
+
+// 绑定的值太长时只显示开头
+const MAX_VALUE_WIDTH: usize = 32;
+
+pub fn ast_to_dot(node: &dyn Node) -> String {
+    let mut out = String::from("digraph ast {\n    node [shape=box, fontname=\"monospace\"];\n");
+    let mut next_id = 0;
+    write_ast_node(node, &mut out, &mut next_id);
+    out.push_str("}\n");
+    out
+}
+
+fn write_ast_node(node: &dyn Node, out: &mut String, next_id: &mut usize) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+    let label = match node_detail(node) {
+        Some(detail) => format!("{}\n{}", node_kind(node), detail),
+        None => node_kind(node).to_owned(),
+    };
+    writeln!(out, "    n{} [label=\"{}\"];", id, escape(&label)).unwrap();
+    for child in children(node) {
+        let child_id = write_ast_node(child, out, next_id);
+        writeln!(out, "    n{} -> n{};", id, child_id).unwrap();
+    }
+    id
+}
+
+// 从给定环境出发，画出 outer 链以及闭包捕获的环境
+// 实线是 outer 指向，虚线从持有闭包的环境指向闭包捕获的环境，边上标注绑定名
+pub fn environment_to_dot(env: &Rc<RefCell<Environment>>) -> String {
+    let mut out =
+        String::from("digraph environment {\n    node [shape=box, fontname=\"monospace\"];\n");
+    let mut visited: Vec<Rc<RefCell<Environment>>> = vec![Rc::clone(env)];
+    let mut index = 0;
+    while index < visited.len() {
+        let current = Rc::clone(&visited[index]);
+        let current = current.borrow();
+
+        let mut bindings = current.iter().collect::<Vec<_>>();
+        bindings.sort_by_key(|(name, _)| *name);
+        let mut label = format!("env{}", index);
+        let mut captures = vec![];
+        for (name, value) in bindings {
+            match value.as_ref() {
+                Value::Function(function) => {
+                    label.push_str(&format!(
+                        "\n{} = fn({})",
+                        name,
+                        parameters(&function.parameters)
+                    ));
+                    captures.push((name.clone(), Rc::clone(&function.env)));
+                }
+                Value::Macro(macro_object) => {
+                    label.push_str(&format!(
+                        "\n{} = macro({})",
+                        name,
+                        parameters(&macro_object.parameters)
+                    ));
+                    captures.push((name.clone(), Rc::clone(&macro_object.env)));
+                }
+                _ => label.push_str(&format!("\n{} = {}", name, truncate(&value.inspect()))),
+            }
+        }
+        writeln!(out, "    env{} [label=\"{}\"];", index, escape(&label)).unwrap();
+
+        if let Some(outer) = current.outer() {
+            let outer_id = environment_id(&mut visited, outer);
+            writeln!(
+                out,
+                "    env{} -> env{} [label=\"outer\"];",
+                index, outer_id
+            )
+            .unwrap();
+        }
+        for (name, captured) in captures {
+            let captured_id = environment_id(&mut visited, captured);
+            writeln!(
+                out,
+                "    env{} -> env{} [label=\"{}\", style=dashed];",
+                index,
+                captured_id,
+                escape(&name)
+            )
+            .unwrap();
+        }
+        index += 1;
+    }
+    out.push_str("}\n");
+    out
+}
+
+// 按指针判断是不是同一个环境，没见过的环境排到队尾等待遍历
+fn environment_id(
+    visited: &mut Vec<Rc<RefCell<Environment>>>,
+    env: Rc<RefCell<Environment>>,
+) -> usize {
+    match visited.iter().position(|seen| Rc::ptr_eq(seen, &env)) {
+        Some(id) => id,
+        None => {
+            visited.push(env);
+            visited.len() - 1
+        }
+    }
+}
+
+fn parameters(parameters: &[Identifier]) -> String {
+    parameters
+        .iter()
+        .map(|parameter| parameter.value.clone())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn truncate(value: &str) -> String {
+    if value.chars().count() <= MAX_VALUE_WIDTH {
+        value.to_owned()
+    } else {
+        let prefix = value.chars().take(MAX_VALUE_WIDTH).collect::<String>();
+        format!("{}...", prefix)
+    }
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,468 @@
+// 语法分析和求值产生的诊断信息都从这里生成，嵌入方可以通过 set_catalog 换成自己的翻译
+// 模板里用 {0}、{1} 这样的占位符引用参数，翻译时可以调整参数的顺序
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+
+use crate::ast::traits::Node;
+use crate::evaluator::object::{Error, Exit, Value};
+use crate::lexer::Span;
+use crate::sync::Rc;
+use crate::token::{Token, TokenType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageId {
+    ExpectedNextToken,
+    NoPrefixParseFunction,
+    InvalidIntegerLiteral,
+    IdentifierNotFound,
+    TypeMismatch,
+    UnknownPrefixOperator,
+    UnknownInfixOperator,
+    NotAFunction,
+    IndexNotSupported,
+    UnusableHashKey,
+    WrongNumberOfArguments,
+    ArgumentNotSupported,
+    ArgumentMustBe,
+    ArgumentsMustBe,
+    ArgumentMustBeNonNegative,
+    InvalidRange,
+    DisabledInSandbox,
+    BuiltinDisabled,
+    UnsupportedOnPlatform,
+    Deadlock,
+    BudgetExceeded,
+    UnquoteOutsideQuote,
+    WriteOutputFailed,
+    ReadLineFailed,
+    ReadInputFailed,
+    SystemTimeFailed,
+    AssertionFailed,
+    AssertionFailedWithMessage,
+    AssertEqFailed,
+    FormatArgumentCount,
+    UnmatchedFormatBrace,
+    EvalParseFailed,
+    FrozenObject,
+    ExpressionParseFailed,
+    NotAnExpression,
+    ModuleNotFound,
+    ModuleParseFailed,
+    ImportCycle,
+    InternalError,
+    DivisionByZero,
+    IntegerOverflow,
+    UnquoteNotSupported,
+    SpliceNotArray,
+    SpliceOutsideList,
+    MacroExpansionTooDeep,
+    IllegalCharacter,
+    UnexpectedAfterIdentifier,
+    DidYouMean,
+    Timeout,
+    OutOfMemory,
+    UnusedBinding,
+    UnreachableCode,
+    ConstantCondition,
+    DuplicateParameter,
+    ShadowedBinding,
+    AnnotationMismatch,
+    NotSupportedByCompiler,
+    OperandTooLarge,
+    LengthTooLarge,
+    InvalidBytecodeFile,
+    BytecodeVersionMismatch,
+    HostFunctionFailed,
+    InvalidHex,
+    ByteOutOfRange,
+    ExitCodeOutOfRange,
+    FileReadFailed,
+    FileWriteFailed,
+    NotComparable,
+    NestingTooDeep,
+    RecursionTooDeep,
+}
+
+pub trait MessageCatalog {
+    // 返回 None 时退回到英文模板
+    fn template(&self, id: MessageId) -> Option<&str>;
+}
+
+// 默认的英文模板
+pub struct English;
+
+impl MessageCatalog for English {
+    fn template(&self, id: MessageId) -> Option<&str> {
+        Some(english(id))
+    }
+}
+
+fn english(id: MessageId) -> &'static str {
+    match id {
+        MessageId::ExpectedNextToken => "expected next token to be {0}, got {1} instead",
+        MessageId::NoPrefixParseFunction => "No prefix parse function for {0} found",
+        MessageId::InvalidIntegerLiteral => "could not parse {0} as integer",
+        MessageId::IdentifierNotFound => "identifier not found: {0}",
+        MessageId::TypeMismatch => "type mismatch: {0} {1} {2}",
+        MessageId::UnknownPrefixOperator => "unknown operator: {0}{1}",
+        MessageId::UnknownInfixOperator => "unknown operator: {0} {1} {2}",
+        MessageId::NotAFunction => "not a function: {0}",
+        MessageId::IndexNotSupported => "index operator not supported: {0}",
+        MessageId::UnusableHashKey => "unusable as hash key: {0}",
+        MessageId::WrongNumberOfArguments => "wrong number of arguments: got={0}, want={1}",
+        MessageId::ArgumentNotSupported => "argument to `{0}` not supported, got {1}",
+        MessageId::ArgumentMustBe => "argument to `{0}` must be {1}, got {2}",
+        MessageId::ArgumentsMustBe => "arguments to `{0}` must be {1}, got {2} and {3}",
+        MessageId::ArgumentMustBeNonNegative => "argument to `{0}` must be non-negative, got {1}",
+        MessageId::InvalidRange => "invalid range for `{0}`: {1} > {2}",
+        MessageId::DisabledInSandbox => "`{0}` is disabled in sandbox mode",
+        MessageId::BuiltinDisabled => "`{0}` is disabled",
+        MessageId::UnsupportedOnPlatform => "`{0}` is not supported on this platform",
+        MessageId::Deadlock => "deadlock: `{0}` would wait forever",
+        MessageId::BudgetExceeded => "evaluation budget exceeded: {0} steps",
+        MessageId::UnquoteOutsideQuote => "`{0}` can only be used inside `quote`",
+        MessageId::WriteOutputFailed => "failed to write output: {0}",
+        MessageId::ReadLineFailed => "failed to read line: {0}",
+        MessageId::ReadInputFailed => "failed to read input: {0}",
+        MessageId::SystemTimeFailed => "failed to read system time: {0}",
+        MessageId::AssertionFailed => "assertion failed",
+        MessageId::AssertionFailedWithMessage => "assertion failed: {0}",
+        MessageId::AssertEqFailed => "assertion failed: left={0}, right={1}",
+        MessageId::FormatArgumentCount => "format string has {0} placeholders, got {1} arguments",
+        MessageId::UnmatchedFormatBrace => "unmatched `{0}` in format string",
+        MessageId::EvalParseFailed => "failed to parse source passed to `eval`: {0}",
+        MessageId::FrozenObject => "`{0}` cannot modify a frozen {1}",
+        MessageId::ExpressionParseFailed => "failed to parse expression: {0}",
+        MessageId::NotAnExpression => "expected a single expression, got `{0}`",
+        MessageId::ModuleNotFound => "cannot import `{0}`: {1}",
+        MessageId::ModuleParseFailed => "failed to parse module `{0}`: {1}",
+        MessageId::ImportCycle => "import cycle detected: {0}",
+        MessageId::InternalError => "internal error: {0}",
+        MessageId::DivisionByZero => "division by zero: {0} / 0",
+        MessageId::IntegerOverflow => "integer overflow: {0}",
+        MessageId::UnquoteNotSupported => "unquote does not support {0}",
+        MessageId::SpliceNotArray => {
+            "`unquote_splice` needs an array or a quoted array literal, got {0}"
+        }
+        MessageId::SpliceOutsideList => {
+            "`unquote_splice` can only be used as a call argument or an array element"
+        }
+        MessageId::MacroExpansionTooDeep => "macro expansion exceeded {0} levels: {1}",
+        MessageId::IllegalCharacter => "illegal character '{0}'",
+        MessageId::UnexpectedAfterIdentifier => "unexpected {0} after identifier `{1}`",
+        MessageId::DidYouMean => "{0}, did you mean `{1}`?",
+        MessageId::Timeout => "evaluation timed out after {0} ms",
+        MessageId::OutOfMemory => "out of memory: allocated more than {0} bytes",
+        MessageId::UnusedBinding => "unused binding `{0}`",
+        MessageId::UnreachableCode => "unreachable code after `return`",
+        MessageId::ConstantCondition => "condition is always {0}",
+        MessageId::DuplicateParameter => "duplicate parameter `{0}`",
+        MessageId::ShadowedBinding => "`{0}` shadows an outer binding",
+        MessageId::AnnotationMismatch => "`{0}` is annotated as {1}, got {2}",
+        MessageId::NotSupportedByCompiler => "{0} is not supported by the bytecode compiler",
+        MessageId::OperandTooLarge => "operand of {0} is too large for the bytecode format: {1}",
+        MessageId::LengthTooLarge => "length {0} is too large for the bytecode file format",
+        MessageId::InvalidBytecodeFile => "invalid bytecode file: {0}",
+        MessageId::BytecodeVersionMismatch => {
+            "bytecode file version {0} is not supported, expected {1}"
+        }
+        MessageId::HostFunctionFailed => "`{0}` failed: {1}",
+        MessageId::InvalidHex => "invalid hex string: {0}",
+        MessageId::ByteOutOfRange => "byte out of range: {0}",
+        MessageId::ExitCodeOutOfRange => "exit code out of range: {0}, must be 0 to 255",
+        MessageId::FileReadFailed => "failed to read `{0}`: {1}",
+        MessageId::FileWriteFailed => "failed to write `{0}`: {1}",
+        MessageId::NotComparable => "cannot compare {0} with {1}",
+        MessageId::NestingTooDeep => "expression nested more than {0} levels deep",
+        MessageId::RecursionTooDeep => "maximum recursion depth exceeded: {0} nested calls",
+    }
+}
+
+// 给初学者看的中文模板
+pub struct Chinese;
+
+impl MessageCatalog for Chinese {
+    fn template(&self, id: MessageId) -> Option<&str> {
+        Some(match id {
+            MessageId::ExpectedNextToken => "下一个词法单元应该是 {0}，实际是 {1}",
+            MessageId::NoPrefixParseFunction => "没有 {0} 的前缀解析函数",
+            MessageId::InvalidIntegerLiteral => "无法把 {0} 解析为整数",
+            MessageId::IdentifierNotFound => "找不到标识符：{0}",
+            MessageId::TypeMismatch => "类型不匹配：{0} {1} {2}",
+            MessageId::UnknownPrefixOperator => "未知的运算符：{0}{1}",
+            MessageId::UnknownInfixOperator => "未知的运算符：{0} {1} {2}",
+            MessageId::NotAFunction => "不是函数：{0}",
+            MessageId::IndexNotSupported => "不支持索引运算：{0}",
+            MessageId::UnusableHashKey => "不能用作哈希的键：{0}",
+            MessageId::WrongNumberOfArguments => "参数数量错误：传入 {0} 个，需要 {1} 个",
+            MessageId::ArgumentNotSupported => "`{0}` 不支持这个参数类型：{1}",
+            MessageId::ArgumentMustBe => "`{0}` 的参数必须是 {1}，实际是 {2}",
+            MessageId::ArgumentsMustBe => "`{0}` 的参数必须是 {1}，实际是 {2} 和 {3}",
+            MessageId::ArgumentMustBeNonNegative => "`{0}` 的参数不能是负数，实际是 {1}",
+            MessageId::InvalidRange => "`{0}` 的范围无效：{1} > {2}",
+            MessageId::DisabledInSandbox => "沙盒模式下不能使用 `{0}`",
+            MessageId::BuiltinDisabled => "`{0}` 已被停用",
+            MessageId::UnsupportedOnPlatform => "当前平台不支持 `{0}`",
+            MessageId::Deadlock => "死锁：`{0}` 会永远等待下去",
+            MessageId::BudgetExceeded => "超出求值预算：{0} 步",
+            MessageId::UnquoteOutsideQuote => "`{0}` 只能在 `quote` 里面使用",
+            MessageId::WriteOutputFailed => "写入输出失败：{0}",
+            MessageId::ReadLineFailed => "读取一行输入失败：{0}",
+            MessageId::ReadInputFailed => "读取输入失败：{0}",
+            MessageId::SystemTimeFailed => "读取系统时间失败：{0}",
+            MessageId::AssertionFailed => "断言失败",
+            MessageId::AssertionFailedWithMessage => "断言失败：{0}",
+            MessageId::AssertEqFailed => "断言失败：左边是 {0}，右边是 {1}",
+            MessageId::FormatArgumentCount => "格式字符串有 {0} 个占位符，传入了 {1} 个参数",
+            MessageId::UnmatchedFormatBrace => "格式字符串里的 `{0}` 没有配对",
+            MessageId::EvalParseFailed => "传给 `eval` 的源码解析失败：{0}",
+            MessageId::FrozenObject => "`{0}` 不能修改已冻结的 {1}",
+            MessageId::ExpressionParseFailed => "表达式解析失败：{0}",
+            MessageId::NotAnExpression => "需要一个表达式，实际是 `{0}`",
+            MessageId::ModuleNotFound => "无法导入 `{0}`：{1}",
+            MessageId::ModuleParseFailed => "模块 `{0}` 解析失败：{1}",
+            MessageId::ImportCycle => "检测到循环导入：{0}",
+            MessageId::InternalError => "解释器内部错误：{0}",
+            MessageId::DivisionByZero => "除数为零：{0} / 0",
+            MessageId::IntegerOverflow => "整数溢出：{0}",
+            MessageId::UnquoteNotSupported => "unquote 不支持 {0}",
+            MessageId::SpliceNotArray => {
+                "`unquote_splice` 需要数组或者被 quote 的数组字面量，实际是 {0}"
+            }
+            MessageId::SpliceOutsideList => "`unquote_splice` 只能用作调用的参数或者数组的元素",
+            MessageId::MacroExpansionTooDeep => "宏展开超过了 {0} 层：{1}",
+            MessageId::IllegalCharacter => "非法字符 '{0}'",
+            MessageId::UnexpectedAfterIdentifier => "标识符 `{1}` 后面不应该出现 {0}",
+            MessageId::DidYouMean => "{0}，是不是想写 `{1}`？",
+            MessageId::Timeout => "求值超时：超过 {0} 毫秒",
+            MessageId::OutOfMemory => "内存不足：分配超过了 {0} 字节",
+            MessageId::UnusedBinding => "绑定 `{0}` 没有被用到",
+            MessageId::UnreachableCode => "`return` 之后的代码不会执行",
+            MessageId::ConstantCondition => "条件总是 {0}",
+            MessageId::DuplicateParameter => "参数 `{0}` 重复",
+            MessageId::ShadowedBinding => "`{0}` 遮住了外层的同名绑定",
+            MessageId::AnnotationMismatch => "`{0}` 标注为 {1}，实际是 {2}",
+            MessageId::NotSupportedByCompiler => "字节码编译器不支持 {0}",
+            MessageId::OperandTooLarge => "{0} 的操作数超出了字节码格式的范围：{1}",
+            MessageId::LengthTooLarge => "长度 {0} 超出了字节码文件格式的范围",
+            MessageId::InvalidBytecodeFile => "无效的字节码文件：{0}",
+            MessageId::BytecodeVersionMismatch => "不支持版本为 {0} 的字节码文件，需要版本 {1}",
+            MessageId::HostFunctionFailed => "`{0}` 执行失败：{1}",
+            MessageId::InvalidHex => "无效的十六进制字符串：{0}",
+            MessageId::ByteOutOfRange => "字节超出范围：{0}",
+            MessageId::ExitCodeOutOfRange => "退出码超出范围：{0}，必须在 0 到 255 之间",
+            MessageId::FileReadFailed => "读取 `{0}` 失败：{1}",
+            MessageId::FileWriteFailed => "写入 `{0}` 失败：{1}",
+            MessageId::NotComparable => "无法比较 {0} 和 {1}",
+            MessageId::NestingTooDeep => "表达式嵌套超过了 {0} 层",
+            MessageId::RecursionTooDeep => "超出最大递归深度：{0} 层调用",
+        })
+    }
+}
+
+thread_local! {
+    static CATALOG: RefCell<Box<dyn MessageCatalog>> = RefCell::new(Box::new(English));
+}
+
+// 替换当前线程使用的模板，返回原来的那个
+pub fn set_catalog(catalog: Box<dyn MessageCatalog>) -> Box<dyn MessageCatalog> {
+    CATALOG.with(|current| std::mem::replace(&mut *current.borrow_mut(), catalog))
+}
+
+pub fn message(id: MessageId, args: &[&dyn Display]) -> String {
+    CATALOG.with(|catalog| {
+        let catalog = catalog.borrow();
+        let template = catalog.template(id).unwrap_or_else(|| english(id));
+        render(template, args)
+    })
+}
+
+pub fn runtime_error(id: MessageId, args: &[&dyn Display]) -> Error {
+    Error {
+        message: message(id, args),
+        span: Span::default(),
+        line: 0,
+        column: 0,
+    }
+}
+
+// 一条语法错误，message 是按当前模板渲染好的文字，其余字段给工具定位用
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    // 期望出现的词法单元，只有 expected next token 这类错误才有
+    pub expected: Option<TokenType>,
+    // 实际遇到的词法单元
+    pub got: Option<TokenType>,
+    // 出错的词法单元在源码里的起始偏移和行列号，行号为 0 表示位置未知
+    pub position: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl ParseError {
+    // 指向 token 的错误
+    pub fn at(message: String, token: &Token) -> Self {
+        Self {
+            message,
+            expected: None,
+            got: None,
+            position: token.span.start,
+            line: token.line,
+            column: token.column,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(
+                f,
+                "{} at line {}, col {}",
+                self.message, self.line, self.column
+            )
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+// 静态检查（lint、resolver、typecheck）给出的一条结果，kind 是各个检查自己的分类
+// message 是按当前模板渲染好的文字，其余字段给工具定位用
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic<K = ()> {
+    pub kind: K,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    // 从 1 开始的行列号，行号为 0 表示位置未知
+    pub line: usize,
+    pub column: usize,
+}
+
+impl<K> Diagnostic<K> {
+    // 指向 node 的结果，位置取 node 自己的词法单元
+    pub fn at(kind: K, severity: Severity, message: String, node: &dyn Node) -> Self {
+        let (line, column) = node
+            .token()
+            .map_or((0, 0), |token| (token.line, token.column));
+        Self {
+            kind,
+            severity,
+            message,
+            span: node.span(),
+            line,
+            column,
+        }
+    }
+}
+
+impl<K> Display for Diagnostic<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.severity, self.message)
+        } else {
+            write!(
+                f,
+                "{}: {} at line {}, col {}",
+                self.severity, self.message, self.line, self.column
+            )
+        }
+    }
+}
+
+// 多条语法错误合成一行，放进运行时错误的消息里
+pub fn join_parse_errors(errors: &[ParseError]) -> String {
+    errors
+        .iter()
+        .map(|error| error.message.as_str())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+// 求值中断的原因，求值函数通过 Err 一路往外传，不用在每一步检查返回的对象
+#[derive(Debug, Clone)]
+pub enum EvalError {
+    // 脚本本身的运行时错误
+    Runtime(Error),
+    // exit() 要求结束整个程序
+    Exit(i64),
+    // 解释器内部的状态不一致，说明解释器自己有 bug
+    Internal(String),
+}
+
+pub type EvalResult = Result<Rc<Value>, EvalError>;
+
+impl EvalError {
+    pub fn internal(detail: &str) -> Self {
+        EvalError::Internal(detail.to_owned())
+    }
+
+    // 交给调用方或者内置函数时还原成对象
+    pub fn into_object(self) -> Rc<Value> {
+        match self {
+            EvalError::Runtime(error) => Rc::new(Value::Error(error)),
+            EvalError::Exit(code) => Rc::new(Value::Exit(Exit { code })),
+            EvalError::Internal(detail) => Rc::new(Value::Error(runtime_error(
+                MessageId::InternalError,
+                &[&detail],
+            ))),
+        }
+    }
+
+    // 内置函数用返回的对象表示错误，调用之后再转换回来
+    pub fn from_object(object: Rc<Value>) -> EvalResult {
+        match object.as_ref() {
+            Value::Error(error) => Err(EvalError::Runtime(error.clone())),
+            Value::Exit(exit) => Err(EvalError::Exit(exit.code)),
+            _ => Ok(object),
+        }
+    }
+}
+
+impl From<Error> for EvalError {
+    fn from(error: Error) -> Self {
+        EvalError::Runtime(error)
+    }
+}
+
+// 只替换 {数字} 形式的占位符，参数本身包含的花括号原样保留
+fn render(template: &str, args: &[&dyn Display]) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let placeholder = rest[start + 1..].find('}').and_then(|end| {
+            let index = rest[start + 1..start + 1 + end].parse::<usize>().ok()?;
+            Some((index, start + 1 + end + 1))
+        });
+        match placeholder {
+            Some((index, next)) if index < args.len() => {
+                out.push_str(&args[index].to_string());
+                rest = &rest[next..];
+            }
+            _ => {
+                out.push('{');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,1719 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::thread;
+
+use web_time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::context::{EvalContext, Rng};
+use super::convert::HostFunction;
+use super::environment::preview;
+use super::eval::{build_hash, eval_source, hash_key_of, is_abrupt, is_truthy};
+use super::macro_expansion::{expand_expression, macro_environment};
+use super::module::import;
+use super::object::{
+    Array, Builtin, Bytes, Channel, Exit, Hash, HashKey, HashPair, Integer, IteratorObject,
+    IteratorSource, Null, Quote, StringObject, Value,
+};
+use super::ordering::{compare, try_sort};
+use super::scheduler;
+use crate::ast::expressions::{Expr, Identifier};
+use crate::errors::{runtime_error, EvalError, MessageId};
+use crate::sync::{Rc, RefCell};
+use crate::token::{Token, TokenType};
+
+#[cfg(not(feature = "sync"))]
+pub type BuiltinFunction = dyn Fn(&EvalContext, &[Rc<Value>]) -> Rc<Value>;
+#[cfg(feature = "sync")]
+pub type BuiltinFunction = dyn Fn(&EvalContext, &[Rc<Value>]) -> Rc<Value> + Send + Sync;
+
+type NativeFunction = fn(&EvalContext, &[Rc<Value>]) -> Rc<Value>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exact(usize),
+    Range(usize, usize),
+    Variadic,
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::Exact(count) => write!(f, "{}", count),
+            Arity::Range(min, max) => write!(f, "{}-{}", min, max),
+            Arity::Variadic => write!(f, "any"),
+        }
+    }
+}
+
+// 帮助信息和补全使用的元数据
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuiltinInfo {
+    pub name: String,
+    pub arity: Arity,
+    pub description: String,
+}
+
+struct BuiltinSpec {
+    name: &'static str,
+    arity: Arity,
+    description: &'static str,
+    func: NativeFunction,
+}
+
+static DEFAULT_BUILTINS: [BuiltinSpec; 45] = [
+    BuiltinSpec {
+        name: "len",
+        arity: Arity::Exact(1),
+        description: "Returns the length of a string or array",
+        func: object_len,
+    },
+    BuiltinSpec {
+        name: "first",
+        arity: Arity::Exact(1),
+        description: "Returns the first element of an array",
+        func: array_first,
+    },
+    BuiltinSpec {
+        name: "last",
+        arity: Arity::Exact(1),
+        description: "Returns the last element of an array",
+        func: array_last,
+    },
+    BuiltinSpec {
+        name: "rest",
+        arity: Arity::Exact(1),
+        description: "Returns a new array without the first element",
+        func: array_rest,
+    },
+    BuiltinSpec {
+        name: "push",
+        arity: Arity::Exact(2),
+        description: "Returns a new array with the value appended",
+        func: array_push,
+    },
+    BuiltinSpec {
+        name: "puts",
+        arity: Arity::Variadic,
+        description: "Prints each argument on its own line",
+        func: puts,
+    },
+    BuiltinSpec {
+        name: "read_line",
+        arity: Arity::Exact(0),
+        description: "Reads one line of input, null at the end of input",
+        func: read_line,
+    },
+    BuiltinSpec {
+        name: "read_all",
+        arity: Arity::Exact(0),
+        description: "Reads the rest of the input as a string",
+        func: read_all,
+    },
+    BuiltinSpec {
+        name: "time",
+        arity: Arity::Exact(0),
+        description: "Returns the current Unix time in seconds",
+        func: time,
+    },
+    BuiltinSpec {
+        name: "clock_ms",
+        arity: Arity::Exact(0),
+        description: "Returns monotonic milliseconds since evaluation started",
+        func: clock_ms,
+    },
+    BuiltinSpec {
+        name: "sleep",
+        arity: Arity::Exact(1),
+        description: "Pauses for the given number of milliseconds",
+        func: sleep,
+    },
+    BuiltinSpec {
+        name: "random",
+        arity: Arity::Exact(0),
+        description: "Returns a random non-negative integer",
+        func: random,
+    },
+    BuiltinSpec {
+        name: "random_int",
+        arity: Arity::Exact(2),
+        description: "Returns a random integer between lo and hi inclusive",
+        func: random_int,
+    },
+    BuiltinSpec {
+        name: "seed_random",
+        arity: Arity::Exact(1),
+        description: "Seeds the random number generator",
+        func: seed_random,
+    },
+    BuiltinSpec {
+        name: "assert",
+        arity: Arity::Range(1, 2),
+        description: "Returns an error with the message when the condition is falsy",
+        func: assert,
+    },
+    BuiltinSpec {
+        name: "assert_eq",
+        arity: Arity::Range(2, 3),
+        description: "Returns an error when the two values differ",
+        func: assert_eq,
+    },
+    BuiltinSpec {
+        name: "exit",
+        arity: Arity::Range(0, 1),
+        description: "Stops evaluation with the given exit code",
+        func: exit,
+    },
+    BuiltinSpec {
+        name: "format",
+        arity: Arity::Variadic,
+        description: "Replaces each {} in the template with the next argument",
+        func: format,
+    },
+    BuiltinSpec {
+        name: "deep_copy",
+        arity: Arity::Exact(1),
+        description: "Returns an unfrozen copy of the value and everything inside it",
+        func: deep_copy,
+    },
+    BuiltinSpec {
+        name: "freeze",
+        arity: Arity::Exact(1),
+        description: "Returns a copy of the value that can no longer be modified",
+        func: freeze,
+    },
+    BuiltinSpec {
+        name: "map",
+        arity: Arity::Exact(2),
+        description: "Returns a new array with the function applied to each element",
+        func: array_map,
+    },
+    BuiltinSpec {
+        name: "eval",
+        arity: Arity::Range(1, 2),
+        description: "Evaluates source code in the calling environment, or an isolated child",
+        func: evaluate,
+    },
+    BuiltinSpec {
+        name: "env",
+        arity: Arity::Exact(0),
+        description: "Lists the bindings visible at the call site with their types",
+        func: environment,
+    },
+    BuiltinSpec {
+        name: "args",
+        arity: Arity::Exact(0),
+        description: "Returns the command-line arguments given after the script",
+        func: script_args,
+    },
+    BuiltinSpec {
+        name: "getenv",
+        arity: Arity::Exact(1),
+        description: "Returns the value of an environment variable, null when it is not set",
+        func: getenv,
+    },
+    BuiltinSpec {
+        name: "import",
+        arity: Arity::Exact(1),
+        description: "Evaluates a source file once and returns its top-level bindings as a hash",
+        func: import_module,
+    },
+    BuiltinSpec {
+        name: "spawn",
+        arity: Arity::Variadic,
+        description: "Schedules a function to run with the given arguments and returns its task",
+        func: spawn_task,
+    },
+    BuiltinSpec {
+        name: "wait",
+        arity: Arity::Exact(1),
+        description: "Runs a task if it has not run yet and returns its result",
+        func: wait_task,
+    },
+    BuiltinSpec {
+        name: "chan",
+        arity: Arity::Exact(0),
+        description: "Creates an unbounded channel",
+        func: channel,
+    },
+    BuiltinSpec {
+        name: "send",
+        arity: Arity::Exact(2),
+        description: "Puts a value at the end of a channel",
+        func: channel_send,
+    },
+    BuiltinSpec {
+        name: "recv",
+        arity: Arity::Exact(1),
+        description: "Takes the first value of a channel, running waiting tasks until there is one",
+        func: channel_recv,
+    },
+    BuiltinSpec {
+        name: "iter",
+        arity: Arity::Exact(1),
+        description: "Returns an iterator over the elements of an array",
+        func: iter,
+    },
+    BuiltinSpec {
+        name: "next",
+        arity: Arity::Exact(1),
+        description: "Returns the next value of an iterator, null when it is exhausted",
+        func: iterator_next,
+    },
+    BuiltinSpec {
+        name: "generate",
+        arity: Arity::Exact(1),
+        description:
+            "Returns an iterator calling the function with 0, 1, 2... until it returns null",
+        func: generate,
+    },
+    BuiltinSpec {
+        name: "lazy_map",
+        arity: Arity::Exact(2),
+        description:
+            "Returns an iterator applying the function to each value only when it is taken",
+        func: lazy_map,
+    },
+    BuiltinSpec {
+        name: "take",
+        arity: Arity::Exact(2),
+        description:
+            "Returns the first n elements of an array, or an iterator over the first n values",
+        func: take,
+    },
+    BuiltinSpec {
+        name: "collect",
+        arity: Arity::Exact(1),
+        description: "Runs an iterator to the end and returns its values as an array",
+        func: collect,
+    },
+    BuiltinSpec {
+        name: "memoize",
+        arity: Arity::Exact(1),
+        description: "Returns a function that caches the results of the given one by its arguments",
+        func: memoize,
+    },
+    BuiltinSpec {
+        name: "bytes",
+        arity: Arity::Exact(1),
+        description: "Returns the UTF-8 bytes of a string, or bytes made from an array of integers",
+        func: to_bytes,
+    },
+    BuiltinSpec {
+        name: "bytes_from_hex",
+        arity: Arity::Exact(1),
+        description: "Parses a string of hex digits into bytes",
+        func: bytes_from_hex,
+    },
+    BuiltinSpec {
+        name: "read_bytes",
+        arity: Arity::Exact(1),
+        description: "Reads a whole file as bytes",
+        func: read_bytes,
+    },
+    BuiltinSpec {
+        name: "write_bytes",
+        arity: Arity::Exact(2),
+        description: "Writes bytes or a string to a file, replacing its contents",
+        func: write_bytes,
+    },
+    BuiltinSpec {
+        name: "sort",
+        arity: Arity::Range(1, 2),
+        description: "Returns a sorted copy of an array, optionally ordered by a key function",
+        func: sort,
+    },
+    BuiltinSpec {
+        name: "gensym",
+        arity: Arity::Range(0, 1),
+        description: "Returns a quoted identifier that cannot clash with any name in the source",
+        func: gensym,
+    },
+    BuiltinSpec {
+        name: "macroexpand",
+        arity: Arity::Exact(1),
+        description: "Expands the macro calls in quoted code without evaluating it",
+        func: macroexpand,
+    },
+];
+
+// 沙盒里默认停用的内置函数：eval 绕过宿主对源码的检查，read_all 和 read_line 读取宿主的标准输入，
+// read_bytes 和 write_bytes 读写宿主的文件
+// getenv、sleep 和读写文件的函数自己也会检查 Limits::sandbox，import 在沙盒里只能导入标准库
+pub const UNSAFE_BUILTINS: [&str; 7] = [
+    "eval",
+    "getenv",
+    "read_all",
+    "read_bytes",
+    "read_line",
+    "sleep",
+    "write_bytes",
+];
+
+#[derive(Clone)]
+struct Entry {
+    builtin: Builtin,
+    info: BuiltinInfo,
+}
+
+// 一次求值过程中可以使用的内置函数，宿主程序可以在运行时注册新的函数或替换已有的实现
+// 用 BTreeMap 保存，列出来的时候总是按名字排序
+#[derive(Clone)]
+pub struct BuiltinRegistry {
+    entries: BTreeMap<String, Entry>,
+}
+
+impl BuiltinRegistry {
+    pub fn new() -> Self {
+        let mut registry = BuiltinRegistry {
+            entries: BTreeMap::new(),
+        };
+        registry.reset();
+        registry
+    }
+
+    // 没有提供元数据的函数按可变参数处理，返回被替换掉的旧实现
+    pub fn register(&mut self, name: &str, func: Rc<BuiltinFunction>) -> Option<Builtin> {
+        self.register_with_info(name, Arity::Variadic, "", func)
+    }
+
+    pub fn register_with_info(
+        &mut self,
+        name: &str,
+        arity: Arity,
+        description: &str,
+        func: Rc<BuiltinFunction>,
+    ) -> Option<Builtin> {
+        let entry = Entry {
+            builtin: Builtin { func },
+            info: BuiltinInfo {
+                name: name.to_owned(),
+                arity,
+                description: description.to_owned(),
+            },
+        };
+        self.entries
+            .insert(name.to_owned(), entry)
+            .map(|entry| entry.builtin)
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Option<Builtin> {
+        self.entries.remove(name).map(|entry| entry.builtin)
+    }
+
+    // 停用的函数仍然占着这个名字，调用时返回错误，字节码和补全不受影响
+    // 打开了 Limits::sandbox 时错误里说明是沙盒模式停用的
+    // 没有这个函数时返回 false
+    pub fn disable(&mut self, name: &str) -> bool {
+        let Some(entry) = self.entries.get_mut(name) else {
+            return false;
+        };
+        let name = name.to_owned();
+        entry.builtin = Builtin {
+            func: Rc::new(move |context, _| {
+                let id = match context.runtime().limits.sandbox {
+                    true => MessageId::DisabledInSandbox,
+                    false => MessageId::BuiltinDisabled,
+                };
+                Rc::new(Value::Error(runtime_error(id, &[&name])))
+            }),
+        };
+        true
+    }
+
+    // 停用名单以外的所有函数，之后注册的函数不受影响
+    pub fn allow_only(&mut self, names: &[&str]) {
+        let disabled = self
+            .entries
+            .keys()
+            .filter(|name| !names.contains(&name.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in disabled {
+            self.disable(&name);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Builtin> {
+        self.entries.get(name).map(|entry| entry.builtin.clone())
+    }
+
+    pub fn info(&self, name: &str) -> Option<&BuiltinInfo> {
+        self.entries.get(name).map(|entry| &entry.info)
+    }
+
+    // 按名字排序
+    // 参数类型写在闭包上，比如 |a: i64, b: i64| a + b，个数和类型的检查自动生成
+    pub fn register_fn<Args>(
+        &mut self,
+        name: &str,
+        description: &str,
+        func: impl HostFunction<Args>,
+    ) -> Option<Builtin> {
+        let arity = Arity::Exact(func.arity());
+        let owned_name = name.to_owned();
+        self.register_with_info(
+            name,
+            arity,
+            description,
+            Rc::new(move |_, args| func.invoke(&owned_name, args)),
+        )
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = &BuiltinInfo> {
+        self.entries.values().map(|entry| &entry.info)
+    }
+
+    // 丢掉所有注册的函数，恢复成默认的内置函数
+    pub fn reset(&mut self) {
+        self.entries.clear();
+        for spec in DEFAULT_BUILTINS.iter() {
+            self.register_with_info(spec.name, spec.arity, spec.description, Rc::new(spec.func));
+        }
+    }
+}
+
+impl Default for BuiltinRegistry {
+    fn default() -> Self {
+        BuiltinRegistry::new()
+    }
+}
+
+fn object_len(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if objects.len() != 1 {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    }
+
+    let first = objects.first().unwrap().as_ref();
+
+    match first {
+        Value::String(string) => Rc::new(Value::Integer(Integer {
+            value: string.value.len() as i64,
+        })),
+        Value::Array(array) => Rc::new(Value::Integer(Integer {
+            value: array.elements.len() as i64,
+        })),
+        Value::Bytes(bytes) => Rc::new(Value::Integer(Integer {
+            value: bytes.value.len() as i64,
+        })),
+        _ => Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentNotSupported,
+            &[&"len", &first.object_type()],
+        ))),
+    }
+}
+
+fn array_first(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if objects.len() != 1 {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    }
+
+    let first = objects.first().unwrap().as_ref();
+
+    match first {
+        Value::Array(array) => array
+            .elements
+            .first()
+            .map_or(Rc::new(Value::Null(Null)), Rc::clone),
+        _ => Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"first", &"Array", &first.object_type()],
+        ))),
+    }
+}
+
+fn array_last(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if objects.len() != 1 {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    }
+
+    let first = objects.first().unwrap().as_ref();
+
+    match first {
+        Value::Array(array) => array
+            .elements
+            .last()
+            .map_or(Rc::new(Value::Null(Null)), Rc::clone),
+        _ => Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"last", &"Array", &first.object_type()],
+        ))),
+    }
+}
+
+fn array_rest(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if objects.len() != 1 {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    }
+
+    let first = objects.first().unwrap().as_ref();
+
+    match first {
+        Value::Array(array) => Rc::new(Value::Array(Array {
+            elements: array.elements.iter().skip(1).cloned().collect::<Vec<_>>(),
+            frozen: array.frozen,
+        })),
+        _ => Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"rest", &"Array", &first.object_type()],
+        ))),
+    }
+}
+
+fn array_push(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if objects.len() != 2 {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &2],
+        )));
+    }
+
+    let first = objects.first().unwrap().as_ref();
+    let object = Rc::clone(objects.get(1).unwrap());
+
+    match first {
+        Value::Array(array) => {
+            if array.frozen {
+                return Rc::new(Value::Error(runtime_error(
+                    MessageId::FrozenObject,
+                    &[&"push", &first.object_type()],
+                )));
+            }
+            // 只复制元素的引用，元素本身和原来的数组共享
+            let mut elements = array.elements.clone();
+            elements.push(object);
+            Rc::new(Value::Array(Array {
+                elements,
+                frozen: false,
+            }))
+        }
+        _ => Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"push", &"Array", &first.object_type()],
+        ))),
+    }
+}
+
+// 依次用每个元素调用 f，遇到错误时立即返回
+fn array_map(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [array, func] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &2],
+        )));
+    };
+    let Value::Array(array) = array.as_ref() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"map", &"Array", &array.object_type()],
+        )));
+    };
+
+    let mut elements = vec![];
+    for element in array.elements.iter() {
+        let result = context.call(func.as_ref(), &[Rc::clone(element)]);
+        if is_abrupt(result.as_ref()) {
+            return result;
+        }
+        elements.push(result);
+    }
+    Rc::new(Value::Array(Array {
+        elements,
+        frozen: false,
+    }))
+}
+
+fn puts(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    for object in objects {
+        if let Err(error) = writeln!(context.runtime().output, "{}", object.inspect()) {
+            return Rc::new(Value::Error(runtime_error(
+                MessageId::WriteOutputFailed,
+                &[&error],
+            )));
+        }
+    }
+    Rc::new(Value::Null(Null))
+}
+
+fn read_line(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if !objects.is_empty() {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &0],
+        )));
+    }
+
+    let mut line = String::new();
+    let result = context.runtime().read_line(&mut line);
+
+    match result {
+        // 读到 EOF 时返回 null，方便脚本用 if 判断输入是否结束
+        Ok(0) => Rc::new(Value::Null(Null)),
+        Ok(_) => {
+            if line.ends_with('\n') {
+                line.pop();
+                if line.ends_with('\r') {
+                    line.pop();
+                }
+            }
+            Rc::new(Value::String(StringObject { value: line }))
+        }
+        Err(error) => Rc::new(Value::Error(runtime_error(
+            MessageId::ReadLineFailed,
+            &[&error],
+        ))),
+    }
+}
+
+fn read_all(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if !objects.is_empty() {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &0],
+        )));
+    }
+
+    let mut content = String::new();
+    let result = context.runtime().read_to_string(&mut content);
+
+    match result {
+        Ok(_) => Rc::new(Value::String(StringObject { value: content })),
+        Err(error) => Rc::new(Value::Error(runtime_error(
+            MessageId::ReadInputFailed,
+            &[&error],
+        ))),
+    }
+}
+
+fn script_args(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if !objects.is_empty() {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &0],
+        )));
+    }
+
+    let elements = context
+        .runtime()
+        .args
+        .iter()
+        .map(|arg| Rc::new(Value::String(StringObject { value: arg.clone() })))
+        .collect();
+    Rc::new(Value::Array(Array {
+        elements,
+        frozen: false,
+    }))
+}
+
+// 没有设置或者值不是合法 UTF-8 的变量都返回 null
+fn getenv(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [name] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    };
+
+    if context.runtime().limits.sandbox {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::DisabledInSandbox,
+            &[&"getenv"],
+        )));
+    }
+
+    let Value::String(name) = name.as_ref() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"getenv", &"String", &name.object_type()],
+        )));
+    };
+    match std::env::var(&name.value) {
+        Ok(value) => Rc::new(Value::String(StringObject { value })),
+        Err(_) => Rc::new(Value::Null(Null)),
+    }
+}
+
+// 当前的 Unix 时间戳，单位是秒
+fn time(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if !objects.is_empty() {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &0],
+        )));
+    }
+
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => Rc::new(Value::Integer(Integer {
+            value: duration.as_secs() as i64,
+        })),
+        Err(error) => Rc::new(Value::Error(runtime_error(
+            MessageId::SystemTimeFailed,
+            &[&error],
+        ))),
+    }
+}
+
+// 单调时钟，只适合用来计算耗时
+fn clock_ms(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if !objects.is_empty() {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &0],
+        )));
+    }
+
+    Rc::new(Value::Integer(Integer {
+        value: context.runtime().elapsed().as_millis() as i64,
+    }))
+}
+
+fn sleep(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if objects.len() != 1 {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    }
+
+    if context.runtime().limits.sandbox {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::DisabledInSandbox,
+            &[&"sleep"],
+        )));
+    }
+
+    // 浏览器里没有线程可以挂起，std 的 sleep 会直接 panic
+    if cfg!(all(target_family = "wasm", target_os = "unknown")) {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::UnsupportedOnPlatform,
+            &[&"sleep"],
+        )));
+    }
+
+    let first = objects.first().unwrap().as_ref();
+    match first {
+        Value::Integer(integer) if integer.value >= 0 => {
+            let requested = Duration::from_millis(integer.value as u64);
+            // 不能睡过超时的期限，睡到期限为止然后报超时
+            let (remaining, timeout) = {
+                let runtime = context.runtime();
+                (runtime.remaining_time(), runtime.limits.timeout)
+            };
+            match (remaining, timeout) {
+                (Some(remaining), Some(timeout)) if remaining < requested => {
+                    thread::sleep(remaining);
+                    Rc::new(Value::Error(runtime_error(
+                        MessageId::Timeout,
+                        &[&timeout.as_millis()],
+                    )))
+                }
+                _ => {
+                    thread::sleep(requested);
+                    Rc::new(Value::Null(Null))
+                }
+            }
+        }
+        Value::Integer(integer) => Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBeNonNegative,
+            &[&"sleep", &integer.value],
+        ))),
+        _ => Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"sleep", &"Integer", &first.object_type()],
+        ))),
+    }
+}
+
+// 没有浮点数，返回一个非负的随机整数
+fn random(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if !objects.is_empty() {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &0],
+        )));
+    }
+
+    Rc::new(Value::Integer(Integer {
+        value: (context.runtime().rng.next_u64() >> 1) as i64,
+    }))
+}
+
+// 返回 [lo, hi] 之间的随机整数，两端都包含
+fn random_int(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if objects.len() != 2 {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &2],
+        )));
+    }
+
+    let (low, high) = match (objects[0].as_ref(), objects[1].as_ref()) {
+        (Value::Integer(low), Value::Integer(high)) => (low.value, high.value),
+        _ => {
+            return Rc::new(Value::Error(runtime_error(
+                MessageId::ArgumentsMustBe,
+                &[
+                    &"random_int",
+                    &"Integer",
+                    &objects[0].object_type(),
+                    &objects[1].object_type(),
+                ],
+            )))
+        }
+    };
+    if low > high {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::InvalidRange,
+            &[&"random_int", &low, &high],
+        )));
+    }
+
+    // 用 u64 计算区间宽度，避免 hi - lo 溢出
+    let span = high.wrapping_sub(low) as u64;
+    let offset = match span.checked_add(1) {
+        Some(bound) => context.runtime().rng.next_below(bound),
+        None => context.runtime().rng.next_u64(),
+    };
+    Rc::new(Value::Integer(Integer {
+        value: low.wrapping_add(offset as i64),
+    }))
+}
+
+fn seed_random(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if objects.len() != 1 {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    }
+
+    let first = objects.first().unwrap().as_ref();
+    match first {
+        Value::Integer(seed) => {
+            context.runtime().rng = Rng::new(seed.value as u64);
+            Rc::new(Value::Null(Null))
+        }
+        _ => Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"seed_random", &"Integer", &first.object_type()],
+        ))),
+    }
+}
+
+// 条件不成立时返回带消息的错误，脚本可以用它写自己的测试
+fn assert(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    match objects {
+        [condition] if is_truthy(condition.as_ref()) => Rc::new(Value::Null(Null)),
+        [_] => Rc::new(Value::Error(runtime_error(MessageId::AssertionFailed, &[]))),
+        [condition, _] if is_truthy(condition.as_ref()) => Rc::new(Value::Null(Null)),
+        [_, message] => Rc::new(Value::Error(runtime_error(
+            MessageId::AssertionFailedWithMessage,
+            &[&display(message.as_ref())],
+        ))),
+        _ => Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &"1 or 2"],
+        ))),
+    }
+}
+
+fn assert_eq(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let (left, right, message) = match objects {
+        [left, right] => (left.as_ref(), right.as_ref(), None),
+        [left, right, message] => (left.as_ref(), right.as_ref(), Some(message.as_ref())),
+        _ => {
+            return Rc::new(Value::Error(runtime_error(
+                MessageId::WrongNumberOfArguments,
+                &[&objects.len(), &"2 or 3"],
+            )))
+        }
+    };
+
+    if left == right {
+        return Rc::new(Value::Null(Null));
+    }
+    match message {
+        Some(message) => Rc::new(Value::Error(runtime_error(
+            MessageId::AssertionFailedWithMessage,
+            &[&display(message)],
+        ))),
+        None => Rc::new(Value::Error(runtime_error(
+            MessageId::AssertEqFailed,
+            &[&left.inspect(), &right.inspect()],
+        ))),
+    }
+}
+
+// 停止整个程序的求值，不带参数时退出码是 0
+// 进程的退出码只有 8 位，超出 0 到 255 的值会被截断成别的退出码，比如 256 变成表示成功的 0，所以直接报错
+fn exit(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    match objects {
+        [] => Rc::new(Value::Exit(Exit { code: 0 })),
+        [code] => match code.as_ref() {
+            Value::Integer(integer) if !(0..=255).contains(&integer.value) => {
+                Rc::new(Value::Error(runtime_error(
+                    MessageId::ExitCodeOutOfRange,
+                    &[&integer.value],
+                )))
+            }
+            Value::Integer(integer) => Rc::new(Value::Exit(Exit {
+                code: integer.value,
+            })),
+            _ => Rc::new(Value::Error(runtime_error(
+                MessageId::ArgumentMustBe,
+                &[&"exit", &"Integer", &code.object_type()],
+            ))),
+        },
+        _ => Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &"0 or 1"],
+        ))),
+    }
+}
+
+// `{}` 依次替换成参数的 inspect()，`{{` 和 `}}` 输出花括号本身
+fn format(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let Some((template, arguments)) = objects.split_first() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&0, &"at least 1"],
+        )));
+    };
+    let Value::String(template) = template.as_ref() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"format", &"String", &template.object_type()],
+        )));
+    };
+
+    let mut result = String::new();
+    let mut used = 0;
+    let mut characters = template.value.chars().peekable();
+    while let Some(character) = characters.next() {
+        match (character, characters.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                characters.next();
+                result.push(character);
+            }
+            ('{', Some('}')) => {
+                characters.next();
+                if let Some(argument) = arguments.get(used) {
+                    result.push_str(&argument.inspect());
+                }
+                used += 1;
+            }
+            ('{', _) | ('}', _) => {
+                return Rc::new(Value::Error(runtime_error(
+                    MessageId::UnmatchedFormatBrace,
+                    &[&character],
+                )));
+            }
+            _ => result.push(character),
+        }
+    }
+
+    if used != arguments.len() {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::FormatArgumentCount,
+            &[&used, &arguments.len()],
+        )));
+    }
+    Rc::new(Value::String(StringObject { value: result }))
+}
+
+fn evaluate(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    eval_source(objects, context.env())
+}
+
+// 每个绑定是一个 {"name", "type", "value"} 哈希，value 是截断过的 inspect，按名字排序
+fn environment(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if !objects.is_empty() {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &0],
+        )));
+    }
+    let string = |value: String| Rc::new(Value::String(StringObject { value }));
+    let elements = context
+        .env()
+        .borrow()
+        .visible()
+        .into_iter()
+        .map(|(name, value)| {
+            build_hash(vec![
+                (string("name".to_owned()), string(name.to_string())),
+                (
+                    string("type".to_owned()),
+                    string(value.object_type().to_string()),
+                ),
+                (string("value".to_owned()), string(preview(&value))),
+            ])
+            .unwrap_or_else(EvalError::into_object)
+        })
+        .collect();
+    Rc::new(Value::Array(Array {
+        elements,
+        frozen: false,
+    }))
+}
+
+fn import_module(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [name] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    };
+    match name.as_ref() {
+        Value::String(name) => import(context, &name.value),
+        _ => Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"import", &"String", &name.object_type()],
+        ))),
+    }
+}
+
+// 数组和哈希本来就按值复制，这里只是显式地复制一份，并去掉冻结标记
+// 函数捕获的环境仍然和原来的函数共享
+fn deep_copy(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    match objects {
+        [object] => set_frozen(Rc::clone(object), false),
+        _ => Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        ))),
+    }
+}
+
+// 返回冻结后的副本，原来的绑定不受影响，需要写成 `let a = freeze(a);`
+// 嵌套的数组和哈希也一起冻结
+fn freeze(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    match objects {
+        [object] => set_frozen(Rc::clone(object), true),
+        _ => Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        ))),
+    }
+}
+
+// 对象是不可变的，只有数组和哈希需要重新创建，其他对象直接共享
+pub(crate) fn set_frozen(object: Rc<Value>, frozen: bool) -> Rc<Value> {
+    match object.as_ref() {
+        Value::Array(array) => Rc::new(Value::Array(Array {
+            elements: array
+                .elements
+                .iter()
+                .map(|element| set_frozen(Rc::clone(element), frozen))
+                .collect(),
+            frozen,
+        })),
+        Value::Hash(hash) => {
+            let pairs = hash
+                .pairs
+                .iter()
+                .map(|(hash_key, pair)| {
+                    let pair = HashPair {
+                        key: Rc::clone(&pair.key),
+                        value: set_frozen(Rc::clone(&pair.value), frozen),
+                    };
+                    (hash_key.clone(), pair)
+                })
+                .collect();
+            Rc::new(Value::Hash(Hash { pairs, frozen }))
+        }
+        _ => object,
+    }
+}
+
+// 字符串直接显示内容，其他对象用 inspect
+fn display(object: &Value) -> String {
+    match object {
+        Value::String(string) => string.value.clone(),
+        _ => object.inspect(),
+    }
+}
+
+fn spawn_task(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let Some((function, args)) = objects.split_first() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&0, &1],
+        )));
+    };
+    if !matches!(
+        function.as_ref(),
+        Value::Function(_) | Value::Builtin(_) | Value::Closure(_)
+    ) {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"spawn", &"Function", &function.object_type()],
+        )));
+    }
+    let task = scheduler::spawn(Rc::clone(function), args.to_vec(), &context.env());
+    Rc::new(Value::Task(task))
+}
+
+fn wait_task(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [task] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    };
+    let Value::Task(task) = task.as_ref() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"wait", &"Task", &task.object_type()],
+        )));
+    };
+    scheduler::run_task(task, &context.env()).unwrap_or_else(EvalError::into_object)
+}
+
+fn channel(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    if !objects.is_empty() {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &0],
+        )));
+    }
+    Rc::new(Value::Channel(Channel::default()))
+}
+
+fn channel_send(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [channel, value] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &2],
+        )));
+    };
+    let Value::Channel(channel) = channel.as_ref() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"send", &"Channel", &channel.object_type()],
+        )));
+    };
+    channel.queue.borrow_mut().push_back(Rc::clone(value));
+    Rc::new(Value::Null(Null))
+}
+
+fn channel_recv(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [channel] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    };
+    let Value::Channel(channel) = channel.as_ref() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"recv", &"Channel", &channel.object_type()],
+        )));
+    };
+    scheduler::receive(channel, &context.env()).unwrap_or_else(EvalError::into_object)
+}
+
+// 数组先转成迭代器，其他类型报错
+fn to_iterator(name: &str, object: &Value) -> Result<IteratorObject, Rc<Value>> {
+    match object {
+        Value::Iterator(iterator) => Ok(iterator.clone()),
+        Value::Array(array) => Ok(IteratorObject::new(IteratorSource::Array(
+            array.elements.clone(),
+            0,
+        ))),
+        other => Err(Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&name, &"Iterator", &other.object_type()],
+        )))),
+    }
+}
+
+enum Step {
+    Call(Rc<Value>, i64),
+    Map(IteratorObject, Rc<Value>),
+    Next(IteratorObject),
+}
+
+// 计算下一个值，序列结束时返回 None，回调出错时返回错误对象
+// 回调运行时不借用迭代器，回调里可以再对同一个迭代器调用 next
+fn advance(
+    context: &EvalContext,
+    iterator: &IteratorObject,
+) -> Result<Option<Rc<Value>>, Rc<Value>> {
+    let step = {
+        let mut source = iterator.source.borrow_mut();
+        match &mut *source {
+            IteratorSource::Empty => return Ok(None),
+            IteratorSource::Array(elements, index) => {
+                let element = elements.get(*index).cloned();
+                *index += 1;
+                if element.is_none() {
+                    *source = IteratorSource::Empty;
+                }
+                return Ok(element);
+            }
+            IteratorSource::Generate(function, index) => {
+                *index += 1;
+                Step::Call(Rc::clone(function), *index - 1)
+            }
+            IteratorSource::Map(inner, function) => Step::Map(inner.clone(), Rc::clone(function)),
+            IteratorSource::Take(_, 0) => {
+                *source = IteratorSource::Empty;
+                return Ok(None);
+            }
+            IteratorSource::Take(inner, remaining) => {
+                *remaining -= 1;
+                Step::Next(inner.clone())
+            }
+        }
+    };
+    let value = match step {
+        Step::Call(function, index) => {
+            let value = context.call(
+                &function,
+                &[Rc::new(Value::Integer(Integer { value: index }))],
+            );
+            if is_abrupt(value.as_ref()) {
+                return Err(value);
+            }
+            Some(value).filter(|value| !matches!(value.as_ref(), Value::Null(_)))
+        }
+        Step::Map(inner, function) => match advance(context, &inner)? {
+            Some(value) => {
+                let value = context.call(&function, &[value]);
+                if is_abrupt(value.as_ref()) {
+                    return Err(value);
+                }
+                Some(value)
+            }
+            None => None,
+        },
+        Step::Next(inner) => advance(context, &inner)?,
+    };
+    if value.is_none() {
+        *iterator.source.borrow_mut() = IteratorSource::Empty;
+    }
+    Ok(value)
+}
+
+fn iter(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [object] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    };
+    match to_iterator("iter", object) {
+        Ok(iterator) => Rc::new(Value::Iterator(iterator)),
+        Err(error) => error,
+    }
+}
+
+// 序列结束时返回 null，数组里本来就是 null 的元素和结束没法区分
+fn iterator_next(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [iterator] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    };
+    let Value::Iterator(iterator) = iterator.as_ref() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"next", &"Iterator", &iterator.object_type()],
+        )));
+    };
+    match advance(context, iterator) {
+        Ok(value) => value.unwrap_or_else(|| Rc::new(Value::Null(Null))),
+        Err(error) => error,
+    }
+}
+
+fn generate(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [function] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    };
+    Rc::new(Value::Iterator(IteratorObject::new(
+        IteratorSource::Generate(Rc::clone(function), 0),
+    )))
+}
+
+fn lazy_map(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [sequence, function] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &2],
+        )));
+    };
+    match to_iterator("lazy_map", sequence) {
+        Ok(inner) => Rc::new(Value::Iterator(IteratorObject::new(IteratorSource::Map(
+            inner,
+            Rc::clone(function),
+        )))),
+        Err(error) => error,
+    }
+}
+
+// 数组立即返回前 n 个元素，迭代器返回一个新的迭代器，取值时才从原来的迭代器里取
+fn take(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [sequence, count] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &2],
+        )));
+    };
+    let Value::Integer(count) = count.as_ref() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"take", &"Integer", &count.object_type()],
+        )));
+    };
+    if count.value < 0 {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBeNonNegative,
+            &[&"take", &count.value],
+        )));
+    }
+    let count = usize::try_from(count.value).unwrap_or(usize::MAX);
+    match sequence.as_ref() {
+        Value::Array(array) => Rc::new(Value::Array(Array {
+            elements: array.elements.iter().take(count).cloned().collect(),
+            frozen: false,
+        })),
+        other => match to_iterator("take", other) {
+            Ok(inner) => Rc::new(Value::Iterator(IteratorObject::new(IteratorSource::Take(
+                inner, count,
+            )))),
+            Err(error) => error,
+        },
+    }
+}
+
+// 无限的序列永远不会结束，先用 take 限制个数
+fn collect(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [iterator] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    };
+    let iterator = match to_iterator("collect", iterator) {
+        Ok(iterator) => iterator,
+        Err(error) => return error,
+    };
+    let mut elements = vec![];
+    loop {
+        match advance(context, &iterator) {
+            Ok(Some(value)) => elements.push(value),
+            Ok(None) => break,
+            Err(error) => return error,
+        }
+    }
+    Rc::new(Value::Array(Array {
+        elements,
+        frozen: false,
+    }))
+}
+
+// 结果按参数的 HashKey 缓存，递归的函数通过绑定调用自己时也会用到缓存
+// 参数里有不能作为哈希键的值时直接调用，出错的结果也不缓存
+fn memoize(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [function] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    };
+    if !matches!(
+        function.as_ref(),
+        Value::Function(_) | Value::Builtin(_) | Value::Closure(_)
+    ) {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"memoize", &"Function", &function.object_type()],
+        )));
+    }
+    let function = Rc::clone(function);
+    let cache: RefCell<HashMap<Vec<HashKey>, Rc<Value>>> = RefCell::default();
+    let func = move |context: &EvalContext, args: &[Rc<Value>]| {
+        let key = args
+            .iter()
+            .map(|arg| hash_key_of(arg))
+            .collect::<Option<Vec<_>>>();
+        if let Some(value) = key
+            .as_ref()
+            .and_then(|key| cache.borrow().get(key).cloned())
+        {
+            return value;
+        }
+        let value = context.call(&function, args);
+        if let Some(key) = key {
+            if !is_abrupt(value.as_ref()) {
+                cache.borrow_mut().insert(key, Rc::clone(&value));
+            }
+        }
+        value
+    };
+    Rc::new(Value::Builtin(Builtin {
+        func: Rc::new(func),
+    }))
+}
+
+fn to_bytes(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [object] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    };
+    let value = match object.as_ref() {
+        Value::String(string) => string.value.as_bytes().to_vec(),
+        Value::Bytes(bytes) => bytes.value.clone(),
+        Value::Array(array) => {
+            let mut value = Vec::with_capacity(array.elements.len());
+            for element in array.elements.iter() {
+                match element.as_ref() {
+                    Value::Integer(integer) => match u8::try_from(integer.value) {
+                        Ok(byte) => value.push(byte),
+                        Err(_) => {
+                            return Rc::new(Value::Error(runtime_error(
+                                MessageId::ByteOutOfRange,
+                                &[&integer.value],
+                            )))
+                        }
+                    },
+                    other => {
+                        return Rc::new(Value::Error(runtime_error(
+                            MessageId::ArgumentMustBe,
+                            &[&"bytes", &"Integer", &other.object_type()],
+                        )))
+                    }
+                }
+            }
+            value
+        }
+        other => {
+            return Rc::new(Value::Error(runtime_error(
+                MessageId::ArgumentNotSupported,
+                &[&"bytes", &other.object_type()],
+            )))
+        }
+    };
+    Rc::new(Value::Bytes(Bytes { value }))
+}
+
+// 两个十六进制数字一个字节，大小写都可以
+fn bytes_from_hex(_context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [hex] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    };
+    let Value::String(hex) = hex.as_ref() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"bytes_from_hex", &"String", &hex.object_type()],
+        )));
+    };
+    let digits = hex.value.as_bytes();
+    let value = digits
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair)
+                .ok()
+                .filter(|pair| pair.len() == 2)?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect::<Option<Vec<_>>>();
+    match value {
+        Some(value) if digits.iter().all(u8::is_ascii_hexdigit) => {
+            Rc::new(Value::Bytes(Bytes { value }))
+        }
+        _ => Rc::new(Value::Error(runtime_error(
+            MessageId::InvalidHex,
+            &[&hex.value],
+        ))),
+    }
+}
+
+fn read_bytes(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [path] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    };
+
+    if context.runtime().limits.sandbox {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::DisabledInSandbox,
+            &[&"read_bytes"],
+        )));
+    }
+
+    let Value::String(path) = path.as_ref() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"read_bytes", &"String", &path.object_type()],
+        )));
+    };
+    match fs::read(&path.value) {
+        Ok(value) => Rc::new(Value::Bytes(Bytes { value })),
+        Err(error) => Rc::new(Value::Error(runtime_error(
+            MessageId::FileReadFailed,
+            &[&path.value, &error],
+        ))),
+    }
+}
+
+// 字符串按 UTF-8 写入
+fn write_bytes(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [path, data] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &2],
+        )));
+    };
+
+    if context.runtime().limits.sandbox {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::DisabledInSandbox,
+            &[&"write_bytes"],
+        )));
+    }
+
+    let Value::String(path) = path.as_ref() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"write_bytes", &"String", &path.object_type()],
+        )));
+    };
+    let data = match data.as_ref() {
+        Value::Bytes(bytes) => bytes.value.as_slice(),
+        Value::String(string) => string.value.as_bytes(),
+        other => {
+            return Rc::new(Value::Error(runtime_error(
+                MessageId::ArgumentMustBe,
+                &[&"write_bytes", &"Bytes", &other.object_type()],
+            )))
+        }
+    };
+    match fs::write(&path.value, data) {
+        Ok(()) => Rc::new(Value::Null(Null)),
+        Err(error) => Rc::new(Value::Error(runtime_error(
+            MessageId::FileWriteFailed,
+            &[&path.value, &error],
+        ))),
+    }
+}
+
+// 稳定排序，规则见 ordering 模块，有 key 函数时按它的返回值排序，每个元素只调用一次
+fn sort(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let (array, key) = match objects {
+        [array] => (array, None),
+        [array, key] => (array, Some(key)),
+        _ => {
+            return Rc::new(Value::Error(runtime_error(
+                MessageId::WrongNumberOfArguments,
+                &[&objects.len(), &"1 or 2"],
+            )))
+        }
+    };
+    let Value::Array(array) = array.as_ref() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"sort", &"Array", &array.object_type()],
+        )));
+    };
+
+    let mut keyed = Vec::with_capacity(array.elements.len());
+    for element in array.elements.iter() {
+        let sort_key = match key {
+            Some(key) => {
+                let sort_key = context.call(key, &[Rc::clone(element)]);
+                if is_abrupt(sort_key.as_ref()) {
+                    return sort_key;
+                }
+                sort_key
+            }
+            None => Rc::clone(element),
+        };
+        keyed.push((sort_key, Rc::clone(element)));
+    }
+
+    match try_sort(keyed, &|(left, _), (right, _)| compare(left, right)) {
+        Ok(keyed) => Rc::new(Value::Array(Array {
+            elements: keyed.into_iter().map(|(_, element)| element).collect(),
+            frozen: false,
+        })),
+        Err(error) => Rc::new(Value::Error(error)),
+    }
+}
+
+// 返回被 quote 的新标识符，宏用 unquote 把它放进生成的代码
+fn gensym(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let name = match objects {
+        [] => "g",
+        [name] => match name.as_ref() {
+            Value::String(name) => name.value.as_str(),
+            other => {
+                return Rc::new(Value::Error(runtime_error(
+                    MessageId::ArgumentMustBe,
+                    &[&"gensym", &"String", &other.object_type()],
+                )))
+            }
+        },
+        _ => {
+            return Rc::new(Value::Error(runtime_error(
+                MessageId::WrongNumberOfArguments,
+                &[&objects.len(), &1],
+            )))
+        }
+    };
+    let name = context.runtime().gensym(name);
+    Rc::new(Value::Quote(Quote {
+        node: Expr::Identifier(Identifier {
+            token: Token::new(TokenType::Ident, Rc::clone(&name)),
+            value: name,
+        }),
+    }))
+}
+
+// 宏在 RuntimeContext::macros 里查找，宿主没有设置时没有宏可以展开，原样返回
+fn macroexpand(context: &EvalContext, objects: &[Rc<Value>]) -> Rc<Value> {
+    let [code] = objects else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::WrongNumberOfArguments,
+            &[&objects.len(), &1],
+        )));
+    };
+    let Value::Quote(quote) = code.as_ref() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"macroexpand", &"Quote", &code.object_type()],
+        )));
+    };
+    let macros = context.runtime().macros.clone();
+    let macros = macros.unwrap_or_else(|| macro_environment(&context.runtime()));
+    match expand_expression(quote.node.clone(), macros) {
+        Ok(node) => Rc::new(Value::Quote(Quote { node })),
+        Err(error) => Rc::new(Value::Error(error)),
+    }
+}
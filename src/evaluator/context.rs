@@ -0,0 +1,558 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+
+use web_time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::builtins::{Arity, BuiltinRegistry, UNSAFE_BUILTINS};
+use super::environment::Environment;
+use super::eval::apply_function;
+use super::gc::Heap;
+use super::module::ModuleLoader;
+use super::object::{Error, Task, Value};
+use super::profile::Profile;
+use super::trace::{format_step, TraceStep, Tracer};
+use crate::ast::traits::Node;
+use crate::errors::{runtime_error, EvalError, MessageId};
+use crate::sync::{MaybeSend, Rc, RefCell, RefMut, Weak};
+
+// sync 配置下宿主提供的输入输出也要能跨线程
+#[cfg(not(feature = "sync"))]
+pub type Output = Box<dyn Write>;
+#[cfg(feature = "sync")]
+pub type Output = Box<dyn Write + Send + Sync>;
+#[cfg(not(feature = "sync"))]
+pub type Input = Box<dyn BufRead>;
+#[cfg(feature = "sync")]
+pub type Input = Box<dyn BufRead + Send + Sync>;
+
+// 一次求值过程中共享的运行时状态：输入输出、限制、随机数、统计、内置函数、模块缓存和观察者
+// 根环境持有它，所有嵌套的环境共享同一份
+pub struct RuntimeContext {
+    pub output: Output,
+    // 为 None 时直接读标准输入，不提前缓冲，避免和 REPL 抢输入
+    pub input: Option<Input>,
+    pub limits: Limits,
+    pub rng: Rng,
+    pub stats: EvalStats,
+    pub options: EvalOptions,
+    pub profile: Profile,
+    pub builtins: BuiltinRegistry,
+    pub modules: ModuleLoader,
+    // 运行脚本时跟在脚本路径后面的命令行参数，由 args() 返回
+    pub args: Vec<String>,
+    // define_macros 定义宏的环境，macroexpand 在这里查找宏
+    pub macros: Option<Rc<RefCell<Environment>>>,
+    // load_prelude 定义的绑定，Environment::save 跳过仍然指向这些值的名字，用 Weak 避免和环境形成循环引用
+    pub prelude: HashMap<Rc<str>, Weak<Value>>,
+    // 可能在循环引用上的函数调用环境，见 gc
+    pub heap: Heap,
+    // spawn() 创建、还没运行的任务，按创建的顺序运行
+    tasks: VecDeque<Task>,
+    // gensym 已经生成的名字的个数
+    symbols: usize,
+    observer: Option<Box<dyn Observer>>,
+    // EvalOptions::trace 打开时接收每一步，为 None 时把每一步写到 output
+    tracer: Option<Tracer>,
+    depth: usize,
+    // 正在执行的用户函数的层数
+    calls: usize,
+    // 上次 refuel 时的步数和时间，步数限制和超时都从这里开始算
+    fuel_start: u64,
+    timer_start: Instant,
+    // 上次 refuel 以来求值产生的字符串、数组和哈希的累计大小
+    allocated: usize,
+    // clock_ms() 的起点
+    started: Instant,
+}
+
+// 内置函数被调用时拿到的上下文：调用处的环境，以及整条环境链共享的运行时状态
+pub struct EvalContext {
+    env: Rc<RefCell<Environment>>,
+    runtime: Rc<RefCell<RuntimeContext>>,
+}
+
+impl EvalContext {
+    pub fn new(env: Rc<RefCell<Environment>>) -> Self {
+        let runtime = env.borrow().context();
+        Self { env, runtime }
+    }
+
+    pub fn env(&self) -> Rc<RefCell<Environment>> {
+        Rc::clone(&self.env)
+    }
+
+    // 返回的借用要在调用 call 之前释放，否则回调里访问运行时状态会重复借用
+    pub fn runtime(&self) -> RefMut<'_, RuntimeContext> {
+        self.runtime.borrow_mut()
+    }
+
+    // 调用用户函数或者其他内置函数
+    pub fn call(&self, func: &Value, args: &[Rc<Value>]) -> Rc<Value> {
+        apply_function(func, args, Rc::clone(&self.env)).unwrap_or_else(EvalError::into_object)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Limits {
+    // 最多允许求值的节点数量，None 表示不限制
+    pub max_steps: Option<u64>,
+    // 沙盒模式下禁用会阻塞执行或者读取宿主信息的 builtin，比如 sleep 和 getenv
+    pub sandbox: bool,
+    // 求值允许花费的最长时间，None 表示不限制
+    pub timeout: Option<Duration>,
+    // 字符串、数组和哈希累计最多占用的字节数，None 表示不限制
+    pub max_heap: Option<usize>,
+    // 宏展开的结果里又有宏调用时最多展开的层数，展开成调用自己的宏会在这里停下
+    pub max_macro_depth: usize,
+    // 用户函数最多嵌套调用的层数，和虚拟机里调用栈的帧数一样，没有终止条件的递归在这里停下
+    pub max_depth: usize,
+}
+
+const DEFAULT_MACRO_DEPTH: usize = 64;
+// 求值器在栈不够时会换到堆上分配的新栈，这个限制只是为了让无限递归尽早报错
+const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_steps: None,
+            sandbox: false,
+            timeout: None,
+            max_heap: None,
+            max_macro_depth: DEFAULT_MACRO_DEPTH,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+
+// 默认关闭的、会拖慢求值的功能
+#[derive(Debug, Clone, Default)]
+pub struct EvalOptions {
+    // 统计每种节点和每个函数被求值的次数和耗时，结果在 RuntimeContext::profile 里
+    pub profile: bool,
+    // 记录每个节点开始求值和求值结束，见 trace 模块
+    pub trace: bool,
+    // 展开宏时把宏在 quote 里引入的绑定换成 gensym 生成的名字，避免捕获调用处的同名变量
+    pub hygiene: bool,
+}
+
+// 开启超时后每求值这么多个节点检查一次时间
+const TIMEOUT_CHECK_INTERVAL: u64 = 256;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EvalStats {
+    pub steps: u64,
+    pub function_calls: u64,
+    pub builtin_calls: u64,
+    pub max_depth: usize,
+    // 一次 refuel 周期里累计分配的最大字节数
+    pub allocated: usize,
+}
+
+// 求值过程的钩子，默认什么都不做
+pub trait Observer: MaybeSend {
+    fn on_enter(&mut self, _node: &dyn Node, _depth: usize) {}
+
+    fn on_exit(&mut self, _node: &dyn Node, _result: &Value, _depth: usize) {}
+}
+
+impl RuntimeContext {
+    pub fn new() -> Self {
+        Self {
+            output: Box::new(io::stdout()),
+            input: None,
+            limits: Limits::default(),
+            rng: Rng::from_time(),
+            stats: EvalStats::default(),
+            options: EvalOptions::default(),
+            profile: Profile::new(),
+            builtins: BuiltinRegistry::new(),
+            modules: ModuleLoader::default(),
+            args: vec![],
+            macros: None,
+            prelude: HashMap::new(),
+            heap: Heap::default(),
+            tasks: VecDeque::new(),
+            symbols: 0,
+            observer: None,
+            tracer: None,
+            depth: 0,
+            calls: 0,
+            fuel_start: 0,
+            timer_start: Instant::now(),
+            allocated: 0,
+            started: Instant::now(),
+        }
+    }
+
+    // 宏展开用的上下文：限制、卫生选项、内置函数、脚本参数和导入的根目录都和 self 一样，宏体里也绕不过沙盒
+    // 输出、输入和统计这些状态是新的
+    pub fn for_macros(&self) -> Self {
+        let mut context = Self::new()
+            .with_limits(self.limits.clone())
+            .with_args(self.args.clone());
+        context.options.hygiene = self.options.hygiene;
+        context.builtins = self.builtins.clone();
+        context.modules = self.modules.fresh();
+        context
+    }
+
+    pub fn with_output(mut self, output: Output) -> Self {
+        self.output = output;
+        self
+    }
+
+    pub fn with_input(mut self, input: Input) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_options(mut self, options: EvalOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    pub fn with_macros(mut self, macros: Rc<RefCell<Environment>>) -> Self {
+        self.macros = Some(macros);
+        self
+    }
+
+    pub fn with_rng(mut self, rng: Rng) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    // 嵌入方在创建上下文时一并注册自己的函数
+    pub fn with_builtin<F>(mut self, name: &str, arity: Arity, description: &str, func: F) -> Self
+    where
+        F: Fn(&EvalContext, &[Rc<Value>]) -> Rc<Value> + MaybeSend + 'static,
+    {
+        self.builtins
+            .register_with_info(name, arity, description, Rc::new(func));
+        self
+    }
+
+    // 运行不受信任的代码：打开 Limits::sandbox 并停用 UNSAFE_BUILTINS
+    pub fn sandboxed(mut self) -> Self {
+        self.limits.sandbox = true;
+        self.with_disabled_builtins(&UNSAFE_BUILTINS)
+    }
+
+    pub fn with_disabled_builtins(mut self, names: &[&str]) -> Self {
+        for name in names {
+            self.builtins.disable(name);
+        }
+        self
+    }
+
+    // 只保留名单里的内置函数，见 BuiltinRegistry::allow_only
+    pub fn with_allowed_builtins(mut self, names: &[&str]) -> Self {
+        self.builtins.allow_only(names);
+        self
+    }
+
+    // import() 的相对路径从 root 开始找
+    pub fn with_module_root(mut self, root: PathBuf) -> Self {
+        self.modules = ModuleLoader::new(root);
+        self
+    }
+
+    pub fn with_observer(mut self, observer: Box<dyn Observer>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    pub fn set_observer(
+        &mut self,
+        observer: Option<Box<dyn Observer>>,
+    ) -> Option<Box<dyn Observer>> {
+        std::mem::replace(&mut self.observer, observer)
+    }
+
+    // 回调执行时运行时上下文正被借用，不能在回调里访问它
+    pub fn with_tracer<F>(mut self, tracer: F) -> Self
+    where
+        F: FnMut(&TraceStep) + MaybeSend + 'static,
+    {
+        self.tracer = Some(Box::new(tracer));
+        self
+    }
+
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) -> Option<Tracer> {
+        std::mem::replace(&mut self.tracer, tracer)
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn call_depth(&self) -> usize {
+        self.calls
+    }
+
+    // 超出调用层数限制时返回错误，这时不增加层数，不需要调用 exit_call
+    pub(crate) fn enter_call(&mut self) -> Result<(), Error> {
+        if self.calls >= self.limits.max_depth {
+            return Err(runtime_error(
+                MessageId::RecursionTooDeep,
+                &[&self.limits.max_depth],
+            ));
+        }
+        self.calls += 1;
+        Ok(())
+    }
+
+    pub(crate) fn exit_call(&mut self) {
+        self.calls = self.calls.saturating_sub(1);
+    }
+
+    // 写到 output 失败时不影响求值
+    pub(crate) fn trace(&mut self, node: &dyn Node, env_depth: usize, result: Option<&Value>) {
+        let step = TraceStep {
+            node,
+            depth: self.depth,
+            call_depth: self.calls,
+            env_depth,
+            result,
+        };
+        match self.tracer.as_mut() {
+            Some(tracer) => tracer(&step),
+            None => {
+                let _ = writeln!(self.output, "{}", format_step(&step));
+            }
+        }
+    }
+
+    pub fn spawn(&mut self, task: Task) {
+        self.tasks.push_back(task);
+    }
+
+    pub fn next_task(&mut self) -> Option<Task> {
+        self.tasks.pop_front()
+    }
+
+    // 出错中断时丢掉剩下的任务，不会留到 REPL 的下一行
+    pub fn clear_tasks(&mut self) {
+        self.tasks.clear();
+    }
+
+    // 名字里带数字，标识符里不能写数字，所以不会和源码里的任何名字相同
+    pub fn gensym(&mut self, name: &str) -> Rc<str> {
+        self.symbols += 1;
+        Rc::from(format!("{}_{}", name, self.symbols))
+    }
+
+    // 重新开始计算步数限制，REPL 在每次输入前调用，让每一行都有完整的预算
+    // 统计里的总步数不受影响
+    pub fn refuel(&mut self) {
+        self.fuel_start = self.stats.steps;
+        self.timer_start = Instant::now();
+        self.allocated = 0;
+    }
+
+    // 记下一个节点或者内置函数新分配的对象，inputs 是内置函数的参数，超出内存限制时返回错误
+    // 这是累计分配的大小，不会因为对象被释放而减少
+    pub fn allocate(&mut self, object: &Value, inputs: &[Rc<Value>]) -> Result<(), Error> {
+        if !matches!(
+            object,
+            Value::String(_) | Value::Bytes(_) | Value::Array(_) | Value::Hash(_)
+        ) {
+            return Ok(());
+        }
+        self.allocated = self
+            .allocated
+            .saturating_add(allocation_size(object, inputs));
+        self.stats.allocated = self.stats.allocated.max(self.allocated);
+        match self.limits.max_heap {
+            Some(max_heap) if self.allocated > max_heap => {
+                Err(runtime_error(MessageId::OutOfMemory, &[&max_heap]))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // 进入一个节点的求值，超出步数限制时返回错误
+    pub fn enter(&mut self, node: &dyn Node) -> Result<(), Error> {
+        self.step()?;
+        self.depth += 1;
+        self.stats.max_depth = self.stats.max_depth.max(self.depth);
+        if self.options.profile {
+            self.profile.enter(self.depth);
+        }
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_enter(node, self.depth);
+        }
+        Ok(())
+    }
+
+    // 记一步，超出步数限制或者超时的时候返回错误
+    // 求值器每个节点记一步，虚拟机每次函数调用和向回跳转记一步
+    pub fn step(&mut self) -> Result<(), Error> {
+        self.stats.steps += 1;
+        if let Some(max_steps) = self.limits.max_steps {
+            if self.stats.steps - self.fuel_start > max_steps {
+                return Err(runtime_error(MessageId::BudgetExceeded, &[&max_steps]));
+            }
+        }
+        // 读时钟比较慢，每隔一段步数检查一次
+        if let Some(timeout) = self.limits.timeout {
+            let steps = self.stats.steps - self.fuel_start;
+            if steps.is_multiple_of(TIMEOUT_CHECK_INTERVAL) && self.timer_start.elapsed() > timeout
+            {
+                return Err(runtime_error(MessageId::Timeout, &[&timeout.as_millis()]));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn exit(&mut self, node: &dyn Node, result: &Value) {
+        if self.options.profile {
+            self.profile.exit(node, self.depth);
+        }
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_exit(node, result, self.depth);
+        }
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    // 距离超时还剩的时间，没有设置超时时返回 None，已经超时时返回 0
+    pub fn remaining_time(&self) -> Option<Duration> {
+        self.limits
+            .timeout
+            .map(|timeout| timeout.saturating_sub(self.timer_start.elapsed()))
+    }
+
+    // 上下文创建以来经过的时间，单调递增，不受系统时钟调整影响
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn read_line(&mut self, buffer: &mut String) -> io::Result<usize> {
+        match self.input.as_mut() {
+            Some(reader) => reader.read_line(buffer),
+            None => io::stdin().read_line(buffer),
+        }
+    }
+
+    pub fn read_to_string(&mut self, buffer: &mut String) -> io::Result<usize> {
+        match self.input.as_mut() {
+            Some(reader) => reader.read_to_string(buffer),
+            None => io::stdin().read_to_string(buffer),
+        }
+    }
+}
+
+impl Default for RuntimeContext {
+    fn default() -> Self {
+        RuntimeContext::new()
+    }
+}
+
+// 把输出收集到内存里，clone 出来的副本共享同一块缓冲区，宿主求值之后用 take 取出
+#[derive(Clone, Default)]
+pub struct OutputBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl OutputBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 取出目前收集到的内容并清空缓冲区
+    pub fn take(&self) -> String {
+        String::from_utf8_lossy(&self.0.take()).into_owned()
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// splitmix64，足够脚本使用，同样的种子得到同样的序列
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn from_time() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or_default();
+        Rng::new(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // [0, bound) 内均匀分布的数，拒绝采样避免取模带来的偏差
+    pub fn next_below(&mut self, bound: u64) -> u64 {
+        assert!(bound > 0, "bound must be positive");
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % bound;
+            }
+        }
+    }
+}
+
+// 新分配的对象大概占用的字节数，只用来做限制，不追求精确
+// 数组和哈希里的元素是共享的，创建它们的时候已经算过，这里只算容器本身和比 inputs 里最大的同类容器多出来的槽位，
+// 所以循环里 push 每次只算增长的一个元素，也不用遍历整个值
+pub fn allocation_size(object: &Value, inputs: &[Rc<Value>]) -> usize {
+    const SLOT: usize = 16;
+    let largest = |len: fn(&Value) -> Option<usize>| {
+        inputs
+            .iter()
+            .filter_map(|input| len(input))
+            .max()
+            .unwrap_or(0)
+    };
+    match object {
+        Value::String(string) => SLOT + string.value.len(),
+        Value::Bytes(bytes) => SLOT + bytes.value.len(),
+        Value::Array(array) => {
+            let largest = largest(|input| match input {
+                Value::Array(array) => Some(array.elements.len()),
+                _ => None,
+            });
+            SLOT + SLOT * array.elements.len().saturating_sub(largest)
+        }
+        Value::Hash(hash) => {
+            let largest = largest(|input| match input {
+                Value::Hash(hash) => Some(hash.pairs.len()),
+                _ => None,
+            });
+            SLOT + 2 * SLOT * hash.pairs.len().saturating_sub(largest)
+        }
+        _ => SLOT,
+    }
+}
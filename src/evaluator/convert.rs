@@ -0,0 +1,308 @@
+// 宿主的 Rust 类型和 Monkey 值之间的转换，嵌入方和写内置函数时不用手动构造对象或者 downcast
+// 转成 Value 用 From/Into，反过来用 TryFrom<&Value>，类型不对时返回 ConversionError
+// HostFunction 在这之上把普通的 Rust 闭包包装成内置函数
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display};
+use std::hash::Hash as StdHash;
+
+use indexmap::IndexMap;
+
+use super::eval::build_hash;
+use super::object::{Array, Boolean, Integer, Null, ObjectType, StringObject, Value};
+use crate::errors::{runtime_error, MessageId};
+use crate::sync::{MaybeSend, Rc};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionError {
+    // 期望的 Monkey 类型名，比如 Integer
+    pub expected: &'static str,
+    pub got: ObjectType,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}, got {}", self.expected, self.got)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(Integer { value })
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Boolean(Boolean::from_native_bool(value))
+    }
+}
+
+impl From<()> for Value {
+    fn from(_: ()) -> Self {
+        Value::Null(Null)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(StringObject { value })
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::from(value.to_owned())
+    }
+}
+
+// None 转成 null
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null(Null), Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Self {
+        Value::Array(Array {
+            elements: values
+                .into_iter()
+                .map(|value| Rc::new(value.into()))
+                .collect(),
+            frozen: false,
+        })
+    }
+}
+
+// 能用作哈希键的宿主类型
+pub trait HashKeyType: Into<Value> {}
+
+impl HashKeyType for i64 {}
+impl HashKeyType for bool {}
+impl HashKeyType for String {}
+impl HashKeyType for &str {}
+impl<T: HashKeyType> HashKeyType for Vec<T> {}
+
+// HashMap 没有顺序，转换出来的哈希按遍历的顺序排列
+macro_rules! impl_from_map {
+    ($($map:ident),* $(,)?) => {
+        $(
+            impl<K: HashKeyType, V: Into<Value>> From<$map<K, V>> for Value {
+                fn from(map: $map<K, V>) -> Self {
+                    let pairs = map
+                        .into_iter()
+                        .map(|(key, value)| (Rc::new(key.into()), Rc::new(value.into())))
+                        .collect();
+                    let hash = build_hash(pairs).expect("HashKeyType values are hashable");
+                    Rc::unwrap_or_clone(hash)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_map!(HashMap, BTreeMap, IndexMap);
+
+impl TryFrom<&Value> for i64 {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Integer(integer) => Ok(integer.value),
+            other => Err(mismatch("Integer", other)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Boolean(boolean) => Ok(boolean.value()),
+            other => Err(mismatch("Boolean", other)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for () {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Null(_) => Ok(()),
+            other => Err(mismatch("Null", other)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for String {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(string) => Ok(string.value.clone()),
+            other => Err(mismatch("String", other)),
+        }
+    }
+}
+
+// 不做转换，原样拿到参数
+impl TryFrom<&Value> for Value {
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, ConversionError> {
+        Ok(value.clone())
+    }
+}
+
+// null 转成 None，其他值按 T 转换
+impl<T> TryFrom<&Value> for Option<T>
+where
+    T: for<'a> TryFrom<&'a Value, Error = ConversionError>,
+{
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Null(_) => Ok(None),
+            other => T::try_from(other).map(Some),
+        }
+    }
+}
+
+// 任何一个元素转换失败都返回那个元素的错误
+impl<T> TryFrom<&Value> for Vec<T>
+where
+    T: for<'a> TryFrom<&'a Value, Error = ConversionError>,
+{
+    type Error = ConversionError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Array(array) => array
+                .elements
+                .iter()
+                .map(|element| T::try_from(element.as_ref()))
+                .collect(),
+            other => Err(mismatch("Array", other)),
+        }
+    }
+}
+
+macro_rules! impl_try_from_map {
+    ($($map:ident: $($bound:path),+);* $(;)?) => {
+        $(
+            impl<K, V> TryFrom<&Value> for $map<K, V>
+            where
+                K: for<'a> TryFrom<&'a Value, Error = ConversionError> $(+ $bound)+,
+                V: for<'a> TryFrom<&'a Value, Error = ConversionError>,
+            {
+                type Error = ConversionError;
+
+                fn try_from(value: &Value) -> Result<Self, Self::Error> {
+                    match value {
+                        Value::Hash(hash) => hash
+                            .pairs
+                            .values()
+                            .map(|pair| {
+                                Ok((K::try_from(pair.key.as_ref())?, V::try_from(pair.value.as_ref())?))
+                            })
+                            .collect(),
+                        other => Err(mismatch("Hash", other)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from_map!(
+    HashMap: Eq, StdHash;
+    BTreeMap: Ord;
+    IndexMap: Eq, StdHash;
+);
+
+fn mismatch(expected: &'static str, got: &Value) -> ConversionError {
+    ConversionError {
+        expected,
+        got: got.object_type(),
+    }
+}
+
+// 宿主函数的返回值：能转成 Value 的类型，或者 Result，Err 变成运行时错误
+pub trait IntoReturnValue {
+    fn into_return_value(self, name: &str) -> Rc<Value>;
+}
+
+impl<T: Into<Value>> IntoReturnValue for T {
+    fn into_return_value(self, _: &str) -> Rc<Value> {
+        Rc::new(self.into())
+    }
+}
+
+impl<T: Into<Value>, E: Display> IntoReturnValue for Result<T, E> {
+    fn into_return_value(self, name: &str) -> Rc<Value> {
+        match self {
+            Ok(value) => Rc::new(value.into()),
+            Err(error) => Rc::new(Value::Error(runtime_error(
+                MessageId::HostFunctionFailed,
+                &[&name, &error],
+            ))),
+        }
+    }
+}
+
+// 参数都能从 Value 转换的 Rust 闭包，Args 是参数类型组成的元组，只用来区分参数个数不同的实现
+// 调用前检查参数个数和类型，错误信息和手写的内置函数一样
+pub trait HostFunction<Args>: MaybeSend + 'static {
+    fn arity(&self) -> usize;
+
+    fn invoke(&self, name: &str, args: &[Rc<Value>]) -> Rc<Value>;
+}
+
+macro_rules! impl_host_function {
+    ($count:expr; $($arg:ident),*) => {
+        impl<F, R, $($arg),*> HostFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + MaybeSend + 'static,
+            R: IntoReturnValue,
+            $($arg: for<'a> TryFrom<&'a Value, Error = ConversionError>,)*
+        {
+            fn arity(&self) -> usize {
+                $count
+            }
+
+            #[allow(non_snake_case)]
+            fn invoke(&self, name: &str, args: &[Rc<Value>]) -> Rc<Value> {
+                let [$($arg),*] = args else {
+                    return Rc::new(Value::Error(runtime_error(
+                        MessageId::WrongNumberOfArguments,
+                        &[&args.len(), &$count],
+                    )));
+                };
+                $(
+                    let $arg = match $arg::try_from($arg.as_ref()) {
+                        Ok(value) => value,
+                        Err(error) => {
+                            return Rc::new(Value::Error(runtime_error(
+                                MessageId::ArgumentMustBe,
+                                &[&name, &error.expected, &error.got],
+                            )))
+                        }
+                    };
+                )*
+                self($($arg),*).into_return_value(name)
+            }
+        }
+    };
+}
+
+impl_host_function!(0;);
+impl_host_function!(1; A);
+impl_host_function!(2; A, B);
+impl_host_function!(3; A, B, C);
+impl_host_function!(4; A, B, C, D);
+impl_host_function!(5; A, B, C, D, E);
@@ -1,41 +1,230 @@
-use super::object;
-use std::collections::HashMap;
-use std::{cell::RefCell, rc::Weak};
+use super::context::{EvalContext, RuntimeContext};
+use super::eval::eval_node;
+use super::object::{self, Value};
+use crate::ast::expressions::Identifier;
+use crate::ast::traits::AsNode;
+use crate::errors::{join_parse_errors, EvalError};
+use crate::formatter::{format_expression, FormatOptions};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::quote::convert_object_to_ast_node;
+use crate::sync::{MaybeSend, Rc, RefCell};
+use crate::token::TokenType;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
 
+// 内层环境强引用外层环境，返回出去的闭包一直能访问到定义它时的整条环境链
+// 函数对象又强引用它所在的环境，递归函数会形成循环引用，见 release 和 gc
 pub struct Environment {
-    store: HashMap<String, Box<dyn object::Object>>,
-    outer: Weak<RefCell<Environment>>,
+    store: HashMap<Rc<str>, Rc<Value>>,
+    outer: Option<Rc<RefCell<Environment>>>,
+    // 同一条环境链上的所有环境共享一个运行时上下文
+    context: Rc<RefCell<RuntimeContext>>,
+}
+
+// 环境链上每个环境在某一时刻的绑定，由 Environment::snapshot 得到
+// 值本身不会被修改，只复制指向它们的 Rc，所以保存一次的开销和绑定的数量成正比
+#[derive(Clone, Default)]
+pub struct Snapshot {
+    store: HashMap<Rc<str>, Rc<Value>>,
+    outer: Option<Box<Snapshot>>,
+}
+
+impl Snapshot {
+    // 这个环境自己的绑定数，不包括外层
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
 }
 
 impl Environment {
     pub fn new() -> Self {
+        Environment::with_context(Rc::new(RefCell::new(RuntimeContext::new())))
+    }
+
+    pub fn with_context(context: Rc<RefCell<RuntimeContext>>) -> Self {
         Environment {
             store: HashMap::new(),
-            outer: Weak::new(),
+            outer: None,
+            context,
         }
     }
 
-    pub fn new_enclosed(outer: Weak<RefCell<Environment>>) -> Self {
+    pub fn new_enclosed(outer: Rc<RefCell<Environment>>) -> Self {
+        let context = outer.borrow().context();
         Environment {
             store: HashMap::new(),
-            outer,
+            outer: Some(outer),
+            context,
         }
     }
 
-    pub fn get(&self, name: &str) -> Option<Box<dyn object::Object>> {
+    pub fn context(&self) -> Rc<RefCell<RuntimeContext>> {
+        Rc::clone(&self.context)
+    }
+
+    // 只有这个环境自己的绑定，没有顺序
+    pub fn iter(&self) -> impl Iterator<Item = (&Rc<str>, &Rc<Value>)> {
+        self.store.iter()
+    }
+
+    // 从这里能访问到的所有名字，包括外层环境的，按名字排序
+    pub fn names(&self) -> Vec<Rc<str>> {
+        self.visible().into_iter().map(|(name, _)| name).collect()
+    }
+
+    // 从这里能访问到的所有绑定，内层的绑定遮住外层的同名绑定，按名字排序
+    pub fn visible(&self) -> Vec<(Rc<str>, Rc<Value>)> {
+        let mut bindings = self.outer.as_ref().map_or_else(BTreeMap::new, |outer| {
+            outer
+                .borrow()
+                .visible()
+                .into_iter()
+                .collect::<BTreeMap<_, _>>()
+        });
+        bindings.extend(
+            self.store
+                .iter()
+                .map(|(name, value)| (Rc::clone(name), Rc::clone(value))),
+        );
+        bindings.into_iter().collect()
+    }
+
+    // 根环境返回 None
+    pub(crate) fn outer(&self) -> Option<Rc<RefCell<Environment>>> {
+        self.outer.clone()
+    }
+
+    // 外面还有几层环境，根环境是 0
+    pub fn depth(&self) -> usize {
+        match self.outer.as_ref() {
+            Some(outer) => 1 + outer.borrow().depth(),
+            None => 0,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Rc<Value>> {
         self.store
             .get(name)
-            .map(|boxed_object| dyn_clone::clone_box(&**boxed_object))
-            .or_else(|| self.outer.upgrade().and_then(|env| env.borrow().get(name)))
+            .cloned()
+            .or_else(|| self.outer.as_ref().and_then(|env| env.borrow().get(name)))
+    }
+
+    // 注册到整条环境链共享的上下文里，返回被替换掉的旧实现
+    pub fn register_builtin<F>(&self, name: &str, func: F) -> Option<object::Builtin>
+    where
+        F: Fn(&EvalContext, &[Rc<Value>]) -> Rc<Value> + MaybeSend + 'static,
+    {
+        self.context
+            .borrow_mut()
+            .builtins
+            .register(name, Rc::new(func))
+    }
+
+    // 函数调用结束时调用，如果这个环境只被它自己的绑定里的函数引用，说明没有闭包逃出去，
+    // 清空绑定打破循环引用。被共享的值一律当作外部引用，宁可泄漏也不能清掉还在用的环境
+    // 返回是否清空了，没清空的环境交给 gc 跟踪
+    pub(crate) fn release(env: &Rc<RefCell<Environment>>) -> bool {
+        let internal = env
+            .borrow()
+            .store
+            .values()
+            .map(|value| internal_references(value, env))
+            .sum::<usize>();
+        let released = Rc::strong_count(env) == internal + 1;
+        if released {
+            env.borrow_mut().store.clear();
+        }
+        released
     }
 
-    pub fn set(
-        &mut self,
-        name: String,
-        value: Box<dyn object::Object>,
-    ) -> Option<Box<dyn object::Object>> {
+    // gc 发现环境已经不可达时清空它的绑定
+    pub(crate) fn clear(&mut self) {
+        self.store.clear();
+    }
+
+    pub fn set(&mut self, name: Rc<str>, value: Rc<Value>) -> Option<Rc<Value>> {
         self.store.insert(name, value)
     }
+
+    // 把这个环境自己的绑定按名字顺序写成 `let name = value;`，load 求值这个文件就能恢复
+    // 只保存数据：整数、布尔、null、字符串、数组、哈希和字节串，函数保存源码
+    // 闭包捕获的别的环境、内置函数、通道这些没法写成源码的绑定不保存，返回它们的名字
+    // load_prelude 定义、之后没有被重新绑定的名字属于标准库，直接跳过
+    pub fn save(
+        env: &Rc<RefCell<Environment>>,
+        path: impl AsRef<Path>,
+    ) -> io::Result<Vec<Rc<str>>> {
+        let mut source = String::new();
+        let mut skipped = vec![];
+        let context = env.borrow().context();
+        let prelude = &context.borrow().prelude;
+        for (name, value) in env.borrow().store.iter().collect::<BTreeMap<_, _>>() {
+            if prelude
+                .get(name)
+                .is_some_and(|prelude| prelude.as_ptr() == Rc::as_ptr(value))
+            {
+                continue;
+            }
+            let expression = Some(value)
+                .filter(|value| is_identifier(name) && is_savable(value, env))
+                .and_then(|value| convert_object_to_ast_node(Rc::clone(value)).ok());
+            match expression {
+                Some(expression) => source.push_str(&format!(
+                    "let {} = {};\n",
+                    name,
+                    format_expression(&expression, &FormatOptions::default())
+                )),
+                None => skipped.push(Rc::clone(name)),
+            }
+        }
+        fs::write(path, source)?;
+        Ok(skipped)
+    }
+
+    // 在 env 里求值 save 写出的文件，函数捕获的是 env；语法错误和运行时错误当作 InvalidData
+    pub fn load(env: &Rc<RefCell<Environment>>, path: impl AsRef<Path>) -> io::Result<()> {
+        let source = fs::read_to_string(path)?;
+        let program = Parser::new(Lexer::new(source))
+            .parse()
+            .map_err(|errors| io::Error::new(ErrorKind::InvalidData, join_parse_errors(&errors)))?;
+        let result =
+            eval_node(program.as_node(), Rc::clone(env)).unwrap_or_else(EvalError::into_object);
+        match result.as_ref() {
+            Value::Error(error) => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                error.message.clone(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // 外层环境也一起保存，REPL 的 `:undo` 用它撤销一次输入
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            store: self.store.clone(),
+            outer: self
+                .outer
+                .as_ref()
+                .map(|outer| Box::new(outer.borrow().snapshot())),
+        }
+    }
+
+    // 把这条环境链上的绑定换回保存时的样子，之后新加的绑定被丢掉
+    // 闭包引用的还是原来这些环境对象，恢复之后看到的也是保存时的绑定
+    // 同一个 snapshot 可以恢复多次
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.store = snapshot.store.clone();
+        if let (Some(outer), Some(snapshot)) = (self.outer.as_ref(), snapshot.outer.as_ref()) {
+            outer.borrow_mut().restore(snapshot);
+        }
+    }
 }
 
 impl Default for Environment {
@@ -43,3 +232,71 @@ impl Default for Environment {
         Environment::new()
     }
 }
+
+// env() 和 REPL 的 :vars 显示的值，太长时截断
+// 函数只显示参数
+pub fn preview(value: &Value) -> String {
+    const MAX_PREVIEW_WIDTH: usize = 40;
+    let parameters = |parameters: &[Identifier]| {
+        parameters
+            .iter()
+            .map(|parameter| parameter.value.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let text = match value {
+        Value::Function(function) => format!("fn({})", parameters(&function.parameters)),
+        Value::Macro(macro_object) => format!("macro({})", parameters(&macro_object.parameters)),
+        _ => value.inspect().replace('\n', " "),
+    };
+    if text.chars().count() <= MAX_PREVIEW_WIDTH {
+        text
+    } else {
+        let prefix = text.chars().take(MAX_PREVIEW_WIDTH).collect::<String>();
+        format!("{}...", prefix)
+    }
+}
+
+// 宿主用 set 放进来的名字不一定能写成源码
+fn is_identifier(name: &str) -> bool {
+    matches!(Lexer::tokenize(name).as_slice(), [token] if token.token_type == TokenType::Ident && *token.literal == *name)
+}
+
+// 字符串里不能有双引号，词法分析器不支持转义；函数只保存定义在 env 里的
+fn is_savable(value: &Value, env: &Rc<RefCell<Environment>>) -> bool {
+    match value {
+        Value::Integer(_) | Value::Boolean(_) | Value::Null(_) | Value::Bytes(_) => true,
+        Value::String(string) => !string.value.contains('"'),
+        Value::Array(array) => array
+            .elements
+            .iter()
+            .all(|element| is_savable(element, env)),
+        Value::Hash(hash) => hash
+            .pairs
+            .values()
+            .all(|pair| is_savable(&pair.key, env) && is_savable(&pair.value, env)),
+        Value::Function(function) => Rc::ptr_eq(&function.env, env),
+        _ => false,
+    }
+}
+
+// value 里只能从 env 的绑定访问到、并且捕获了 env 的函数的数量
+fn internal_references(value: &Rc<Value>, env: &Rc<RefCell<Environment>>) -> usize {
+    if Rc::strong_count(value) != 1 {
+        return 0;
+    }
+    match value.as_ref() {
+        Value::Function(function) => usize::from(Rc::ptr_eq(&function.env, env)),
+        Value::Array(array) => array
+            .elements
+            .iter()
+            .map(|element| internal_references(element, env))
+            .sum(),
+        Value::Hash(hash) => hash
+            .pairs
+            .values()
+            .map(|pair| internal_references(&pair.value, env))
+            .sum(),
+        _ => 0,
+    }
+}
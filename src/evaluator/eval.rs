@@ -1,344 +1,465 @@
+use super::context::EvalContext;
 use super::environment::Environment;
+use super::gc;
 use super::object::{
-    self, Boolean, HashPair, Hashable, Integer, Null, Object, ObjectType, StringObject, BUILTINS,
+    self, Boolean, Error, HashKey, HashPair, Hashable, Integer, Null, Object, ObjectType,
+    StringObject, Value,
 };
-use crate::ast::expressions::{HashLiteral, Identifier};
+use super::scheduler;
+use crate::ast::expressions::{Expr, HashLiteral, Identifier};
 use crate::ast::program::Program;
-use crate::ast::statements::BlockStatement;
-use crate::ast::traits::{AsNode, Expression, Node};
-use std::collections::HashMap;
-use std::{cell::RefCell, rc::Rc};
-
-// TODO: Rust 里面好像不允许对一个 dynamic dispatch 的类型做判断，但我不太确定：https://www.reddit.com/r/rust/comments/ajd0je/how_to_get_type_of_a_boximpl_trait/
-// 所以我这里扩展了之前的 node trait
-pub fn eval(node: &dyn Node, env: Rc<RefCell<Environment>>) -> Box<dyn Object> {
-    node.eval_to_object(env)
+use crate::ast::statements::{BlockStatement, Stmt};
+use crate::ast::traits::{AsNode, Node};
+use crate::ast::walk::NodeRef;
+use crate::errors::{join_parse_errors, runtime_error, EvalError, EvalResult, MessageId};
+use crate::evaluator::macro_expansion::{define_macros, expand_macro, macro_environment};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::sync::{Rc, RefCell};
+use indexmap::IndexMap;
+use web_time::Instant;
+
+// 宿主的入口，中断求值的错误会还原成 Error 或 Exit 对象返回
+// 返回之前运行 spawn() 创建、还没运行的任务，所以只在整个程序求值完时调用
+// eval()、import 这些在程序中途求值的地方用 eval_node，任务留到程序结束时运行
+pub fn eval(node: &dyn Node, env: Rc<RefCell<Environment>>) -> Rc<Value> {
+    let result = eval_node(node, Rc::clone(&env));
+    scheduler::finish(result, &env).unwrap_or_else(EvalError::into_object)
+}
+
+pub fn eval_node(node: &dyn Node, env: Rc<RefCell<Environment>>) -> EvalResult {
+    grow_stack(|| eval_node_on_stack(node, env))
+}
+
+// 调试构建下每层求值大约用掉 2 KB 的栈，剩下的不够时换到一段新分配的栈上继续，
+// 递归的深度只受 Limits::max_depth 限制，不受线程栈大小的限制
+#[cfg(not(target_family = "wasm"))]
+fn grow_stack<R>(f: impl FnOnce() -> R) -> R {
+    const RED_ZONE: usize = 64 * 1024;
+    const SEGMENT_SIZE: usize = 1024 * 1024;
+    stacker::maybe_grow(RED_ZONE, SEGMENT_SIZE, f)
+}
+
+#[cfg(target_family = "wasm")]
+fn grow_stack<R>(f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+fn eval_node_on_stack(node: &dyn Node, env: Rc<RefCell<Environment>>) -> EvalResult {
+    let context = env.borrow().context();
+    context.borrow_mut().enter(node)?;
+    let env_depth = match context.borrow().options.trace {
+        true => Some(env.borrow().depth()),
+        false => None,
+    };
+    if let Some(env_depth) = env_depth {
+        context.borrow_mut().trace(node, env_depth, None);
+    }
+    let result = node
+        .eval_to_object(env)
+        .and_then(|object| {
+            if allocates(node) {
+                context.borrow_mut().allocate(object.as_ref(), &[])?;
+            }
+            Ok(object)
+        })
+        .map_err(|error| locate(error, node));
+    let object = match &result {
+        Ok(object) => Rc::clone(object),
+        Err(error) => error.clone().into_object(),
+    };
+    if let Some(env_depth) = env_depth {
+        context
+            .borrow_mut()
+            .trace(node, env_depth, Some(object.as_ref()));
+    }
+    context.borrow_mut().exit(node, object.as_ref());
+    result
+}
+
+// 会创建新的字符串、数组或哈希的节点，变量和返回值只是传递已有的值，不重复计算
+fn allocates(node: &dyn Node) -> bool {
+    matches!(
+        node.node_ref(),
+        NodeRef::String(_) | NodeRef::Array(_) | NodeRef::Hash(_) | NodeRef::Infix(_)
+    )
 }
 
-pub fn eval_program(program: &Program, env: Rc<RefCell<Environment>>) -> Box<dyn Object> {
-    let mut result = Box::new(Null) as Box<dyn Object>;
+// 错误第一次经过的节点就是出错的地方，外层的节点不再覆盖
+fn locate(error: EvalError, node: &dyn Node) -> EvalError {
+    match (error, node.token()) {
+        (EvalError::Runtime(error), Some(token)) if error.line == 0 && token.line != 0 => {
+            EvalError::Runtime(Error {
+                span: node.span(),
+                line: token.line,
+                column: token.column,
+                ..error
+            })
+        }
+        (error, _) => error,
+    }
+}
+
+pub fn eval_program(program: &Program, env: Rc<RefCell<Environment>>) -> EvalResult {
+    let mut result = Rc::new(Value::Null(Null));
     for statement in program.statements.iter() {
-        result = eval(statement.as_node(), Rc::clone(&env));
-        if matches!(result.object_type(), ObjectType::ReturnValue) {
-            let return_object = result
-                .downcast::<object::ReturnValue>()
-                .map_err(|_| "Shouldn't happen.")
-                .unwrap();
-            return return_object.value;
-        } else if matches!(result.object_type(), ObjectType::Error) {
-            return result;
+        result = eval_node(statement.as_node(), Rc::clone(&env))?;
+        if matches!(result.as_ref(), Value::ReturnValue(_)) {
+            return unwrap_return_value(result);
         }
     }
-    result
+    Ok(result)
 }
 
+// 遇到 return 时把 ReturnValue 原样往外传，由函数调用或者 Program 拆开
 pub fn eval_block_statement(
     block_statement: &BlockStatement,
     env: Rc<RefCell<Environment>>,
-) -> Box<dyn Object> {
-    let mut result = Box::new(Null) as Box<dyn Object>;
+) -> EvalResult {
+    let mut result = Rc::new(Value::Null(Null));
     for statement in block_statement.statements.iter() {
-        result = eval(statement.as_node(), Rc::clone(&env));
-        if matches!(result.object_type(), ObjectType::ReturnValue)
-            || matches!(result.object_type(), ObjectType::Error)
-        {
-            return result;
+        result = eval_node(statement.as_node(), Rc::clone(&env))?;
+        if matches!(result.as_ref(), Value::ReturnValue(_)) {
+            return Ok(result);
         }
     }
-    result
+    Ok(result)
 }
 
-pub fn eval_prefix_expression(operator: &str, right: &dyn Object) -> Box<dyn Object> {
+pub fn eval_prefix_expression(operator: &str, right: &Value) -> EvalResult {
     match operator {
-        "!" => eval_bang_operator_expression(right),
+        "!" => Ok(eval_bang_operator_expression(right)),
         "-" => eval_minus_prefix_operator_expression(right),
-        _ => Box::new(object::Error {
-            message: format!("unknown operator: {}{:?}", operator, right.object_type()),
-        }),
+        _ => Err(runtime_error(
+            MessageId::UnknownPrefixOperator,
+            &[&operator, &right.object_type()],
+        )
+        .into()),
     }
 }
 
-pub fn eval_infix_expression(
-    left: &dyn Object,
-    operator: &str,
-    right: &dyn Object,
-) -> Box<dyn Object> {
-    if matches!(left.object_type(), ObjectType::Integer)
-        && matches!(right.object_type(), ObjectType::Integer)
-    {
-        let left_integer = left.downcast_ref::<Integer>().unwrap();
-        let right_integer = right.downcast_ref::<Integer>().unwrap();
-        eval_integer_infix_expression(left_integer, operator, right_integer)
-    } else if matches!(left.object_type(), ObjectType::Boolean)
-        && matches!(right.object_type(), ObjectType::Boolean)
-    {
-        let left_boolean = left.as_any().downcast_ref::<Boolean>().unwrap();
-        let right_boolean = right.as_any().downcast_ref::<Boolean>().unwrap();
-        eval_boolean_infix_expression(left_boolean, operator, right_boolean)
-    } else if matches!(left.object_type(), ObjectType::String)
-        && matches!(right.object_type(), ObjectType::String)
-    {
-        let left_string = left.as_any().downcast_ref::<StringObject>().unwrap();
-        let right_string = right.as_any().downcast_ref::<StringObject>().unwrap();
-        eval_string_infix_expression(left_string, operator, right_string)
-    } else if left.object_type() != right.object_type() {
-        Box::new(object::Error {
-            message: format!(
-                "type mismatch: {:?} {} {:?}",
-                left.object_type(),
-                operator,
-                right.object_type(),
-            ),
-        })
-    } else {
-        Box::new(object::Error {
-            message: format!(
-                "unknown operator: {:?} {} {:?}",
-                left.object_type(),
-                operator,
-                right.object_type()
-            ),
-        })
+pub fn eval_infix_expression(left: &Value, operator: &str, right: &Value) -> EvalResult {
+    match (left, right) {
+        // 同类型的值都可以比较相等，数组和哈希按结构比较
+        _ if matches!(operator, "==" | "!=") && left.object_type() == right.object_type() => {
+            let equal = left == right;
+            Ok(Rc::new(Value::Boolean(Boolean::from_native_bool(
+                equal == (operator == "=="),
+            ))))
+        }
+        (Value::Integer(left), Value::Integer(right)) => {
+            eval_integer_infix_expression(left, operator, right)
+        }
+        (Value::String(left), Value::String(right)) => {
+            eval_string_infix_expression(left, operator, right)
+        }
+        _ if left.object_type() != right.object_type() => Err(runtime_error(
+            MessageId::TypeMismatch,
+            &[&left.object_type(), &operator, &right.object_type()],
+        )
+        .into()),
+        _ => Err(runtime_error(
+            MessageId::UnknownInfixOperator,
+            &[&left.object_type(), &operator, &right.object_type()],
+        )
+        .into()),
     }
 }
 
 pub fn eval_expressions(
-    exps: &[Box<dyn Expression>],
+    exps: &[Expr],
     env: Rc<RefCell<Environment>>,
-) -> Vec<Box<dyn Object>> {
-    let mut results = Vec::new();
-    for exp in exps {
-        let object = eval(exp.as_node(), Rc::clone(&env));
-        if is_error(object.as_ref()) {
-            return vec![object];
-        }
-        results.push(object);
-    }
-    results
+) -> Result<Vec<Rc<Value>>, EvalError> {
+    exps.iter()
+        .map(|exp| eval_node(exp.as_node(), Rc::clone(&env)))
+        .collect()
 }
 
-pub fn eval_identifier(identifier: &Identifier, env: Rc<RefCell<Environment>>) -> Box<dyn Object> {
-    env.borrow()
-        .get(&identifier.value)
+pub fn eval_identifier(identifier: &Identifier, env: Rc<RefCell<Environment>>) -> EvalResult {
+    let env = env.borrow();
+    env.get(&identifier.value)
         .or_else(|| {
-            BUILTINS
-                .get(&*identifier.value) // https://stackoverflow.com/questions/65549983/trait-borrowstring-is-not-implemented-for-str
-                .map(|buildin| dyn_clone::clone_box(buildin) as Box<dyn Object>)
+            env.context()
+                .borrow()
+                .builtins
+                .get(&identifier.value)
+                .map(|builtin| Rc::new(Value::Builtin(builtin)))
         })
-        .unwrap_or(Box::new(object::Error {
-            message: format!("identifier not found: {}", identifier.value),
-        }))
+        .ok_or_else(|| runtime_error(MessageId::IdentifierNotFound, &[&identifier.value]).into())
 }
 
-pub fn eval_index_expression(left: &dyn Object, index: &dyn Object) -> Box<dyn Object> {
-    let left_type = left.object_type();
-    if matches!(left.object_type(), ObjectType::Array)
-        && matches!(index.object_type(), ObjectType::Integer)
-    {
-        let array = left.downcast_ref::<object::Array>().unwrap();
-        let index = index.downcast_ref::<object::Integer>().unwrap();
-        if array.elements.len() <= index.value as usize || index.value < 0 {
-            return Box::new(object::Null);
+pub fn eval_index_expression(left: &Value, index: &Value) -> EvalResult {
+    match (left, index) {
+        (Value::Array(array), Value::Integer(index)) => {
+            let element = usize::try_from(index.value)
+                .ok()
+                .and_then(|index| array.elements.get(index));
+            Ok(match element {
+                Some(element) => Rc::clone(element),
+                None => Rc::new(Value::Null(Null)),
+            })
         }
-
-        return dyn_clone::clone_box(array.elements[index.value as usize].as_ref());
-    } else if matches!(left.object_type(), ObjectType::Hash) {
-        let hash = left.downcast_ref::<object::Hash>().unwrap();
-        return eval_hash_index_expression(hash, index);
+        (Value::Bytes(bytes), Value::Integer(index)) => {
+            let byte = usize::try_from(index.value)
+                .ok()
+                .and_then(|index| bytes.value.get(index));
+            Ok(match byte {
+                Some(byte) => Rc::new(Value::Integer(Integer {
+                    value: i64::from(*byte),
+                })),
+                None => Rc::new(Value::Null(Null)),
+            })
+        }
+        (Value::Hash(hash), _) => eval_hash_index_expression(hash, index),
+        _ => Err(runtime_error(MessageId::IndexNotSupported, &[&left.object_type()]).into()),
     }
-
-    Box::new(object::Error {
-        message: format!("index operator not supported: {:?}", left_type),
-    })
 }
 
-pub fn eval_hash_literal(node: &HashLiteral, env: Rc<RefCell<Environment>>) -> Box<dyn Object> {
-    let mut pairs = HashMap::new();
+pub fn eval_hash_literal(node: &HashLiteral, env: Rc<RefCell<Environment>>) -> EvalResult {
+    let mut pairs = IndexMap::new();
     for (key, value) in node.pairs.iter() {
-        let evaluated_key = eval(key.as_node(), Rc::clone(&env));
-        if is_error(evaluated_key.as_ref()) {
-            return evaluated_key;
-        }
-        let evaluated_value = eval(value.as_node(), Rc::clone(&env));
-        if is_error(evaluated_value.as_ref()) {
-            return evaluated_value;
-        }
-        match evaluated_key.object_type() {
-            object::ObjectType::String => {
-                let str = evaluated_key
-                    .downcast::<object::StringObject>()
-                    .map_err(|_| "Shouldn't happen.")
-                    .unwrap();
-                pairs.insert(
-                    str.hash_key(),
-                    HashPair {
-                        key: str,
-                        value: evaluated_value,
-                    },
-                );
-            }
-            object::ObjectType::Integer => {
-                let integer = evaluated_key
-                    .downcast::<object::Integer>()
-                    .map_err(|_| "Shouldn't happen.")
-                    .unwrap();
-                pairs.insert(
-                    integer.hash_key(),
-                    HashPair {
-                        key: integer,
-                        value: evaluated_value,
-                    },
-                );
-            }
-            object::ObjectType::Boolean => {
-                let boolean = evaluated_key
-                    .downcast::<object::Boolean>()
-                    .map_err(|_| "Shouldn't happen.")
-                    .unwrap();
-                pairs.insert(
-                    boolean.hash_key(),
-                    HashPair {
-                        key: boolean,
-                        value: evaluated_value,
-                    },
-                );
-            }
-            _ => {
-                return Box::new(object::Error {
-                    message: format!("unusable as hash key: {:?}", evaluated_key.object_type()),
-                });
-            }
+        let key = eval_node(key.as_node(), Rc::clone(&env))?;
+        let value = eval_node(value.as_node(), Rc::clone(&env))?;
+        let Some(hash_key) = hash_key_of(key.as_ref()) else {
+            return Err(runtime_error(MessageId::UnusableHashKey, &[&key.object_type()]).into());
         };
+        pairs.insert(hash_key, HashPair { key, value });
     }
-    Box::new(object::Hash { pairs })
+    Ok(Rc::new(Value::Hash(object::Hash {
+        pairs,
+        frozen: false,
+    })))
 }
 
-pub fn is_truthy(object: &dyn Object) -> bool {
-    if let Some(object) = object.downcast_ref::<Boolean>() {
-        matches!(object, Boolean::True)
-    } else {
-        true
+// 按顺序由键值对组成哈希，字节码虚拟机创建哈希字面量时用
+pub fn build_hash(pairs: Vec<(Rc<Value>, Rc<Value>)>) -> EvalResult {
+    let mut hash_pairs = IndexMap::new();
+    for (key, value) in pairs {
+        let Some(hash_key) = hash_key_of(key.as_ref()) else {
+            return Err(runtime_error(MessageId::UnusableHashKey, &[&key.object_type()]).into());
+        };
+        hash_pairs.insert(hash_key, HashPair { key, value });
     }
+    Ok(Rc::new(Value::Hash(object::Hash {
+        pairs: hash_pairs,
+        frozen: false,
+    })))
+}
+
+pub fn is_truthy(object: &Value) -> bool {
+    !matches!(object, Value::Boolean(Boolean::False))
+}
+
+pub fn is_error(object: &Value) -> bool {
+    matches!(object, Value::Error(_))
 }
 
-pub fn is_error(object: &dyn Object) -> bool {
-    matches!(object.object_type(), ObjectType::Error)
+// 错误和 exit() 都会中断求值，内置函数拿到这样的对象时要原样往外传
+pub fn is_abrupt(object: &Value) -> bool {
+    matches!(object, Value::Error(_) | Value::Exit(_))
 }
 
-pub fn apply_function(func: &dyn Object, args: &[Box<dyn Object>]) -> Box<dyn Object> {
-    let func_type = func.object_type();
-    match func.object_type() {
-        ObjectType::Function => {
-            let f = func.downcast_ref::<object::Function>().unwrap();
-            let env = extend_function_env(f, args);
-            let object = eval(f.body.as_node(), Rc::new(RefCell::new(env)));
-            unwrap_return_value(object)
+// env 是调用处的环境，内置函数通过它访问运行时状态或者回调用户函数
+pub fn apply_function(
+    func: &Value,
+    args: &[Rc<Value>],
+    env: Rc<RefCell<Environment>>,
+) -> EvalResult {
+    match func {
+        Value::Function(f) => {
+            if args.len() != f.parameters.len() {
+                return Err(runtime_error(
+                    MessageId::WrongNumberOfArguments,
+                    &[&args.len(), &f.parameters.len()],
+                )
+                .into());
+            }
+            let context = env.borrow().context();
+            context.borrow_mut().stats.function_calls += 1;
+            let started = Instant::now();
+            context.borrow_mut().enter_call()?;
+            let env = Rc::new(RefCell::new(extend_function_env(f, args)));
+            let result = eval_node(f.body.as_node(), Rc::clone(&env));
+            if !Environment::release(&env) {
+                gc::track(&context, &env);
+            }
+            let mut runtime = context.borrow_mut();
+            runtime.exit_call();
+            if runtime.options.profile {
+                runtime.profile.record_call(f, started.elapsed());
+            }
+            drop(runtime);
+            unwrap_return_value(result?)
         }
-        ObjectType::Builtin => {
-            let f = func.downcast_ref::<object::Builtin>().unwrap();
-            let args = args.iter().map(Box::as_ref).collect::<Vec<_>>();
-            (f.func)(&args)
+        Value::Closure(closure) => crate::vm::call_closure(closure, args, env),
+        Value::Builtin(f) => {
+            let context = EvalContext::new(env);
+            context.runtime().stats.builtin_calls += 1;
+            let object = EvalError::from_object((f.func)(&context, args))?;
+            context.runtime().allocate(object.as_ref(), args)?;
+            Ok(object)
         }
-        _ => Box::new(object::Error {
-            message: format!("not a function: {:?}", func_type),
-        }),
+        _ => Err(runtime_error(MessageId::NotAFunction, &[&func.object_type()]).into()),
     }
 }
 
-fn extend_function_env(func: &object::Function, args: &[Box<dyn Object>]) -> Environment {
-    let mut enclosed_env = Environment::new_enclosed(Rc::downgrade(&func.env));
+// eval(source) 在调用处的环境里执行源码，eval(source, true) 在一个隔离的子环境里执行，
+// 新的绑定不会留在调用处
+pub fn eval_source(args: &[Rc<Value>], env: Rc<RefCell<Environment>>) -> Rc<Value> {
+    let (source, isolated) = match args {
+        [source] => (source, false),
+        [source, isolated] => (source, is_truthy(isolated.as_ref())),
+        _ => {
+            return Rc::new(Value::Error(runtime_error(
+                MessageId::WrongNumberOfArguments,
+                &[&args.len(), &"1 or 2"],
+            )))
+        }
+    };
+    let Value::String(source) = source.as_ref() else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::ArgumentMustBe,
+            &[&"eval", &"String", &source.object_type()],
+        )));
+    };
 
-    for (index, param) in func.parameters.iter().enumerate() {
-        enclosed_env.set(
-            param.value.clone(),
-            dyn_clone::clone_box(args[index].as_ref()),
-        );
+    let mut program = match Parser::new(Lexer::new(source.value.clone())).parse() {
+        Ok(program) => program,
+        Err(errors) => {
+            return Rc::new(Value::Error(runtime_error(
+                MessageId::EvalParseFailed,
+                &[&join_parse_errors(&errors)],
+            )))
+        }
+    };
+    // 宏体受调用处的限制和沙盒约束，程序里定义的宏也能用，源码里定义的宏不会留到程序里
+    let context = env.borrow().context();
+    let macro_env = macro_environment(&context.borrow());
+    let macros = context.borrow().macros.clone();
+    if let Some(macros) = macros {
+        for (name, value) in macros.borrow().visible() {
+            macro_env.borrow_mut().set(name, value);
+        }
+    }
+    define_macros(&mut program, Rc::clone(&macro_env));
+    if let Err(error) = expand_macro(&mut program, macro_env) {
+        return Rc::new(Value::Error(error));
     }
 
-    enclosed_env
+    let env = if isolated {
+        Rc::new(RefCell::new(Environment::new_enclosed(Rc::clone(&env))))
+    } else {
+        env
+    };
+    eval_node(program.as_node(), env).unwrap_or_else(EvalError::into_object)
+}
+
+// 在已有的环境里求值一个表达式，比如调试时的监视表达式或者 REPL 的 `:type`
+// 在子环境里执行，表达式不能引入新的绑定，也不会修改 env 里已有的绑定
+pub fn eval_expression_in(source: &str, env: &Rc<RefCell<Environment>>) -> Rc<Value> {
+    let program = match Parser::new(Lexer::new(source.to_owned())).parse() {
+        Ok(program) => program,
+        Err(errors) => {
+            return Rc::new(Value::Error(runtime_error(
+                MessageId::ExpressionParseFailed,
+                &[&join_parse_errors(&errors)],
+            )))
+        }
+    };
+    let expression = match program.statements.as_slice() {
+        [Stmt::Expression(statement)] => Some(statement.expression.as_node()),
+        _ => None,
+    };
+    let Some(expression) = expression else {
+        return Rc::new(Value::Error(runtime_error(
+            MessageId::NotAnExpression,
+            &[&source.trim()],
+        )));
+    };
+
+    let scope = Rc::new(RefCell::new(Environment::new_enclosed(Rc::clone(env))));
+    eval_node(expression, scope).unwrap_or_else(EvalError::into_object)
 }
 
-fn unwrap_return_value(object: Box<dyn Object>) -> Box<dyn Object> {
-    if matches!(object.object_type(), ObjectType::ReturnValue) {
-        return object
-            .downcast::<object::ReturnValue>()
-            .map_err(|_| "Shouldn't happen.")
-            .unwrap();
+fn extend_function_env(func: &object::Function, args: &[Rc<Value>]) -> Environment {
+    let mut enclosed_env = Environment::new_enclosed(Rc::clone(&func.env));
+
+    for (param, arg) in func.parameters.iter().zip(args) {
+        enclosed_env.set(param.value.clone(), Rc::clone(arg));
     }
 
-    object
+    enclosed_env
 }
 
-fn eval_bang_operator_expression(right: &dyn Object) -> Box<dyn Object> {
-    if is_truthy(right) {
-        Box::new(Boolean::False)
-    } else {
-        Box::new(Boolean::True)
+fn unwrap_return_value(object: Rc<Value>) -> EvalResult {
+    match object.as_ref() {
+        Value::ReturnValue(return_value) => Ok(Rc::clone(&return_value.value)),
+        _ => Ok(object),
     }
 }
 
-fn eval_minus_prefix_operator_expression(right: &dyn Object) -> Box<dyn Object> {
-    let object_type = right.object_type();
-    if let Some(integer) = right.downcast_ref::<Integer>() {
-        Box::new(Integer {
-            value: -integer.value,
-        })
+fn eval_bang_operator_expression(right: &Value) -> Rc<Value> {
+    if is_truthy(right) {
+        Rc::new(Value::Boolean(Boolean::False))
     } else {
-        Box::new(object::Error {
-            message: format!("unknown operator: -{:?}", object_type),
-        })
+        Rc::new(Value::Boolean(Boolean::True))
     }
 }
 
-fn eval_integer_infix_expression(
-    left: &Integer,
-    operator: &str,
-    right: &Integer,
-) -> Box<dyn Object> {
-    match operator {
-        "+" => Box::new(Integer {
-            value: left.value + right.value,
-        }),
-        "-" => Box::new(Integer {
-            value: left.value - right.value,
-        }),
-        "*" => Box::new(Integer {
-            value: left.value * right.value,
-        }),
-        "/" => Box::new(Integer {
-            value: left.value / right.value,
-        }),
-        "<" => Box::new(Boolean::from_native_bool(left.value < right.value)),
-        ">" => Box::new(Boolean::from_native_bool(left.value > right.value)),
-        "==" => Box::new(Boolean::from_native_bool(left.value == right.value)),
-        "!=" => Box::new(Boolean::from_native_bool(left.value != right.value)),
-        _ => Box::new(object::Error {
-            message: format!(
-                "unknown operator: {:?} {} {:?}",
-                left.object_type(),
-                operator,
-                right.object_type()
-            ),
-        }),
+fn eval_minus_prefix_operator_expression(right: &Value) -> EvalResult {
+    match right {
+        Value::Integer(integer) => match integer.value.checked_neg() {
+            Some(value) => Ok(Rc::new(Value::Integer(Integer { value }))),
+            None => Err(runtime_error(
+                MessageId::IntegerOverflow,
+                &[&format!("-({})", integer.value)],
+            )
+            .into()),
+        },
+        _ => Err(runtime_error(
+            MessageId::UnknownPrefixOperator,
+            &[&"-", &right.object_type()],
+        )
+        .into()),
     }
 }
 
-fn eval_boolean_infix_expression(
-    left: &Boolean,
-    operator: &str,
-    right: &Boolean,
-) -> Box<dyn Object> {
-    match operator {
-        "==" => Box::new(Boolean::from_native_bool(left == right)),
-        "!=" => Box::new(Boolean::from_native_bool(left != right)),
-        _ => Box::new(object::Error {
-            message: format!(
-                "unknown operator: {:?} {} {:?}",
-                left.object_type(),
-                operator,
-                right.object_type()
-            ),
-        }),
+fn eval_integer_infix_expression(left: &Integer, operator: &str, right: &Integer) -> EvalResult {
+    let (left, right) = (left.value, right.value);
+    let value = match operator {
+        "+" => left.checked_add(right),
+        "-" => left.checked_sub(right),
+        "*" => left.checked_mul(right),
+        "/" if right == 0 => {
+            return Err(runtime_error(MessageId::DivisionByZero, &[&left]).into());
+        }
+        "/" => left.checked_div(right),
+        "<" => {
+            return Ok(Rc::new(Value::Boolean(Boolean::from_native_bool(
+                left < right,
+            ))))
+        }
+        ">" => {
+            return Ok(Rc::new(Value::Boolean(Boolean::from_native_bool(
+                left > right,
+            ))))
+        }
+        _ => {
+            return Err(runtime_error(
+                MessageId::UnknownInfixOperator,
+                &[&ObjectType::Integer, &operator, &ObjectType::Integer],
+            )
+            .into())
+        }
+    };
+    // 溢出时报错，而不是在 debug 构建里 panic
+    match value {
+        Some(value) => Ok(Rc::new(Value::Integer(Integer { value }))),
+        None => Err(runtime_error(
+            MessageId::IntegerOverflow,
+            &[&format!("{} {} {}", left, operator, right)],
+        )
+        .into()),
     }
 }
 
@@ -346,44 +467,37 @@ fn eval_string_infix_expression(
     left: &StringObject,
     operator: &str,
     right: &StringObject,
-) -> Box<dyn Object> {
+) -> EvalResult {
     match operator {
-        "+" => Box::new(StringObject {
+        "+" => Ok(Rc::new(Value::String(StringObject {
             value: left.value.clone() + &right.value,
-        }),
-        _ => Box::new(object::Error {
-            message: format!(
-                "unknown operator: {:?} {} {:?}",
-                left.object_type(),
-                operator,
-                right.object_type()
-            ),
-        }),
+        }))),
+        _ => Err(runtime_error(
+            MessageId::UnknownInfixOperator,
+            &[&left.object_type(), &operator, &right.object_type()],
+        )
+        .into()),
     }
 }
 
-fn eval_hash_index_expression(hash: &object::Hash, index: &dyn Object) -> Box<dyn Object> {
-    let hash_key = match index.object_type() {
-        ObjectType::String => {
-            let str = index.downcast_ref::<object::StringObject>().unwrap();
-            str.hash_key()
-        }
-        ObjectType::Integer => {
-            let integer = index.downcast_ref::<object::Integer>().unwrap();
-            integer.hash_key()
-        }
-        ObjectType::Boolean => {
-            let boolean = index.downcast_ref::<object::Boolean>().unwrap();
-            boolean.hash_key()
-        }
-        _ => {
-            return Box::new(object::Error {
-                message: format!("unusable as hash key: {:?}", index.object_type()),
-            });
-        }
+// 字符串、整数、布尔值，以及元素都能作为键的数组可以作为哈希的键
+pub fn hash_key_of(object: &Value) -> Option<HashKey> {
+    match object {
+        Value::String(string) => Some(string.hash_key()),
+        Value::Integer(integer) => Some(integer.hash_key()),
+        Value::Boolean(boolean) => Some(boolean.hash_key()),
+        Value::Array(array) => array.hash_key(),
+        _ => None,
+    }
+}
+
+fn eval_hash_index_expression(hash: &object::Hash, index: &Value) -> EvalResult {
+    let Some(hash_key) = hash_key_of(index) else {
+        return Err(runtime_error(MessageId::UnusableHashKey, &[&index.object_type()]).into());
     };
-    hash.pairs
+    Ok(hash
+        .pairs
         .get(&hash_key)
-        .map(|pair| dyn_clone::clone_box(pair.value.as_ref()))
-        .unwrap_or(Box::new(object::Null))
+        .map(|pair| Rc::clone(&pair.value))
+        .unwrap_or(Rc::new(Value::Null(Null))))
 }
@@ -0,0 +1,226 @@
+// 环境的循环引用回收器
+// 函数对象强引用定义它的环境，环境的绑定又强引用函数对象。闭包逃出了函数调用时 Environment::release
+// 清不掉这样的环，之后闭包不再被用到，环上的环境和值也不会释放
+// 这里用试探删除找出这些环：从跟踪的环境出发，把环境、函数、宏、数组和哈希之间的强引用连成一张图，
+// 每个节点的强引用数减去图里指向它的边，剩下的来自图外面，比如 Rust 的局部变量、宿主和任务队列
+// 有外部引用的节点和从它们出发能访问到的节点都还在用，其余的环境清空绑定，环上的 Rc 随之释放
+// 通道、任务、迭代器和虚拟机闭包里的引用不会被减去，被它们引用的节点一律当作还在用，宁可泄漏也不能清错
+use std::collections::HashMap;
+
+use super::context::RuntimeContext;
+use super::environment::Environment;
+use super::object::Value;
+use crate::sync::{Rc, RefCell, Weak};
+
+// 跟踪的环境达到这么多个时自动回收一次，回收之后阈值变成剩下的个数的两倍
+const MIN_THRESHOLD: usize = 1000;
+
+// 函数调用结束时没能被 release 清空的环境，它们可能在环上
+pub struct Heap {
+    envs: Vec<Weak<RefCell<Environment>>>,
+    threshold: usize,
+    collected: usize,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self {
+            envs: vec![],
+            threshold: MIN_THRESHOLD,
+            collected: 0,
+        }
+    }
+}
+
+impl Heap {
+    // 跟踪的环境里还没有释放的个数
+    pub fn len(&self) -> usize {
+        self.envs
+            .iter()
+            .filter(|env| env.strong_count() > 0)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // 到目前为止一共清空了多少个环境
+    pub fn collected(&self) -> usize {
+        self.collected
+    }
+}
+
+// 开始跟踪 env，跟踪的环境够多时顺便回收一次
+pub(crate) fn track(runtime: &Rc<RefCell<RuntimeContext>>, env: &Rc<RefCell<Environment>>) {
+    let mut context = runtime.borrow_mut();
+    context.heap.envs.push(Rc::downgrade(env));
+    let due = context.heap.envs.len() >= context.heap.threshold;
+    drop(context);
+    if due {
+        collect(runtime, &[]);
+    }
+}
+
+// 回收跟踪的环境里已经不可达的部分，返回清空了多少个环境
+// owned 是调用方马上要放弃的环境，也参与回收，调用方手里的那一个引用不算外部引用
+pub fn collect(
+    runtime: &Rc<RefCell<RuntimeContext>>,
+    owned: &[&Rc<RefCell<Environment>>],
+) -> usize {
+    let tracked = std::mem::take(&mut runtime.borrow_mut().heap.envs);
+    let mut graph = Graph::default();
+    for env in tracked.iter().filter_map(Weak::upgrade) {
+        graph.add(Object::Env(env));
+    }
+    for env in owned {
+        let index = graph.add(Object::Env(Rc::clone(env)));
+        graph.nodes[index].internal += 1;
+    }
+    graph.scan();
+    let collected = graph.sweep();
+    // 图里的引用放掉以后，清空了的环境才真正释放
+    drop(graph);
+    let mut context = runtime.borrow_mut();
+    let heap = &mut context.heap;
+    heap.envs
+        .extend(tracked.into_iter().filter(|env| env.strong_count() > 0));
+    heap.threshold = MIN_THRESHOLD.max(heap.envs.len() * 2);
+    heap.collected += collected;
+    collected
+}
+
+enum Object {
+    Env(Rc<RefCell<Environment>>),
+    Value(Rc<Value>),
+}
+
+impl Object {
+    // 同一个对象只在图里出现一次，按地址区分
+    fn address(&self) -> usize {
+        match self {
+            Object::Env(env) => Rc::as_ptr(env) as *const () as usize,
+            Object::Value(value) => Rc::as_ptr(value) as *const () as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Object::Env(env) => Rc::strong_count(env),
+            Object::Value(value) => Rc::strong_count(value),
+        }
+    }
+
+    // 这个对象直接强引用的环境和可能引用环境的值，环境被借出了读不到时返回 None
+    fn children(&self) -> Option<Vec<Object>> {
+        let mut children = vec![];
+        let mut push_value = |value: &Rc<Value>| {
+            if matches!(
+                value.as_ref(),
+                Value::Function(_) | Value::Macro(_) | Value::Array(_) | Value::Hash(_)
+            ) {
+                children.push(Object::Value(Rc::clone(value)));
+            }
+        };
+        match self {
+            Object::Env(env) => {
+                let env = env.try_borrow().ok()?;
+                env.iter().for_each(|(_, value)| push_value(value));
+                children.extend(env.outer().map(Object::Env));
+            }
+            Object::Value(value) => match value.as_ref() {
+                Value::Function(function) => children.push(Object::Env(Rc::clone(&function.env))),
+                Value::Macro(macro_object) => {
+                    children.push(Object::Env(Rc::clone(&macro_object.env)))
+                }
+                Value::Array(array) => array.elements.iter().for_each(push_value),
+                Value::Hash(hash) => hash.pairs.values().for_each(|pair| {
+                    push_value(&pair.key);
+                    push_value(&pair.value);
+                }),
+                _ => {}
+            },
+        }
+        Some(children)
+    }
+}
+
+struct Node {
+    object: Object,
+    // 图里指向这个节点的边数
+    internal: usize,
+    edges: Vec<usize>,
+    // 环境被借出、没能读到它的绑定时为 false
+    scanned: bool,
+}
+
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Node>,
+    indices: HashMap<usize, usize>,
+}
+
+impl Graph {
+    fn add(&mut self, object: Object) -> usize {
+        let next = self.nodes.len();
+        let index = *self.indices.entry(object.address()).or_insert(next);
+        if index == next {
+            self.nodes.push(Node {
+                object,
+                internal: 0,
+                edges: vec![],
+                scanned: false,
+            });
+        }
+        index
+    }
+
+    // 从已有的节点出发找出所有能访问到的节点和它们之间的边
+    fn scan(&mut self) {
+        let mut pending = (0..self.nodes.len()).collect::<Vec<_>>();
+        while let Some(index) = pending.pop() {
+            let Some(children) = self.nodes[index].object.children() else {
+                continue;
+            };
+            self.nodes[index].scanned = true;
+            for child in children {
+                let next = self.nodes.len();
+                let child = self.add(child);
+                if child == next {
+                    pending.push(child);
+                }
+                self.nodes[child].internal += 1;
+                self.nodes[index].edges.push(child);
+            }
+        }
+    }
+
+    // 除了图里的边和 Graph 自己持有的一个引用之外还有引用的节点，是标记的起点
+    fn is_root(node: &Node) -> bool {
+        !node.scanned || node.object.strong_count() > node.internal + 1
+    }
+
+    fn sweep(&self) -> usize {
+        let mut marked = vec![false; self.nodes.len()];
+        let mut pending = (0..self.nodes.len())
+            .filter(|index| Self::is_root(&self.nodes[*index]))
+            .collect::<Vec<_>>();
+        while let Some(index) = pending.pop() {
+            if !std::mem::replace(&mut marked[index], true) {
+                pending.extend(&self.nodes[index].edges);
+            }
+        }
+        let mut collected = 0;
+        for (node, _) in self.nodes.iter().zip(marked).filter(|(_, marked)| !marked) {
+            if let Object::Env(env) = &node.object {
+                if let Ok(mut env) = env.try_borrow_mut() {
+                    if env.iter().next().is_some() {
+                        env.clear();
+                        collected += 1;
+                    }
+                }
+            }
+        }
+        collected
+    }
+}
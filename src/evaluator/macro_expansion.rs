@@ -1,64 +1,208 @@
-use std::{cell::RefCell, rc::Rc};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 
 use crate::ast::{
-    expressions::{CallExpression, Identifier, MacroLiteral},
-    modify::modify,
+    expressions::{CallExpression, Expr, Identifier},
+    modify::{fold_block, fold_expression, fold_statements, Fold},
     program::Program,
-    statements::LetStatement,
-    traits::{AsNode, Node, Statement},
+    statements::{BlockStatement, Stmt},
+    traits::{AsNode, Node},
+    walk::{children, NodeRef},
 };
 
 use super::{
+    context::RuntimeContext,
     environment::Environment,
     eval::eval,
-    object::{Macro, Quote},
+    object::{Error, Macro, Quote, Value},
 };
+use crate::errors::{runtime_error, MessageId};
+use crate::sync::{Rc, RefCell};
+
+type Renames = HashMap<Rc<str>, Rc<str>>;
+
+// 宏展开用的根环境，上下文由 RuntimeContext::for_macros 从调用方的上下文得到，宏体受同样的限制和沙盒约束
+pub fn macro_environment(context: &RuntimeContext) -> Rc<RefCell<Environment>> {
+    Rc::new(RefCell::new(Environment::with_context(Rc::new(
+        RefCell::new(context.for_macros()),
+    ))))
+}
 
 pub fn define_macros(program: &mut Program, env: Rc<RefCell<Environment>>) {
-    let mut macro_indices = vec![];
-    for (i, statement) in program.statements.iter().enumerate() {
-        if is_macro_definiation(statement.as_ref()) {
-            macro_indices.push(i);
-            add_macro(statement.as_ref(), Rc::clone(&env));
+    define_scope_macros(&mut program.statements, &env);
+}
+
+// 展开的结果里还有宏调用时继续展开，超过 Limits::max_macro_depth 层时返回错误，错误里带着展开的链条
+// 代码块（函数体、if 的分支）里定义的宏只在这个代码块里可以用
+pub fn expand_macro(program: &mut Program, env: Rc<RefCell<Environment>>) -> Result<(), Error> {
+    let mut expander = Expander::new(env);
+    program.statements = fold_statements(&mut expander, std::mem::take(&mut program.statements));
+    expander.finish(())
+}
+
+// macroexpand 用，只展开一个表达式
+pub fn expand_expression(expression: Expr, env: Rc<RefCell<Environment>>) -> Result<Expr, Error> {
+    let mut expander = Expander::new(env);
+    let expanded = expander.fold_expression(expression);
+    expander.finish(expanded)
+}
+
+// 宏定义从语句里拿走，按顺序放进 env
+fn define_scope_macros(statements: &mut Vec<Stmt>, env: &Rc<RefCell<Environment>>) {
+    statements.retain(|statement| {
+        if is_macro_definiation(statement) {
+            add_macro(statement, Rc::clone(env));
+            return false;
+        }
+        true
+    });
+}
+
+// env 是当前代码块能看到的宏，chain 是外层正在展开的宏的名字
+struct Expander {
+    env: Rc<RefCell<Environment>>,
+    chain: Vec<Rc<str>>,
+    error: Option<Error>,
+}
+
+impl Expander {
+    fn new(env: Rc<RefCell<Environment>>) -> Self {
+        Expander {
+            env,
+            chain: vec![],
+            error: None,
         }
     }
 
-    for index in macro_indices.iter().rev() {
-        program.statements.remove(*index);
+    fn finish<T>(self, node: T) -> Result<T, Error> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(node),
+        }
     }
-}
 
-pub fn expand_macro(program: &mut Program, env: Rc<RefCell<Environment>>) -> Box<dyn Node> {
-    modify(program, &|node| {
-        if let Some(call_exp) = node.downcast_ref::<CallExpression>() {
-            if let Some(macro_object) = is_macro_call(call_exp, Rc::clone(&env)) {
-                let args = quote_args(call_exp);
-                let eval_env = extend_macro_env(&macro_object, args);
-                let node = eval(macro_object.body.as_node(), Rc::new(RefCell::new(eval_env)));
-                if let Some(quote) = node.downcast_ref::<Quote>() {
-                    return dyn_clone::clone_box(quote.node.as_ref());
-                }
+    // 宏调用的参数原样交给宏，不先展开；普通调用展开函数和参数里的宏调用
+    fn expand_call(&mut self, call_exp: CallExpression) -> Expr {
+        if self.error.is_some() {
+            return Expr::Call(call_exp);
+        }
+        let Some((name, macro_object)) = is_macro_call(&call_exp, Rc::clone(&self.env)) else {
+            return fold_expression(self, Expr::Call(call_exp));
+        };
+        let context = self.env.borrow().context();
+        let max_depth = context.borrow().limits.max_macro_depth;
+        if self.chain.len() >= max_depth {
+            let names = self
+                .chain
+                .iter()
+                .map(|name| name.as_ref())
+                .chain([name.as_ref()])
+                .collect::<Vec<_>>();
+            self.error = Some(runtime_error(
+                MessageId::MacroExpansionTooDeep,
+                &[&max_depth, &names.join(" -> ")],
+            ));
+            return Expr::Call(call_exp);
+        }
+        let args = quote_args(&call_exp);
+        let eval_env = extend_macro_env(&macro_object, args);
+        let body = if context.borrow().options.hygiene {
+            hygienic(&macro_object.body, &context)
+        } else {
+            macro_object.body.clone()
+        };
+        let expanded = eval(body.as_node(), Rc::new(RefCell::new(eval_env)));
+        let quote = match expanded.as_ref() {
+            Value::Quote(quote) => quote,
+            // 宏体求值出错，比如超出了步数限制，展开到此为止
+            Value::Error(macro_error) => {
+                self.error = Some(macro_error.clone());
+                return Expr::Call(call_exp);
             }
+            _ => return Expr::Call(call_exp),
+        };
+        self.chain.push(name);
+        let expanded = self.fold_expression(quote.node.clone());
+        self.chain.pop();
+        // 错误的位置是程序里最外层的宏调用，里层的调用来自宏体
+        if self.chain.is_empty() {
+            if let Some(error) = self.error.as_mut() {
+                locate(error, &call_exp);
+            }
+        }
+        expanded
+    }
+}
+
+impl Fold for Expander {
+    // quote、unquote 和 unquote_splice 里的代码等到它们求值时才处理，不进去
+    fn fold_expression(&mut self, expression: Expr) -> Expr {
+        match expression {
+            Expr::Call(call) => self.expand_call(call),
+            expression @ (Expr::Macro(_)
+            | Expr::Quote(_)
+            | Expr::Unquote(_)
+            | Expr::UnquoteSplice(_)) => expression,
+            expression => fold_expression(self, expression),
+        }
+    }
+
+    // 先定义整个代码块里的宏再展开，所以定义之前的调用也能展开
+    fn fold_block(&mut self, mut block: BlockStatement) -> BlockStatement {
+        if !block.statements.iter().any(is_macro_definiation) {
+            return fold_block(self, block);
+        }
+        let scope = Rc::new(RefCell::new(Environment::new_enclosed(Rc::clone(
+            &self.env,
+        ))));
+        define_scope_macros(&mut block.statements, &scope);
+        let outer = std::mem::replace(&mut self.env, scope);
+        let block = fold_block(self, block);
+        self.env = outer;
+        block
+    }
+}
+
+fn locate(error: &mut Error, call_exp: &CallExpression) {
+    if let Some(token) = call_exp.function.token() {
+        if error.line == 0 {
+            error.span = call_exp.span();
+            error.line = token.line;
+            error.column = token.column;
         }
-        node
-    })
+    }
 }
 
-fn is_macro_definiation(statement: &dyn Statement) -> bool {
-    if let Some(let_statement) = statement.downcast_ref::<LetStatement>() {
-        let_statement.value.downcast_ref::<MacroLiteral>().is_some()
-    } else {
-        false
+// 程序里所有用 let 定义的宏的名字，包括代码块里的，不区分作用域
+// 静态检查用它认出宏调用，宏调用的参数是代码而不是值
+pub fn macro_names(program: &Program) -> HashSet<Rc<str>> {
+    fn collect(node: &dyn Node, names: &mut HashSet<Rc<str>>) {
+        if let NodeRef::Let(let_statement) = node.node_ref() {
+            if matches!(*let_statement.value, Expr::Macro(_)) {
+                names.insert(Rc::clone(&let_statement.name.value));
+            }
+        }
+        for child in children(node) {
+            collect(child, names);
+        }
     }
+    let mut names = HashSet::new();
+    collect(program, &mut names);
+    names
+}
+
+fn is_macro_definiation(statement: &Stmt) -> bool {
+    matches!(statement, Stmt::Let(let_statement) if matches!(*let_statement.value, Expr::Macro(_)))
 }
 
-fn add_macro(statement: &dyn Statement, env: Rc<RefCell<Environment>>) {
-    if let Some(let_statement) = statement.downcast_ref::<LetStatement>() {
-        if let Some(macro_literal) = let_statement.value.downcast_ref::<MacroLiteral>() {
+fn add_macro(statement: &Stmt, env: Rc<RefCell<Environment>>) {
+    if let Stmt::Let(let_statement) = statement {
+        if let Expr::Macro(macro_literal) = let_statement.value.as_ref() {
             // 教程里面没有通过 eval 方法，因为默认 eval_to_object 的调用阶段是在求值阶段。我这里只是为了保持统一。
-            let macro_object = macro_literal.eval_to_object(Rc::clone(&env));
-            env.borrow_mut()
-                .set(let_statement.name.string(), macro_object);
+            if let Ok(macro_object) = macro_literal.eval_to_object(Rc::clone(&env)) {
+                env.borrow_mut()
+                    .set(Rc::clone(&let_statement.name.value), macro_object);
+            }
         }
     }
 }
@@ -66,10 +210,10 @@ fn add_macro(statement: &dyn Statement, env: Rc<RefCell<Environment>>) {
 fn is_macro_call(
     call_expression: &CallExpression,
     env: Rc<RefCell<Environment>>,
-) -> Option<Box<Macro>> {
-    if let Some(ident) = call_expression.function.downcast_ref::<Identifier>() {
-        if let Some(obj) = env.borrow().get(&ident.string()) {
-            return obj.downcast::<Macro>().ok();
+) -> Option<(Rc<str>, Macro)> {
+    if let Expr::Identifier(ident) = call_expression.function.as_ref() {
+        if let Some(Value::Macro(macro_object)) = env.borrow().get(&ident.value).as_deref() {
+            return Some((Rc::clone(&ident.value), macro_object.clone()));
         }
     }
     None
@@ -78,17 +222,96 @@ fn is_macro_call(
 fn quote_args(call_expression: &CallExpression) -> Vec<Quote> {
     let mut args = vec![];
     for arg in call_expression.arguments.iter() {
-        args.push(Quote {
-            node: dyn_clone::clone_box(arg.as_node()),
-        })
+        args.push(Quote { node: arg.clone() })
     }
     args
 }
 
 fn extend_macro_env(macro_object: &Macro, args: Vec<Quote>) -> Environment {
-    let mut env = Environment::new_enclosed(Rc::downgrade(&macro_object.env));
-    for (i, arg) in args.into_iter().enumerate() {
-        env.set(macro_object.parameters[i].string(), Box::new(arg));
+    let mut env = Environment::new_enclosed(Rc::clone(&macro_object.env));
+    for (param, arg) in macro_object.parameters.iter().zip(args) {
+        env.set(Rc::clone(&param.value), Rc::new(Value::Quote(arg)));
     }
     env
 }
+
+// 宏体里 quote 中的代码引入的绑定（函数参数和 let 的名字）在每次展开时换成 gensym 生成的新名字
+// 调用处的代码通过 unquote 插入，不会被改名，也就不会被宏引入的绑定捕获
+fn hygienic(body: &BlockStatement, context: &Rc<RefCell<RuntimeContext>>) -> BlockStatement {
+    let mut names = vec![];
+    collect_bindings(body.as_node(), false, &mut names);
+    if names.is_empty() {
+        return body.clone();
+    }
+    let mut renames = Renames::new();
+    for name in names {
+        if let Entry::Vacant(entry) = renames.entry(name) {
+            let renamed = context.borrow_mut().gensym(entry.key());
+            entry.insert(renamed);
+        }
+    }
+    let mut renamer = Renamer {
+        renames: &renames,
+        quoted: false,
+    };
+    renamer.fold_block(body.clone())
+}
+
+// quoted 表示在 quote 里面，unquote 里又回到宏自己的代码
+fn collect_bindings(node: &dyn Node, quoted: bool, names: &mut Vec<Rc<str>>) {
+    let quoted = match node.node_ref() {
+        NodeRef::Quote(_) => true,
+        NodeRef::Unquote(_) | NodeRef::UnquoteSplice(_) => false,
+        NodeRef::Function(function) if quoted => {
+            names.extend(function.parameters.iter().map(|p| Rc::clone(&p.value)));
+            quoted
+        }
+        NodeRef::Let(let_statement) if quoted => {
+            names.push(Rc::clone(&let_statement.name.value));
+            quoted
+        }
+        _ => quoted,
+    };
+    for child in children(node) {
+        collect_bindings(child, quoted, names);
+    }
+}
+
+// quoted 表示在 quote 里面，unquote 里又回到宏自己的代码
+struct Renamer<'a> {
+    renames: &'a Renames,
+    quoted: bool,
+}
+
+impl Fold for Renamer<'_> {
+    fn fold_expression(&mut self, expression: Expr) -> Expr {
+        let quoted = match expression {
+            Expr::Identifier(identifier) if self.quoted => {
+                return Expr::Identifier(rename_identifier(identifier, self.renames));
+            }
+            Expr::Quote(_) => true,
+            Expr::Unquote(_) | Expr::UnquoteSplice(_) => false,
+            _ => self.quoted,
+        };
+        let outer = std::mem::replace(&mut self.quoted, quoted);
+        let expression = fold_expression(self, expression);
+        self.quoted = outer;
+        expression
+    }
+
+    fn fold_binding(&mut self, identifier: Identifier) -> Identifier {
+        if self.quoted {
+            rename_identifier(identifier, self.renames)
+        } else {
+            identifier
+        }
+    }
+}
+
+fn rename_identifier(mut identifier: Identifier, renames: &Renames) -> Identifier {
+    if let Some(renamed) = renames.get(&identifier.value) {
+        identifier.token.literal = Rc::clone(renamed);
+        identifier.value = Rc::clone(renamed);
+    }
+    identifier
+}
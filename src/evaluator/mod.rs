@@ -1,4 +1,13 @@
+pub mod builtins;
+pub mod context;
+pub mod convert;
 pub mod environment;
 pub mod eval;
+pub mod gc;
 pub mod macro_expansion;
+pub mod module;
 pub mod object;
+pub mod ordering;
+pub mod profile;
+pub mod scheduler;
+pub mod trace;
@@ -0,0 +1,182 @@
+// import("lib/utils") 读取另一个文件，在独立的环境里求值，把顶层绑定包装成一个冻结的哈希返回
+// 同一个文件只求值一次，之后的导入直接返回缓存的结果
+// 以 std/ 开头的名字优先使用 crate 里嵌入的标准库
+use indexmap::IndexMap;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::builtins::set_frozen;
+use super::context::EvalContext;
+use super::environment::Environment;
+use super::eval::{eval_node, is_abrupt};
+use super::macro_expansion::{define_macros, expand_macro, macro_environment};
+use super::object::{Hash, HashPair, Hashable, StringObject, Value};
+use crate::ast::traits::AsNode;
+use crate::errors::{join_parse_errors, runtime_error, EvalError, MessageId};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::stdlib;
+use crate::sync::{Rc, RefCell};
+
+// 没有写扩展名时补上
+const EXTENSION: &str = "mky";
+
+#[derive(Default)]
+pub struct ModuleLoader {
+    // 顶层导入的相对路径从这里开始找，None 时使用当前工作目录
+    root: Option<PathBuf>,
+    cache: HashMap<PathBuf, Rc<Value>>,
+    // 正在求值的模块，用来检测循环导入，嵌套导入的相对路径也从栈顶模块所在的目录开始找
+    loading: Vec<PathBuf>,
+}
+
+impl ModuleLoader {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root: Some(root),
+            ..Self::default()
+        }
+    }
+
+    // 根目录相同、还没有导入过模块的加载器
+    pub fn fresh(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            ..Self::default()
+        }
+    }
+
+    fn resolve(&self, name: &str) -> PathBuf {
+        let base = match self.loading.last() {
+            Some(current) => current.parent().map(Path::to_path_buf),
+            None => self.root.clone(),
+        };
+        let mut path = match base {
+            Some(base) => base.join(name),
+            None => PathBuf::from(name),
+        };
+        if path.extension().is_none() {
+            path.set_extension(EXTENSION);
+        }
+        path
+    }
+}
+
+pub fn import(context: &EvalContext, name: &str) -> Rc<Value> {
+    // 嵌入的模块用名字本身作为缓存的键，不访问文件系统，沙盒模式下也可以导入
+    let (path, embedded) = match stdlib::find(name) {
+        Some(module) => (PathBuf::from(module.name), Some(module.source)),
+        None => {
+            if context.runtime().limits.sandbox {
+                return Rc::new(Value::Error(runtime_error(
+                    MessageId::DisabledInSandbox,
+                    &[&"import"],
+                )));
+            }
+            let path = context.runtime().modules.resolve(name);
+            match fs::canonicalize(&path) {
+                Ok(path) => (path, None),
+                Err(error) => {
+                    return Rc::new(Value::Error(runtime_error(
+                        MessageId::ModuleNotFound,
+                        &[&name, &error],
+                    )))
+                }
+            }
+        }
+    };
+    {
+        let runtime = context.runtime();
+        let modules = &runtime.modules;
+        if let Some(module) = modules.cache.get(&path) {
+            return Rc::clone(module);
+        }
+        if let Some(start) = modules.loading.iter().position(|loading| loading == &path) {
+            let cycle = modules.loading[start..]
+                .iter()
+                .chain([&path])
+                .map(|loading| loading.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Rc::new(Value::Error(runtime_error(
+                MessageId::ImportCycle,
+                &[&cycle],
+            )));
+        }
+    }
+
+    let source = match embedded {
+        Some(source) => source.to_owned(),
+        None => match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(error) => {
+                return Rc::new(Value::Error(runtime_error(
+                    MessageId::ModuleNotFound,
+                    &[&name, &error],
+                )))
+            }
+        },
+    };
+    let mut program = match Parser::new(Lexer::new(source)).parse() {
+        Ok(program) => program,
+        Err(errors) => {
+            return Rc::new(Value::Error(runtime_error(
+                MessageId::ModuleParseFailed,
+                &[&name, &join_parse_errors(&errors)],
+            )))
+        }
+    };
+    // 宏体受导入方的限制和沙盒约束，宏体里导入的相对路径从模块所在的目录开始找
+    let macro_env = macro_environment(&context.runtime());
+    if let (None, Some(directory)) = (embedded, path.parent()) {
+        macro_env.borrow().context().borrow_mut().modules =
+            ModuleLoader::new(directory.to_path_buf());
+    }
+    define_macros(&mut program, Rc::clone(&macro_env));
+    if let Err(error) = expand_macro(&mut program, macro_env) {
+        return Rc::new(Value::Error(error));
+    }
+
+    // 模块有自己的根环境，但和导入方共享输出、内置函数和模块缓存
+    let runtime = context.env().borrow().context();
+    let env = Rc::new(RefCell::new(Environment::with_context(runtime)));
+    context.runtime().modules.loading.push(path.clone());
+    let result =
+        eval_node(program.as_node(), Rc::clone(&env)).unwrap_or_else(EvalError::into_object);
+    context.runtime().modules.loading.pop();
+    if is_abrupt(result.as_ref()) {
+        return result;
+    }
+
+    // 环境里的绑定没有顺序，按名字排序，导出的哈希每次显示都一样
+    let env = env.borrow();
+    let mut bindings = env.iter().collect::<Vec<_>>();
+    bindings.sort_by_key(|(name, _)| *name);
+    let mut pairs = IndexMap::new();
+    for (binding, value) in bindings {
+        let key = StringObject {
+            value: binding.to_string(),
+        };
+        pairs.insert(
+            key.hash_key(),
+            HashPair {
+                key: Rc::new(Value::String(key)),
+                value: Rc::clone(value),
+            },
+        );
+    }
+    let module = set_frozen(
+        Rc::new(Value::Hash(Hash {
+            pairs,
+            frozen: false,
+        })),
+        true,
+    );
+    context
+        .runtime()
+        .modules
+        .cache
+        .insert(path, Rc::clone(&module));
+    module
+}
@@ -1,214 +1,208 @@
 use downcast_rs::{impl_downcast, Downcast};
-use dyn_clone::DynClone;
-use once_cell::sync::Lazy;
-use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
-use std::hash::Hasher;
-use std::{cell::RefCell, rc::Rc};
+use indexmap::IndexMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+use super::builtins::BuiltinFunction;
 use super::environment::Environment;
-use crate::ast::{expressions::Identifier, statements::BlockStatement, traits::Node};
-
-type BuiltinFunction = fn(&[&dyn Object]) -> Box<dyn Object>;
-
-pub static BUILTINS: Lazy<HashMap<&'static str, Builtin>> = Lazy::new(|| {
-    HashMap::from([
-        ("len", Builtin { func: object_len }),
-        ("first", Builtin { func: array_first }),
-        ("last", Builtin { func: array_last }),
-        ("rest", Builtin { func: array_rest }),
-        ("push", Builtin { func: array_push }),
-        ("puts", Builtin { func: puts }),
-    ])
-});
-
-fn object_len(objects: &[&dyn Object]) -> Box<dyn Object> {
-    if objects.len() != 1 {
-        return Box::new(Error {
-            message: format!("wrong number of arguments: got={}, want=1", objects.len()),
-        });
-    }
-
-    let first = *objects.first().unwrap();
-
-    match first.object_type() {
-        ObjectType::String => {
-            let string = first.downcast_ref::<StringObject>().unwrap();
-            Box::new(Integer {
-                value: string.value.len() as i64,
-            })
-        }
-        ObjectType::Array => {
-            let array = first.downcast_ref::<Array>().unwrap();
-            Box::new(Integer {
-                value: array.elements.len() as i64,
-            })
-        }
-        _ => Box::new(Error {
-            message: format!(
-                "argument to `len` not supported, got {:?}",
-                first.object_type()
-            ),
-        }),
-    }
-}
+use super::eval::hash_key_of;
+use crate::ast::{
+    expressions::{Expr, Identifier},
+    statements::BlockStatement,
+    traits::Node,
+};
+use crate::compiler::code::Location;
+use crate::lexer::Span;
+use crate::sync::{Rc, RefCell};
+use crate::vm::Globals;
 
-fn array_first(objects: &[&dyn Object]) -> Box<dyn Object> {
-    if objects.len() != 1 {
-        return Box::new(Error {
-            message: format!("wrong number of arguments: got={}, want=1", objects.len()),
-        });
-    }
-
-    let first = *objects.first().unwrap();
-
-    match first.object_type() {
-        ObjectType::Array => {
-            let array = first.downcast_ref::<Array>().unwrap();
-            array
-                .elements
-                .first()
-                .map_or(Box::new(Null), |first| dyn_clone::clone_box(first.as_ref()))
-        }
-        _ => Box::new(Error {
-            message: format!(
-                "argument to `first` must be Array, got {:?}",
-                first.object_type()
-            ),
-        }),
-    }
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ObjectType {
+    Integer,
+    Boolean,
+    Null,
+    ReturnValue,
+    Error,
+    Function,
+    String,
+    Builtin,
+    Array,
+    Hash,
+    Quote,
+    Macro,
+    Exit,
+    Channel,
+    Task,
+    Iterator,
+    Bytes,
 }
 
-fn array_last(objects: &[&dyn Object]) -> Box<dyn Object> {
-    if objects.len() != 1 {
-        return Box::new(Error {
-            message: format!("wrong number of arguments: got={}, want=1", objects.len()),
-        });
-    }
-
-    let first = *objects.first().unwrap();
-
-    match first.object_type() {
-        ObjectType::Array => {
-            let array = first.downcast_ref::<Array>().unwrap();
-            array
-                .elements
-                .iter()
-                .last()
-                .map_or(Box::new(Null), |last| dyn_clone::clone_box(last.as_ref()))
-        }
-        _ => Box::new(Error {
-            message: format!(
-                "argument to `last` must be Array, got {:?}",
-                first.object_type()
-            ),
-        }),
+// 错误信息里直接使用类型名
+impl fmt::Display for ObjectType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
     }
 }
 
-fn array_rest(objects: &[&dyn Object]) -> Box<dyn Object> {
-    if objects.len() != 1 {
-        return Box::new(Error {
-            message: format!("wrong number of arguments: got={}, want=1", objects.len()),
-        });
-    }
-
-    let first = *objects.first().unwrap();
+pub trait Object: Downcast {
+    fn object_type(&self) -> ObjectType;
 
-    match first.object_type() {
-        ObjectType::Array => {
-            let array = dyn_clone::clone_box(first)
-                .downcast::<Array>()
-                .map_err(|_| "Shouldn't happen.")
-                .unwrap();
-            Box::new(Array {
-                elements: array.elements.into_iter().skip(1).collect::<Vec<_>>(),
-            })
-        }
-        _ => Box::new(Error {
-            message: format!(
-                "argument to `last` must be Array, got {:?}",
-                first.object_type()
-            ),
-        }),
-    }
+    fn inspect(&self) -> String;
 }
 
-fn array_push(objects: &[&dyn Object]) -> Box<dyn Object> {
-    if objects.len() != 2 {
-        return Box::new(Error {
-            message: format!("wrong number of arguments: got={}, want=2", objects.len()),
-        });
-    }
-
-    let first = *objects.first().unwrap();
-    let object = dyn_clone::clone_box(*objects.get(1).unwrap());
+impl_downcast!(Object);
 
-    match first.object_type() {
-        ObjectType::Array => {
-            let mut array = dyn_clone::clone_box(first)
-                .downcast::<Array>()
-                .map_err(|_| "Shouldn't happen.")
-                .unwrap();
-            array.elements.push(object);
-            array
+// 求值得到的值，求值器按类型 match，不再逐个 downcast
+// 解引用成具体对象的 dyn Object，inspect、object_type 和 downcast_ref 仍然可以直接用
+// 相等比较是结构上的：类型不同的值总是不相等，数组和哈希逐个比较里面的值
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Value {
+    Integer(Integer),
+    Boolean(Boolean),
+    Null(Null),
+    ReturnValue(ReturnValue),
+    Error(Error),
+    // 函数、内置函数和宏带着环境或者闭包，序列化时报错，也不会被反序列化出来
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Function(Function),
+    String(StringObject),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Builtin(Builtin),
+    Array(Array),
+    Hash(Hash),
+    Quote(Quote),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Macro(Macro),
+    Exit(Exit),
+    // 字节码虚拟机里的函数，只在 vm 后端出现
+    #[cfg_attr(feature = "serde", serde(skip))]
+    CompiledFunction(Rc<CompiledFunction>),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Closure(Closure),
+    // 通道和任务属于正在运行的调度器，同样不能序列化
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Channel(Channel),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Task(Task),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Iterator(IteratorObject),
+    Bytes(Bytes),
+}
+
+impl Deref for Value {
+    type Target = dyn Object;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Value::Integer(object) => object,
+            Value::Boolean(object) => object,
+            Value::Null(object) => object,
+            Value::ReturnValue(object) => object,
+            Value::Error(object) => object,
+            Value::Function(object) => object,
+            Value::String(object) => object,
+            Value::Builtin(object) => object,
+            Value::Array(object) => object,
+            Value::Hash(object) => object,
+            Value::Quote(object) => object,
+            Value::Macro(object) => object,
+            Value::Exit(object) => object,
+            Value::CompiledFunction(object) => object.as_ref(),
+            Value::Closure(object) => object,
+            Value::Channel(object) => object,
+            Value::Task(object) => object,
+            Value::Iterator(object) => object,
+            Value::Bytes(object) => object,
         }
-        _ => Box::new(Error {
-            message: format!(
-                "argument to `push` must be Array, got {:?}",
-                first.object_type()
-            ),
-        }),
-    }
-}
-
-fn puts(objects: &[&dyn Object]) -> Box<dyn Object> {
-    for &object in objects {
-        println!("{}", object.inspect());
     }
-    Box::new(Null)
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum ObjectType {
+macro_rules! impl_from_object {
+    ($($variant:ident($object:ty)),* $(,)?) => {
+        $(
+            impl From<$object> for Value {
+                fn from(object: $object) -> Self {
+                    Value::$variant(object)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_object!(
+    Integer(Integer),
+    Boolean(Boolean),
+    Null(Null),
+    ReturnValue(ReturnValue),
+    Error(Error),
+    Function(Function),
+    String(StringObject),
+    Builtin(Builtin),
+    Array(Array),
+    Hash(Hash),
+    Quote(Quote),
+    Macro(Macro),
+    Exit(Exit),
+    Closure(Closure),
+    Channel(Channel),
+    Task(Task),
+    Iterator(IteratorObject),
+    Bytes(Bytes),
+);
+
+// Display 就是 inspect 的结果，REPL 里看到的是什么，format! 出来就是什么
+macro_rules! impl_display_for_object {
+    ($($object:ty),* $(,)?) => {
+        $(
+            impl fmt::Display for $object {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    f.write_str(&self.inspect())
+                }
+            }
+        )*
+    };
+}
+
+impl_display_for_object!(
+    Value,
     Integer,
     Boolean,
     Null,
     ReturnValue,
     Error,
     Function,
-    String,
+    StringObject,
     Builtin,
     Array,
     Hash,
     Quote,
     Macro,
-}
-
-pub trait Object: DynClone + Downcast {
-    fn object_type(&self) -> ObjectType;
-
-    fn inspect(&self) -> String;
-}
-
-impl_downcast!(Object);
-dyn_clone::clone_trait_object!(Object);
+    Exit,
+    CompiledFunction,
+    Closure,
+    Channel,
+    Task,
+    IteratorObject,
+    Bytes,
+);
 
 pub trait Hashable {
     fn hash_key(&self) -> HashKey;
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Integer {
     pub value: i64,
 }
 
 impl Hashable for Integer {
     fn hash_key(&self) -> HashKey {
-        HashKey {
-            object_type: self.object_type(),
-            value: self.value as u64,
-        }
+        HashKey::Integer(self.value)
     }
 }
 
@@ -222,7 +216,8 @@ impl Object for Integer {
     }
 }
 
-#[derive(PartialEq, Eq, Clone)]
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Boolean {
     True,
     False,
@@ -230,11 +225,7 @@ pub enum Boolean {
 
 impl Hashable for Boolean {
     fn hash_key(&self) -> HashKey {
-        let value = if matches!(self, Boolean::True) { 1 } else { 0 };
-        HashKey {
-            object_type: self.object_type(),
-            value,
-        }
+        HashKey::Boolean(self.value())
     }
 }
 
@@ -268,7 +259,8 @@ impl Boolean {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Null;
 
 impl Object for Null {
@@ -281,9 +273,10 @@ impl Object for Null {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReturnValue {
-    pub value: Box<dyn Object>,
+    pub value: Rc<Value>,
 }
 
 impl Object for ReturnValue {
@@ -296,14 +289,150 @@ impl Object for ReturnValue {
     }
 }
 
-#[derive(Clone)]
+// exit() 产生的控制流对象，和 Error 一样一路传到最外层，不会被函数调用拆开
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Exit {
+    pub code: i64,
+}
+
+impl Object for Exit {
+    fn inspect(&self) -> String {
+        format!("exit({})", self.code)
+    }
+
+    fn object_type(&self) -> ObjectType {
+        ObjectType::Exit
+    }
+}
+
+// chan() 创建的通道，clone 出来的副本共享同一个队列，send 从队尾放入，recv 从队头取出
+// 队列没有容量上限，send 从不等待
+#[derive(Clone, Debug, Default)]
+pub struct Channel {
+    pub queue: Rc<RefCell<VecDeque<Rc<Value>>>>,
+}
+
+// 同一个通道才相等
+impl PartialEq for Channel {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.queue, &other.queue)
+    }
+}
+
+impl Object for Channel {
+    fn inspect(&self) -> String {
+        "channel".to_owned()
+    }
+
+    fn object_type(&self) -> ObjectType {
+        ObjectType::Channel
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum TaskState {
+    // 还没运行，保存着要调用的函数和参数
+    Pending(Rc<Value>, Vec<Rc<Value>>),
+    Running,
+    // 函数的返回值，出错时是 Error 或者 Exit
+    Done(Rc<Value>),
+}
+
+// spawn() 创建的任务，由调度器在 recv、join 需要等待时或者程序结束时运行
+#[derive(Clone, Debug)]
+pub struct Task {
+    pub state: Rc<RefCell<TaskState>>,
+}
+
+impl PartialEq for Task {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl Object for Task {
+    fn inspect(&self) -> String {
+        "task".to_owned()
+    }
+
+    fn object_type(&self) -> ObjectType {
+        ObjectType::Task
+    }
+}
+
+// 惰性序列的来源，next() 每次只计算一个值
+#[derive(Clone, Debug)]
+pub enum IteratorSource {
+    // 数组的元素和下一个元素的位置
+    Array(Vec<Rc<Value>>, usize),
+    // 依次用 0、1、2…… 调用函数，函数返回 null 时结束
+    Generate(Rc<Value>, i64),
+    // 对另一个序列的每个值调用函数
+    Map(IteratorObject, Rc<Value>),
+    // 另一个序列最多还能取出的值的个数
+    Take(IteratorObject, usize),
+    // 已经结束，之后总是返回 null
+    Empty,
+}
+
+// iter()、generate() 这些函数创建的迭代器，clone 出来的副本共享同一个位置
+#[derive(Clone, Debug)]
+pub struct IteratorObject {
+    pub source: Rc<RefCell<IteratorSource>>,
+}
+
+impl IteratorObject {
+    pub fn new(source: IteratorSource) -> Self {
+        IteratorObject {
+            source: Rc::new(RefCell::new(source)),
+        }
+    }
+}
+
+impl PartialEq for IteratorObject {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.source, &other.source)
+    }
+}
+
+impl Object for IteratorObject {
+    fn inspect(&self) -> String {
+        "iterator".to_owned()
+    }
+
+    fn object_type(&self) -> ObjectType {
+        ObjectType::Iterator
+    }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Error {
     pub message: String,
+    // 出错的节点在源码里的区间和行列号，由求值器填写，行号为 0 表示位置未知
+    pub span: Span,
+    pub line: usize,
+    pub column: usize,
+}
+
+// 位置只是诊断信息，消息相同的错误就相等
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        self.message == other.message
+    }
 }
 
 impl Object for Error {
     fn inspect(&self) -> String {
-        format!("Error: {}", self.message)
+        if self.line == 0 {
+            format!("Error: {}", self.message)
+        } else {
+            format!(
+                "Error: {} at line {}, col {}",
+                self.message, self.line, self.column
+            )
+        }
     }
 
     fn object_type(&self) -> ObjectType {
@@ -311,11 +440,42 @@ impl Object for Error {
     }
 }
 
+// 函数、宏和闭包的身份，创建值的时候分配
+// clone、移动或者随快照复制出来的值保留原来的身份，仍然和原来的值相等
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Identity(u64);
+
+impl Identity {
+    pub fn fresh() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Identity(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
 #[derive(Clone)]
 pub struct Function {
     pub parameters: Vec<Identifier>,
     pub body: BlockStatement,
     pub env: Rc<RefCell<Environment>>,
+    pub identity: Identity,
+}
+
+// 函数按身份比较：只有同一次求值函数字面量得到的值才相等，传来传去的还是同一个值
+// 虚拟机的闭包用同样的规则，两个后端的结果一致
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        self.identity == other.identity
+    }
+}
+
+// 闭包环境可能引用函数自己，只打印参数和函数体
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("parameters", &self.parameters)
+            .field("body", &self.body)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Object for Function {
@@ -334,19 +494,15 @@ impl Object for Function {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StringObject {
     pub value: String,
 }
 
 impl Hashable for StringObject {
     fn hash_key(&self) -> HashKey {
-        let mut hasher = DefaultHasher::new();
-        hasher.write(self.value.as_bytes());
-        HashKey {
-            object_type: self.object_type(),
-            value: hasher.finish(),
-        }
+        HashKey::String(self.value.clone())
     }
 }
 
@@ -360,9 +516,48 @@ impl Object for StringObject {
     }
 }
 
+// 二进制数据，下标取出的是 0 到 255 的整数
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bytes {
+    pub value: Vec<u8>,
+}
+
+impl Bytes {
+    pub fn hex(&self) -> String {
+        self.value
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+// 显示成能重新得到同样数据的调用
+impl Object for Bytes {
+    fn inspect(&self) -> String {
+        format!("bytes_from_hex(\"{}\")", self.hex())
+    }
+
+    fn object_type(&self) -> ObjectType {
+        ObjectType::Bytes
+    }
+}
+
 #[derive(Clone)]
 pub struct Builtin {
-    pub func: BuiltinFunction,
+    pub func: Rc<BuiltinFunction>,
+}
+
+impl PartialEq for Builtin {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.func, &other.func)
+    }
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builtin").finish_non_exhaustive()
+    }
 }
 
 impl Object for Builtin {
@@ -375,9 +570,30 @@ impl Object for Builtin {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Array {
-    pub elements: Vec<Box<dyn Object>>,
+    pub elements: Vec<Rc<Value>>,
+    // 被 freeze() 冻结之后，修改它的内置函数都会返回错误
+    pub frozen: bool,
+}
+
+// 冻结与否不影响相等
+impl PartialEq for Array {
+    fn eq(&self, other: &Self) -> bool {
+        self.elements == other.elements
+    }
+}
+
+impl Array {
+    // 元素都能作为键时按内容计算数组的键，相等的数组得到同样的键
+    pub fn hash_key(&self) -> Option<HashKey> {
+        self.elements
+            .iter()
+            .map(|element| hash_key_of(element))
+            .collect::<Option<Vec<_>>>()
+            .map(HashKey::Array)
+    }
 }
 
 impl Object for Array {
@@ -396,21 +612,44 @@ impl Object for Array {
     }
 }
 
+// 哈希里用来查找的键，保存键本身而不是它的散列值
+// 不同类型的键总是不同，字符串之间也不会因为散列值相同而冲突
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
-pub struct HashKey {
-    object_type: ObjectType,
-    value: u64,
+pub enum HashKey {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+    Array(Vec<HashKey>),
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HashPair {
-    pub key: Box<dyn Object>,
-    pub value: Box<dyn Object>,
+    pub key: Rc<Value>,
+    pub value: Rc<Value>,
 }
 
-#[derive(Clone)]
+// 按键第一次插入的顺序保存，inspect 的输出总是和字面量里的顺序一致
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Hash {
-    pub pairs: HashMap<HashKey, HashPair>,
+    // 序列化成键值对的列表，HashKey 反序列化时重新计算
+    #[cfg_attr(feature = "serde", serde(with = "hash_pairs"))]
+    pub pairs: IndexMap<HashKey, HashPair>,
+    pub frozen: bool,
+}
+
+// 和插入顺序无关，键相同并且每个键对应的值相等
+impl PartialEq for Hash {
+    fn eq(&self, other: &Self) -> bool {
+        self.pairs.len() == other.pairs.len()
+            && self.pairs.iter().all(|(key, pair)| {
+                other
+                    .pairs
+                    .get(key)
+                    .is_some_and(|other| pair.value == other.value)
+            })
+    }
 }
 
 impl Object for Hash {
@@ -429,9 +668,16 @@ impl Object for Hash {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Quote {
-    pub node: Box<dyn Node>,
+    pub node: Expr,
+}
+
+impl PartialEq for Quote {
+    fn eq(&self, other: &Self) -> bool {
+        self.node.string() == other.node.string()
+    }
 }
 
 impl Object for Quote {
@@ -449,6 +695,23 @@ pub struct Macro {
     pub parameters: Vec<Identifier>,
     pub body: BlockStatement,
     pub env: Rc<RefCell<Environment>>,
+    pub identity: Identity,
+}
+
+// 和函数一样按身份比较
+impl PartialEq for Macro {
+    fn eq(&self, other: &Self) -> bool {
+        self.identity == other.identity
+    }
+}
+
+impl fmt::Debug for Macro {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Macro")
+            .field("parameters", &self.parameters)
+            .field("body", &self.body)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Object for Macro {
@@ -466,3 +729,93 @@ impl Object for Macro {
         ObjectType::Macro
     }
 }
+
+// 编译好的函数体，只放在常量池里，执行时总是先包装成 Closure
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompiledFunction {
+    pub instructions: Vec<u8>,
+    pub locations: Vec<Location>,
+    pub num_locals: usize,
+    pub num_parameters: usize,
+    // 函数字面量的源码，和树遍历求值器里的函数显示成一样
+    pub literal: String,
+}
+
+// 类型名和树遍历求值器的函数一样，两个后端的错误信息一致
+impl Object for CompiledFunction {
+    fn inspect(&self) -> String {
+        self.literal.clone()
+    }
+
+    fn object_type(&self) -> ObjectType {
+        ObjectType::Function
+    }
+}
+
+#[derive(Clone)]
+pub struct Closure {
+    pub function: Rc<CompiledFunction>,
+    // 创建闭包时从外层函数复制过来的自由变量
+    pub free: Vec<Rc<Value>>,
+    // 常量池和全局变量，内置函数回调闭包时靠它继续执行
+    pub globals: Rc<Globals>,
+    pub identity: Identity,
+}
+
+// 和树遍历求值器的函数一样按身份比较，每次执行 OpClosure 都得到一个不相等的新闭包
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        self.identity == other.identity
+    }
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Closure")
+            .field("function", &self.function)
+            .field("free", &self.free)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Object for Closure {
+    fn inspect(&self) -> String {
+        self.function.inspect()
+    }
+
+    fn object_type(&self) -> ObjectType {
+        ObjectType::Function
+    }
+}
+
+#[cfg(feature = "serde")]
+mod hash_pairs {
+    use indexmap::IndexMap;
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::{HashKey, HashPair};
+    use crate::evaluator::eval::hash_key_of;
+
+    pub fn serialize<S: Serializer>(
+        pairs: &IndexMap<HashKey, HashPair>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(pairs.values())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<IndexMap<HashKey, HashPair>, D::Error> {
+        Vec::<HashPair>::deserialize(deserializer)?
+            .into_iter()
+            .map(|pair| {
+                // 和求值器用同一个函数算键，能作为键的类型始终一致
+                let key = hash_key_of(pair.key.as_ref()).ok_or_else(|| {
+                    D::Error::custom(format!("unusable as hash key: {}", pair.key.object_type()))
+                })?;
+                Ok((key, pair))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,75 @@
+// 值之间的顺序，sort 和以后需要排序的集合都按这里的规则比较
+// 同一种类型之间：整数按大小，字符串和字节按字节逐个比较，false 在 true 前面，
+// 数组从第一个元素开始逐个比较，前面都相等时短的在前
+// 类型不同的值按类型排：布尔 < 整数 < 字符串 < 字节 < 数组，所以混着类型的 key 也能排序
+// 哈希、函数、null 这些没有顺序的值不能比较，返回错误而不是随便给一个顺序
+// 语言里还没有浮点数，以后加上时和整数放在同一级按数值比较
+use std::cmp::Ordering;
+
+use super::object::{Error, Value};
+use crate::errors::{runtime_error, MessageId};
+
+pub fn compare(left: &Value, right: &Value) -> Result<Ordering, Error> {
+    match (left, right) {
+        (Value::Integer(left), Value::Integer(right)) => Ok(left.value.cmp(&right.value)),
+        (Value::String(left), Value::String(right)) => Ok(left.value.cmp(&right.value)),
+        (Value::Boolean(left), Value::Boolean(right)) => Ok(left.value().cmp(&right.value())),
+        (Value::Bytes(left), Value::Bytes(right)) => Ok(left.value.cmp(&right.value)),
+        (Value::Array(left), Value::Array(right)) => {
+            for (left, right) in left.elements.iter().zip(right.elements.iter()) {
+                match compare(left, right)? {
+                    Ordering::Equal => continue,
+                    ordering => return Ok(ordering),
+                }
+            }
+            Ok(left.elements.len().cmp(&right.elements.len()))
+        }
+        _ => match (rank(left), rank(right)) {
+            (Some(left), Some(right)) => Ok(left.cmp(&right)),
+            _ => Err(runtime_error(
+                MessageId::NotComparable,
+                &[&left.object_type(), &right.object_type()],
+            )),
+        },
+    }
+}
+
+// 有顺序的类型之间的先后，None 表示这种值不能比较
+fn rank(value: &Value) -> Option<u8> {
+    match value {
+        Value::Boolean(_) => Some(0),
+        Value::Integer(_) => Some(1),
+        Value::String(_) => Some(2),
+        Value::Bytes(_) => Some(3),
+        Value::Array(_) => Some(4),
+        _ => None,
+    }
+}
+
+// 稳定的归并排序，比较出错时立即返回错误
+// slice::sort_by 要求比较函数给出一致的全序，出错后随便返回一个顺序会让结果不确定
+pub fn try_sort<T>(
+    mut items: Vec<T>,
+    compare: &impl Fn(&T, &T) -> Result<Ordering, Error>,
+) -> Result<Vec<T>, Error> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let left = try_sort(items, compare)?;
+    let right = try_sort(right, compare)?;
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(first), Some(second)) = (left.peek(), right.peek()) {
+        // 只有左边更大时才先放右边的，相等时保持原来的顺序
+        let next = match compare(first, second)? {
+            Ordering::Greater => right.next(),
+            _ => left.next(),
+        };
+        merged.extend(next);
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
@@ -0,0 +1,152 @@
+// EvalOptions::profile 打开时收集的数据：每种节点和每个函数被求值的次数和耗时
+// 耗时包括子节点和被调用的函数，递归调用会被重复计算
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use indexmap::IndexMap;
+use web_time::{Duration, Instant};
+
+use super::object::Function;
+use crate::ast::traits::Node;
+use crate::ast::walk::node_kind;
+use crate::lexer::Span;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProfileEntry {
+    pub count: u64,
+    pub total: Duration,
+}
+
+impl ProfileEntry {
+    fn record(&mut self, elapsed: Duration) {
+        self.count += 1;
+        self.total += elapsed;
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    // 按节点类型名统计，比如 CallExpression
+    pub nodes: HashMap<&'static str, ProfileEntry>,
+    // 按函数字面量统计，按第一次调用的顺序排列
+    // 键是 `fn(x, y) at line 1, col 9` 这样的描述，位置是函数体左花括号的位置
+    pub functions: IndexMap<String, ProfileEntry>,
+    // 正在求值的节点所在的层级和开始的时间
+    started: Vec<(usize, Instant)>,
+    // 同一个函数字面量的描述只生成一次
+    labels: HashMap<Span, String>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Profile::default()
+    }
+
+    pub(crate) fn enter(&mut self, depth: usize) {
+        self.started.push((depth, Instant::now()));
+    }
+
+    pub(crate) fn exit(&mut self, node: &dyn Node, depth: usize) {
+        // 求值中途打开或者关闭分析时，层级对不上的节点不计时
+        while self
+            .started
+            .last()
+            .is_some_and(|(started, _)| *started > depth)
+        {
+            self.started.pop();
+        }
+        let Some((_, started)) = self.started.pop_if(|(started, _)| *started == depth) else {
+            return;
+        };
+        self.nodes
+            .entry(node_kind(node))
+            .or_default()
+            .record(started.elapsed());
+    }
+
+    pub(crate) fn record_call(&mut self, function: &Function, elapsed: Duration) {
+        let label = self
+            .labels
+            .entry(function.body.span())
+            .or_insert_with(|| function_label(function));
+        match self.functions.get_mut(label.as_str()) {
+            Some(entry) => entry.record(elapsed),
+            None => {
+                let mut entry = ProfileEntry::default();
+                entry.record(elapsed);
+                self.functions.insert(label.clone(), entry);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.functions.is_empty()
+    }
+
+    // 丢掉收集到的数据，正在求值的节点照常计时
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.functions.clear();
+    }
+
+    // 两张表都按总耗时从高到低排列
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let mut nodes = self.nodes.iter().collect::<Vec<_>>();
+        nodes.sort_by(|(a_kind, a), (b_kind, b)| b.total.cmp(&a.total).then(a_kind.cmp(b_kind)));
+        let mut functions = self.functions.iter().collect::<Vec<_>>();
+        functions.sort_by_key(|(_, entry)| Reverse(entry.total));
+        let width = nodes
+            .iter()
+            .map(|(kind, _)| kind.len())
+            .chain(functions.iter().map(|(label, _)| label.len()))
+            .max()
+            .unwrap_or_default();
+        let mut table = |title: &str, rows: Vec<(&str, &ProfileEntry)>| {
+            writeln!(out, "{}:", title).unwrap();
+            for (name, entry) in rows {
+                writeln!(
+                    out,
+                    "  {:width$}  {:>10}  {:>12.3?}",
+                    name,
+                    entry.count,
+                    entry.total,
+                    width = width
+                )
+                .unwrap();
+            }
+        };
+        table(
+            "nodes",
+            nodes
+                .into_iter()
+                .map(|(kind, entry)| (*kind, entry))
+                .collect(),
+        );
+        table(
+            "functions",
+            functions
+                .into_iter()
+                .map(|(label, entry)| (label.as_str(), entry))
+                .collect(),
+        );
+        out
+    }
+}
+
+fn function_label(function: &Function) -> String {
+    let parameters = function
+        .parameters
+        .iter()
+        .map(|parameter| parameter.value.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    match function.body.token() {
+        Some(token) => format!(
+            "fn({}) at line {}, col {}",
+            parameters, token.line, token.column
+        ),
+        None => format!("fn({})", parameters),
+    }
+}
@@ -0,0 +1,86 @@
+// spawn() 创建的任务的协作式调度：任务不会被抢占，一旦开始就在当前线程里运行到结束
+// recv 遇到空通道、wait 遇到还没运行的任务时，按创建的顺序运行排队的任务，直到可以继续
+// 剩下的任务在 eval 返回之前运行，任何一个任务出错都会中断整个程序
+use super::environment::Environment;
+use super::eval::apply_function;
+use super::object::{Channel, Task, TaskState, Value};
+use crate::errors::{runtime_error, EvalError, EvalResult, MessageId};
+use crate::sync::{Rc, RefCell};
+
+// 返回任务的结果，已经运行过的任务直接返回上次的结果
+pub fn run_task(task: &Task, env: &Rc<RefCell<Environment>>) -> EvalResult {
+    let state = task.state.replace(TaskState::Running);
+    match state {
+        TaskState::Pending(function, args) => {
+            let result = apply_function(&function, &args, Rc::clone(env));
+            let value = match &result {
+                Ok(value) => Rc::clone(value),
+                Err(error) => error.clone().into_object(),
+            };
+            *task.state.borrow_mut() = TaskState::Done(value);
+            result
+        }
+        // 任务在等待它自己
+        TaskState::Running => Err(runtime_error(MessageId::Deadlock, &[&"wait"]).into()),
+        TaskState::Done(value) => {
+            *task.state.borrow_mut() = TaskState::Done(Rc::clone(&value));
+            EvalError::from_object(value)
+        }
+    }
+}
+
+// 通道是空的并且没有任务可以运行时再等下去也不会有值，报告死锁
+pub fn receive(channel: &Channel, env: &Rc<RefCell<Environment>>) -> EvalResult {
+    let context = env.borrow().context();
+    loop {
+        let value = channel.queue.borrow_mut().pop_front();
+        if let Some(value) = value {
+            return Ok(value);
+        }
+        let task = context.borrow_mut().next_task();
+        match task {
+            Some(task) => run_queued(&task, env)?,
+            None => return Err(runtime_error(MessageId::Deadlock, &[&"recv"]).into()),
+        }
+    }
+}
+
+fn run_pending_tasks(env: &Rc<RefCell<Environment>>) -> Result<(), EvalError> {
+    let context = env.borrow().context();
+    loop {
+        let task = context.borrow_mut().next_task();
+        match task {
+            Some(task) => run_queued(&task, env)?,
+            None => return Ok(()),
+        }
+    }
+}
+
+// 被 wait 提前运行过的任务仍然在队列里，不再运行，它的错误也已经交给了 wait 的调用者
+fn run_queued(task: &Task, env: &Rc<RefCell<Environment>>) -> Result<(), EvalError> {
+    let pending = matches!(*task.state.borrow(), TaskState::Pending(..));
+    if pending {
+        run_task(task, env)?;
+    }
+    Ok(())
+}
+
+// 交给宿主之前运行剩下的任务，出错时丢掉还没运行的任务
+pub fn finish(result: EvalResult, env: &Rc<RefCell<Environment>>) -> EvalResult {
+    let result = result.and_then(|value| {
+        run_pending_tasks(env)?;
+        Ok(value)
+    });
+    if result.is_err() {
+        env.borrow().context().borrow_mut().clear_tasks();
+    }
+    result
+}
+
+pub fn spawn(function: Rc<Value>, args: Vec<Rc<Value>>, env: &Rc<RefCell<Environment>>) -> Task {
+    let task = Task {
+        state: Rc::new(RefCell::new(TaskState::Pending(function, args))),
+    };
+    env.borrow().context().borrow_mut().spawn(task.clone());
+    task
+}
@@ -0,0 +1,56 @@
+// EvalOptions::trace 打开时每个节点开始求值和求值结束各产生一步，可以当作简单的单步调试器
+// 默认按函数调用的层数缩进写到运行时上下文的 output，也可以交给 RuntimeContext::with_tracer 的回调
+use crate::ast::traits::Node;
+use crate::ast::walk::node_kind;
+
+use super::object::Value;
+
+// 源码和结果超过这个长度时截断
+const MAX_TEXT_WIDTH: usize = 40;
+
+pub struct TraceStep<'a> {
+    pub node: &'a dyn Node,
+    // 节点求值的嵌套层数，和 Observer 收到的一样
+    pub depth: usize,
+    // 正在执行的用户函数的层数
+    pub call_depth: usize,
+    // 求值所在的环境外面还有几层环境
+    pub env_depth: usize,
+    // 开始求值时为 None
+    pub result: Option<&'a Value>,
+}
+
+#[cfg(not(feature = "sync"))]
+pub type Tracer = Box<dyn FnMut(&TraceStep)>;
+#[cfg(feature = "sync")]
+pub type Tracer = Box<dyn FnMut(&TraceStep) + Send + Sync>;
+
+// 开始求值是 `-> Kind source [env N]`，求值结束是 `<- Kind = result`
+pub fn format_step(step: &TraceStep) -> String {
+    let indent = "  ".repeat(step.call_depth);
+    match step.result {
+        None => format!(
+            "{}-> {} {} [env {}]",
+            indent,
+            node_kind(step.node),
+            truncate(&step.node.string()),
+            step.env_depth
+        ),
+        Some(result) => format!(
+            "{}<- {} = {}",
+            indent,
+            node_kind(step.node),
+            truncate(&result.inspect())
+        ),
+    }
+}
+
+fn truncate(text: &str) -> String {
+    let text = text.replace('\n', " ");
+    if text.chars().count() <= MAX_TEXT_WIDTH {
+        text
+    } else {
+        let prefix = text.chars().take(MAX_TEXT_WIDTH).collect::<String>();
+        format!("{}...", prefix)
+    }
+}
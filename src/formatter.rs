@@ -0,0 +1,334 @@
+// 把 AST 重新输出成统一风格的源码：一行一条语句，块按层级缩进，只在需要时加括号
+// 词法分析会丢掉注释，format_with_comments 按区间把高亮流里的注释放回语句前后
+use std::cell::Cell;
+
+use crate::ast::expressions::{Expr, Identifier};
+use crate::ast::program::Program;
+use crate::ast::statements::{BlockStatement, Stmt};
+use crate::ast::traits::Node;
+use crate::highlight::{highlight, Category};
+use crate::lexer::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NewlineStyle {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl NewlineStyle {
+    // 沿用源码里第一个换行的风格，没有换行时用 \n
+    pub fn detect(source: &str) -> Self {
+        match source.find('\n') {
+            Some(index) if source[..index].ends_with('\r') => NewlineStyle::CrLf,
+            _ => NewlineStyle::Lf,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NewlineStyle::Lf => "\n",
+            NewlineStyle::CrLf => "\r\n",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    pub indent: String,
+    pub newline: NewlineStyle,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent: "    ".to_owned(),
+            newline: NewlineStyle::Lf,
+        }
+    }
+}
+
+// 没有源码可对照（比如宏展开之后的程序），注释无从找回
+pub fn format_program(program: &Program, options: &FormatOptions) -> String {
+    format_with_comments("", program, options)
+}
+
+// program 必须是从 source 解析出来的，注释按区间挂到前后的语句上：
+// 语句前面的注释单独成行，和语句结尾同一行的注释留在行尾，表达式内部的注释移到语句行尾
+pub fn format_with_comments(source: &str, program: &Program, options: &FormatOptions) -> String {
+    let formatter = Formatter {
+        options,
+        source,
+        comments: highlight(source)
+            .into_iter()
+            .filter(|highlight| highlight.category == Category::Comment)
+            .map(|highlight| highlight.span)
+            .collect(),
+        next: Cell::new(0),
+    };
+    let mut out = String::new();
+    let mut previous_multiline = false;
+    for (index, statement) in program.statements.iter().enumerate() {
+        let leading = formatter.leading(statement.span().start, 0);
+        let text = formatter.statement(statement, 0, false);
+        // 多行的语句（通常是函数定义）前后各空一行
+        let multiline = text.contains('\n');
+        if index > 0 && (multiline || previous_multiline) {
+            out.push('\n');
+        }
+        out.push_str(&leading);
+        out.push_str(&text);
+        out.push_str(&formatter.trailing(statement.span().end, source.len()));
+        out.push('\n');
+        previous_multiline = multiline;
+    }
+    out.push_str(&formatter.leading(source.len(), 0));
+    // 内部统一用 \n 拼接，最后再换成需要的换行风格
+    match options.newline {
+        NewlineStyle::Lf => out,
+        NewlineStyle::CrLf => out.replace('\n', "\r\n"),
+    }
+}
+
+// 单独一个表达式，不带结尾的换行
+pub fn format_expression(expression: &Expr, options: &FormatOptions) -> String {
+    let formatter = Formatter {
+        options,
+        source: "",
+        comments: vec![],
+        next: Cell::new(0),
+    };
+    let out = formatter.expression(expression, 0, LOWEST);
+    match options.newline {
+        NewlineStyle::Lf => out,
+        NewlineStyle::CrLf => out.replace('\n', "\r\n"),
+    }
+}
+
+// 和 parser 里的优先级保持一致
+const LOWEST: u8 = 1;
+const PREFIX: u8 = 6;
+const CALL: u8 = 7;
+
+fn infix_precedence(operator: &str) -> u8 {
+    match operator {
+        "==" | "!=" => 2,
+        "<" | ">" => 3,
+        "+" | "-" => 4,
+        "*" | "/" => 5,
+        _ => LOWEST,
+    }
+}
+
+struct Formatter<'a> {
+    options: &'a FormatOptions,
+    source: &'a str,
+    // 按源码顺序排好的注释区间，next 之前的已经输出过
+    comments: Vec<Span>,
+    next: Cell<usize>,
+}
+
+impl Formatter<'_> {
+    fn pad(&self, depth: usize) -> String {
+        self.options.indent.repeat(depth)
+    }
+
+    // 取出下一条满足条件的注释
+    fn take_comment(&self, accept: impl Fn(Span) -> bool) -> Option<&str> {
+        let span = *self.comments.get(self.next.get())?;
+        if !accept(span) {
+            return None;
+        }
+        self.next.set(self.next.get() + 1);
+        Some(&self.source[span.start..span.end])
+    }
+
+    // 在 before 之前还没输出的注释，每条单独一行
+    fn leading(&self, before: usize, depth: usize) -> String {
+        let mut out = String::new();
+        while let Some(comment) = self.take_comment(|span| span.start < before) {
+            out.push_str(&self.pad(depth));
+            out.push_str(comment);
+            out.push('\n');
+        }
+        out
+    }
+
+    // 语句内部没输出的注释，加上语句结尾之后、换行之前的那一条，都接在行尾
+    // 块里的语句不去拿右花括号之后的注释，它属于外层的语句
+    fn trailing(&self, after: usize, limit: usize) -> String {
+        let mut out = String::new();
+        while let Some(comment) = self.take_comment(|span| span.start < after) {
+            out.push(' ');
+            out.push_str(comment);
+        }
+        let same_line =
+            |span: Span| span.start < limit && !self.source[after..span.start].contains('\n');
+        if let Some(comment) = self.take_comment(same_line) {
+            out.push(' ');
+            out.push_str(comment);
+        }
+        out
+    }
+
+    // is_tail 表示块里的最后一条表达式语句，它是块的值，不加分号
+    fn statement(&self, statement: &Stmt, depth: usize, is_tail: bool) -> String {
+        match statement {
+            Stmt::Let(let_statement) => {
+                let name = match let_statement.type_annotation.as_ref() {
+                    Some(type_annotation) => {
+                        format!("{}: {}", let_statement.name.value, type_annotation.value)
+                    }
+                    None => let_statement.name.value.to_string(),
+                };
+                format!(
+                    "let {} = {};",
+                    name,
+                    self.expression(&let_statement.value, depth, LOWEST)
+                )
+            }
+            Stmt::Return(return_statement) => format!(
+                "return {};",
+                self.expression(&return_statement.return_value, depth, LOWEST)
+            ),
+            Stmt::Expression(expression_statement) => {
+                let expression = self.expression(&expression_statement.expression, depth, LOWEST);
+                if is_tail {
+                    expression
+                } else {
+                    format!("{};", expression)
+                }
+            }
+            Stmt::Block(block) => self.block(block, depth),
+        }
+    }
+
+    fn block(&self, block: &BlockStatement, depth: usize) -> String {
+        let end = block.span().end;
+        let has_comment = self
+            .comments
+            .get(self.next.get())
+            .is_some_and(|span| span.start < end);
+        if block.statements.is_empty() && !has_comment {
+            return "{}".to_owned();
+        }
+        let mut out = "{\n".to_owned();
+        for (index, statement) in block.statements.iter().enumerate() {
+            let is_tail = index + 1 == block.statements.len();
+            out.push_str(&self.leading(statement.span().start, depth + 1));
+            out.push_str(&self.pad(depth + 1));
+            out.push_str(&self.statement(statement, depth + 1, is_tail));
+            out.push_str(&self.trailing(statement.span().end, end));
+            out.push('\n');
+        }
+        // 块里最后一条语句之后、右花括号之前的注释
+        out.push_str(&self.leading(end, depth + 1));
+        out.push_str(&self.pad(depth));
+        out.push('}');
+        out
+    }
+
+    // 子表达式的优先级低于 parent 时需要加括号
+    fn expression(&self, expression: &Expr, depth: usize, parent: u8) -> String {
+        match expression {
+            Expr::Identifier(identifier) => identifier.value.to_string(),
+            Expr::Integer(integer) => integer.value.to_string(),
+            Expr::Boolean(boolean) => boolean.value.to_string(),
+            Expr::String(string) => format!("\"{}\"", string.value),
+            Expr::Prefix(prefix) => format!(
+                "{}{}",
+                prefix.operator,
+                self.expression(&prefix.right, depth, PREFIX)
+            ),
+            Expr::Infix(infix) => {
+                let precedence = infix_precedence(&infix.operator);
+                // 运算符都是左结合的，右边同级的表达式需要括号
+                let text = format!(
+                    "{} {} {}",
+                    self.expression(&infix.left, depth, precedence),
+                    infix.operator,
+                    self.expression(&infix.right, depth, precedence + 1)
+                );
+                if precedence < parent {
+                    format!("({})", text)
+                } else {
+                    text
+                }
+            }
+            Expr::If(if_expression) => {
+                let mut text = format!(
+                    "if ({}) {}",
+                    self.expression(&if_expression.condition, depth, LOWEST),
+                    self.block(&if_expression.consequence, depth)
+                );
+                if let Some(alternative) = if_expression.alternative.as_ref() {
+                    text.push_str(&format!(" else {}", self.block(alternative, depth)));
+                }
+                text
+            }
+            Expr::Function(function) => format!(
+                "fn({}) {}",
+                parameters(&function.parameters),
+                self.block(&function.body, depth)
+            ),
+            Expr::Macro(macro_literal) => format!(
+                "macro({}) {}",
+                parameters(&macro_literal.parameters),
+                self.block(&macro_literal.body, depth)
+            ),
+            Expr::Call(call) => format!(
+                "{}({})",
+                self.expression(&call.function, depth, CALL),
+                self.expressions(&call.arguments, depth)
+            ),
+            Expr::Quote(quote) => format!(
+                "quote({})",
+                self.expression(&quote.expression, depth, LOWEST)
+            ),
+            Expr::Unquote(unquote) => format!(
+                "unquote({})",
+                self.expression(&unquote.expression, depth, LOWEST)
+            ),
+            Expr::UnquoteSplice(splice) => format!(
+                "unquote_splice({})",
+                self.expression(&splice.expression, depth, LOWEST)
+            ),
+            Expr::Array(array) => format!("[{}]", self.expressions(&array.elements, depth)),
+            Expr::Index(index) => format!(
+                "{}[{}]",
+                self.expression(&index.left, depth, CALL),
+                self.expression(&index.index, depth, LOWEST)
+            ),
+            Expr::Hash(hash) => {
+                let pairs = hash
+                    .pairs
+                    .iter()
+                    .map(|(key, value)| {
+                        format!(
+                            "{}: {}",
+                            self.expression(key, depth, LOWEST),
+                            self.expression(value, depth, LOWEST)
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                format!("{{{}}}", pairs.join(", "))
+            }
+        }
+    }
+
+    fn expressions(&self, expressions: &[Expr], depth: usize) -> String {
+        expressions
+            .iter()
+            .map(|expression| self.expression(expression, depth, LOWEST))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn parameters(parameters: &[Identifier]) -> String {
+    parameters
+        .iter()
+        .map(|parameter| parameter.value.clone())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,133 @@
+// 给语法高亮用的词法单元流：每个词法单元按语义归类，带着在源码里的区间
+// 词法分析器跳过的注释从词法单元之间的空隙里找回来，按源码顺序和词法单元排在一起
+use std::fmt::Write;
+
+use crate::lexer::{Lexer, Span};
+use crate::token::TokenType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Category {
+    Keyword,
+    // 整数、字符串、true 和 false
+    Literal,
+    Operator,
+    Identifier,
+    // 括号、逗号、分号和冒号
+    Punctuation,
+    Comment,
+    Illegal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Highlight {
+    pub category: Category,
+    pub span: Span,
+}
+
+// 区间是字节偏移，相邻的区间之间只有空白
+pub fn highlight(source: &str) -> Vec<Highlight> {
+    let mut highlights = vec![];
+    let mut end = 0;
+    for token in Lexer::new(source) {
+        comments(source, end, token.span.start, &mut highlights);
+        highlights.push(Highlight {
+            category: category(token.token_type),
+            span: token.span,
+        });
+        end = token.span.end;
+    }
+    comments(source, end, source.len(), &mut highlights);
+    highlights
+}
+
+pub fn category(token_type: TokenType) -> Category {
+    match token_type {
+        TokenType::Function
+        | TokenType::Let
+        | TokenType::If
+        | TokenType::Else
+        | TokenType::Return
+        | TokenType::Macro
+        | TokenType::Quote
+        | TokenType::Unquote
+        | TokenType::UnquoteSplice => Category::Keyword,
+        TokenType::Int | TokenType::String | TokenType::True | TokenType::False => {
+            Category::Literal
+        }
+        TokenType::Assign
+        | TokenType::Plus
+        | TokenType::Minus
+        | TokenType::Bang
+        | TokenType::Asterisk
+        | TokenType::Slash
+        | TokenType::LessThan
+        | TokenType::GreaterThan
+        | TokenType::Equal
+        | TokenType::NotEqual => Category::Operator,
+        TokenType::Ident => Category::Identifier,
+        TokenType::Comma
+        | TokenType::Semicolon
+        | TokenType::Colon
+        | TokenType::LeftParen
+        | TokenType::RightParen
+        | TokenType::LeftBrace
+        | TokenType::RightBrace
+        | TokenType::LeftBracket
+        | TokenType::RightBracket => Category::Punctuation,
+        TokenType::Illegal | TokenType::EOF => Category::Illegal,
+    }
+}
+
+// 两个词法单元之间只有空白和 `//` 开头的行注释，注释不包括行尾的换行
+fn comments(source: &str, start: usize, end: usize, highlights: &mut Vec<Highlight>) {
+    let gap = &source[start..end];
+    let mut offset = 0;
+    while let Some(found) = gap[offset..].find("//") {
+        let comment_start = offset + found;
+        let comment_end = gap[comment_start..]
+            .find(['\r', '\n'])
+            .map_or(gap.len(), |newline| comment_start + newline);
+        highlights.push(Highlight {
+            category: Category::Comment,
+            span: Span::new(start + comment_start, start + comment_end),
+        });
+        offset = comment_end;
+    }
+}
+
+// 终端里每个类别的 ANSI 颜色代码，标识符和标点不上色
+pub fn ansi_code(category: Category) -> Option<&'static str> {
+    match category {
+        Category::Keyword => Some("35"),
+        Category::Literal => Some("32"),
+        Category::Operator => Some("36"),
+        Category::Comment => Some("90"),
+        Category::Illegal => Some("31"),
+        Category::Identifier | Category::Punctuation => None,
+    }
+}
+
+// 按类别给源码加上 ANSI 颜色，空白原样保留
+pub fn to_ansi(source: &str) -> String {
+    let mut out = String::new();
+    let mut end = 0;
+    for highlight in highlight(source) {
+        let span = highlight.span;
+        out.push_str(&source[end..span.start]);
+        match ansi_code(highlight.category) {
+            Some(code) => write!(
+                out,
+                "\x1b[{}m{}\x1b[0m",
+                code,
+                &source[span.start..span.end]
+            )
+            .unwrap(),
+            None => out.push_str(&source[span.start..span.end]),
+        }
+        end = span.end;
+    }
+    out.push_str(&source[end..]);
+    out
+}
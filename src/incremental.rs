@@ -0,0 +1,117 @@
+// 编辑器和 REPL 的多行编辑每改一次都要重新解析整个缓冲区，这里只解析改动过的顶层语句
+// 源码按顶层（不在括号里）的分号切成片段，位置、行列号和内容的哈希都和上次一样的片段直接用上次的语句
+// 改动之后的片段位置变了，也会重新解析
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use crate::ast::program::Program;
+use crate::ast::statements::Stmt;
+use crate::errors::ParseError;
+use crate::lexer::{Lexer, Span};
+use crate::parser::Parser;
+use crate::sync::Rc;
+use crate::token::TokenType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Chunk {
+    span: Span,
+    line: usize,
+    column: usize,
+    text: u64,
+}
+
+#[derive(Default)]
+pub struct IncrementalParser {
+    // 只保留上一次解析的片段
+    cache: HashMap<Chunk, Vec<Stmt>>,
+    reparsed: usize,
+}
+
+impl IncrementalParser {
+    pub fn new() -> Self {
+        IncrementalParser::default()
+    }
+
+    // 结果和 Parser::parse 一样；有片段解析失败时整个重新解析，错误信息也一样
+    pub fn parse(&mut self, source: &str) -> Result<Program, Vec<ParseError>> {
+        let source = Rc::<str>::from(source);
+        let mut statements = vec![];
+        let mut cache = HashMap::new();
+        self.reparsed = 0;
+        for chunk in chunks(&source) {
+            let parsed = match self.cache.remove(&chunk) {
+                Some(parsed) => parsed,
+                None => {
+                    self.reparsed += 1;
+                    let lexer =
+                        Lexer::with_range(Rc::clone(&source), chunk.span, chunk.line, chunk.column);
+                    match Parser::new(lexer).parse() {
+                        Ok(program) => program.statements,
+                        Err(_) => {
+                            self.cache.clear();
+                            return Parser::new(Lexer::new(source)).parse();
+                        }
+                    }
+                }
+            };
+            statements.extend(parsed.iter().cloned());
+            cache.insert(chunk, parsed);
+        }
+        self.cache = cache;
+        Ok(Program {
+            statements,
+            source: Some(source),
+        })
+    }
+
+    // 上一次 parse 重新解析了几个片段
+    pub fn reparsed(&self) -> usize {
+        self.reparsed
+    }
+}
+
+// 片段从第一个词法单元开始，到顶层的分号为止，最后一个片段可以没有分号
+// 片段之间的空白和注释不属于任何片段
+fn chunks(source: &Rc<str>) -> Vec<Chunk> {
+    let mut chunks = vec![];
+    let mut start = None;
+    let mut depth = 0usize;
+    let mut end = 0;
+    for token in Lexer::new(Rc::clone(source)) {
+        end = token.span.end;
+        let (begin, line, column) =
+            *start.get_or_insert((token.span.start, token.line, token.column));
+        match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => {
+                depth = depth.saturating_sub(1)
+            }
+            TokenType::Semicolon if depth == 0 => {
+                chunks.push(chunk(
+                    source,
+                    Span::new(begin, token.span.end),
+                    line,
+                    column,
+                ));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((begin, line, column)) = start {
+        chunks.push(chunk(source, Span::new(begin, end), line, column));
+    }
+    chunks
+}
+
+fn chunk(source: &str, span: Span, line: usize, column: usize) -> Chunk {
+    let mut hasher = DefaultHasher::new();
+    source[span.start..span.end].hash(&mut hasher);
+    Chunk {
+        span,
+        line,
+        column,
+        text: hasher.finish(),
+    }
+}
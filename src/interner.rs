@@ -0,0 +1,32 @@
+use crate::sync::Rc;
+use std::collections::HashSet;
+
+// 相同的字符串只保存一份，词法单元、标识符和环境里的名字都共享同一个 Rc<str>
+// 复制名字只是增加引用计数，不会重新分配
+#[derive(Default)]
+pub struct Interner {
+    strings: HashSet<Rc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    pub fn intern(&mut self, string: &str) -> Rc<str> {
+        if let Some(interned) = self.strings.get(string) {
+            return Rc::clone(interned);
+        }
+        let interned = Rc::<str>::from(string);
+        self.strings.insert(Rc::clone(&interned));
+        interned
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
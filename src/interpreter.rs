@@ -0,0 +1,166 @@
+// 嵌入用的入口：把词法分析、语法分析、宏展开和求值串在一起
+// 多次调用 eval_str 共用同一个根环境，之前定义的绑定和宏一直可见
+use std::fmt::{self, Display};
+
+use crate::ast::traits::AsNode;
+use crate::errors::ParseError;
+use crate::evaluator::builtins::BuiltinRegistry;
+use crate::evaluator::context::RuntimeContext;
+use crate::evaluator::convert::HostFunction;
+use crate::evaluator::environment::{Environment, Snapshot};
+use crate::evaluator::eval::eval;
+use crate::evaluator::gc;
+use crate::evaluator::macro_expansion::{define_macros, expand_macro, macro_environment};
+use crate::evaluator::object::{Error, Object, Value};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::stdlib::load_prelude;
+use crate::sync::{Rc, RefCell};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterpreterError {
+    Parse(Vec<ParseError>),
+    Runtime(Error),
+    // 脚本调用了 exit()，由宿主决定怎么处理
+    Exit(i64),
+}
+
+impl Display for InterpreterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpreterError::Parse(errors) => {
+                let lines = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+                write!(f, "{}", lines.join("\n"))
+            }
+            InterpreterError::Runtime(error) => write!(f, "{}", error.inspect()),
+            InterpreterError::Exit(code) => write!(f, "exited with code {}", code),
+        }
+    }
+}
+
+impl std::error::Error for InterpreterError {}
+
+// 根环境和宏环境在某一时刻的绑定，见 Interpreter::checkpoint
+#[derive(Clone)]
+pub struct Checkpoint {
+    env: Snapshot,
+    macros: Snapshot,
+}
+
+pub struct Interpreter {
+    env: Rc<RefCell<Environment>>,
+    macro_env: Rc<RefCell<Environment>>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    // 标准库已经加载，puts 写到标准输出
+    pub fn new() -> Self {
+        Self::with_context(RuntimeContext::new())
+    }
+
+    // 见 RuntimeContext::sandboxed，需要同时限制步数或者内存时用 with_context
+    pub fn sandboxed() -> Self {
+        Self::with_context(RuntimeContext::new().sandboxed())
+    }
+
+    // 需要捕获输出、限制资源或者注册宿主函数时先配置好运行时上下文
+    // 宏在单独的环境里展开，EvalOptions::hygiene 和展开的层数限制也要在那里设置
+    pub fn with_context(context: RuntimeContext) -> Self {
+        let macro_env = macro_environment(&context);
+        let env = Rc::new(RefCell::new(Environment::with_context(Rc::new(
+            RefCell::new(context.with_macros(Rc::clone(&macro_env))),
+        ))));
+        load_prelude(&env);
+        Self { env, macro_env }
+    }
+
+    // 返回最后一条语句的值，步数和时间的限制对每次调用的宏展开和求值分别计算
+    pub fn eval_str(&mut self, source: &str) -> Result<Rc<Value>, InterpreterError> {
+        let mut program = Parser::new(Lexer::new(source))
+            .parse()
+            .map_err(InterpreterError::Parse)?;
+        self.macro_env.borrow().context().borrow_mut().refuel();
+        define_macros(&mut program, Rc::clone(&self.macro_env));
+        expand_macro(&mut program, Rc::clone(&self.macro_env))
+            .map_err(InterpreterError::Runtime)?;
+        self.env.borrow().context().borrow_mut().refuel();
+        let value = eval(program.as_node(), Rc::clone(&self.env));
+        match value.as_ref() {
+            Value::Error(error) => Err(InterpreterError::Runtime(error.clone())),
+            Value::Exit(exit) => Err(InterpreterError::Exit(exit.code)),
+            _ => Ok(value),
+        }
+    }
+
+    // 相当于在根环境里执行 let，会覆盖同名的绑定
+    pub fn set_global(&mut self, name: &str, value: impl Into<Value>) {
+        self.env
+            .borrow_mut()
+            .set(Rc::from(name), Rc::new(value.into()));
+    }
+
+    // 只查找绑定，不包括内置函数
+    pub fn get_global(&self, name: &str) -> Option<Rc<Value>> {
+        self.env.borrow().get(name)
+    }
+
+    // 见 BuiltinRegistry::register_fn
+    pub fn register_fn<Args>(&mut self, name: &str, func: impl HostFunction<Args>) {
+        let context = self.env.borrow().context();
+        context.borrow_mut().builtins.register_fn(name, "", func);
+        self.sync_macro_builtins();
+    }
+
+    pub fn register_builtins(&mut self, register: impl FnOnce(&mut BuiltinRegistry)) {
+        register(&mut self.env.borrow().context().borrow_mut().builtins);
+        self.sync_macro_builtins();
+    }
+
+    // 宏体里能调用的内置函数和程序里的一样
+    fn sync_macro_builtins(&self) {
+        let builtins = self.env.borrow().context().borrow().builtins.clone();
+        self.macro_env.borrow().context().borrow_mut().builtins = builtins;
+    }
+
+    // 保存目前的绑定和宏，之后可以用 restore 回到这里，比如执行不受信任的代码之前
+    // 内置函数的注册和运行时上下文不在里面
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            env: self.env.borrow().snapshot(),
+            macros: self.macro_env.borrow().snapshot(),
+        }
+    }
+
+    pub fn restore(&mut self, checkpoint: &Checkpoint) {
+        self.env.borrow_mut().restore(&checkpoint.env);
+        self.macro_env.borrow_mut().restore(&checkpoint.macros);
+    }
+
+    // 需要直接操作环境的宿主可以拿到根环境
+    pub fn environment(&self) -> Rc<RefCell<Environment>> {
+        Rc::clone(&self.env)
+    }
+
+    // 立即回收不可达的闭包环境，返回清空了多少个环境，平时求值过程中会自动回收
+    pub fn collect_garbage(&self) -> usize {
+        let context = self.env.borrow().context();
+        gc::collect(&context, &[])
+    }
+}
+
+// 根环境和在里面定义的函数互相引用，解释器不在了以后按不可达的环回收
+// 宿主还拿着的函数和环境有外部引用，不受影响
+impl Drop for Interpreter {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            let context = self.env.borrow().context();
+            gc::collect(&context, &[&self.env]);
+        }
+    }
+}
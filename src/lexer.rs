@@ -1,128 +1,383 @@
+use crate::interner::Interner;
+use crate::sync::Rc;
 use crate::token::{self, Token, TokenType};
+use std::io::{self, ErrorKind, Read};
+use std::iter::FusedIterator;
 
+// 源码中的一段区间，使用字节偏移，左闭右开
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // 合并两个区间，得到能同时覆盖它们的最小区间
+    pub fn merge(&self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    // 覆盖所有非空区间的最小区间，宏展开生成的节点没有位置，不参与计算
+    pub fn cover(spans: impl IntoIterator<Item = Span>) -> Span {
+        spans
+            .into_iter()
+            .filter(|span| !span.is_empty())
+            .reduce(|covered, span| covered.merge(span))
+            .unwrap_or_default()
+    }
+
+    // 越界或者没有落在字符边界上时返回 None，而不是 panic
+    pub fn slice<'a>(&self, source: &'a str) -> Option<&'a str> {
+        source.get(self.start..self.end)
+    }
+}
+
+// 每次从 reader 读取的字节数
+const CHUNK_SIZE: usize = 8 * 1024;
+
+// 源码要么一开始就全部在内存里，要么由 from_reader 边扫描边从 reader 读取
+// 从 reader 读取时只保留还没扫描完的部分，已经产生了词法单元的源码会被丢掉
+enum Input {
+    Shared(Rc<str>),
+    Streamed(Stream),
+}
+
+struct Stream {
+    // 源码里从 base 开始的一段
+    text: String,
+    base: usize,
+    reader: Box<dyn Read>,
+    // 被切断在两次读取之间的多字节字符，等下一次读取补全
+    pending: Vec<u8>,
+    finished: bool,
+    error: Option<io::Error>,
+}
+
+// 偏移都按整个源码计算，不管前面的部分是否已经丢掉
+impl Input {
+    // 保证至少读到第 wanted 个字节，除非 reader 已经读完
+    fn fill(&mut self, wanted: usize) {
+        if let Input::Streamed(stream) = self {
+            while stream.base + stream.text.len() < wanted && !stream.finished {
+                stream.read_chunk();
+            }
+        }
+    }
+
+    // 已经丢掉的部分返回 None
+    fn text(&self, start: usize, end: usize) -> Option<&str> {
+        match self {
+            Input::Shared(input) => input.get(start..end),
+            Input::Streamed(stream) => stream
+                .text
+                .get(start.checked_sub(stream.base)?..end.checked_sub(stream.base)?),
+        }
+    }
+
+    fn rest(&self, offset: usize) -> Option<&str> {
+        match self {
+            Input::Shared(input) => input.get(offset..),
+            Input::Streamed(stream) => stream.text.get(offset.checked_sub(stream.base)?..),
+        }
+    }
+
+    // offset 之前的源码不会再用到，攒够一块再丢，避免每个词法单元都移动剩下的内容
+    fn discard(&mut self, offset: usize) {
+        if let Input::Streamed(stream) = self {
+            let consumed = offset.saturating_sub(stream.base).min(stream.text.len());
+            if consumed >= CHUNK_SIZE && stream.text.is_char_boundary(consumed) {
+                stream.text.drain(..consumed);
+                stream.base += consumed;
+            }
+        }
+    }
+}
+
+impl Stream {
+    fn read_chunk(&mut self) {
+        let mut chunk = [0; CHUNK_SIZE];
+        match self.reader.read(&mut chunk) {
+            Ok(0) => self.finish(),
+            Ok(count) => {
+                self.pending.extend_from_slice(&chunk[..count]);
+                self.decode();
+            }
+            Err(error) if error.kind() == ErrorKind::Interrupted => {}
+            Err(error) => {
+                self.error = Some(error);
+                self.finish();
+            }
+        }
+    }
+
+    // 不合法的 UTF-8 换成 U+FFFD，和 String::from_utf8_lossy 一样
+    fn decode(&mut self) {
+        loop {
+            let error = match std::str::from_utf8(&self.pending) {
+                Ok(valid) => {
+                    self.text.push_str(valid);
+                    self.pending.clear();
+                    return;
+                }
+                Err(error) => error,
+            };
+            let valid = error.valid_up_to();
+            self.text
+                .push_str(&String::from_utf8_lossy(&self.pending[..valid]));
+            match error.error_len() {
+                Some(invalid) => {
+                    self.text.push(char::REPLACEMENT_CHARACTER);
+                    self.pending.drain(..valid + invalid);
+                }
+                None => {
+                    self.pending.drain(..valid);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+        self.text.push_str(&String::from_utf8_lossy(&self.pending));
+        self.pending.clear();
+    }
+}
+
+// position 和 read_position 都是字节偏移，这样切片时不会落在多字节字符的中间
+// 标识符、数字和字符串直接从源码里切出来，不经过中间的 String
 pub struct Lexer {
-    input: String,
+    input: Input,
     position: usize,
     read_position: usize,
     current_character: Option<char>,
+    // 当前字符的行号和列号，读字符的时候顺便更新，不用每个词法单元都从行首重新数一遍
+    line: usize,
+    column: usize,
+    // 超过这个偏移就当作输入结束，见 with_range
+    end: usize,
+    interner: Interner,
 }
 
 impl Lexer {
-    pub fn new(input: String) -> Self {
+    // 已经是 Rc<str> 的源码不会再复制一份
+    pub fn new(input: impl Into<Rc<str>>) -> Self {
+        Lexer::with_input(Input::Shared(input.into()))
+    }
+
+    // 不需要先把整个文件读进 String，边扫描边按块读取
+    // 读取出错时当作输入在这里结束，错误可以通过 read_error 取到
+    pub fn from_reader(reader: impl Read + 'static) -> Self {
+        Lexer::with_input(Input::Streamed(Stream {
+            text: String::new(),
+            base: 0,
+            reader: Box::new(reader),
+            pending: vec![],
+            finished: false,
+            error: None,
+        }))
+    }
+
+    // 只扫描源码里的一段，词法单元的区间和行列号仍然按整个源码计算
+    // line 和 column 是 range.start 所在的行列号，由调用的地方给出，不用从头数一遍
+    pub fn with_range(input: impl Into<Rc<str>>, range: Span, line: usize, column: usize) -> Self {
+        let mut lexer = Self {
+            input: Input::Shared(input.into()),
+            position: range.start,
+            read_position: range.start,
+            current_character: None,
+            line,
+            column,
+            end: range.end,
+            interner: Interner::new(),
+        };
+        lexer.read_character();
+        lexer
+    }
+
+    fn with_input(input: Input) -> Self {
         let mut lexer = Self {
             input,
             position: 0,
             read_position: 0,
             current_character: None,
+            line: 1,
+            column: 1,
+            end: usize::MAX,
+            interner: Interner::new(),
         };
         lexer.read_character();
         lexer
     }
 
+    // 一次性切分整段源码，结果里不包含最后的 EOF
+    pub fn tokenize(input: &str) -> Vec<Token> {
+        Lexer::new(input).collect()
+    }
+
+    // 共享的原始源码，parser 会把它挂到 Program 上
+    // 从 reader 读取的源码没有完整地保留下来，返回 None
+    pub fn source(&self) -> Option<Rc<str>> {
+        match &self.input {
+            Input::Shared(input) => Some(Rc::clone(input)),
+            Input::Streamed(_) => None,
+        }
+    }
+
+    pub fn read_error(&self) -> Option<&io::Error> {
+        match &self.input {
+            Input::Shared(_) => None,
+            Input::Streamed(stream) => stream.error.as_ref(),
+        }
+    }
+
+    // 从 reader 读取时只能取到还没丢掉的部分
+    pub fn slice(&self, span: Span) -> Option<&str> {
+        self.input.text(span.start, span.end)
+    }
+    // 列号按字符计算，一个汉字算一列
     pub fn read_character(&mut self) {
-        self.current_character = self.input.chars().nth(self.read_position);
+        match self.current_character {
+            Some('\n') => {
+                self.line += 1;
+                self.column = 1;
+            }
+            Some(_) => self.column += 1,
+            None => {}
+        }
+        // 当前字符和 peek 的下一个字符都要完整地读进来，一个字符最多 4 个字节
+        self.input.fill(self.read_position + 8);
+        self.current_character = self.character_at(self.read_position);
         self.position = self.read_position;
-        self.read_position += 1;
+        self.read_position += self.current_character.map_or(0, char::len_utf8);
+    }
+
+    // 源码大多是 ASCII，按字节判断，只有遇到多字节字符时才解码
+    fn character_at(&self, offset: usize) -> Option<char> {
+        if offset >= self.end {
+            return None;
+        }
+        let rest = self.input.rest(offset)?;
+        match rest.as_bytes().first() {
+            Some(byte) if byte.is_ascii() => Some(char::from(*byte)),
+            Some(_) => rest.chars().next(),
+            None => None,
+        }
     }
 
     pub fn next_token(&mut self) -> Token {
         let mut need_read_next = true;
         self.skip_whitespace();
-        // can return value in `match`
-        let token =
-            self.current_character
-                .map_or(Token::new(TokenType::EOF, "".to_owned()), |current| {
-                    match current {
-                        '=' => {
-                            if self.peek_character() == '=' {
-                                self.read_character();
-                                // token.push(self.current_character);
-                                Token::new(
-                                    TokenType::Equal,
-                                    // `+` on String's right hands is &str
-                                    current.to_string()
-                                        + &self.current_character.unwrap().to_string(),
-                                )
-                            } else {
-                                Token::new(TokenType::Assign, current.to_string())
-                            }
-                        }
-                        ';' => Token::new(TokenType::Semicolon, current.to_string()),
-                        '(' => Token::new(TokenType::LeftParen, current.to_string()),
-                        ')' => Token::new(TokenType::RightParen, current.to_string()),
-                        ',' => Token::new(TokenType::Comma, current.to_string()),
-                        '+' => Token::new(TokenType::Plus, current.to_string()),
-                        '-' => Token::new(TokenType::Minus, current.to_string()),
-                        '{' => Token::new(TokenType::LeftBrace, current.to_string()),
-                        '}' => Token::new(TokenType::RightBrace, current.to_string()),
-                        '!' => {
-                            if self.peek_character() == '=' {
-                                self.read_character();
-                                Token::new(
-                                    TokenType::NotEqual,
-                                    current.to_string()
-                                        + &self.current_character.unwrap().to_string(),
-                                )
-                            } else {
-                                Token::new(TokenType::Bang, current.to_string())
-                            }
-                        }
-                        '/' => Token::new(TokenType::Slash, current.to_string()),
-                        '*' => Token::new(TokenType::Asterisk, current.to_string()),
-                        '<' => Token::new(TokenType::LessThan, current.to_string()),
-                        '>' => Token::new(TokenType::GreaterThan, current.to_string()),
-                        '"' => Token::new(TokenType::String, self.read_string()),
-                        '[' => Token::new(TokenType::LeftBracket, current.to_string()),
-                        ']' => Token::new(TokenType::RightBracket, current.to_string()),
-                        ':' => Token::new(TokenType::Colon, current.to_string()),
-                        _ => {
-                            if is_letter(current) {
-                                let identifier = self.read_identifier();
-                                let token_type = token::lookup_identifier(&identifier);
-                                need_read_next = false;
-                                Token::new(token_type, identifier)
-                            } else if current.is_ascii_digit() {
-                                need_read_next = false;
-                                Token::new(TokenType::Int, self.read_number())
-                            } else {
-                                Token::new(TokenType::Illegal, current.to_string())
-                            }
-                        }
-                    }
-                });
-
+        let (start, line, column) = (self.position, self.line, self.column);
+        self.input.discard(start);
+        let token_type = match self.current_character {
+            None => TokenType::EOF,
+            Some('=') if self.peek_character() == '=' => {
+                self.read_character();
+                TokenType::Equal
+            }
+            Some('=') => TokenType::Assign,
+            Some(';') => TokenType::Semicolon,
+            Some('(') => TokenType::LeftParen,
+            Some(')') => TokenType::RightParen,
+            Some(',') => TokenType::Comma,
+            Some('+') => TokenType::Plus,
+            Some('-') => TokenType::Minus,
+            Some('{') => TokenType::LeftBrace,
+            Some('}') => TokenType::RightBrace,
+            Some('!') if self.peek_character() == '=' => {
+                self.read_character();
+                TokenType::NotEqual
+            }
+            Some('!') => TokenType::Bang,
+            Some('/') => TokenType::Slash,
+            Some('*') => TokenType::Asterisk,
+            Some('<') => TokenType::LessThan,
+            Some('>') => TokenType::GreaterThan,
+            Some('[') => TokenType::LeftBracket,
+            Some(']') => TokenType::RightBracket,
+            Some(':') => TokenType::Colon,
+            // 字符串的字面量是引号里面的内容，不是源码里的原文
+            Some('"') => {
+                let content = self.read_string();
+                self.read_character();
+                // 跨行的字符串在 Windows 换行的文件里也得到 \n，和 Unix 换行的文件保持一致
+                let value = self
+                    .input
+                    .text(content.start, content.end)
+                    .unwrap_or_default();
+                let literal = if value.contains("\r\n") {
+                    self.interner.intern(&value.replace("\r\n", "\n"))
+                } else {
+                    self.interner.intern(value)
+                };
+                return Token {
+                    token_type: TokenType::String,
+                    literal,
+                    span: Span::new(start, self.position),
+                    line,
+                    column,
+                };
+            }
+            Some(current) if is_letter(current) => {
+                self.read_identifier();
+                need_read_next = false;
+                token::lookup_identifier(self.input.text(start, self.position).unwrap_or_default())
+            }
+            Some(current) if current.is_ascii_digit() => {
+                self.read_number();
+                need_read_next = false;
+                TokenType::Int
+            }
+            Some(_) => TokenType::Illegal,
+        };
         if need_read_next {
             self.read_character();
         }
-        token
+        Token {
+            token_type,
+            literal: self
+                .interner
+                .intern(self.input.text(start, self.position).unwrap_or_default()),
+            span: Span::new(start, self.position),
+            line,
+            column,
+        }
     }
 
-    fn read_identifier(&mut self) -> String {
-        let start_position = self.position;
-        while let Some(current) = self.current_character {
-            if is_letter(current) {
-                self.read_character()
-            } else {
-                break;
-            }
+    fn read_identifier(&mut self) {
+        while self.current_character.is_some_and(is_letter) {
+            self.read_character();
         }
-        // the way to get a substring
-        self.input[start_position..self.position].to_owned()
     }
 
-    fn read_number(&mut self) -> String {
-        let start_position = self.position;
-        while let Some(current) = self.current_character {
-            if current.is_ascii_digit() {
-                self.read_character();
-            } else {
-                break;
-            }
+    fn read_number(&mut self) {
+        while self
+            .current_character
+            .is_some_and(|current| current.is_ascii_digit())
+        {
+            self.read_character();
         }
-        self.input[start_position..self.position].to_owned()
     }
 
-    fn read_string(&mut self) -> String {
+    // 返回引号里面的内容在源码里的区间
+    fn read_string(&mut self) -> Span {
         let start_position = self.read_position;
         self.read_character();
         while let Some(current) = self.current_character {
@@ -132,13 +387,22 @@ impl Lexer {
                 self.read_character();
             }
         }
-        self.input[start_position..self.position].to_owned()
+        Span::new(start_position, self.position)
     }
 
+    // `//` 开头的行注释和空白一样跳过
     fn skip_whitespace(&mut self) {
         while let Some(current) = self.current_character {
+            self.input.discard(self.position);
             if is_whitespace(current) {
                 self.read_character();
+            } else if current == '/' && self.peek_character() == '/' {
+                while self
+                    .current_character
+                    .is_some_and(|current| current != '\n')
+                {
+                    self.read_character();
+                }
             } else {
                 break;
             }
@@ -146,13 +410,23 @@ impl Lexer {
     }
 
     fn peek_character(&self) -> char {
-        self.input
-            .chars()
-            .nth(self.read_position)
-            .unwrap_or_default()
+        self.character_at(self.read_position).unwrap_or_default()
+    }
+}
+
+// 依次产生词法单元，读到 EOF 时结束，EOF 本身不会产生
+// next_token 在 EOF 之后一直返回 EOF，所以结束之后再调用也总是 None
+impl Iterator for Lexer {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.next_token();
+        (token.token_type != TokenType::EOF).then_some(token)
     }
 }
 
+impl FusedIterator for Lexer {}
+
 fn is_letter(character: char) -> bool {
     character.is_ascii_alphabetic() || character == '_'
 }
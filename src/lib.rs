@@ -1,7 +1,29 @@
 pub mod ast;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod compiler;
+pub mod corpus;
+pub mod dot;
+pub mod errors;
 pub mod evaluator;
+pub mod formatter;
+pub mod highlight;
+pub mod incremental;
+pub mod interner;
+pub mod interpreter;
 pub mod lexer;
+pub mod lint;
 pub mod parser;
+pub mod pool;
 pub mod quote;
 pub mod repl;
+pub mod resolver;
+pub mod sexpr;
+pub mod stdlib;
+pub mod sync;
 pub mod token;
+pub mod transpile;
+pub mod typecheck;
+pub mod vm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,212 @@
+// 求值之前对 Program 做的静态检查，只给出警告，不影响求值
+// 目前检查函数里没有用到的 let 绑定、return 之后执行不到的语句和条件恒定的 if
+use std::collections::HashMap;
+
+use crate::ast::expressions::{Expr, Identifier};
+use crate::ast::program::Program;
+use crate::ast::statements::{BlockStatement, LetStatement, Stmt};
+use crate::ast::traits::Node;
+use crate::errors::{message, Diagnostic, MessageId, Severity};
+use crate::sync::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    UnusedBinding,
+    UnreachableCode,
+    ConstantCondition,
+}
+
+// 一条警告，见 errors::Diagnostic
+pub type Warning = Diagnostic<WarningKind>;
+
+fn warning(kind: WarningKind, message: String, node: &dyn Node) -> Warning {
+    Diagnostic::at(kind, Severity::Warning, message, node)
+}
+
+// 按源码顺序返回所有警告
+pub fn lint(program: &Program) -> Vec<Warning> {
+    let mut linter = Linter::default();
+    linter.statements(&program.statements);
+    linter.warnings.sort_by_key(|warning| warning.span.start);
+    linter.warnings
+}
+
+// 一个函数体里的 let 绑定，记下名字是否被引用过
+// 顶层的绑定可能被之后的 REPL 输入或者导入模块的代码用到，不参与检查
+struct Scope<'a> {
+    bindings: HashMap<Rc<str>, (&'a Identifier, bool)>,
+}
+
+#[derive(Default)]
+struct Linter<'a> {
+    scopes: Vec<Scope<'a>>,
+    warnings: Vec<Warning>,
+}
+
+impl<'a> Linter<'a> {
+    fn statements(&mut self, statements: &'a [Stmt]) {
+        let mut returned = false;
+        for statement in statements {
+            if returned {
+                self.warnings.push(warning(
+                    WarningKind::UnreachableCode,
+                    message(MessageId::UnreachableCode, &[]),
+                    statement.as_node(),
+                ));
+                // 只报告第一条执行不到的语句，后面的照常检查
+                returned = false;
+            }
+            self.statement(statement);
+            returned = matches!(statement, Stmt::Return(_)) || returned;
+        }
+    }
+
+    fn statement(&mut self, statement: &'a Stmt) {
+        match statement {
+            Stmt::Let(let_statement) => self.let_statement(let_statement),
+            Stmt::Return(return_statement) => self.expression(&return_statement.return_value),
+            Stmt::Expression(expression_statement) => {
+                self.expression(&expression_statement.expression)
+            }
+            Stmt::Block(block) => self.block(block),
+        }
+    }
+
+    // 函数在调用时才查找自己的名字，递归函数先绑定再检查函数体
+    // 函数体里对自己的递归调用不算用到
+    fn let_statement(&mut self, let_statement: &'a LetStatement) {
+        if matches!(*let_statement.value, Expr::Function(_)) {
+            self.bind(&let_statement.name);
+            self.expression(&let_statement.value);
+            if let Some(scope) = self.scopes.last_mut() {
+                if let Some((name, used)) = scope.bindings.get_mut(&let_statement.name.value) {
+                    if std::ptr::eq(*name, &let_statement.name) {
+                        *used = false;
+                    }
+                }
+            }
+        } else {
+            self.expression(&let_statement.value);
+            self.bind(&let_statement.name);
+        }
+    }
+
+    fn block(&mut self, block: &'a BlockStatement) {
+        self.statements(&block.statements);
+    }
+
+    fn expression(&mut self, expression: &'a Expr) {
+        match expression {
+            Expr::Identifier(identifier) => self.reference(identifier),
+            Expr::Integer(_) | Expr::Boolean(_) | Expr::String(_) => {}
+            // 宏的参数是代码本身，展开之前没法知道里面的名字怎么用
+            Expr::Macro(_) => {}
+            Expr::Prefix(prefix) => self.expression(&prefix.right),
+            Expr::Quote(quote) => self.expression(&quote.expression),
+            Expr::Unquote(unquote) => self.expression(&unquote.expression),
+            Expr::UnquoteSplice(splice) => self.expression(&splice.expression),
+            Expr::Infix(infix) => {
+                self.expression(&infix.left);
+                self.expression(&infix.right);
+            }
+            Expr::If(if_expression) => {
+                if let Some(value) = constant_truthiness(&if_expression.condition) {
+                    self.warnings.push(warning(
+                        WarningKind::ConstantCondition,
+                        message(MessageId::ConstantCondition, &[&value]),
+                        if_expression.condition.as_node(),
+                    ));
+                }
+                self.expression(&if_expression.condition);
+                self.block(&if_expression.consequence);
+                if let Some(alternative) = if_expression.alternative.as_ref() {
+                    self.block(alternative);
+                }
+            }
+            Expr::Function(function) => {
+                // 参数不要求一定用到，放进作用域只是为了遮住外层的同名绑定
+                let bindings = function
+                    .parameters
+                    .iter()
+                    .map(|parameter| (Rc::clone(&parameter.value), (parameter, true)))
+                    .collect();
+                self.scopes.push(Scope { bindings });
+                self.block(&function.body);
+                let scope = self.scopes.pop().expect("scope pushed above");
+                self.report_unused(scope);
+            }
+            Expr::Call(call) => {
+                self.expression(&call.function);
+                for argument in call.arguments.iter() {
+                    self.expression(argument);
+                }
+            }
+            Expr::Array(array) => {
+                for element in array.elements.iter() {
+                    self.expression(element);
+                }
+            }
+            Expr::Index(index) => {
+                self.expression(&index.left);
+                self.expression(&index.index);
+            }
+            Expr::Hash(hash) => {
+                for (key, value) in hash.pairs.iter() {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+        }
+    }
+
+    // 以下划线开头的名字表示有意不用
+    fn bind(&mut self, name: &'a Identifier) {
+        if name.value.starts_with('_') {
+            return;
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            // 同一个函数里重新 let 同名变量时，前一个绑定到这里还没用到就不会再用到了
+            if let Some((previous, false)) =
+                scope.bindings.insert(Rc::clone(&name.value), (name, false))
+            {
+                self.warnings.push(unused_binding(previous));
+            }
+        }
+    }
+
+    fn reference(&mut self, identifier: &Identifier) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some((_, used)) = scope.bindings.get_mut(&identifier.value) {
+                *used = true;
+                return;
+            }
+        }
+    }
+
+    fn report_unused(&mut self, scope: Scope<'a>) {
+        for (name, used) in scope.bindings.into_values() {
+            if !used {
+                self.warnings.push(unused_binding(name));
+            }
+        }
+    }
+}
+
+fn unused_binding(name: &Identifier) -> Warning {
+    warning(
+        WarningKind::UnusedBinding,
+        message(MessageId::UnusedBinding, &[&name.value]),
+        name,
+    )
+}
+
+// 字面量作为条件时结果是确定的，和 is_truthy 一样，除了 false 之外都算真
+fn constant_truthiness(condition: &Expr) -> Option<bool> {
+    match condition {
+        Expr::Boolean(boolean) => Some(boolean.value),
+        Expr::Integer(_) | Expr::String(_) | Expr::Array(_) | Expr::Hash(_) | Expr::Function(_) => {
+            Some(true)
+        }
+        _ => None,
+    }
+}
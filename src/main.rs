@@ -1,13 +1,445 @@
-use implement_parser::repl;
-use std::io::stdout;
+use clap::{Parser as _, Subcommand};
+use implement_parser::ast::program::Program;
+use implement_parser::ast::traits::{AsNode, Node};
+use implement_parser::compiler::disasm::disassemble_bytecode;
+use implement_parser::compiler::{compile, file, Bytecode};
+use implement_parser::evaluator::context::RuntimeContext;
+use implement_parser::evaluator::environment::Environment;
+use implement_parser::evaluator::eval::eval;
+use implement_parser::evaluator::macro_expansion::{
+    define_macros, expand_macro, macro_environment,
+};
+use implement_parser::evaluator::object::Value;
+use implement_parser::formatter::{format_with_comments, FormatOptions, NewlineStyle};
+use implement_parser::stdlib::load_prelude;
+use implement_parser::sync::{Rc, RefCell};
+use implement_parser::vm::Vm;
+use implement_parser::{
+    dot::ast_to_dot, lexer::Lexer, lint::lint, parser::Parser, repl, repl::Backend,
+    resolver::resolve, sexpr::to_sexpr, typecheck::check,
+};
+use std::io::{self, stdout, Read};
+use std::path::{Path, PathBuf};
+use std::{fs, process};
 use uzers::{get_current_uid, get_user_by_uid};
 
+// 不带子命令时执行 FILE 或者 --eval 给出的代码，都没有时启动 REPL
+// 可以读标准输入的子命令在没有给出 FILE 或者 FILE 是 `-` 时读标准输入
+#[derive(clap::Parser)]
+#[command(
+    name = "monkey",
+    bin_name = "monkey",
+    about = "The Monkey programming language",
+    args_conflicts_with_subcommands = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(help = "Script to run, `-` reads standard input")]
+    file: Option<String>,
+    // 紧跟在 FILE 后面的 `--` 由 clap 当作分隔符去掉，脚本参数开始之后的所有参数都原样保留，包括 `--`
+    #[arg(
+        requires = "file",
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        help = "Arguments for the script, returned by args()"
+    )]
+    args: Vec<String>,
+    #[arg(
+        short,
+        long,
+        value_name = "CODE",
+        conflicts_with = "file",
+        help = "Evaluate CODE and print the result"
+    )]
+    eval: Option<String>,
+    #[arg(
+        long,
+        help = "Use the bytecode compiler and VM instead of the evaluator"
+    )]
+    vm: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Start the interactive REPL")]
+    Repl {
+        #[arg(
+            long,
+            help = "Use the bytecode compiler and VM instead of the evaluator"
+        )]
+        vm: bool,
+    },
+    #[command(about = "Run a source file or a compiled .monkeyc file")]
+    Run {
+        file: String,
+        #[arg(long, help = "Run source files with the bytecode compiler and VM")]
+        vm: bool,
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            help = "Arguments for the script, returned by args()"
+        )]
+        args: Vec<String>,
+    },
+    #[command(about = "Print the source in the standard format")]
+    Fmt {
+        file: Option<String>,
+        #[arg(short, long, conflicts_with = "check", help = "Rewrite FILE in place")]
+        write: bool,
+        #[arg(long, help = "Exit with 1 if the source is not formatted")]
+        check: bool,
+    },
+    #[command(about = "Report type errors")]
+    Check { file: Option<String> },
+    #[command(about = "Report undefined names and lint warnings")]
+    Lint { file: Option<String> },
+    #[command(about = "Print the tokens of the source")]
+    Tokens { file: Option<String> },
+    #[command(alias = "parse", about = "Print the syntax tree")]
+    Ast {
+        #[arg(long, help = "Print the tree in Graphviz DOT format")]
+        dot: bool,
+        #[arg(
+            long,
+            conflicts_with = "dot",
+            help = "Print the tree as an S-expression (default)"
+        )]
+        sexpr: bool,
+        file: Option<String>,
+    },
+    #[command(about = "Compile a source file to a .monkeyc file")]
+    Compile {
+        file: String,
+        #[arg(
+            short,
+            long,
+            help = "Output path, defaults to FILE with the .monkeyc extension"
+        )]
+        output: Option<String>,
+    },
+    #[command(about = "Print the annotated bytecode of a file")]
+    Disasm { file: String },
+}
+
 fn main() {
+    let cli = Cli::parse();
+    let backend = |vm: bool| if vm { Backend::Vm } else { Backend::TreeWalker };
+    match cli.command {
+        None => match (cli.eval, cli.file) {
+            (Some(code), _) => eval_code(code, backend(cli.vm)),
+            (None, Some(file)) => run_source(source_path(&file), backend(cli.vm), cli.args),
+            (None, None) => start_repl(backend(cli.vm)),
+        },
+        Some(Command::Repl { vm }) => start_repl(backend(vm)),
+        Some(Command::Run { file, vm, args }) => run_file(&file, backend(vm), args),
+        Some(Command::Fmt { file, write, check }) => {
+            format_file(optional_path(&file), write, check)
+        }
+        Some(Command::Check { file }) => print_type_errors(optional_path(&file)),
+        Some(Command::Lint { file }) => print_warnings(optional_path(&file)),
+        Some(Command::Tokens { file }) => print_tokens(optional_path(&file)),
+        Some(Command::Ast {
+            dot: true, file, ..
+        }) => print_ast(optional_path(&file), ast_to_dot),
+        Some(Command::Ast { file, .. }) => print_ast(optional_path(&file), to_sexpr),
+        Some(Command::Compile { file, output }) => compile_file(&file, output.as_deref()),
+        Some(Command::Disasm { file }) => print!("{}", disassemble_bytecode(&load_bytecode(&file))),
+    }
+}
+
+// `-` 表示标准输入
+fn source_path(path: &str) -> Option<&str> {
+    (path != "-").then_some(path)
+}
+
+fn optional_path(path: &Option<String>) -> Option<&str> {
+    path.as_deref().and_then(source_path)
+}
+
+fn start_repl(backend: Backend) {
     let user = get_user_by_uid(get_current_uid()).expect("Can not get current user!");
     println!(
         "Hello {:?}! This is the Monkey programming language!",
         user.name()
     );
     println!("Feel free to type in commands");
-    repl::start(stdout()).unwrap();
+    let options = repl::Options {
+        backend,
+        ..repl::Options::default()
+    };
+    let code = repl::start_interactive(stdout(), options).unwrap();
+    process::exit(code as i32);
+}
+
+fn print_ast(path: Option<&str>, render: fn(&dyn Node) -> String) {
+    let program = parse_source(path);
+    if program.is_empty() {
+        eprintln!("{}: nothing to parse", path.unwrap_or("<stdin>"));
+        return;
+    }
+    print!("{}", render(program.as_node()));
+}
+
+// 名字检查和 lint 的结果按位置排在一起，有错误时退出码为 1
+fn print_warnings(path: Option<&str>) {
+    let program = parse_source(path);
+    let diagnostics = resolve(&program);
+    let has_errors = diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.kind.is_error());
+    let mut lines = diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.span.start, diagnostic.to_string()))
+        .chain(
+            lint(&program)
+                .iter()
+                .map(|warning| (warning.span.start, warning.to_string())),
+        )
+        .collect::<Vec<_>>();
+    lines.sort_by_key(|(start, _)| *start);
+    for (_, line) in lines {
+        println!("{}", line);
+    }
+    if has_errors {
+        process::exit(1);
+    }
+}
+
+// 有类型错误时退出码为 1
+fn print_type_errors(path: Option<&str>) {
+    let errors = check(&parse_source(path));
+    for error in &errors {
+        println!("{}", error);
+    }
+    if !errors.is_empty() {
+        process::exit(1);
+    }
+}
+
+// 宏展开之后编译成 .monkeyc 文件，默认和源文件放在一起
+fn compile_file(path: &str, output: Option<&str>) {
+    let bytecode = compile(&expand(parse_source(Some(path)), path)).unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    });
+    let bytes = file::encode(&bytecode).unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    });
+    let output = output.map_or_else(
+        || Path::new(path).with_extension(file::EXTENSION),
+        PathBuf::from,
+    );
+    if let Err(error) = fs::write(&output, bytes) {
+        eprintln!("failed to write {}: {}", output.display(), error);
+        process::exit(1);
+    }
+}
+
+// 执行整个源文件，没有给出文件时从标准输入读取，args 是脚本的命令行参数
+fn run_source(path: Option<&str>, backend: Backend, args: Vec<String>) {
+    run_script(read_source(path), script_context(path, args), backend);
+}
+
+// 宏在脚本自己的上下文里展开，import 的根目录、参数和限制和求值时一样
+fn run_script(source: String, context: RuntimeContext, backend: Backend) {
+    let macros = macro_environment(&context);
+    let program = expand_with(parse(source), &macros);
+    exit_with(evaluate(&program, context.with_macros(macros), backend));
+}
+
+// .monkeyc 文件总是交给虚拟机执行，文件只读一次
+fn run_file(path: &str, backend: Backend, args: Vec<String>) {
+    let Some(path) = source_path(path) else {
+        return run_source(None, backend, args);
+    };
+    let bytes = read_file(path);
+    let context = script_context(Some(path), args);
+    if !bytes.starts_with(file::MAGIC) {
+        return run_script(decode_source(bytes), context, backend);
+    }
+    let bytecode = file::decode(&bytes).unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    });
+    exit_with(Vm::with_environment(new_environment(context)).run(&bytecode));
+}
+
+// `--eval` 的代码求值之后打印结果，结果是 null 时不打印
+fn eval_code(code: String, backend: Backend) {
+    let context = RuntimeContext::new();
+    let macros = macro_environment(&context);
+    let program = expand_with(parse(code), &macros);
+    let value = evaluate(&program, context.with_macros(macros), backend);
+    if !matches!(
+        value.as_ref(),
+        Value::Null(_) | Value::Error(_) | Value::Exit(_)
+    ) {
+        println!("{}", value.inspect());
+    }
+    exit_with(value);
+}
+
+// import() 的相对路径从脚本所在的目录开始找
+fn script_context(path: Option<&str>, args: Vec<String>) -> RuntimeContext {
+    let context = RuntimeContext::new().with_args(args);
+    match path.and_then(|path| Path::new(path).parent()) {
+        Some(root) => context.with_module_root(root.to_path_buf()),
+        None => context,
+    }
+}
+
+fn new_environment(context: RuntimeContext) -> Rc<RefCell<Environment>> {
+    let env = Rc::new(RefCell::new(Environment::with_context(Rc::new(
+        RefCell::new(context),
+    ))));
+    load_prelude(&env);
+    env
+}
+
+fn evaluate(program: &Program, context: RuntimeContext, backend: Backend) -> Rc<Value> {
+    let env = new_environment(context);
+    match backend {
+        Backend::TreeWalker => eval(program.as_node(), env),
+        Backend::Vm => {
+            let bytecode = compile(program).unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                process::exit(1);
+            });
+            Vm::with_environment(env).run(&bytecode)
+        }
+    }
+}
+
+// 格式化之后的源码打印出来，或者写回文件；--check 时只检查，不一致的以 1 退出
+fn format_file(path: Option<&str>, write: bool, check: bool) {
+    let source = read_source(path);
+    let options = FormatOptions {
+        newline: NewlineStyle::detect(&source),
+        ..FormatOptions::default()
+    };
+    let formatted = format_with_comments(&source, &parse(source.clone()), &options);
+    let name = path.unwrap_or("<stdin>");
+    if check {
+        if formatted != source {
+            eprintln!("{}: not formatted", name);
+            process::exit(1);
+        }
+        return;
+    }
+    match (write, path) {
+        (true, Some(path)) => {
+            if formatted != source {
+                if let Err(error) = fs::write(path, formatted) {
+                    eprintln!("failed to write {}: {}", path, error);
+                    process::exit(1);
+                }
+            }
+        }
+        (true, None) => {
+            eprintln!("--write needs a file");
+            process::exit(2);
+        }
+        (false, _) => print!("{}", formatted),
+    }
+}
+
+// 一行一个词法单元：位置、类型和字面量
+fn print_tokens(path: Option<&str>) {
+    for token in Lexer::new(read_source(path)) {
+        println!(
+            "{}:{}\t{:?}\t{}",
+            token.line, token.column, token.token_type, token.literal
+        );
+    }
+}
+
+// 运行时错误打印出来并以 1 退出，exit() 给出的退出码原样返回，exit() 只接受 0 到 255，转换不会截断
+fn exit_with(value: Rc<Value>) {
+    match value.as_ref() {
+        Value::Error(_) => {
+            eprintln!("{}", value.inspect());
+            process::exit(1);
+        }
+        Value::Exit(exit) => process::exit(exit.code as i32),
+        _ => {}
+    }
+}
+
+// 按文件开头的魔数区分 .monkeyc 文件和源文件
+fn load_bytecode(path: &str) -> Bytecode {
+    let bytes = read_file(path);
+    let bytecode = if bytes.starts_with(file::MAGIC) {
+        file::decode(&bytes)
+    } else {
+        compile(&expand(parse(decode_source(bytes)), path))
+    };
+    bytecode.unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    })
+}
+
+// 不运行脚本时也按脚本所在的目录展开宏，宏体里的 import 和运行时找到同样的模块
+fn expand(program: Program, path: &str) -> Program {
+    expand_with(
+        program,
+        &macro_environment(&script_context(Some(path), vec![])),
+    )
+}
+
+// 宏定义留在 macro_env 里，求值时的 macroexpand 还能用到
+fn expand_with(mut program: Program, macro_env: &Rc<RefCell<Environment>>) -> Program {
+    define_macros(&mut program, Rc::clone(macro_env));
+    if let Err(error) = expand_macro(&mut program, Rc::clone(macro_env)) {
+        exit_with(Rc::new(Value::Error(error)));
+    }
+    program
+}
+
+fn read_file(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|error| {
+        eprintln!("failed to read {}: {}", path, error);
+        process::exit(1);
+    })
+}
+
+// 和 read_to_string 一样，不是 UTF-8 的源码当作读取失败
+fn decode_source(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes).unwrap_or_else(|error| {
+        eprintln!("failed to read source: {}", error);
+        process::exit(1);
+    })
+}
+
+// 没有给出文件时从标准输入读取源码
+fn read_source(path: Option<&str>) -> String {
+    let source = match path {
+        Some(path) => fs::read_to_string(path),
+        None => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source).map(|_| source)
+        }
+    };
+    source.unwrap_or_else(|error| {
+        eprintln!("failed to read source: {}", error);
+        process::exit(1);
+    })
+}
+
+fn parse_source(path: Option<&str>) -> Program {
+    parse(read_source(path))
+}
+
+// 有语法错误时打印出来并退出
+fn parse(source: String) -> Program {
+    Parser::new(Lexer::new(source))
+        .parse()
+        .unwrap_or_else(|errors| {
+            for error in &errors {
+                eprintln!("{}", error);
+            }
+            process::exit(1);
+        })
 }
@@ -1,30 +1,41 @@
-use by_address::ByAddress;
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
 
 use crate::ast::expressions::{
-    ArrayLiteral, Boolean, CallExpression, FunctionLiteral, HashLiteral, Identifier, IfExpression,
-    IndexExpression, InfixExpression, IntegerLiteral, MacroLiteral, PrefixExpression,
-    StringLiteral,
+    ArrayLiteral, Boolean, CallExpression, Expr, FunctionLiteral, HashLiteral, Identifier,
+    IfExpression, IndexExpression, InfixExpression, IntegerLiteral, MacroLiteral, PrefixExpression,
+    QuoteExpression, StringLiteral, UnquoteExpression, UnquoteSpliceExpression,
 };
 use crate::ast::program::Program;
-use crate::ast::statements::{BlockStatement, ExpressionStatement, LetStatement, ReturnStatement};
-use crate::ast::traits::{Expression, Statement};
-use crate::token::TokenType;
-use crate::{lexer::Lexer, token::Token};
+use crate::ast::statements::{
+    BlockStatement, ExpressionStatement, LetStatement, ReturnStatement, Stmt,
+};
+use crate::errors::{message, MessageId, ParseError};
+use crate::lexer::{Lexer, Span};
+use crate::token::{suggest_keyword, Token, TokenType};
 
-type PrefixParseFn = fn(&mut Parser) -> Result<Box<dyn Expression>, String>;
-type InfixParseFn = fn(&mut Parser, Box<dyn Expression>) -> Result<Box<dyn Expression>, String>;
+type PrefixParseFn = fn(&mut Parser) -> Result<Expr, ParseError>;
+type InfixParseFn = fn(&mut Parser, Expr) -> Result<Expr, ParseError>;
 
 pub struct Parser {
     lexer: Lexer,
     current_token: Option<Token>,
     peek_token: Option<Token>,
+    errors: Vec<ParseError>,
+    // 最近遇到的疑似拼错的关键字，下一个错误会带上建议
+    typo: Option<&'static str>,
+    // 和 errors 一一对应的文字，保留给还在直接读这个字段的调用方
     pub error_messages: Vec<String>,
     prefix_parse_fns: HashMap<TokenType, PrefixParseFn>,
     infix_parse_fns: HashMap<TokenType, InfixParseFn>,
+    // 正在解析的表达式的嵌套层数，见 MAX_NESTING
+    depth: usize,
 }
 
+// 表达式最多嵌套的层数，括号、前缀运算符、代码块都算一层
+// 解析和之后遍历语法树都是递归的，嵌套太深会耗尽栈空间
+pub const MAX_NESTING: usize = 256;
+
 #[derive(Debug, Clone, Copy)]
 enum ExpressionPrecedence {
     Lowest = 1,      // 标识符
@@ -52,7 +63,7 @@ static PRECEDENCES: Lazy<HashMap<TokenType, ExpressionPrecedence>> = Lazy::new(|
     ])
 });
 
-type HashLiteralPairsType = HashMap<ByAddress<Box<dyn Expression>>, Box<dyn Expression>>;
+type HashLiteralPairsType = Vec<(Expr, Expr)>;
 
 impl Parser {
     pub fn new(lexer: Lexer) -> Parser {
@@ -60,9 +71,12 @@ impl Parser {
             lexer,
             current_token: None,
             peek_token: None,
+            errors: vec![],
+            typo: None,
             error_messages: vec![],
             prefix_parse_fns: HashMap::new(),
             infix_parse_fns: HashMap::new(),
+            depth: 0,
         };
         parser.register_prefix(TokenType::Ident, Parser::parse_identifier);
         parser.register_prefix(TokenType::Int, Parser::parse_integer_literal);
@@ -77,6 +91,9 @@ impl Parser {
         parser.register_prefix(TokenType::LeftBracket, Parser::parse_array_literal);
         parser.register_prefix(TokenType::LeftBrace, Parser::parse_hash_literal);
         parser.register_prefix(TokenType::Macro, Parser::parse_macro_literal);
+        parser.register_prefix(TokenType::Quote, Parser::parse_quote_expression);
+        parser.register_prefix(TokenType::Unquote, Parser::parse_quote_expression);
+        parser.register_prefix(TokenType::UnquoteSplice, Parser::parse_quote_expression);
 
         parser.register_infix(TokenType::Plus, Parser::parse_infix_expression);
         parser.register_infix(TokenType::Minus, Parser::parse_infix_expression);
@@ -95,23 +112,49 @@ impl Parser {
 
     fn next_token(&mut self) {
         self.current_token = self.peek_token.take();
-        self.peek_token = Some(self.lexer.next_token());
+        // 不认识的字符在这里报告并跳过，不会再以 no prefix parse function 的形式出现
+        let mut token = self.lexer.next_token();
+        while token.token_type == TokenType::Illegal {
+            let message = message(MessageId::IllegalCharacter, &[&token.literal]);
+            self.record_error(ParseError {
+                got: Some(TokenType::Illegal),
+                ..ParseError::at(message, &token)
+            });
+            token = self.lexer.next_token();
+        }
+        self.peek_token = Some(token);
+    }
+
+    // 有语法错误时返回全部错误，不需要再检查 error_messages
+    pub fn parse(&mut self) -> Result<Program, Vec<ParseError>> {
+        let program = self.parse_program();
+        if self.errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(self.errors.clone())
+        }
+    }
+
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
     }
 
     pub fn parse_program(&mut self) -> Program {
-        let mut program = Program { statements: vec![] };
+        let mut program = Program {
+            statements: vec![],
+            source: self.lexer.source(),
+        };
 
         loop {
             if let Some(token) = self.current_token.clone() {
                 if token.token_type != TokenType::EOF {
-                    self.parse_statement().map_or_else(
-                        |error_message| {
-                            self.error_messages.push(error_message);
-                        },
-                        |statement| {
-                            program.statements.push(statement);
-                        },
-                    );
+                    match self.parse_statement() {
+                        Ok(statement) => program.statements.push(statement),
+                        Err(error) => {
+                            self.record_error(error);
+                            self.synchronize();
+                        }
+                    }
                     self.next_token();
                 } else {
                     break;
@@ -121,24 +164,65 @@ impl Parser {
         program
     }
 
-    fn parse_statement(&mut self) -> Result<Box<dyn Statement>, String> {
+    fn parse_statement(&mut self) -> Result<Stmt, ParseError> {
         let current_token_type = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .token_type;
-        match current_token_type {
+        let statement = match current_token_type {
             TokenType::Let => self.parse_let_statement(),
             TokenType::Return => self.parse_return_statement(),
+            TokenType::Ident => {
+                self.check_statement_keyword_typo()?;
+                self.parse_expression_statement()
+            }
             _ => self.parse_expression_statement(),
+        };
+        // 以分号结束的语句已经完整了，前面的拼写和后面的错误无关
+        if statement.is_ok() && self.current_token_is(TokenType::Semicolon) {
+            self.typo = None;
         }
+        statement
     }
 
-    fn parse_let_statement(&mut self) -> Result<Box<dyn Statement>, String> {
+    // `retrun x;`、`lett x = 1;` 本来会被当成两个相邻的表达式，这里直接报错
+    // 只检查同一行里紧跟着另一个表达式的情况，换行分开的两条语句仍然合法
+    fn check_statement_keyword_typo(&mut self) -> Result<(), ParseError> {
+        let (Some(current), Some(peek)) = (self.current_token.as_ref(), self.peek_token.as_ref())
+        else {
+            return Ok(());
+        };
+        let keyword = suggest_keyword(&current.literal);
+        let adjacent = matches!(
+            peek.token_type,
+            TokenType::Ident
+                | TokenType::Int
+                | TokenType::String
+                | TokenType::True
+                | TokenType::False
+        ) && peek.line == current.line;
+        if !adjacent || !matches!(keyword, Some("let" | "return")) {
+            return Ok(());
+        }
+        self.typo = keyword;
+        Err(ParseError {
+            got: Some(peek.token_type),
+            ..ParseError::at(
+                message(
+                    MessageId::UnexpectedAfterIdentifier,
+                    &[&format!("{:?}", peek.token_type), &current.literal],
+                ),
+                peek,
+            )
+        })
+    }
+
+    fn parse_let_statement(&mut self) -> Result<Stmt, ParseError> {
         let let_token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
 
         self.expect_peek_token(TokenType::Ident)?;
@@ -148,75 +232,107 @@ impl Parser {
             value: iden.literal,
         };
 
+        let type_annotation = if self.peek_token_is(TokenType::Colon) {
+            self.next_token();
+            self.expect_peek_token(TokenType::Ident)?;
+            let type_token = self.current_token.as_ref().unwrap().clone();
+            Some(Identifier {
+                token: type_token.clone(),
+                value: type_token.literal,
+            })
+        } else {
+            None
+        };
+
         self.expect_peek_token(TokenType::Assign)?;
         self.next_token();
 
-        let let_statement = LetStatement {
+        let value = Box::new(self.parse_expression(ExpressionPrecedence::Lowest)?);
+        Ok(Stmt::Let(LetStatement {
             token: let_token,
             name: identifier,
-            value: self.parse_expression(ExpressionPrecedence::Lowest)?,
-        };
-        if self.peek_token_is(TokenType::Semicolon) {
-            self.next_token();
-        }
-        Ok(Box::new(let_statement))
+            type_annotation,
+            value,
+            closing: self.skip_semicolon(),
+        }))
     }
 
-    fn parse_return_statement(&mut self) -> Result<Box<dyn Statement>, String> {
+    fn parse_return_statement(&mut self) -> Result<Stmt, ParseError> {
         let return_token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
 
         self.next_token();
 
         let return_value = self.parse_expression(ExpressionPrecedence::Lowest)?;
-
-        if self.peek_token_is(TokenType::Semicolon) {
-            self.next_token();
-        }
-
-        Ok(Box::new(ReturnStatement {
+        Ok(Stmt::Return(ReturnStatement {
             token: return_token,
-            return_value,
+            return_value: Box::new(return_value),
+            closing: self.skip_semicolon(),
         }))
     }
 
-    fn parse_expression_statement(&mut self) -> Result<Box<dyn Statement>, String> {
+    fn parse_expression_statement(&mut self) -> Result<Stmt, ParseError> {
         let token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
-        let statement = Ok(Box::new(ExpressionStatement {
+        let expression = Box::new(self.parse_expression(ExpressionPrecedence::Lowest)?);
+        Ok(Stmt::Expression(ExpressionStatement {
             token,
-            expression: self.parse_expression(ExpressionPrecedence::Lowest)?,
-        }) as Box<dyn Statement>);
+            expression,
+            closing: self.skip_semicolon(),
+        }))
+    }
 
-        if self.peek_token_is(TokenType::Semicolon) {
-            self.next_token();
+    // 语句后面可以有一个分号，返回分号的区间，没有分号时返回空区间
+    fn skip_semicolon(&mut self) -> Span {
+        if !self.peek_token_is(TokenType::Semicolon) {
+            return Span::default();
         }
+        self.next_token();
+        self.current_span()
+    }
 
-        statement
+    // 结尾的括号刚被 expect_peek_token 读到，是当前的词法单元
+    fn current_span(&self) -> Span {
+        self.current_token
+            .as_ref()
+            .map_or_else(Span::default, |token| token.span)
+    }
+
+    fn parse_expression(&mut self, precedence: ExpressionPrecedence) -> Result<Expr, ParseError> {
+        if self.depth >= MAX_NESTING {
+            return Err(self.error(&message(MessageId::NestingTooDeep, &[&MAX_NESTING])));
+        }
+        self.depth += 1;
+        let expression = self.parse_nested_expression(precedence);
+        self.depth -= 1;
+        expression
     }
 
-    fn parse_expression(
+    fn parse_nested_expression(
         &mut self,
         precedence: ExpressionPrecedence,
-    ) -> Result<Box<dyn Expression>, String> {
+    ) -> Result<Expr, ParseError> {
         let token_type = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .token_type;
         let prefix_parse_function = self
             .prefix_parse_fns
             .get(&token_type) // 感觉这里加了 `as_ref` 就变成了对内部 token 的引用了
-            .ok_or(format!(
-                "No prefix parse function for {:?} found",
-                token_type
-            ))?;
+            .ok_or_else(|| ParseError {
+                got: Some(token_type),
+                ..self.error(&message(
+                    MessageId::NoPrefixParseFunction,
+                    &[&format!("{:?}", token_type)],
+                ))
+            })?;
         let mut left_expression = prefix_parse_function(self)?;
 
         while !self.peek_token_is(TokenType::Semicolon)
@@ -225,12 +341,12 @@ impl Parser {
             let peek_token_type = self
                 .peek_token
                 .as_ref()
-                .ok_or("Peek token is None")?
+                .ok_or_else(|| self.error("Peek token is None"))?
                 .token_type;
             match self.infix_parse_fns.get(&peek_token_type) {
-                Some(infix_parse_fn) => {
-                    left_expression = infix_parse_fn(self, left_expression)?;
+                Some(&infix_parse_fn) => {
                     self.next_token();
+                    left_expression = infix_parse_fn(self, left_expression)?;
                 }
                 None => {
                     return Ok(left_expression);
@@ -240,67 +356,69 @@ impl Parser {
         Ok(left_expression)
     }
 
-    fn parse_identifier(&mut self) -> Result<Box<dyn Expression>, String> {
+    fn parse_identifier(&mut self) -> Result<Expr, ParseError> {
         let token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
-        Ok(Box::new(Identifier {
+        if let Some(keyword) = suggest_keyword(&token.literal) {
+            self.typo = Some(keyword);
+        }
+        Ok(Expr::Identifier(Identifier {
             token: token.clone(),
-            value: token.literal,
-        }) as Box<dyn Expression>)
+            value: token.literal.clone(),
+        }))
     }
 
-    fn parse_integer_literal(&mut self) -> Result<Box<dyn Expression>, String> {
+    fn parse_integer_literal(&mut self) -> Result<Expr, ParseError> {
         let token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
-        Ok(Box::new(IntegerLiteral {
+        Ok(Expr::Integer(IntegerLiteral {
             token: token.clone(),
-            value: token
-                .literal
-                .parse()
-                .map_err(|error| format!("{:?}", error))?, // TODO
-        }) as Box<dyn Expression>)
+            value: token.literal.parse().map_err(|_| {
+                self.error(&message(
+                    MessageId::InvalidIntegerLiteral,
+                    &[&token.literal],
+                ))
+            })?,
+        }))
     }
 
-    fn parse_prefix_expression(&mut self) -> Result<Box<dyn Expression>, String> {
+    fn parse_prefix_expression(&mut self) -> Result<Expr, ParseError> {
         let token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
         self.next_token(); // 只有需要继续解析才需要调用 next_token
-        Ok(Box::new(PrefixExpression {
+        Ok(Expr::Prefix(PrefixExpression {
             token: token.clone(),
             operator: token.literal,
-            right: self.parse_expression(ExpressionPrecedence::Prefix)?,
-        }) as Box<dyn Expression>)
+            right: Box::new(self.parse_expression(ExpressionPrecedence::Prefix)?),
+        }))
     }
 
-    fn parse_infix_expression(
-        &mut self,
-        left: Box<dyn Expression>,
-    ) -> Result<Box<dyn Expression>, String> {
+    fn parse_infix_expression(&mut self, left: Expr) -> Result<Expr, ParseError> {
         let token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
         let precedence = self.current_precedence();
         self.next_token();
-        Ok(Box::new(InfixExpression {
+        Ok(Expr::Infix(InfixExpression {
             token: token.clone(),
-            left,
+            left: Box::new(left),
             operator: token.literal,
-            right: self.parse_expression(precedence)?,
-        }) as Box<dyn Expression>)
+            right: Box::new(self.parse_expression(precedence)?),
+        }))
     }
 
-    fn parse_grouped_expression(&mut self) -> Result<Box<dyn Expression>, String> {
+    fn parse_grouped_expression(&mut self) -> Result<Expr, ParseError> {
         self.next_token();
 
         let expression = self.parse_expression(ExpressionPrecedence::Lowest)?;
@@ -308,23 +426,23 @@ impl Parser {
         Ok(expression)
     }
 
-    fn parse_boolean(&mut self) -> Result<Box<dyn Expression>, String> {
+    fn parse_boolean(&mut self) -> Result<Expr, ParseError> {
         let token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
-        Ok(Box::new(Boolean {
+        Ok(Expr::Boolean(Boolean {
             token,
             value: self.current_token_is(TokenType::True),
         }))
     }
 
-    fn parse_if_expression(&mut self) -> Result<Box<dyn Expression>, String> {
+    fn parse_if_expression(&mut self) -> Result<Expr, ParseError> {
         let token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
         self.expect_peek_token(TokenType::LeftParen)?;
         self.next_token();
@@ -334,7 +452,7 @@ impl Parser {
         let consequence = self.parse_block_statement()?;
         let mut if_expression = IfExpression {
             token,
-            condition,
+            condition: Box::new(condition),
             consequence,
             alternative: None,
         };
@@ -345,26 +463,26 @@ impl Parser {
             if_expression.alternative = Some(self.parse_block_statement()?);
         }
 
-        Ok(Box::new(if_expression))
+        Ok(Expr::If(if_expression))
     }
 
-    fn parse_function_literal(&mut self) -> Result<Box<dyn Expression>, String> {
+    fn parse_function_literal(&mut self) -> Result<Expr, ParseError> {
         let token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
         self.expect_peek_token(TokenType::LeftParen)?;
         let parameters = self.parse_function_parameters()?;
         self.expect_peek_token(TokenType::LeftBrace)?;
-        Ok(Box::new(FunctionLiteral {
+        Ok(Expr::Function(FunctionLiteral {
             token,
             parameters,
             body: self.parse_block_statement()?,
         }))
     }
 
-    fn parse_function_parameters(&mut self) -> Result<Vec<Identifier>, String> {
+    fn parse_function_parameters(&mut self) -> Result<Vec<Identifier>, ParseError> {
         let mut idents = Vec::new();
         self.next_token();
         if self.current_token_is(TokenType::RightParen) {
@@ -375,7 +493,7 @@ impl Parser {
             let token = self
                 .current_token
                 .as_ref()
-                .ok_or("Current token is None")?
+                .ok_or_else(|| self.error("Current token is None"))?
                 .clone();
             let identifier = Identifier {
                 token: token.clone(),
@@ -393,27 +511,22 @@ impl Parser {
         Ok(idents)
     }
 
-    fn parse_call_expression(
-        &mut self,
-        left: Box<dyn Expression>,
-    ) -> Result<Box<dyn Expression>, String> {
+    fn parse_call_expression(&mut self, left: Expr) -> Result<Expr, ParseError> {
         let token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
         let arguments = self.parse_expression_list(TokenType::RightParen)?;
-        Ok(Box::new(CallExpression {
+        Ok(Expr::Call(CallExpression {
             token,
-            function: left,
+            function: Box::new(left),
             arguments,
+            closing: self.current_span(),
         }))
     }
 
-    fn parse_expression_list(
-        &mut self,
-        end: TokenType,
-    ) -> Result<Vec<Box<dyn Expression>>, String> {
+    fn parse_expression_list(&mut self, end: TokenType) -> Result<Vec<Expr>, ParseError> {
         let mut args = Vec::new();
         self.next_token();
         if self.current_token_is(end) {
@@ -435,74 +548,100 @@ impl Parser {
         Ok(args)
     }
 
-    fn parse_index_expression(
-        &mut self,
-        left: Box<dyn Expression>,
-    ) -> Result<Box<dyn Expression>, String> {
+    fn parse_index_expression(&mut self, left: Expr) -> Result<Expr, ParseError> {
         let token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
         self.next_token();
         let index = self.parse_expression(ExpressionPrecedence::Lowest)?;
         self.expect_peek_token(TokenType::RightBracket)?;
-        Ok(Box::new(IndexExpression { token, left, index }) as Box<dyn Expression>)
+        Ok(Expr::Index(IndexExpression {
+            token,
+            left: Box::new(left),
+            index: Box::new(index),
+            closing: self.current_span(),
+        }))
     }
 
-    fn parse_block_statement(&mut self) -> Result<BlockStatement, String> {
+    fn parse_block_statement(&mut self) -> Result<BlockStatement, ParseError> {
         let token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
         let mut statements = vec![];
         self.next_token();
         while !self.current_token_is(TokenType::RightBrace)
             && !self.current_token_is(TokenType::EOF)
         {
-            if let Ok(statement) = self.parse_statement() {
-                statements.push(statement);
+            match self.parse_statement() {
+                Ok(statement) => statements.push(statement),
+                Err(error) => {
+                    self.record_error(error);
+                    self.synchronize();
+                    // 出错的词法单元本身就是块的结尾
+                    if self.current_token_is(TokenType::RightBrace) {
+                        break;
+                    }
+                }
             }
             self.next_token();
         }
-        Ok(BlockStatement { token, statements })
+        let closing = match self.current_token_is(TokenType::RightBrace) {
+            true => self.current_span(),
+            false => Span::default(),
+        };
+        Ok(BlockStatement {
+            token,
+            statements,
+            closing,
+        })
     }
 
-    fn parse_string_literal(&mut self) -> Result<Box<dyn Expression>, String> {
+    fn parse_string_literal(&mut self) -> Result<Expr, ParseError> {
         let token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
-        Ok(Box::new(StringLiteral {
+        Ok(Expr::String(StringLiteral {
             token: token.clone(),
-            value: token.literal,
-        }) as Box<dyn Expression>)
+            value: token.literal.to_string(),
+        }))
     }
 
-    fn parse_array_literal(&mut self) -> Result<Box<dyn Expression>, String> {
+    fn parse_array_literal(&mut self) -> Result<Expr, ParseError> {
         let token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
         let elements = self.parse_expression_list(TokenType::RightBracket)?;
-        Ok(Box::new(ArrayLiteral { token, elements }) as Box<dyn Expression>)
+        Ok(Expr::Array(ArrayLiteral {
+            token,
+            elements,
+            closing: self.current_span(),
+        }))
     }
 
-    fn parse_hash_literal(&mut self) -> Result<Box<dyn Expression>, String> {
+    fn parse_hash_literal(&mut self) -> Result<Expr, ParseError> {
         let token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
         let pairs = self.parse_expression_pair()?;
-        Ok(Box::new(HashLiteral { token, pairs }) as Box<dyn Expression>)
+        Ok(Expr::Hash(HashLiteral {
+            token,
+            pairs,
+            closing: self.current_span(),
+        }))
     }
 
-    fn parse_expression_pair(&mut self) -> Result<HashLiteralPairsType, String> {
-        let mut pairs = HashMap::new();
+    fn parse_expression_pair(&mut self) -> Result<HashLiteralPairsType, ParseError> {
+        let mut pairs = Vec::new();
         self.next_token();
         if self.current_token_is(TokenType::RightBrace) {
             return Ok(pairs);
@@ -513,7 +652,7 @@ impl Parser {
             self.expect_peek_token(TokenType::Colon)?;
             self.next_token();
             let value = self.parse_expression(ExpressionPrecedence::Lowest)?;
-            pairs.insert(ByAddress(key), value);
+            pairs.push((key, value));
             if self.peek_token_is(TokenType::Comma) {
                 self.next_token();
                 self.next_token();
@@ -526,46 +665,118 @@ impl Parser {
         Ok(pairs)
     }
 
-    fn parse_macro_literal(&mut self) -> Result<Box<dyn Expression>, String> {
+    fn parse_macro_literal(&mut self) -> Result<Expr, ParseError> {
         let token = self
             .current_token
             .as_ref()
-            .ok_or("Current token is None")?
+            .ok_or_else(|| self.error("Current token is None"))?
             .clone();
         self.expect_peek_token(TokenType::LeftParen)?;
         let parameters = self.parse_function_parameters()?;
         self.expect_peek_token(TokenType::LeftBrace)?;
-        Ok(Box::new(MacroLiteral {
+        Ok(Expr::Macro(MacroLiteral {
             token,
             parameters,
             body: self.parse_block_statement()?,
         }))
     }
 
+    // quote(...)、unquote(...) 和 unquote_splice(...) 的括号里必须正好是一个表达式
+    fn parse_quote_expression(&mut self) -> Result<Expr, ParseError> {
+        let token = self
+            .current_token
+            .as_ref()
+            .ok_or_else(|| self.error("Current token is None"))?
+            .clone();
+        self.expect_peek_token(TokenType::LeftParen)?;
+        self.next_token();
+        let expression = Box::new(self.parse_expression(ExpressionPrecedence::Lowest)?);
+        self.expect_peek_token(TokenType::RightParen)?;
+        let closing = self.current_span();
+        Ok(match token.token_type {
+            TokenType::Quote => Expr::Quote(QuoteExpression {
+                token,
+                expression,
+                closing,
+            }),
+            TokenType::Unquote => Expr::Unquote(UnquoteExpression {
+                token,
+                expression,
+                closing,
+            }),
+            _ => Expr::UnquoteSplice(UnquoteSpliceExpression {
+                token,
+                expression,
+                closing,
+            }),
+        })
+    }
+
     fn current_token_is(&self, token_type: TokenType) -> bool {
         self.current_token
             .as_ref()
-            .map_or(false, |token| token.token_type == token_type)
+            .is_some_and(|token| token.token_type == token_type)
     }
 
     fn peek_token_is(&self, token_type: TokenType) -> bool {
         self.peek_token
             .as_ref()
-            .map_or(false, |token| token.token_type == token_type)
+            .is_some_and(|token| token.token_type == token_type)
     }
 
-    fn expect_peek_token(&mut self, token_type: TokenType) -> Result<(), String> {
+    fn expect_peek_token(&mut self, token_type: TokenType) -> Result<(), ParseError> {
         if self.peek_token_is(token_type) {
             self.next_token();
             Ok(())
         } else {
-            Err(format!(
-                "expected next token to be {:?}, got {:?} instead",
-                token_type,
-                self.peek_token
-                    .as_ref()
-                    .map_or(TokenType::Illegal, |token| token.token_type)
-            ))
+            let peek_token = self
+                .peek_token
+                .clone()
+                .unwrap_or_else(|| Token::new(TokenType::Illegal, String::new()));
+            let message = message(
+                MessageId::ExpectedNextToken,
+                &[
+                    &format!("{:?}", token_type),
+                    &format!("{:?}", peek_token.token_type),
+                ],
+            );
+            Err(ParseError {
+                expected: Some(token_type),
+                got: Some(peek_token.token_type),
+                ..ParseError::at(message, &peek_token)
+            })
+        }
+    }
+
+    fn record_error(&mut self, mut error: ParseError) {
+        if let Some(keyword) = self.typo.take() {
+            error.message = message(MessageId::DidYouMean, &[&error.message, &keyword]);
+        }
+        self.error_messages.push(error.message.clone());
+        self.errors.push(error);
+    }
+
+    // 语句出错后跳过剩下的词法单元，停在 `;` 上或者 `}` 前面，调用方前进一步就是下一条语句
+    // 这样一个错误不会让后面的语句跟着错位，一次就能报告所有的语法错误
+    fn synchronize(&mut self) {
+        while !self.current_token_is(TokenType::Semicolon)
+            && !self.current_token_is(TokenType::RightBrace)
+            && !self.current_token_is(TokenType::EOF)
+            && !self.peek_token_is(TokenType::RightBrace)
+            && !self.peek_token_is(TokenType::EOF)
+        {
+            self.next_token();
+        }
+    }
+
+    // 指向当前词法单元的错误
+    fn error(&self, message: &str) -> ParseError {
+        match self.current_token.as_ref() {
+            Some(token) => ParseError::at(message.to_owned(), token),
+            None => ParseError::at(
+                message.to_owned(),
+                &Token::new(TokenType::Illegal, String::new()),
+            ),
         }
     }
 
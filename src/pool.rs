@@ -0,0 +1,113 @@
+// 批量求值：在几个工作线程里并行运行互不相关的脚本，收集每个脚本的输出和结果
+// 每个任务在工作线程里新建自己的解释器，线程之间只传递源码和字符串形式的结果，不打开 sync 功能也能用
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use web_time::{Duration, Instant};
+
+use crate::evaluator::context::{OutputBuffer, RuntimeContext};
+use crate::interpreter::{Interpreter, InterpreterError};
+
+// 求值是递归的，嵌套深的脚本在默认的 2 MiB 线程栈上会溢出
+const STACK_SIZE: usize = 8 * 1024 * 1024;
+
+type ContextFactory = dyn Fn() -> RuntimeContext + Send + Sync;
+
+// 一个脚本的运行结果，value 是最后一条语句的值
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobResult {
+    // puts 写出的内容
+    pub output: String,
+    pub value: Result<String, InterpreterError>,
+    pub elapsed: Duration,
+}
+
+pub struct EvaluatorPool {
+    workers: usize,
+    timeout: Option<Duration>,
+    context: Arc<ContextFactory>,
+}
+
+impl Default for EvaluatorPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EvaluatorPool {
+    // 工作线程的数量和 CPU 核数相同
+    pub fn new() -> Self {
+        let workers = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        Self {
+            workers,
+            timeout: None,
+            context: Arc::new(RuntimeContext::new),
+        }
+    }
+
+    // 至少一个线程
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    // 每个脚本单独计时，超时的脚本得到运行时错误，不影响其他脚本
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // 每个任务调用一次，得到的上下文的输出会被替换成收集用的缓冲区，设置了 with_timeout 时覆盖它的超时
+    pub fn with_context(
+        mut self,
+        context: impl Fn() -> RuntimeContext + Send + Sync + 'static,
+    ) -> Self {
+        self.context = Arc::new(context);
+        self
+    }
+
+    // 结果的顺序和传入的脚本相同，所有脚本都运行完才返回
+    pub fn run<S: AsRef<str> + Sync>(&self, scripts: &[S]) -> Vec<JobResult> {
+        let next = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 0..self.workers.min(scripts.len()) {
+                let sender = sender.clone();
+                let next = &next;
+                thread::Builder::new()
+                    .stack_size(STACK_SIZE)
+                    .spawn_scoped(scope, move || loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(script) = scripts.get(index) else {
+                            break;
+                        };
+                        let _ = sender.send((index, self.run_one(script.as_ref())));
+                    })
+                    .expect("failed to spawn evaluator thread");
+            }
+        });
+        drop(sender);
+        let mut results = receiver.into_iter().collect::<Vec<_>>();
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    fn run_one(&self, source: &str) -> JobResult {
+        let start = Instant::now();
+        let output = OutputBuffer::new();
+        let mut context = (self.context)().with_output(Box::new(output.clone()));
+        if self.timeout.is_some() {
+            context.limits.timeout = self.timeout;
+        }
+        let value = Interpreter::with_context(context)
+            .eval_str(source)
+            .map(|value| value.inspect());
+        JobResult {
+            output: output.take(),
+            value,
+            elapsed: start.elapsed(),
+        }
+    }
+}
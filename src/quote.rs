@@ -1,76 +1,228 @@
+use crate::sync::{Rc, RefCell};
 use crate::{
     ast::{
-        expressions::{self, CallExpression, IntegerLiteral},
-        modify::modify,
+        expressions::{
+            self, ArrayLiteral, CallExpression, Expr, FunctionLiteral, HashLiteral, Identifier,
+            IfExpression, InfixExpression, IntegerLiteral, StringLiteral,
+        },
+        modify::transform,
+        statements::BlockStatement,
         traits::Node,
+        walk::{children, NodeRef},
     },
+    errors::{runtime_error, EvalError, EvalResult, MessageId},
     evaluator::{
         environment::Environment,
-        object::{self, Integer, Object, Quote},
+        eval::eval_node,
+        object::{self, Quote, Value},
     },
+    lexer::Span,
     token::{Token, TokenType},
 };
-use std::{cell::RefCell, rc::Rc};
 
-pub fn quote(node: &mut Box<dyn Node>, environment: Rc<RefCell<Environment>>) -> Box<dyn Object> {
-    let new_node = eval_unquote_calls(node.as_mut_node(), environment);
-    Box::new(Quote { node: new_node })
+pub fn quote(node: &Expr, environment: Rc<RefCell<Environment>>) -> EvalResult {
+    let new_node = eval_unquote_calls(node, environment)?;
+    Ok(Rc::new(Value::Quote(Quote { node: new_node })))
 }
 
-// 没有办法用一个 &dyn Node 的内容去替换另一个，因为都不知道 dyn Node 具体类型的大小，也就不知道要复制多少过去
-// https://stackoverflow.com/questions/25246443/how-can-i-downcast-from-boxany-to-a-trait-object-type
-fn eval_unquote_calls(node: &mut dyn Node, environment: Rc<RefCell<Environment>>) -> Box<dyn Node> {
-    modify(node, &|node| {
-        if !is_unquote_call(node.as_ref()) {
+fn eval_unquote_calls(
+    node: &Expr,
+    environment: Rc<RefCell<Environment>>,
+) -> Result<Expr, EvalError> {
+    // transform 的回调没法返回错误，先记下第一个错误，遍历完再返回
+    let error = RefCell::new(None);
+    let new_node = replace_unquotes(node, &environment, &error);
+    if let Some(error) = error.into_inner() {
+        return Err(error);
+    }
+    if has_stray_splice(new_node.as_node()) {
+        return Err(runtime_error(MessageId::SpliceOutsideList, &[]).into());
+    }
+    Ok(new_node)
+}
+
+fn replace_unquotes(
+    node: &Expr,
+    environment: &Rc<RefCell<Environment>>,
+    error: &RefCell<Option<EvalError>>,
+) -> Expr {
+    transform(node, &|node| {
+        if error.borrow().is_some() {
             return node;
         }
-        if let Some(expression) = node.downcast_ref::<CallExpression>() {
-            if expression.arguments.len() == 1 {
-                let new_node = convert_object_to_ast_node(
-                    expression.arguments[0].eval_to_object(Rc::clone(&environment)),
-                );
-                return new_node;
+        match node {
+            Expr::Unquote(unquote) => {
+                let converted = eval_node(unquote.expression.as_node(), Rc::clone(environment))
+                    .and_then(convert_object_to_ast_node);
+                match converted {
+                    Ok(new_node) => new_node,
+                    Err(unquote_error) => {
+                        *error.borrow_mut() = Some(unquote_error);
+                        Expr::Unquote(unquote)
+                    }
+                }
             }
+            Expr::Call(mut call) => {
+                call.arguments = splice(call.arguments, environment, error);
+                Expr::Call(call)
+            }
+            Expr::Array(mut array) => {
+                array.elements = splice(array.elements, environment, error);
+                Expr::Array(array)
+            }
+            node => node,
         }
-        node
     })
 }
 
-fn is_unquote_call(node: &dyn Node) -> bool {
-    node.downcast_ref::<CallExpression>()
-        .map(|expression| expression.function.string() == "unquote")
-        .unwrap_or_default()
-}
-
-fn convert_object_to_ast_node(object: Box<dyn Object>) -> Box<dyn Node> {
-    if let Some(integer) = object.downcast_ref::<Integer>() {
-        let token = Token {
-            token_type: TokenType::Int,
-            literal: format!("{}", integer.value),
+// 把 unquote_splice 换成它求值得到的多个表达式
+fn splice(
+    elements: Vec<Expr>,
+    environment: &Rc<RefCell<Environment>>,
+    error: &RefCell<Option<EvalError>>,
+) -> Vec<Expr> {
+    let mut spliced = vec![];
+    for element in elements {
+        let Expr::UnquoteSplice(splice) = element else {
+            spliced.push(element);
+            continue;
         };
-        Box::new(IntegerLiteral {
-            token,
-            value: integer.value,
-        })
-    } else if let Some(boolean) = object.downcast_ref::<object::Boolean>() {
-        let token = if matches!(boolean, object::Boolean::True) {
-            Token {
-                token_type: TokenType::True,
-                literal: "true".to_owned(),
-            }
-        } else {
-            Token {
-                token_type: TokenType::False,
-                literal: "false".to_owned(),
+        if error.borrow().is_some() {
+            spliced.push(Expr::UnquoteSplice(splice));
+            continue;
+        }
+        let converted = eval_node(splice.expression.as_node(), Rc::clone(environment))
+            .and_then(convert_object_to_ast_nodes);
+        match converted {
+            Ok(nodes) => spliced.extend(nodes),
+            Err(splice_error) => {
+                *error.borrow_mut() = Some(splice_error);
+                spliced.push(Expr::UnquoteSplice(splice));
             }
-        };
-        Box::new(expressions::Boolean {
-            token,
-            value: boolean.value(),
-        })
-    } else if let Some(quote) = object.downcast_ref::<object::Quote>() {
-        dyn_clone::clone_box(quote.node.as_ref())
-    } else {
-        panic!("other object types are not supported");
+        }
+    }
+    spliced
+}
+
+// 展开之后还剩下的 unquote_splice 不在调用参数或者数组元素的位置上，里层 quote 里的留给里层处理
+fn has_stray_splice(node: &dyn Node) -> bool {
+    match node.node_ref() {
+        NodeRef::Quote(_) => false,
+        NodeRef::UnquoteSplice(_) => true,
+        _ => children(node).into_iter().any(has_stray_splice),
+    }
+}
+
+// 数组的每个元素各自转换成表达式，被 quote 的数组字面量直接取出里面的表达式
+fn convert_object_to_ast_nodes(object: Rc<Value>) -> Result<Vec<Expr>, EvalError> {
+    match object.as_ref() {
+        Value::Array(array) => array
+            .elements
+            .iter()
+            .cloned()
+            .map(convert_object_to_ast_node)
+            .collect(),
+        Value::Quote(Quote {
+            node: Expr::Array(array),
+        }) => Ok(array.elements.clone()),
+        _ => Err(runtime_error(MessageId::SpliceNotArray, &[&object.object_type()]).into()),
+    }
+}
+
+pub(crate) fn convert_object_to_ast_node(object: Rc<Value>) -> Result<Expr, EvalError> {
+    match object.as_ref() {
+        // 负数写出来是取负的表达式，i64::MIN 的绝对值超出了整数字面量的范围，写成 (-i64::MAX - 1)
+        Value::Integer(integer) if integer.value == i64::MIN => Ok(Expr::Infix(InfixExpression {
+            token: Token::new(TokenType::Minus, "-"),
+            left: Box::new(integer_literal(-i64::MAX)),
+            operator: Rc::from("-"),
+            right: Box::new(integer_literal(1)),
+        })),
+        Value::Integer(integer) => Ok(integer_literal(integer.value)),
+        Value::Boolean(boolean) => {
+            let token = if matches!(boolean, object::Boolean::True) {
+                Token::new(TokenType::True, "true".to_owned())
+            } else {
+                Token::new(TokenType::False, "false".to_owned())
+            };
+            Ok(Expr::Boolean(expressions::Boolean {
+                token,
+                value: boolean.value(),
+            }))
+        }
+        Value::String(string) => Ok(Expr::String(StringLiteral {
+            token: Token::new(TokenType::String, string.value.clone()),
+            value: string.value.clone(),
+        })),
+        Value::Array(array) => Ok(Expr::Array(ArrayLiteral {
+            token: Token::new(TokenType::LeftBracket, "["),
+            elements: array
+                .elements
+                .iter()
+                .cloned()
+                .map(convert_object_to_ast_node)
+                .collect::<Result<_, _>>()?,
+            closing: Span::default(),
+        })),
+        Value::Hash(hash) => Ok(Expr::Hash(HashLiteral {
+            token: Token::new(TokenType::LeftBrace, "{"),
+            pairs: hash
+                .pairs
+                .values()
+                .map(|pair| {
+                    Ok((
+                        convert_object_to_ast_node(Rc::clone(&pair.key))?,
+                        convert_object_to_ast_node(Rc::clone(&pair.value))?,
+                    ))
+                })
+                .collect::<Result<_, EvalError>>()?,
+            closing: Span::default(),
+        })),
+        // 没有 null 字面量，换成求值结果是 null 的 if (false) {}
+        Value::Null(_) => Ok(Expr::If(IfExpression {
+            token: Token::new(TokenType::If, "if"),
+            condition: Box::new(Expr::Boolean(expressions::Boolean {
+                token: Token::new(TokenType::False, "false"),
+                value: false,
+            })),
+            consequence: empty_block(),
+            alternative: None,
+        })),
+        // 只保留参数和函数体，闭包捕获的环境不在语法树里，函数体里的自由变量在展开的位置查找
+        Value::Function(function) => Ok(Expr::Function(FunctionLiteral {
+            token: Token::new(TokenType::Function, "fn"),
+            parameters: function.parameters.clone(),
+            body: function.body.clone(),
+        })),
+        Value::Bytes(bytes) => Ok(Expr::Call(CallExpression {
+            token: Token::new(TokenType::LeftParen, "("),
+            function: Box::new(Expr::Identifier(Identifier {
+                token: Token::new(TokenType::Ident, "bytes_from_hex"),
+                value: Rc::from("bytes_from_hex"),
+            })),
+            arguments: vec![Expr::String(StringLiteral {
+                token: Token::new(TokenType::String, bytes.hex()),
+                value: bytes.hex(),
+            })],
+            closing: Span::default(),
+        })),
+        Value::Quote(quote) => Ok(quote.node.clone()),
+        // 内置函数、宏、通道这些值没法写成源码
+        _ => Err(runtime_error(MessageId::UnquoteNotSupported, &[&object.object_type()]).into()),
+    }
+}
+
+fn integer_literal(value: i64) -> Expr {
+    Expr::Integer(IntegerLiteral {
+        token: Token::new(TokenType::Int, format!("{}", value)),
+        value,
+    })
+}
+
+fn empty_block() -> BlockStatement {
+    BlockStatement {
+        token: Token::new(TokenType::LeftBrace, "{"),
+        statements: vec![],
+        closing: Span::default(),
     }
 }
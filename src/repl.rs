@@ -1,42 +1,992 @@
-use crate::evaluator::macro_expansion::{define_macros, expand_macro};
+use crate::ast::statements::Stmt;
+use crate::compiler::{CompileError, Compiler};
+use crate::dot::environment_to_dot;
+use crate::errors::ParseError;
+use crate::evaluator::builtins::BuiltinRegistry;
+use crate::evaluator::environment::{preview, Snapshot};
+use crate::evaluator::eval::eval_expression_in;
+use crate::evaluator::macro_expansion::{define_macros, expand_macro, macro_environment};
+use crate::evaluator::object::Value;
+use crate::formatter::{format_program, FormatOptions};
+use crate::highlight::{ansi_code, to_ansi, Category};
+use crate::lint::lint;
+use crate::stdlib::load_prelude;
+use crate::sync::{Rc, RefCell};
+#[cfg(feature = "cli")]
+use crate::token::keywords;
+use crate::token::TokenType;
+use crate::vm::Vm;
 use crate::{
     ast::traits::AsNode, evaluator::environment::Environment, evaluator::eval::eval, lexer::Lexer,
     parser::Parser,
 };
-use std::io::{self, Write};
-use std::{cell::RefCell, rc::Rc};
+#[cfg(feature = "cli")]
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::DefaultHistory, validate::Validator, CompletionType, Config, Context, Editor, Helper,
+};
+#[cfg(feature = "cli")]
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::fs;
+#[cfg(feature = "cli")]
+use std::io::IsTerminal;
+use std::io::{self, BufRead, Write};
+#[cfg(feature = "cli")]
+use std::path::{Path, PathBuf};
 
 const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+// ANSI 颜色代码
+const PROMPT_COLOR: &str = "1;34";
+const ERROR_COLOR: &str = "31";
+const WARNING_COLOR: &str = "33";
+#[cfg(feature = "cli")]
+const HISTORY_FILE: &str = ".monkey_history";
+// `:undo` 最多能撤销的输入数
+const MAX_UNDO: usize = 100;
+// Tab 补全用的命令名
+const COMMANDS: [&str; 15] = [
+    ":budget",
+    ":envgraph",
+    ":expand",
+    ":help",
+    ":help builtins",
+    ":load",
+    ":profile",
+    ":quit",
+    ":reload-builtins",
+    ":reset",
+    ":save",
+    ":trace",
+    ":type",
+    ":undo",
+    ":vars",
+];
+
+#[cfg(feature = "cli")]
+type LineEditor = Editor<Completion, DefaultHistory>;
+
+// 执行每一行输入的后端
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    #[default]
+    TreeWalker,
+    // 先编译成字节码再交给虚拟机执行，步数限制只对树遍历求值器生效
+    Vm,
+}
+
+// 提示符、结果和错误要不要用 ANSI 颜色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    // 输出是终端并且没有设置 NO_COLOR 环境变量时才上色
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn enabled(self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                terminal && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Options {
+    pub backend: Backend,
+    pub color: ColorChoice,
+}
+
+// 以冒号开头的输入是 REPL 自己的命令，在求值之前解析，不交给解释器
+// 参数原样保留，由执行命令的地方检查
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    HelpBuiltins,
+    Quit,
+    Vars,
+    // 丢掉会话里的所有绑定、宏和设置，重新开始
+    Reset,
+    // 撤销上一次输入定义的绑定和宏
+    Undo,
+    // 把文件的内容当作一次输入求值，定义的绑定留在会话里
+    Load(String),
+    // 把会话里求值成功的输入写进文件，之后可以用 `:load` 恢复
+    Save(String),
+    Type(String),
+    // 打印宏展开之后的代码，不求值
+    Expand(String),
+    Budget(String),
+    Trace(String),
+    Profile(String),
+    EnvGraph,
+    ReloadBuiltins,
+    Unknown(String),
+}
+
+impl Command {
+    // 不是命令的输入返回 None
+    pub fn parse(line: &str) -> Option<Command> {
+        let line = line.trim();
+        let rest = line.strip_prefix(':')?;
+        let (name, argument) = match rest.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (rest, ""),
+        };
+        let command = match (name, argument) {
+            ("help", "") => Command::Help,
+            ("help", "builtins") => Command::HelpBuiltins,
+            ("quit", "") => Command::Quit,
+            ("vars", "") => Command::Vars,
+            ("reset", "") => Command::Reset,
+            ("undo", "") => Command::Undo,
+            ("load", path) => Command::Load(path.to_owned()),
+            ("save", path) => Command::Save(path.to_owned()),
+            ("type", expression) => Command::Type(expression.to_owned()),
+            ("expand", code) => Command::Expand(code.to_owned()),
+            ("budget", budget) => Command::Budget(budget.to_owned()),
+            ("trace", trace) => Command::Trace(trace.to_owned()),
+            ("profile", profile) => Command::Profile(profile.to_owned()),
+            ("envgraph", "") => Command::EnvGraph,
+            ("reload-builtins", "") => Command::ReloadBuiltins,
+            _ => Command::Unknown(line.to_owned()),
+        };
+        Some(command)
+    }
+}
+
+// 从 input 逐行读取输入，提示符、结果和错误都写到 output，puts 等内置函数仍然写到标准输出
+// 返回脚本通过 exit() 给出的退出码，读到输入结尾或者 `:quit` 时返回 0
+pub fn start<R: BufRead, W: Write>(input: R, output: W) -> io::Result<i64> {
+    start_with_builtins(input, output, &|_| {})
+}
+
+pub fn start_with_backend<R: BufRead, W: Write>(
+    input: R,
+    output: W,
+    backend: Backend,
+) -> io::Result<i64> {
+    start_with_options(
+        input,
+        output,
+        Options {
+            backend,
+            ..Options::default()
+        },
+    )
+}
+
+// 不是从终端读取输入，ColorChoice::Auto 不上色
+pub fn start_with_options<R: BufRead, W: Write>(
+    input: R,
+    output: W,
+    options: Options,
+) -> io::Result<i64> {
+    let color = options.color.enabled(false);
+    run_session(
+        &mut Input::Reader(input),
+        output,
+        options.backend,
+        color,
+        &|_| {},
+    )
+}
+
+// register 在启动时、每次 `:reload-builtins` 和 `:reset` 时调用，用来注册宿主程序自己的函数
+// 这样修改宿主函数后不用重启会话
+pub fn start_with_builtins<R: BufRead, W: Write>(
+    input: R,
+    output: W,
+    register: &dyn Fn(&mut BuiltinRegistry),
+) -> io::Result<i64> {
+    run_session(
+        &mut Input::Reader(input),
+        output,
+        Backend::TreeWalker,
+        false,
+        register,
+    )
+}
+
+// 从终端读取输入，支持行编辑、历史记录和 Tab 补全，提示符由 rustyline 直接写到终端
+// ColorChoice::Auto 按标准输出是不是终端决定要不要上色
+#[cfg(feature = "cli")]
+pub fn start_interactive<W: Write>(output: W, options: Options) -> io::Result<i64> {
+    let config = Config::builder()
+        .completion_type(CompletionType::List)
+        .build();
+    let mut editor = LineEditor::with_config(config).map_err(io::Error::other)?;
+    let history = history_path();
+    if let Some(history) = history.as_ref() {
+        // 第一次启动时还没有历史文件
+        let _ = editor.load_history(history);
+    }
+    let mut input = Input::<io::Empty>::Terminal(Box::new(editor));
+    let color = options.color.enabled(io::stdout().is_terminal());
+    let code = run_session(&mut input, output, options.backend, color, &|_| {});
+    if let (Input::Terminal(editor), Some(history)) = (&mut input, history.as_ref()) {
+        let _ = editor.save_history(history);
+    }
+    code
+}
+
+// 终端交给 rustyline，其他来源自己把提示符写到输出里
+enum Input<R> {
+    #[cfg(feature = "cli")]
+    Terminal(Box<LineEditor>),
+    Reader(R),
+}
+
+// 终端上的 Ctrl-C 和 Ctrl-D 分别对应 Interrupted 和 Eof
+enum ReadError {
+    #[cfg(feature = "cli")]
+    Interrupted,
+    Eof,
+    Io(io::Error),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(error: io::Error) -> Self {
+        ReadError::Io(error)
+    }
+}
+
+#[cfg(feature = "cli")]
+impl From<ReadlineError> for ReadError {
+    fn from(error: ReadlineError) -> Self {
+        match error {
+            ReadlineError::Interrupted => ReadError::Interrupted,
+            ReadlineError::Eof => ReadError::Eof,
+            ReadlineError::Io(error) => ReadError::Io(error),
+            error => ReadError::Io(io::Error::other(error)),
+        }
+    }
+}
+
+impl<R: BufRead> Input<R> {
+    // 读到输入结尾时返回 ReadError::Eof，和在终端上按 Ctrl-D 一样
+    // 终端上的提示符由 Completion 上色
+    fn read_line<W: Write>(
+        &mut self,
+        prompt: &str,
+        output: &mut W,
+        color: bool,
+    ) -> Result<String, ReadError> {
+        match self {
+            #[cfg(feature = "cli")]
+            Input::Terminal(editor) => Ok(editor.readline(prompt)?),
+            Input::Reader(reader) => {
+                write!(output, "{}", paint(prompt, PROMPT_COLOR, color))?;
+                output.flush()?;
+                let mut line = String::new();
+                if reader.read_line(&mut line)? == 0 {
+                    return Err(ReadError::Eof);
+                }
+                let len = line.trim_end_matches(['\n', '\r']).len();
+                line.truncate(len);
+                Ok(line)
+            }
+        }
+    }
+
+    // 以冒号开头的命令只占一行，其他输入在括号闭合之前继续读下一行
+    fn read_input<W: Write>(&mut self, output: &mut W, color: bool) -> Result<String, ReadError> {
+        let mut input = self.read_line(PROMPT, output, color)?;
+        if Command::parse(&input).is_some() {
+            return Ok(input);
+        }
+        while is_incomplete(&input) {
+            let line = self.read_line(CONTINUATION_PROMPT, output, color)?;
+            input.push('\n');
+            input.push_str(&line);
+        }
+        Ok(input)
+    }
+}
 
-pub fn start<W: Write>(mut output: W) -> io::Result<()> {
-    let env = Rc::new(RefCell::new(Environment::new()));
-    let macro_env = Rc::new(RefCell::new(Environment::new()));
+fn run_session<R: BufRead, W: Write>(
+    input: &mut Input<R>,
+    mut output: W,
+    backend: Backend,
+    color: bool,
+    register: &dyn Fn(&mut BuiltinRegistry),
+) -> io::Result<i64> {
+    let mut session = Session::new(backend, color, register);
     loop {
-        let mut line = String::new();
-        write!(output, "{}", PROMPT)?;
-        io::Write::flush(&mut io::stdout())?;
+        #[cfg(feature = "cli")]
+        if let Input::Terminal(editor) = input {
+            editor.set_helper(Some(Completion {
+                names: session.completion_names(),
+                color,
+            }));
+        }
+        let line = match input.read_input(&mut output, color) {
+            Ok(line) => line,
+            // Ctrl-C 丢掉正在输入的内容
+            #[cfg(feature = "cli")]
+            Err(ReadError::Interrupted) => continue,
+            // Ctrl-D 或者输入结束
+            Err(ReadError::Eof) => {
+                writeln!(output)?;
+                return Ok(0);
+            }
+            Err(ReadError::Io(error)) => return Err(error),
+        };
+        // 多行的输入整段作为一条历史记录
+        #[cfg(feature = "cli")]
+        if let Input::Terminal(editor) = input {
+            if !line.trim().is_empty() {
+                let _ = editor.add_history_entry(line.as_str());
+            }
+        }
+        let exit = match Command::parse(&line) {
+            Some(command) => session.run_command(&mut output, command)?,
+            None => session.eval(&mut output, line)?,
+        };
+        if let Some(code) = exit {
+            return Ok(code);
+        }
+    }
+}
+
+// 还有没闭合的 `{`、`(` 或 `[` 时输入没有结束，字符串和注释里的括号不算
+// 多出来的右括号说明输入已经错了，交给语法分析器报错
+pub fn is_incomplete(source: &str) -> bool {
+    let mut depth = 0usize;
+    for token in Lexer::new(source) {
+        match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => {
+                let Some(outer) = depth.checked_sub(1) else {
+                    return false;
+                };
+                depth = outer;
+            }
+            _ => {}
+        }
+    }
+    depth > 0
+}
 
-        io::stdin().read_line(&mut line)?;
-        let lexer = Lexer::new(line);
-        let mut parser = Parser::new(lexer);
-        let mut program = parser.parse_program();
+// 按 Tab 时补全光标前面的标识符或者命令
+pub struct Completion {
+    // 关键字、内置函数和会话里的绑定，每次读入之前更新
+    pub names: BTreeSet<String>,
+    // 给正在输入的代码和提示符上色
+    pub color: bool,
+}
 
-        if !parser.error_messages.is_empty() {
-            print_parser_errors(&mut output, &parser.error_messages)?;
-            continue;
+impl Completion {
+    // 返回被替换部分的起点和按字母顺序排列的候选
+    // 行首以冒号开头的单词补全成 REPL 命令，其他位置补全标识符
+    pub fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let before = &line[..pos];
+        if before.trim_start().starts_with(':') {
+            let prefix = before.trim_start();
+            let commands = COMMANDS
+                .iter()
+                .filter(|command| command.starts_with(prefix))
+                .map(|command| command.to_string())
+                .collect::<BTreeSet<_>>();
+            return (pos - prefix.len(), commands.into_iter().collect());
+        }
+        let start = before
+            .rfind(|character: char| !(character.is_ascii_alphabetic() || character == '_'))
+            .map_or(0, |index| {
+                // 前面的字符可能是多字节的，跳过整个字符
+                index + before[index..].chars().next().map_or(1, char::len_utf8)
+            });
+        let prefix = &before[start..];
+        // 数字后面和字符串里面不补全
+        if prefix.is_empty() || is_incomplete_string(before) {
+            return (pos, vec![]);
         }
+        let names = self
+            .names
+            .iter()
+            .filter(|name| name.starts_with(prefix))
+            .cloned()
+            .collect();
+        (start, names)
+    }
+}
+
+// 光标前面有没闭合的双引号
+fn is_incomplete_string(before: &str) -> bool {
+    before.matches('"').count() % 2 == 1
+}
+
+#[cfg(feature = "cli")]
+impl Completer for Completion {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.candidates(line, pos))
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Hinter for Completion {
+    type Hint = String;
+}
+
+#[cfg(feature = "cli")]
+impl Highlighter for Completion {
+    fn highlight<'l>(&self, line: &'l str, _: usize) -> Cow<'l, str> {
+        match self.color {
+            true => Cow::Owned(to_ansi(line)),
+            false => Cow::Borrowed(line),
+        }
+    }
+
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(&'s self, prompt: &'p str, _: bool) -> Cow<'b, str> {
+        match self.color {
+            true => Cow::Owned(paint(prompt, PROMPT_COLOR, true)),
+            false => Cow::Borrowed(prompt),
+        }
+    }
+
+    // 每输入一个字符都重新上色
+    fn highlight_char(&self, _: &str, _: usize, _: bool) -> bool {
+        self.color
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Validator for Completion {}
+
+#[cfg(feature = "cli")]
+impl Helper for Completion {}
+
+// 历史记录保存在用户主目录下，找不到主目录时只在会话内保留
+#[cfg(feature = "cli")]
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| Path::new(&home).join(HISTORY_FILE))
+}
+
+// 一次 REPL 会话的全部状态，`:reset` 时整个换成新的
+struct Session<'a> {
+    backend: Backend,
+    color: bool,
+    register: &'a dyn Fn(&mut BuiltinRegistry),
+    env: Rc<RefCell<Environment>>,
+    // 虚拟机模式下整个会话共用一个编译器和虚拟机，之前定义的全局变量一直可见
+    machine: Option<(Compiler, Vm)>,
+    macro_env: Rc<RefCell<Environment>>,
+    // 顶层 let 语句写下的类型标注，给 `:type` 用
+    annotations: HashMap<String, String>,
+    // 求值成功的输入格式化之后的源码，`:save` 时按顺序写进文件
+    transcript: Vec<String>,
+    // 每次求值之前的状态，最后一个是上一次输入之前的
+    undo: Vec<Undo>,
+}
+
+struct Undo {
+    env: Snapshot,
+    macros: Snapshot,
+    annotations: HashMap<String, String>,
+    transcript: usize,
+}
+
+impl<'a> Session<'a> {
+    fn new(backend: Backend, color: bool, register: &'a dyn Fn(&mut BuiltinRegistry)) -> Self {
+        let env = Rc::new(RefCell::new(Environment::new()));
+        register(&mut env.borrow().context().borrow_mut().builtins);
+        // 标准库的函数直接放进会话的根环境
+        load_prelude(&env);
+        let machine = match backend {
+            Backend::TreeWalker => None,
+            Backend::Vm => {
+                let compiler =
+                    Compiler::default().with_builtins(&env.borrow().context().borrow().builtins);
+                Some((compiler, Vm::with_environment(Rc::clone(&env))))
+            }
+        };
+        let macro_env = macro_environment(&env.borrow().context().borrow());
+        env.borrow().context().borrow_mut().macros = Some(Rc::clone(&macro_env));
+        Session {
+            backend,
+            color,
+            register,
+            env,
+            machine,
+            macro_env,
+            annotations: HashMap::new(),
+            transcript: vec![],
+            undo: vec![],
+        }
+    }
+
+    // 虚拟机模式下会话里定义的变量在虚拟机的全局变量里
+    #[cfg(feature = "cli")]
+    fn completion_names(&self) -> BTreeSet<String> {
+        let mut names = keywords()
+            .into_iter()
+            .map(str::to_owned)
+            .collect::<BTreeSet<_>>();
+        let env = self.env.borrow();
+        names.extend(
+            env.context()
+                .borrow()
+                .builtins
+                .list()
+                .map(|info| info.name.to_string()),
+        );
+        names.extend(env.names().iter().map(|name| name.to_string()));
+        if let Some((_, vm)) = self.machine.as_ref() {
+            names.extend(vm.globals().into_iter().map(|(name, _)| name.to_string()));
+        }
+        names
+    }
+
+    // 返回 Some 时会话结束，里面是退出码
+    fn run_command<W: Write>(
+        &mut self,
+        output: &mut W,
+        command: Command,
+    ) -> io::Result<Option<i64>> {
+        let env = &self.env;
+        match command {
+            Command::Help => print_help(output)?,
+            Command::HelpBuiltins => {
+                print_builtins(output, &env.borrow().context().borrow().builtins)?
+            }
+            Command::Quit => return Ok(Some(0)),
+            Command::Vars => print_vars(output, env, self.machine.as_ref().map(|(_, vm)| vm))?,
+            Command::Reset => {
+                *self = Session::new(self.backend, self.color, self.register);
+                writeln!(output, "session reset")?;
+            }
+            Command::Undo => self.undo(output)?,
+            Command::Load(path) if path.is_empty() => writeln!(output, "usage: :load <file>")?,
+            Command::Load(path) => match fs::read_to_string(&path) {
+                Ok(source) => return self.eval(output, source),
+                Err(error) => writeln!(output, "could not read {}: {}", path, error)?,
+            },
+            Command::Save(path) if path.is_empty() => writeln!(output, "usage: :save <file>")?,
+            Command::Save(path) => match fs::write(&path, self.transcript.concat()) {
+                Ok(()) => writeln!(output, "saved {} inputs to {}", self.transcript.len(), path)?,
+                Err(error) => writeln!(output, "could not write {}: {}", path, error)?,
+            },
+            Command::Type(expression) if expression.is_empty() => {
+                writeln!(output, "usage: :type <expr>")?
+            }
+            Command::Type(expression) => {
+                match eval_type_expression(&expression, env, &mut self.machine) {
+                    Ok(value) => print_type(output, &expression, value, &self.annotations)?,
+                    Err(error) => writeln!(output, "{}", error)?,
+                }
+            }
+            Command::Expand(code) if code.is_empty() => writeln!(output, "usage: :expand <code>")?,
+            Command::Expand(code) => self.expand(output, code)?,
+            Command::Budget(budget) => {
+                set_budget(output, &budget, env)?;
+                self.sync_macro_context();
+            }
+            Command::Trace(trace) => set_trace(output, &trace, env)?,
+            Command::Profile(command) => profile(output, &command, env)?,
+            Command::EnvGraph => write!(output, "{}", environment_to_dot(env))?,
+            Command::ReloadBuiltins => {
+                let context = env.borrow().context();
+                let builtins = &mut context.borrow_mut().builtins;
+                builtins.reset();
+                (self.register)(builtins);
+                self.sync_macro_context();
+                writeln!(output, "builtins reloaded")?;
+            }
+            Command::Unknown(command) => writeln!(
+                output,
+                "unknown command {}, :help lists the commands",
+                command
+            )?,
+        }
+        Ok(None)
+    }
+
+    // 虚拟机的全局变量不在环境里，没法撤销
+    fn undo<W: Write>(&mut self, output: &mut W) -> io::Result<()> {
+        if self.machine.is_some() {
+            return writeln!(output, ":undo is not available with the VM backend");
+        }
+        let Some(undo) = self.undo.pop() else {
+            return writeln!(output, "nothing to undo");
+        };
+        self.restore(undo);
+        writeln!(output, "undid the last input")
+    }
+
+    fn restore(&mut self, undo: Undo) {
+        self.env.borrow_mut().restore(&undo.env);
+        self.macro_env.borrow_mut().restore(&undo.macros);
+        self.annotations = undo.annotations;
+        self.transcript.truncate(undo.transcript);
+    }
+
+    // 宏体和输入受同样的步数限制，能调用的内置函数也一样
+    fn sync_macro_context(&self) {
+        let context = self.env.borrow().context();
+        let context = context.borrow();
+        let macro_context = self.macro_env.borrow().context();
+        let mut macro_context = macro_context.borrow_mut();
+        macro_context.limits = context.limits.clone();
+        macro_context.builtins = context.builtins.clone();
+    }
+
+    fn save_undo(&mut self) {
+        if self.machine.is_some() {
+            return;
+        }
+        if self.undo.len() == MAX_UNDO {
+            self.undo.remove(0);
+        }
+        self.undo.push(Undo {
+            env: self.env.borrow().snapshot(),
+            macros: self.macro_env.borrow().snapshot(),
+            annotations: self.annotations.clone(),
+            transcript: self.transcript.len(),
+        });
+    }
+
+    // 这里定义的宏只在这次展开里有效，不会留在会话里
+    fn expand<W: Write>(&self, output: &mut W, source: String) -> io::Result<()> {
+        let mut program = match Parser::new(Lexer::new(source)).parse() {
+            Ok(program) => program,
+            Err(errors) => return print_parser_errors(output, &errors, self.color),
+        };
+        let macro_env = Rc::new(RefCell::new(Environment::new_enclosed(Rc::clone(
+            &self.macro_env,
+        ))));
         define_macros(&mut program, Rc::clone(&macro_env));
-        expand_macro(&mut program, Rc::clone(&macro_env));
-        let evaluated = eval(program.as_node(), Rc::clone(&env));
-        writeln!(output, "{}", evaluated.inspect())?;
+        match expand_macro(&mut program, macro_env) {
+            Ok(()) => {
+                let formatted = format_program(&program, &FormatOptions::default());
+                match self.color {
+                    true => write!(output, "{}", to_ansi(&formatted)),
+                    false => write!(output, "{}", formatted),
+                }
+            }
+            Err(error) => writeln!(output, "{}", paint_value(&Value::Error(error), self.color)),
+        }
+    }
+
+    // 求值一次输入，脚本调用 exit() 时返回退出码
+    fn eval<W: Write>(&mut self, output: &mut W, source: String) -> io::Result<Option<i64>> {
+        let lexer = Lexer::new(source);
+        let mut program = match Parser::new(lexer).parse() {
+            Ok(program) => program,
+            Err(errors) => {
+                print_parser_errors(output, &errors, self.color)?;
+                return Ok(None);
+            }
+        };
+        if program.is_empty() {
+            return Ok(None);
+        }
+        self.save_undo();
+        // 警告只是提示，这一行照常求值
+        for warning in lint(&program) {
+            writeln!(
+                output,
+                "{}",
+                paint(&warning.to_string(), WARNING_COLOR, self.color)
+            )?;
+        }
+        for statement in program.statements.iter() {
+            if let Stmt::Let(let_statement) = statement {
+                match let_statement.type_annotation.as_ref() {
+                    Some(type_annotation) => self.annotations.insert(
+                        let_statement.name.value.to_string(),
+                        type_annotation.value.to_string(),
+                    ),
+                    None => self.annotations.remove(&*let_statement.name.value),
+                };
+            }
+        }
+        // 宏定义会被 define_macros 从程序里拿走，要在这之前格式化
+        let formatted = format_program(&program, &FormatOptions::default());
+        self.macro_env.borrow().context().borrow_mut().refuel();
+        define_macros(&mut program, Rc::clone(&self.macro_env));
+        if let Err(error) = expand_macro(&mut program, Rc::clone(&self.macro_env)) {
+            // 展开失败的输入和语法错误一样不生效，已经定义的宏和类型标注撤回去，也不留下撤销记录
+            if let Some(undo) = self.undo.pop().filter(|_| self.machine.is_none()) {
+                self.restore(undo);
+            }
+            writeln!(output, "{}", paint_value(&Value::Error(error), self.color))?;
+            return Ok(None);
+        }
+        self.env.borrow().context().borrow_mut().refuel();
+        let evaluated = match self.machine.as_mut() {
+            None => eval(program.as_node(), Rc::clone(&self.env)),
+            Some((compiler, vm)) => match compiler.compile(&program) {
+                Ok(bytecode) => vm.run(&bytecode),
+                Err(error) => {
+                    writeln!(
+                        output,
+                        "{}",
+                        paint(&error.to_string(), ERROR_COLOR, self.color)
+                    )?;
+                    return Ok(None);
+                }
+            },
+        };
+        if let Value::Exit(exit) = evaluated.as_ref() {
+            return Ok(Some(exit.code));
+        }
+        if !matches!(evaluated.as_ref(), Value::Error(_)) {
+            self.transcript.push(formatted);
+        }
+        writeln!(output, "{}", paint_value(&evaluated, self.color))?;
+        Ok(None)
     }
 }
 
-fn print_parser_errors<W: Write>(output: &mut W, errors: &[String]) -> io::Result<()> {
+// 参数可以是任意表达式，求值不会改动会话里的绑定
+// 虚拟机模式下全局变量不在 env 里，表达式要用会话的编译器和虚拟机执行
+fn eval_type_expression(
+    expression: &str,
+    env: &Rc<RefCell<Environment>>,
+    machine: &mut Option<(Compiler, Vm)>,
+) -> Result<Rc<Value>, CompileError> {
+    let Some((compiler, vm)) = machine else {
+        return Ok(eval_expression_in(expression, env));
+    };
+    match Parser::new(Lexer::new(expression.to_owned())).parse() {
+        Ok(program) if matches!(program.statements.as_slice(), [Stmt::Expression(_)]) => {
+            Ok(vm.run(&compiler.compile(&program)?))
+        }
+        // 语法错误和不是表达式的输入按树遍历求值器的方式报告
+        _ => Ok(eval_expression_in(expression, env)),
+    }
+}
+
+// 虚拟机模式下会话里定义的变量在虚拟机的全局变量里，遮住环境里的同名绑定
+fn print_vars<W: Write>(
+    output: &mut W,
+    env: &Rc<RefCell<Environment>>,
+    vm: Option<&Vm>,
+) -> io::Result<()> {
+    let mut bindings = env
+        .borrow()
+        .visible()
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+    if let Some(vm) = vm {
+        bindings.extend(vm.globals());
+    }
+    for (name, value) in bindings {
+        writeln!(
+            output,
+            "{}: {} = {}",
+            name,
+            value.object_type(),
+            preview(&value)
+        )?;
+    }
+    Ok(())
+}
+
+// 带标注的变量同时显示标注和运行时的实际类型，标注不做检查
+fn print_type<W: Write>(
+    output: &mut W,
+    expression: &str,
+    value: Rc<Value>,
+    annotations: &HashMap<String, String>,
+) -> io::Result<()> {
+    if matches!(value.as_ref(), Value::Error(_)) {
+        return writeln!(output, "{}", value.inspect());
+    }
+    match annotations.get(expression) {
+        Some(annotation) => writeln!(
+            output,
+            "{}: {} ({})",
+            expression,
+            annotation,
+            value.object_type()
+        ),
+        None => writeln!(output, "{}: {}", expression, value.object_type()),
+    }
+}
+
+// `:budget 10000` 限制之后每一行输入最多求值的节点数（虚拟机里是函数调用的次数），`:budget off` 取消限制
+fn set_budget<W: Write>(
+    output: &mut W,
+    budget: &str,
+    env: &Rc<RefCell<Environment>>,
+) -> io::Result<()> {
+    let max_steps = match budget {
+        "off" => None,
+        steps => match steps.parse::<u64>() {
+            Ok(steps) => Some(steps),
+            Err(_) => return writeln!(output, "usage: :budget <steps>|off"),
+        },
+    };
+    env.borrow().context().borrow_mut().limits.max_steps = max_steps;
+    match max_steps {
+        Some(steps) => writeln!(output, "budget set to {} steps per input", steps),
+        None => writeln!(output, "budget removed"),
+    }
+}
+
+// `:profile on` 之后的输入都会统计节点和函数的求值次数和耗时，`:profile` 打印目前的结果
+// 只统计树遍历求值器
+fn profile<W: Write>(
+    output: &mut W,
+    command: &str,
+    env: &Rc<RefCell<Environment>>,
+) -> io::Result<()> {
+    let context = env.borrow().context();
+    let mut context = context.borrow_mut();
+    match command {
+        "" if context.profile.is_empty() => writeln!(output, "no profile data"),
+        "" => write!(output, "{}", context.profile.report()),
+        "on" => {
+            context.options.profile = true;
+            writeln!(output, "profiling enabled")
+        }
+        "off" => {
+            context.options.profile = false;
+            writeln!(output, "profiling disabled")
+        }
+        "reset" => {
+            context.profile.clear();
+            writeln!(output, "profile cleared")
+        }
+        _ => writeln!(output, "usage: :profile [on|off|reset]"),
+    }
+}
+
+// 打开之后每个节点的求值过程都会写到输出里，只对树遍历求值器生效
+fn set_trace<W: Write>(
+    output: &mut W,
+    command: &str,
+    env: &Rc<RefCell<Environment>>,
+) -> io::Result<()> {
+    let trace = match command {
+        "on" => true,
+        "off" => false,
+        _ => return writeln!(output, "usage: :trace on|off"),
+    };
+    env.borrow().context().borrow_mut().options.trace = trace;
+    match trace {
+        true => writeln!(output, "tracing enabled"),
+        false => writeln!(output, "tracing disabled"),
+    }
+}
+
+fn print_help<W: Write>(output: &mut W) -> io::Result<()> {
+    writeln!(output, ":help              list the commands")?;
+    writeln!(output, ":help builtins     list builtin functions")?;
+    writeln!(
+        output,
+        ":vars              list the bindings of the session"
+    )?;
+    writeln!(output, ":load <file>       evaluate a file in the session")?;
+    writeln!(
+        output,
+        ":save <file>       write the inputs of the session to a file"
+    )?;
+    writeln!(
+        output,
+        ":reset             start over with a fresh environment"
+    )?;
+    writeln!(
+        output,
+        ":type <expr>       show the runtime type of an expression"
+    )?;
+    writeln!(
+        output,
+        ":undo              revert the bindings of the last input"
+    )?;
+    writeln!(
+        output,
+        ":expand <code>     show the code after macro expansion"
+    )?;
+    writeln!(
+        output,
+        ":budget <n>|off    limit the evaluation steps of each input"
+    )?;
+    writeln!(
+        output,
+        ":profile [on|off|reset]  show or control evaluation profiling"
+    )?;
+    writeln!(output, ":trace on|off      print every evaluation step")?;
+    writeln!(
+        output,
+        ":envgraph          print the environment graph in DOT format"
+    )?;
+    writeln!(
+        output,
+        ":reload-builtins   restore builtins and re-register host functions"
+    )?;
+    writeln!(output, ":quit              leave the session")?;
+    Ok(())
+}
+
+fn print_builtins<W: Write>(output: &mut W, builtins: &BuiltinRegistry) -> io::Result<()> {
+    let signatures = builtins
+        .list()
+        .map(|info| (format!("{}/{}", info.name, info.arity), &info.description))
+        .collect::<Vec<_>>();
+    let width = signatures
+        .iter()
+        .map(|(signature, _)| signature.len())
+        .max()
+        .unwrap_or_default();
+    for (signature, description) in signatures {
+        writeln!(
+            output,
+            "{:width$}  {}",
+            signature,
+            description,
+            width = width
+        )?;
+    }
+    Ok(())
+}
+
+fn print_parser_errors<W: Write>(
+    output: &mut W,
+    errors: &[ParseError],
+    color: bool,
+) -> io::Result<()> {
     writeln!(output, "Woops! We ran into some monkey bussiness here!")?;
     writeln!(output, " parser errors:")?;
     for error in errors {
-        writeln!(output, "{}", error)?;
+        writeln!(output, "{}", paint(&error.to_string(), ERROR_COLOR, color))?;
     }
     Ok(())
 }
+
+// 关掉颜色时原样返回
+fn paint(text: &str, code: &str, color: bool) -> String {
+    match color {
+        true => format!("\x1b[{}m{}\x1b[0m", code, text),
+        false => text.to_owned(),
+    }
+}
+
+// 结果按源码的类别上色，字符串整个是字面量，错误用红色
+fn paint_value(value: &Value, color: bool) -> String {
+    let inspected = value.inspect();
+    if !color {
+        return inspected;
+    }
+    match value {
+        Value::Error(_) => paint(&inspected, ERROR_COLOR, true),
+        Value::String(_) => paint(&inspected, ansi_code(Category::Literal).unwrap(), true),
+        _ => to_ansi(&inspected),
+    }
+}
@@ -0,0 +1,365 @@
+// 求值之前按词法作用域检查名字：引用了没有定义的标识符、函数参数重名、内层绑定遮住外层的同名绑定
+// Monkey 的函数在调用时才查找外层的名字，所以函数体里可以引用外层作用域稍后才 let 的绑定
+use std::collections::HashSet;
+
+use crate::ast::expressions::{Expr, Identifier};
+use crate::ast::program::Program;
+use crate::ast::statements::{BlockStatement, Stmt};
+use crate::errors::{self, message, MessageId, Severity};
+use crate::evaluator::builtins::BuiltinRegistry;
+use crate::evaluator::environment::Environment;
+use crate::evaluator::macro_expansion::macro_names;
+use crate::sync::{Rc, RefCell};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiagnosticKind {
+    UndefinedIdentifier,
+    DuplicateParameter,
+    Shadowing,
+}
+
+impl DiagnosticKind {
+    // 遮蔽只是提醒，其余的求值时一定会出错或者得不到想要的结果
+    pub fn is_error(&self) -> bool {
+        !matches!(self, DiagnosticKind::Shadowing)
+    }
+}
+
+// 一条检查结果，严重程度由 kind 决定，见 errors::Diagnostic
+pub type Diagnostic = errors::Diagnostic<DiagnosticKind>;
+
+fn diagnostic(kind: DiagnosticKind, message: String, identifier: &Identifier) -> Diagnostic {
+    let severity = if kind.is_error() {
+        Severity::Error
+    } else {
+        Severity::Warning
+    };
+    Diagnostic::at(kind, severity, message, identifier)
+}
+
+// 用默认的内置函数检查
+pub fn resolve(program: &Program) -> Vec<Diagnostic> {
+    Resolver::new().resolve(program)
+}
+
+// globals 是程序开始之前就已经存在的名字，default() 得到的检查器不认识任何名字，包括内置函数
+#[derive(Default)]
+pub struct Resolver {
+    globals: HashSet<Rc<str>>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver::default().with_builtins(&BuiltinRegistry::new())
+    }
+
+    // 环境链上已有的绑定加上上下文里注册的内置函数，REPL 用它检查新输入的一行
+    pub fn for_environment(env: &Rc<RefCell<Environment>>) -> Self {
+        let mut resolver =
+            Resolver::default().with_builtins(&env.borrow().context().borrow().builtins);
+        let mut current = Some(Rc::clone(env));
+        while let Some(env) = current {
+            let env = env.borrow();
+            resolver
+                .globals
+                .extend(env.iter().map(|(name, _)| Rc::clone(name)));
+            current = env.outer();
+        }
+        resolver
+    }
+
+    pub fn with_builtins(mut self, builtins: &BuiltinRegistry) -> Self {
+        self.globals
+            .extend(builtins.list().map(|info| Rc::from(info.name.as_str())));
+        self
+    }
+
+    pub fn with_globals<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<Rc<str>>,
+    {
+        self.globals.extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    // 按源码顺序返回所有问题
+    pub fn resolve(&self, program: &Program) -> Vec<Diagnostic> {
+        let mut pass = Pass {
+            globals: &self.globals,
+            macros: macro_names(program),
+            scopes: vec![],
+            diagnostics: vec![],
+        };
+        pass.scopes.push(Scope::new(&program.statements));
+        pass.statements(&program.statements);
+        pass.diagnostics
+            .sort_by_key(|diagnostic| diagnostic.span.start);
+        pass.diagnostics
+    }
+}
+
+// 程序顶层或者一个函数体，if 的代码块不产生新的作用域
+// hoisted 是整个作用域里 let 过的所有名字，defined 是按执行顺序到目前为止已经绑定的名字
+struct Scope {
+    hoisted: HashSet<Rc<str>>,
+    defined: HashSet<Rc<str>>,
+}
+
+impl Scope {
+    fn new(statements: &[Stmt]) -> Self {
+        let mut hoisted = HashSet::new();
+        collect_statements(statements, &mut hoisted);
+        Scope {
+            hoisted,
+            defined: HashSet::new(),
+        }
+    }
+}
+
+struct Pass<'a> {
+    globals: &'a HashSet<Rc<str>>,
+    // 绑定到宏的名字，宏调用的参数是代码本身，不在这里检查
+    macros: HashSet<Rc<str>>,
+    scopes: Vec<Scope>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Pass<'_> {
+    fn statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Let(let_statement) => {
+                self.expression(&let_statement.value);
+                self.define(&let_statement.name);
+            }
+            Stmt::Return(return_statement) => self.expression(&return_statement.return_value),
+            Stmt::Expression(expression_statement) => {
+                self.expression(&expression_statement.expression)
+            }
+            Stmt::Block(block) => self.statements(&block.statements),
+        }
+    }
+
+    fn expression(&mut self, expression: &Expr) {
+        match expression {
+            Expr::Identifier(identifier) => self.reference(identifier),
+            Expr::Integer(_) | Expr::Boolean(_) | Expr::String(_) => {}
+            Expr::Prefix(prefix) => self.expression(&prefix.right),
+            Expr::Infix(infix) => {
+                self.expression(&infix.left);
+                self.expression(&infix.right);
+            }
+            Expr::If(if_expression) => {
+                self.expression(&if_expression.condition);
+                self.statements(&if_expression.consequence.statements);
+                if let Some(alternative) = if_expression.alternative.as_ref() {
+                    self.statements(&alternative.statements);
+                }
+            }
+            Expr::Function(function) => self.function(&function.parameters, &function.body),
+            Expr::Macro(macro_literal) => {
+                self.function(&macro_literal.parameters, &macro_literal.body)
+            }
+            Expr::Call(call) => {
+                let quoted = match call.function.as_ref() {
+                    Expr::Identifier(identifier) if self.macros.contains(&identifier.value) => {
+                        self.reference(identifier);
+                        true
+                    }
+                    function => {
+                        self.expression(function);
+                        false
+                    }
+                };
+                for argument in call.arguments.iter() {
+                    if quoted {
+                        self.quoted(argument);
+                    } else {
+                        self.expression(argument);
+                    }
+                }
+            }
+            Expr::Quote(quote) => self.quoted(&quote.expression),
+            Expr::Unquote(unquote) => self.expression(&unquote.expression),
+            Expr::UnquoteSplice(splice) => self.expression(&splice.expression),
+            Expr::Array(array) => {
+                for element in array.elements.iter() {
+                    self.expression(element);
+                }
+            }
+            Expr::Index(index) => {
+                self.expression(&index.left);
+                self.expression(&index.index);
+            }
+            Expr::Hash(hash) => {
+                for (key, value) in hash.pairs.iter() {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+        }
+    }
+
+    // 被 quote 的代码不会求值，只有 unquote 的参数需要检查
+    fn quoted(&mut self, expression: &Expr) {
+        let mut pending = vec![expression];
+        while let Some(expression) = pending.pop() {
+            match expression {
+                Expr::Unquote(unquote) => self.expression(&unquote.expression),
+                Expr::UnquoteSplice(splice) => self.expression(&splice.expression),
+                Expr::Quote(quote) => pending.push(&quote.expression),
+                Expr::Prefix(prefix) => pending.push(&prefix.right),
+                Expr::Infix(infix) => pending.extend([infix.left.as_ref(), infix.right.as_ref()]),
+                Expr::Call(call) => {
+                    pending.push(&call.function);
+                    pending.extend(call.arguments.iter());
+                }
+                Expr::Array(array) => pending.extend(array.elements.iter()),
+                Expr::Index(index) => pending.extend([index.left.as_ref(), index.index.as_ref()]),
+                Expr::Hash(hash) => {
+                    pending.extend(hash.pairs.iter().flat_map(|(key, value)| [key, value]))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn function(&mut self, parameters: &[Identifier], body: &BlockStatement) {
+        self.scopes.push(Scope::new(&body.statements));
+        for parameter in parameters {
+            let scope = self.scopes.last_mut().expect("scope pushed above");
+            if !scope.defined.insert(Rc::clone(&parameter.value)) {
+                self.diagnostics.push(diagnostic(
+                    DiagnosticKind::DuplicateParameter,
+                    message(MessageId::DuplicateParameter, &[&parameter.value]),
+                    parameter,
+                ));
+                continue;
+            }
+            scope.hoisted.insert(Rc::clone(&parameter.value));
+            self.check_shadowing(parameter);
+        }
+        self.statements(&body.statements);
+        self.scopes.pop();
+    }
+
+    fn define(&mut self, name: &Identifier) {
+        // 程序顶层重新 let 一个名字只是重新绑定
+        if self.scopes.len() > 1 {
+            let scope = self.scopes.last().expect("program scope is always present");
+            if !scope.defined.contains(&name.value) {
+                self.check_shadowing(name);
+            }
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.defined.insert(Rc::clone(&name.value));
+        }
+    }
+
+    // 外层函数、程序顶层或者内置函数里有同名的绑定
+    fn check_shadowing(&mut self, name: &Identifier) {
+        let outer = &self.scopes[..self.scopes.len().saturating_sub(1)];
+        let shadows = self.globals.contains(&name.value)
+            || outer
+                .iter()
+                .any(|scope| scope.hoisted.contains(&name.value));
+        if shadows {
+            self.diagnostics.push(diagnostic(
+                DiagnosticKind::Shadowing,
+                message(MessageId::ShadowedBinding, &[&name.value]),
+                name,
+            ));
+        }
+    }
+
+    // 当前作用域里必须已经执行过 let，外层作用域的绑定只要存在就行，调用函数时它们多半已经定义好了
+    fn reference(&mut self, identifier: &Identifier) {
+        let Some((current, outer)) = self.scopes.split_last() else {
+            return;
+        };
+        let found = current.defined.contains(&identifier.value)
+            || outer
+                .iter()
+                .any(|scope| scope.hoisted.contains(&identifier.value))
+            || self.globals.contains(&identifier.value);
+        if !found {
+            self.diagnostics.push(diagnostic(
+                DiagnosticKind::UndefinedIdentifier,
+                message(MessageId::IdentifierNotFound, &[&identifier.value]),
+                identifier,
+            ));
+        }
+    }
+}
+
+// 一个作用域里 let 的名字，不进入嵌套的函数和宏
+fn collect_statements(statements: &[Stmt], names: &mut HashSet<Rc<str>>) {
+    for statement in statements {
+        match statement {
+            Stmt::Let(let_statement) => {
+                names.insert(Rc::clone(&let_statement.name.value));
+                collect_expression(&let_statement.value, names);
+            }
+            Stmt::Return(return_statement) => {
+                collect_expression(&return_statement.return_value, names)
+            }
+            Stmt::Expression(expression_statement) => {
+                collect_expression(&expression_statement.expression, names)
+            }
+            Stmt::Block(block) => collect_statements(&block.statements, names),
+        }
+    }
+}
+
+fn collect_expression(expression: &Expr, names: &mut HashSet<Rc<str>>) {
+    match expression {
+        Expr::If(if_expression) => {
+            collect_expression(&if_expression.condition, names);
+            collect_statements(&if_expression.consequence.statements, names);
+            if let Some(alternative) = if_expression.alternative.as_ref() {
+                collect_statements(&alternative.statements, names);
+            }
+        }
+        Expr::Prefix(prefix) => collect_expression(&prefix.right, names),
+        Expr::Quote(quote) => collect_expression(&quote.expression, names),
+        Expr::Unquote(unquote) => collect_expression(&unquote.expression, names),
+        Expr::UnquoteSplice(splice) => collect_expression(&splice.expression, names),
+        Expr::Infix(infix) => {
+            collect_expression(&infix.left, names);
+            collect_expression(&infix.right, names);
+        }
+        Expr::Call(call) => {
+            collect_expression(&call.function, names);
+            for argument in call.arguments.iter() {
+                collect_expression(argument, names);
+            }
+        }
+        Expr::Array(array) => {
+            for element in array.elements.iter() {
+                collect_expression(element, names);
+            }
+        }
+        Expr::Index(index) => {
+            collect_expression(&index.left, names);
+            collect_expression(&index.index, names);
+        }
+        Expr::Hash(hash) => {
+            for (key, value) in hash.pairs.iter() {
+                collect_expression(key, names);
+                collect_expression(value, names);
+            }
+        }
+        Expr::Identifier(_)
+        | Expr::Integer(_)
+        | Expr::Boolean(_)
+        | Expr::String(_)
+        | Expr::Function(_)
+        | Expr::Macro(_) => {}
+    }
+}
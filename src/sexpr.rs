@@ -0,0 +1,31 @@
+// 把 AST 打印成缩进的 S 表达式，每个节点显示类型名、值或运算符以及在源码里的区间
+// 排查优先级问题时可以直接看出括号是怎么结合的
+use std::fmt::Write;
+
+use crate::ast::traits::Node;
+use crate::ast::walk::{children, node_detail, node_kind};
+
+const INDENT: &str = "  ";
+
+pub fn to_sexpr(node: &dyn Node) -> String {
+    let mut out = String::new();
+    write_sexpr(node, 0, &mut out);
+    out.push('\n');
+    out
+}
+
+fn write_sexpr(node: &dyn Node, depth: usize, out: &mut String) {
+    out.push('(');
+    out.push_str(node_kind(node));
+    if let Some(detail) = node_detail(node) {
+        write!(out, " {}", detail).unwrap();
+    }
+    let span = node.span();
+    write!(out, " @{}..{}", span.start, span.end).unwrap();
+    for child in children(node) {
+        out.push('\n');
+        out.push_str(&INDENT.repeat(depth + 1));
+        write_sexpr(child, depth + 1, out);
+    }
+    out.push(')');
+}
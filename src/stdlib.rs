@@ -0,0 +1,74 @@
+// 用 Monkey 写的标准库，源码在编译期嵌入
+// 可以用 import("std/list") 按模块导入，也可以用 load_prelude 把所有模块的绑定直接放进一个环境
+use crate::ast::traits::AsNode;
+use crate::ast::walk::NodeRef;
+use crate::errors::{join_parse_errors, runtime_error, EvalError, MessageId};
+use crate::evaluator::environment::Environment;
+use crate::evaluator::eval::{eval_node, is_abrupt};
+use crate::evaluator::object::{Null, Value};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::sync::{Rc, RefCell};
+
+#[derive(Debug)]
+pub struct StdModule {
+    // import() 使用的名字
+    pub name: &'static str,
+    pub source: &'static str,
+}
+
+static MODULES: [StdModule; 3] = [
+    StdModule {
+        name: "std/list",
+        source: include_str!("../std/list.mky"),
+    },
+    StdModule {
+        name: "std/string",
+        source: include_str!("../std/string.mky"),
+    },
+    StdModule {
+        name: "std/func",
+        source: include_str!("../std/func.mky"),
+    },
+];
+
+pub fn modules() -> &'static [StdModule] {
+    &MODULES
+}
+
+pub fn find(name: &str) -> Option<&'static StdModule> {
+    MODULES.iter().find(|module| module.name == name)
+}
+
+// 依次在 env 里求值所有模块，之后的代码可以直接使用这些函数，同名的绑定会被覆盖
+// 模块顶层定义的名字记在运行时上下文里，Environment::save 不保存它们
+pub fn load_prelude(env: &Rc<RefCell<Environment>>) -> Rc<Value> {
+    for module in MODULES.iter() {
+        let program = match Parser::new(Lexer::new(module.source.to_owned())).parse() {
+            Ok(program) => program,
+            Err(errors) => {
+                return Rc::new(Value::Error(runtime_error(
+                    MessageId::ModuleParseFailed,
+                    &[&module.name, &join_parse_errors(&errors)],
+                )))
+            }
+        };
+        let result =
+            eval_node(program.as_node(), Rc::clone(env)).unwrap_or_else(EvalError::into_object);
+        if is_abrupt(result.as_ref()) {
+            return result;
+        }
+        let scope = env.borrow();
+        let context = scope.context();
+        let mut context = context.borrow_mut();
+        for statement in program.statements.iter() {
+            if let NodeRef::Let(let_statement) = statement.node_ref() {
+                let name = Rc::clone(&let_statement.name.value);
+                if let Some(value) = scope.get(&name) {
+                    context.prelude.insert(name, Rc::downgrade(&value));
+                }
+            }
+        }
+    }
+    Rc::new(Value::Null(Null))
+}
@@ -0,0 +1,101 @@
+// 求值用到的共享指针和内部可变性，默认是单线程的 Rc 和 RefCell
+// 打开 sync 功能后换成 Arc 和用 RwLock 实现的同名 RefCell，解释器和求值得到的值可以移动到其他线程
+// 两种配置的接口相同，其他模块统一从这里引用
+#[cfg(not(feature = "sync"))]
+pub use std::cell::{Ref, RefCell, RefMut};
+#[cfg(not(feature = "sync"))]
+pub use std::rc::{Rc, Weak};
+
+#[cfg(feature = "sync")]
+pub use self::lock::{Ref, RefCell, RefMut};
+#[cfg(feature = "sync")]
+pub use std::sync::{Arc as Rc, Weak};
+
+// 宿主传进来的回调、输入和输出要满足的约束，sync 配置下必须能跨线程
+#[cfg(not(feature = "sync"))]
+pub trait MaybeSend {}
+#[cfg(not(feature = "sync"))]
+impl<T: ?Sized> MaybeSend for T {}
+
+#[cfg(feature = "sync")]
+pub trait MaybeSend: Send + Sync {}
+#[cfg(feature = "sync")]
+impl<T: Send + Sync + ?Sized> MaybeSend for T {}
+
+#[cfg(feature = "sync")]
+mod lock {
+    use std::fmt;
+    use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+
+    pub type Ref<'a, T> = RwLockReadGuard<'a, T>;
+    pub type RefMut<'a, T> = RwLockWriteGuard<'a, T>;
+
+    // 拿不到锁时 try_borrow 和 try_borrow_mut 返回的错误
+    #[derive(Debug)]
+    pub struct BorrowError;
+
+    // 同一个线程里已经借出时再 borrow_mut 会死锁，而不是像 std 的 RefCell 那样 panic
+    // 锁中毒说明别的线程求值时 panic 了，里面的数据仍然照常使用
+    #[derive(Default)]
+    pub struct RefCell<T: ?Sized>(RwLock<T>);
+
+    impl<T> RefCell<T> {
+        pub fn new(value: T) -> Self {
+            RefCell(RwLock::new(value))
+        }
+
+        pub fn into_inner(self) -> T {
+            self.0.into_inner().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        pub fn replace(&self, value: T) -> T {
+            std::mem::replace(&mut *self.borrow_mut(), value)
+        }
+
+        pub fn take(&self) -> T
+        where
+            T: Default,
+        {
+            self.replace(T::default())
+        }
+    }
+
+    impl<T: ?Sized> RefCell<T> {
+        pub fn borrow(&self) -> Ref<'_, T> {
+            self.0.read().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        pub fn borrow_mut(&self) -> RefMut<'_, T> {
+            self.0.write().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        // 和 std 的 RefCell 一样，已经借出、要等待时返回 Err 而不是阻塞
+        pub fn try_borrow(&self) -> Result<Ref<'_, T>, BorrowError> {
+            match self.0.try_read() {
+                Ok(guard) => Ok(guard),
+                Err(TryLockError::Poisoned(error)) => Ok(error.into_inner()),
+                Err(TryLockError::WouldBlock) => Err(BorrowError),
+            }
+        }
+
+        pub fn try_borrow_mut(&self) -> Result<RefMut<'_, T>, BorrowError> {
+            match self.0.try_write() {
+                Ok(guard) => Ok(guard),
+                Err(TryLockError::Poisoned(error)) => Ok(error.into_inner()),
+                Err(TryLockError::WouldBlock) => Err(BorrowError),
+            }
+        }
+    }
+
+    impl<T: Clone> Clone for RefCell<T> {
+        fn clone(&self) -> Self {
+            RefCell::new(self.borrow().clone())
+        }
+    }
+
+    impl<T: ?Sized + fmt::Debug> fmt::Debug for RefCell<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_tuple("RefCell").field(&&*self.borrow()).finish()
+        }
+    }
+}
@@ -1,17 +1,30 @@
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
 
+use crate::lexer::Span;
+use crate::sync::Rc;
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Token {
     pub token_type: TokenType,
-    pub literal: String,
+    // lexer 产生的字面量经过 Interner，相同的字面量共享同一份字符串
+    pub literal: Rc<str>,
+    // 在源码里的位置，由 lexer 填写，手动构造的词法单元 line 为 0
+    pub span: Span,
+    // 从 1 开始的行号和列号
+    pub line: usize,
+    pub column: usize,
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, literal: String) -> Self {
+    pub fn new(token_type: TokenType, literal: impl Into<Rc<str>>) -> Self {
         Self {
             token_type,
-            literal,
+            literal: literal.into(),
+            span: Span::default(),
+            line: 0,
+            column: 0,
         }
     }
 }
@@ -26,6 +39,9 @@ static KEYWORDS: Lazy<HashMap<&'static str, TokenType>> = Lazy::new(|| {
         ("else", TokenType::Else),
         ("return", TokenType::Return),
         ("macro", TokenType::Macro),
+        ("quote", TokenType::Quote),
+        ("unquote", TokenType::Unquote),
+        ("unquote_splice", TokenType::UnquoteSplice),
     ])
 });
 
@@ -33,7 +49,52 @@ pub fn lookup_identifier(identifier: &str) -> TokenType {
     *KEYWORDS.get(identifier).unwrap_or(&TokenType::Ident)
 }
 
+// 按字母顺序排列
+pub fn keywords() -> Vec<&'static str> {
+    let mut keywords = KEYWORDS.keys().copied().collect::<Vec<_>>();
+    keywords.sort();
+    keywords
+}
+
+// 看起来像是关键字拼错了的标识符，比如 retrun、fnn，返回最接近的关键字
+// 短关键字只允许差一个字符，标识符比关键字短时不算，避免把 f、i 这样的变量名当成拼写错误
+pub fn suggest_keyword(identifier: &str) -> Option<&'static str> {
+    let mut candidates = KEYWORDS
+        .keys()
+        .filter(|keyword| identifier != **keyword && identifier.len() >= keyword.len())
+        .map(|keyword| (edit_distance(identifier, keyword), *keyword))
+        .filter(|(distance, keyword)| *distance <= if keyword.len() <= 3 { 1 } else { 2 })
+        .collect::<Vec<_>>();
+    candidates.sort();
+    candidates.first().map(|(_, keyword)| *keyword)
+}
+
+// 编辑距离，相邻字符交换也算一步
+fn edit_distance(left: &str, right: &str) -> usize {
+    let left = left.chars().collect::<Vec<_>>();
+    let right = right.chars().collect::<Vec<_>>();
+    let mut distances = vec![vec![0; right.len() + 1]; left.len() + 1];
+    distances[0] = (0..=right.len()).collect();
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for i in 1..=left.len() {
+        for j in 1..=right.len() {
+            let cost = usize::from(left[i - 1] != right[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && left[i - 1] == right[j - 2] && left[i - 2] == right[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[left.len()][right.len()]
+}
+
 #[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TokenType {
     Illegal,
     EOF,
@@ -67,4 +128,7 @@ pub enum TokenType {
     RightBracket,
     Colon,
     Macro,
+    Quote,
+    Unquote,
+    UnquoteSplice,
 }
@@ -0,0 +1,312 @@
+// 实验性的后端：把 Monkey 程序翻译成可读的 Rust 源码，
+// 生成的代码依赖 runtime.rs 里的运行时，输出时会一并拼接进去，因此可以单独用 rustc 编译
+pub mod runtime;
+
+use std::collections::HashSet;
+
+use crate::ast::expressions::{Expr, FunctionLiteral, Identifier};
+use crate::ast::program::Program;
+use crate::ast::statements::{BlockStatement, Stmt};
+use crate::ast::traits::{AsNode, Node};
+use crate::ast::walk::{children, NodeRef};
+
+const RUNTIME: &str = include_str!("runtime.rs");
+
+const BUILTIN_NAMES: [&str; 6] = ["len", "first", "last", "rest", "push", "puts"];
+
+// 输出一个完整的、带 main 函数的 Rust 源文件
+pub fn transpile(program: &Program) -> Result<String, String> {
+    let main = transpile_program(program)?;
+    Ok(format!(
+        "// Generated from Monkey source by implement_parser::transpile.\n\
+         #![allow(dead_code, unused_variables, unreachable_code, clippy::all)]\n\n\
+         {}\n{}",
+        RUNTIME, main
+    ))
+}
+
+// 只输出 main 函数部分，不包含运行时
+pub fn transpile_program(program: &Program) -> Result<String, String> {
+    let mut emitter = Emitter { scopes: vec![] };
+    let body = emitter.function_body(&program.statements, &[], 1)?;
+    Ok(format!("fn main() {{\n{}}}\n", body))
+}
+
+struct Emitter {
+    // 每一层函数作用域里声明过的变量名，Monkey 的块语句不会开新的作用域
+    scopes: Vec<HashSet<String>>,
+}
+
+impl Emitter {
+    fn function_body(
+        &mut self,
+        statements: &[Stmt],
+        parameters: &[Identifier],
+        indent: usize,
+    ) -> Result<String, String> {
+        let pad = "    ".repeat(indent);
+        let mut scope = HashSet::new();
+        let mut out = String::new();
+        for (index, parameter) in parameters.iter().enumerate() {
+            out.push_str(&format!(
+                "{}let {} = Var::bound(arguments[{}].clone());\n",
+                pad,
+                variable(&parameter.value),
+                index
+            ));
+            scope.insert(parameter.value.to_string());
+        }
+        // 提前声明所有的 let 绑定，这样递归函数可以捕获到自己
+        let mut locals = vec![];
+        for statement in statements {
+            collect_let_names(statement.as_node(), &mut locals);
+        }
+        for name in locals {
+            if scope.insert(name.clone()) {
+                out.push_str(&format!("{}let {} = Var::new();\n", pad, variable(&name)));
+            }
+        }
+
+        self.scopes.push(scope);
+        let result = self.statements(statements, indent);
+        self.scopes.pop();
+        let (lines, value) = result?;
+        out.push_str(&lines);
+        if self.scopes.is_empty() {
+            if value != "Value::Null" {
+                out.push_str(&format!("{}let _ = {};\n", pad, value));
+            }
+        } else {
+            out.push_str(&format!("{}{}\n", pad, value));
+        }
+        Ok(out)
+    }
+
+    // 返回语句列表和代表块取值的表达式
+    fn statements(
+        &mut self,
+        statements: &[Stmt],
+        indent: usize,
+    ) -> Result<(String, String), String> {
+        let pad = "    ".repeat(indent);
+        let mut lines = String::new();
+        let mut value = "Value::Null".to_owned();
+        for (index, statement) in statements.iter().enumerate() {
+            let is_last = index + 1 == statements.len();
+            match statement {
+                Stmt::Let(let_statement) => {
+                    let expression = self.expression(&let_statement.value, indent)?;
+                    lines.push_str(&format!(
+                        "{}{}.set({});\n",
+                        pad,
+                        variable(&let_statement.name.value),
+                        expression
+                    ));
+                }
+                Stmt::Return(return_statement) => {
+                    let expression = self.expression(&return_statement.return_value, indent)?;
+                    if self.scopes.len() > 1 && is_last {
+                        value = format!("return {}", expression);
+                    } else if self.scopes.len() > 1 {
+                        lines.push_str(&format!("{}return {};\n", pad, expression));
+                    } else {
+                        lines
+                            .push_str(&format!("{}let _ = {};\n{}return;\n", pad, expression, pad));
+                    }
+                }
+                Stmt::Expression(expression_statement) => {
+                    let expression = self.expression(&expression_statement.expression, indent)?;
+                    if is_last {
+                        value = expression;
+                    } else {
+                        lines.push_str(&format!("{}{};\n", pad, expression));
+                    }
+                }
+                Stmt::Block(block) => {
+                    let block = self.block(block, indent)?;
+                    if is_last {
+                        value = block;
+                    } else {
+                        lines.push_str(&format!("{}{};\n", pad, block));
+                    }
+                }
+            }
+        }
+        Ok((lines, value))
+    }
+
+    fn block(&mut self, block: &BlockStatement, indent: usize) -> Result<String, String> {
+        let (lines, value) = self.statements(&block.statements, indent + 1)?;
+        Ok(format!(
+            "{{\n{}{}{}\n{}}}",
+            lines,
+            "    ".repeat(indent + 1),
+            value,
+            "    ".repeat(indent)
+        ))
+    }
+
+    fn expression(&mut self, expression: &Expr, indent: usize) -> Result<String, String> {
+        match expression {
+            Expr::Integer(integer) => Ok(format!("Value::Int({})", integer.value)),
+            Expr::Boolean(boolean) => Ok(format!("Value::Bool({})", boolean.value)),
+            Expr::String(string) => Ok(format!("Value::str({:?})", string.value)),
+            Expr::Identifier(identifier) => self.identifier(&identifier.value),
+            Expr::Prefix(prefix) => {
+                let right = self.expression(&prefix.right, indent)?;
+                match &*prefix.operator {
+                    "!" => Ok(format!("not({})", right)),
+                    "-" => Ok(format!("neg({})", right)),
+                    operator => Err(format!("unknown prefix operator: {}", operator)),
+                }
+            }
+            Expr::Infix(infix) => {
+                let function = match &*infix.operator {
+                    "+" => "add",
+                    "-" => "sub",
+                    "*" => "mul",
+                    "/" => "div",
+                    "<" => "lt",
+                    ">" => "gt",
+                    "==" => "eq",
+                    "!=" => "not_eq",
+                    operator => return Err(format!("unknown infix operator: {}", operator)),
+                };
+                let left = self.expression(&infix.left, indent)?;
+                let right = self.expression(&infix.right, indent)?;
+                Ok(format!("{}({}, {})", function, left, right))
+            }
+            Expr::If(if_expression) => {
+                let condition = self.expression(&if_expression.condition, indent)?;
+                let consequence = self.block(&if_expression.consequence, indent)?;
+                let alternative = match if_expression.alternative.as_ref() {
+                    Some(alternative) => self.block(alternative, indent)?,
+                    None => "{ Value::Null }".to_owned(),
+                };
+                Ok(format!(
+                    "if truthy(&{}) {} else {}",
+                    condition, consequence, alternative
+                ))
+            }
+            Expr::Function(function) => self.function(function, indent),
+            Expr::Call(call) => {
+                let function = self.expression(&call.function, indent)?;
+                let arguments = self.expressions(&call.arguments, indent)?;
+                Ok(format!("call({}, vec![{}])", function, arguments))
+            }
+            Expr::Array(array) => {
+                let elements = self.expressions(&array.elements, indent)?;
+                Ok(format!("Value::array(vec![{}])", elements))
+            }
+            Expr::Index(index) => {
+                let left = self.expression(&index.left, indent)?;
+                let index = self.expression(&index.index, indent)?;
+                Ok(format!("index({}, {})", left, index))
+            }
+            Expr::Hash(hash) => {
+                let mut pairs = vec![];
+                for (key, value) in hash.pairs.iter() {
+                    let key = self.expression(key, indent)?;
+                    let value = self.expression(value, indent)?;
+                    pairs.push(format!("({}, {})", key, value));
+                }
+                Ok(format!("Value::hash(vec![{}])", pairs.join(", ")))
+            }
+            Expr::Macro(_) => Err("macros must be expanded before transpiling".to_owned()),
+            Expr::Quote(_) | Expr::Unquote(_) | Expr::UnquoteSplice(_) => Err(format!(
+                "`{}` is not supported by the transpiler",
+                expression.token_literal()
+            )),
+        }
+    }
+
+    fn expressions(&mut self, expressions: &[Expr], indent: usize) -> Result<String, String> {
+        let mut results = vec![];
+        for expression in expressions {
+            results.push(self.expression(expression, indent)?);
+        }
+        Ok(results.join(", "))
+    }
+
+    fn identifier(&self, name: &str) -> Result<String, String> {
+        if self.scopes.iter().any(|scope| scope.contains(name)) {
+            Ok(format!("{}.get({:?})", variable(name), name))
+        } else if BUILTIN_NAMES.contains(&name) {
+            Ok(format!("builtin_{}()", name))
+        } else {
+            Err(format!("identifier not found: {}", name))
+        }
+    }
+
+    fn function(&mut self, function: &FunctionLiteral, indent: usize) -> Result<String, String> {
+        let pad = "    ".repeat(indent + 1);
+        // 只捕获函数体里真正用到的外层变量
+        let mut used = HashSet::new();
+        collect_identifiers(function.body.as_node(), &mut used);
+        let mut captures = used
+            .into_iter()
+            .filter(|name| self.scopes.iter().any(|scope| scope.contains(name)))
+            .collect::<Vec<_>>();
+        captures.sort();
+
+        if captures.is_empty() {
+            let body =
+                self.function_body(&function.body.statements, &function.parameters, indent + 1)?;
+            return Ok(format!(
+                "Value::func(move |arguments: Vec<Value>| -> Value {{\n{}expect_arguments(&arguments, {});\n{}{}}})",
+                pad,
+                function.parameters.len(),
+                body,
+                "    ".repeat(indent)
+            ));
+        }
+
+        let mut out = "Value::func({\n".to_owned();
+        for name in captures {
+            out.push_str(&format!(
+                "{}let {} = {}.clone();\n",
+                pad,
+                variable(&name),
+                variable(&name)
+            ));
+        }
+        out.push_str(&format!(
+            "{}move |arguments: Vec<Value>| -> Value {{\n{}    expect_arguments(&arguments, {});\n",
+            pad,
+            pad,
+            function.parameters.len()
+        ));
+        out.push_str(&self.function_body(
+            &function.body.statements,
+            &function.parameters,
+            indent + 2,
+        )?);
+        out.push_str(&format!("{}}}\n{}}})", pad, "    ".repeat(indent)));
+        Ok(out)
+    }
+}
+
+// Monkey 的标识符加上前缀，避免和 Rust 关键字以及运行时里的名字冲突
+fn variable(name: &str) -> String {
+    format!("v_{}", name)
+}
+
+fn collect_let_names(node: &dyn Node, names: &mut Vec<String>) {
+    match node.node_ref() {
+        NodeRef::Function(_) => return,
+        NodeRef::Let(let_statement) => names.push(let_statement.name.value.to_string()),
+        _ => {}
+    }
+    for child in children(node) {
+        collect_let_names(child, names);
+    }
+}
+
+fn collect_identifiers(node: &dyn Node, names: &mut HashSet<String>) {
+    if let NodeRef::Identifier(identifier) = node.node_ref() {
+        names.insert(identifier.value.to_string());
+    }
+    for child in children(node) {
+        collect_identifiers(child, names);
+    }
+}
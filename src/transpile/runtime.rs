@@ -0,0 +1,333 @@
+// 转译出来的 Rust 代码依赖的运行时，生成代码时会原样拼接到输出文件里，
+// 所以这里不能引用 crate 里的其他模块
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Clone)]
+pub enum Value {
+    Null,
+    Int(i64),
+    Bool(bool),
+    Str(Rc<str>),
+    Array(Rc<Vec<Value>>),
+    Hash(Rc<Vec<(Value, Value)>>),
+    Func(Rc<dyn Fn(Vec<Value>) -> Value>),
+}
+
+impl Value {
+    pub fn str(value: &str) -> Value {
+        Value::Str(Rc::from(value))
+    }
+
+    pub fn array(elements: Vec<Value>) -> Value {
+        Value::Array(Rc::new(elements))
+    }
+
+    pub fn hash(pairs: Vec<(Value, Value)>) -> Value {
+        let mut deduplicated: Vec<(Value, Value)> = vec![];
+        for (key, value) in pairs {
+            check_hash_key(&key);
+            match deduplicated
+                .iter_mut()
+                .find(|(existing, _)| same(existing, &key))
+            {
+                Some(pair) => pair.1 = value,
+                None => deduplicated.push((key, value)),
+            }
+        }
+        Value::Hash(Rc::new(deduplicated))
+    }
+
+    pub fn func(function: impl Fn(Vec<Value>) -> Value + 'static) -> Value {
+        Value::Func(Rc::new(function))
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "Null",
+            Value::Int(_) => "Integer",
+            Value::Bool(_) => "Boolean",
+            Value::Str(_) => "String",
+            Value::Array(_) => "Array",
+            Value::Hash(_) => "Hash",
+            Value::Func(_) => "Function",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Str(value) => write!(f, "{}", value),
+            Value::Array(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| element.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "[{}]", elements)
+            }
+            Value::Hash(pairs) => {
+                let pairs = pairs
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "{{{}}}", pairs)
+            }
+            Value::Func(_) => write!(f, "fn"),
+        }
+    }
+}
+
+// 一个可变的绑定，闭包通过克隆它来捕获外层变量，递归函数也依赖这一点
+#[derive(Clone, Default)]
+pub struct Var(Rc<RefCell<Option<Value>>>);
+
+impl Var {
+    pub fn new() -> Var {
+        Var::default()
+    }
+
+    pub fn bound(value: Value) -> Var {
+        Var(Rc::new(RefCell::new(Some(value))))
+    }
+
+    pub fn get(&self, name: &str) -> Value {
+        self.0
+            .borrow()
+            .clone()
+            .unwrap_or_else(|| fail(format!("identifier not found: {}", name)))
+    }
+
+    pub fn set(&self, value: Value) -> Value {
+        *self.0.borrow_mut() = Some(value);
+        Value::Null
+    }
+}
+
+pub fn fail(message: String) -> ! {
+    panic!("Error: {}", message)
+}
+
+pub fn truthy(value: &Value) -> bool {
+    !matches!(value, Value::Bool(false))
+}
+
+fn same(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Int(left), Value::Int(right)) => left == right,
+        (Value::Bool(left), Value::Bool(right)) => left == right,
+        (Value::Str(left), Value::Str(right)) => left == right,
+        (Value::Null, Value::Null) => true,
+        (Value::Array(left), Value::Array(right)) => {
+            left.len() == right.len() && left.iter().zip(right.iter()).all(|(l, r)| same(l, r))
+        }
+        // 和解释器一样，哈希相等和键值对的顺序无关
+        (Value::Hash(left), Value::Hash(right)) => {
+            left.len() == right.len()
+                && left.iter().all(|(key, value)| {
+                    right.iter().any(|(other_key, other_value)| {
+                        same(key, other_key) && same(value, other_value)
+                    })
+                })
+        }
+        (Value::Func(left), Value::Func(right)) => Rc::ptr_eq(left, right),
+        _ => false,
+    }
+}
+
+fn check_hash_key(key: &Value) {
+    if !matches!(key, Value::Int(_) | Value::Bool(_) | Value::Str(_)) {
+        fail(format!("unusable as hash key: {}", key.type_name()))
+    }
+}
+
+fn integer_operands(left: &Value, operator: &str, right: &Value) -> (i64, i64) {
+    match (left, right) {
+        (Value::Int(left), Value::Int(right)) => (*left, *right),
+        _ if left.type_name() != right.type_name() => fail(format!(
+            "type mismatch: {} {} {}",
+            left.type_name(),
+            operator,
+            right.type_name()
+        )),
+        _ => fail(format!(
+            "unknown operator: {} {} {}",
+            left.type_name(),
+            operator,
+            right.type_name()
+        )),
+    }
+}
+
+pub fn add(left: Value, right: Value) -> Value {
+    if let (Value::Str(left), Value::Str(right)) = (&left, &right) {
+        return Value::str(&format!("{}{}", left, right));
+    }
+    let (left, right) = integer_operands(&left, "+", &right);
+    Value::Int(left + right)
+}
+
+pub fn sub(left: Value, right: Value) -> Value {
+    let (left, right) = integer_operands(&left, "-", &right);
+    Value::Int(left - right)
+}
+
+pub fn mul(left: Value, right: Value) -> Value {
+    let (left, right) = integer_operands(&left, "*", &right);
+    Value::Int(left * right)
+}
+
+pub fn div(left: Value, right: Value) -> Value {
+    let (left, right) = integer_operands(&left, "/", &right);
+    if right == 0 {
+        fail("division by zero".to_owned())
+    }
+    Value::Int(left / right)
+}
+
+pub fn lt(left: Value, right: Value) -> Value {
+    let (left, right) = integer_operands(&left, "<", &right);
+    Value::Bool(left < right)
+}
+
+pub fn gt(left: Value, right: Value) -> Value {
+    let (left, right) = integer_operands(&left, ">", &right);
+    Value::Bool(left > right)
+}
+
+pub fn eq(left: Value, right: Value) -> Value {
+    if left.type_name() != right.type_name() {
+        fail(format!(
+            "type mismatch: {} == {}",
+            left.type_name(),
+            right.type_name()
+        ))
+    }
+    Value::Bool(same(&left, &right))
+}
+
+pub fn not_eq(left: Value, right: Value) -> Value {
+    Value::Bool(!truthy(&eq(left, right)))
+}
+
+pub fn not(right: Value) -> Value {
+    Value::Bool(!truthy(&right))
+}
+
+pub fn neg(right: Value) -> Value {
+    match right {
+        Value::Int(value) => Value::Int(-value),
+        _ => fail(format!("unknown operator: -{}", right.type_name())),
+    }
+}
+
+pub fn index(left: Value, index: Value) -> Value {
+    match (&left, &index) {
+        (Value::Array(elements), Value::Int(index)) => usize::try_from(*index)
+            .ok()
+            .and_then(|index| elements.get(index).cloned())
+            .unwrap_or(Value::Null),
+        (Value::Hash(pairs), key) => {
+            check_hash_key(key);
+            pairs
+                .iter()
+                .find(|(existing, _)| same(existing, key))
+                .map(|(_, value)| value.clone())
+                .unwrap_or(Value::Null)
+        }
+        _ => fail(format!(
+            "index operator not supported: {}",
+            left.type_name()
+        )),
+    }
+}
+
+pub fn call(function: Value, arguments: Vec<Value>) -> Value {
+    match function {
+        Value::Func(function) => function(arguments),
+        _ => fail(format!("not a function: {}", function.type_name())),
+    }
+}
+
+pub fn expect_arguments(arguments: &[Value], want: usize) {
+    if arguments.len() != want {
+        fail(format!(
+            "wrong number of arguments: got={}, want={}",
+            arguments.len(),
+            want
+        ))
+    }
+}
+
+pub fn builtin_len() -> Value {
+    Value::func(|arguments| {
+        expect_arguments(&arguments, 1);
+        match &arguments[0] {
+            Value::Str(value) => Value::Int(value.len() as i64),
+            Value::Array(elements) => Value::Int(elements.len() as i64),
+            other => fail(format!(
+                "argument to `len` not supported, got {}",
+                other.type_name()
+            )),
+        }
+    })
+}
+
+fn array_argument(arguments: &[Value], name: &str) -> Rc<Vec<Value>> {
+    match &arguments[0] {
+        Value::Array(elements) => Rc::clone(elements),
+        other => fail(format!(
+            "argument to `{}` must be Array, got {}",
+            name,
+            other.type_name()
+        )),
+    }
+}
+
+pub fn builtin_first() -> Value {
+    Value::func(|arguments| {
+        expect_arguments(&arguments, 1);
+        let elements = array_argument(&arguments, "first");
+        elements.first().cloned().unwrap_or(Value::Null)
+    })
+}
+
+pub fn builtin_last() -> Value {
+    Value::func(|arguments| {
+        expect_arguments(&arguments, 1);
+        let elements = array_argument(&arguments, "last");
+        elements.last().cloned().unwrap_or(Value::Null)
+    })
+}
+
+pub fn builtin_rest() -> Value {
+    Value::func(|arguments| {
+        expect_arguments(&arguments, 1);
+        let elements = array_argument(&arguments, "rest");
+        Value::array(elements.iter().skip(1).cloned().collect())
+    })
+}
+
+pub fn builtin_push() -> Value {
+    Value::func(|arguments| {
+        expect_arguments(&arguments, 2);
+        let mut elements = array_argument(&arguments, "push").as_ref().clone();
+        elements.push(arguments[1].clone());
+        Value::array(elements)
+    })
+}
+
+pub fn builtin_puts() -> Value {
+    Value::func(|arguments| {
+        for argument in arguments {
+            println!("{}", argument);
+        }
+        Value::Null
+    })
+}
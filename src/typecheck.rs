@@ -0,0 +1,532 @@
+// 求值之前推断表达式的类型，找出一定会在运行时出错的运算，比如 5 + true
+// 推断是渐进的：参数、宏展开的结果和推不出来的地方都是 any，和 any 有关的运算都不报错
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+
+use crate::ast::expressions::{
+    CallExpression, Expr, FunctionLiteral, Identifier, IfExpression, IndexExpression,
+    InfixExpression,
+};
+use crate::ast::program::Program;
+use crate::ast::statements::{BlockStatement, Stmt};
+use crate::ast::traits::Node;
+use crate::errors::{message, Diagnostic, MessageId, Severity};
+use crate::evaluator::builtins::{Arity, BuiltinRegistry};
+use crate::evaluator::macro_expansion::macro_names;
+use crate::sync::Rc;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Any,
+    Int,
+    Bool,
+    String,
+    Null,
+    Array(Box<Type>),
+    Hash,
+    // 参数类型和返回值类型
+    Function(Vec<Type>, Box<Type>),
+    Builtin(Rc<str>, Arity),
+}
+
+impl Type {
+    // 两条路径上的类型合在一起，不一样时退回到 any
+    pub fn join(self, other: Type) -> Type {
+        match (self, other) {
+            (Type::Array(left), Type::Array(right)) => Type::Array(Box::new(left.join(*right))),
+            (left, right) if left == right => left,
+            _ => Type::Any,
+        }
+    }
+
+    fn is_known(&self) -> bool {
+        !matches!(self, Type::Any)
+    }
+
+    // 对应运行时的 ObjectType，类型不同的值不能做运算，也不能比较相等
+    fn same_kind(&self, other: &Type) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    // 和求值器一样，元素都能作为键的数组也可以作为键
+    fn is_hashable(&self) -> bool {
+        match self {
+            Type::Array(element) => element.is_hashable(),
+            other => matches!(other, Type::Any | Type::Int | Type::Bool | Type::String),
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "any"),
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::String => write!(f, "string"),
+            Type::Null => write!(f, "null"),
+            Type::Array(element) => write!(f, "array<{}>", element),
+            Type::Hash => write!(f, "hash"),
+            Type::Function(parameters, result) => {
+                let parameters = parameters
+                    .iter()
+                    .map(|parameter| parameter.to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "fn({}) -> {}", parameters.join(", "), result)
+            }
+            Type::Builtin(name, _) => write!(f, "builtin {}", name),
+        }
+    }
+}
+
+// 一条类型错误，没有再细分种类，见 errors::Diagnostic
+pub type TypeError = Diagnostic;
+
+// 用默认的内置函数检查
+pub fn check(program: &Program) -> Vec<TypeError> {
+    TypeChecker::new().check(program)
+}
+
+// globals 是程序开始之前就已经存在的名字和它们的类型，default() 得到的检查器不认识任何名字
+#[derive(Default)]
+pub struct TypeChecker {
+    globals: HashMap<Rc<str>, Type>,
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        TypeChecker::default().with_builtins(&BuiltinRegistry::new())
+    }
+
+    pub fn with_builtins(mut self, builtins: &BuiltinRegistry) -> Self {
+        self.globals.extend(builtins.list().map(|info| {
+            let name = Rc::<str>::from(info.name.as_str());
+            (Rc::clone(&name), Type::Builtin(name, info.arity))
+        }));
+        self
+    }
+
+    pub fn with_global(mut self, name: &str, typ: Type) -> Self {
+        self.globals.insert(Rc::from(name), typ);
+        self
+    }
+
+    // 按源码顺序返回所有类型错误
+    pub fn check(&self, program: &Program) -> Vec<TypeError> {
+        self.run(program).1
+    }
+
+    // 程序最后一条语句的类型，有类型错误时返回错误
+    pub fn infer(&self, program: &Program) -> Result<Type, Vec<TypeError>> {
+        match self.run(program) {
+            (typ, errors) if errors.is_empty() => Ok(typ),
+            (_, errors) => Err(errors),
+        }
+    }
+
+    fn run(&self, program: &Program) -> (Type, Vec<TypeError>) {
+        let mut pass = Pass {
+            globals: &self.globals,
+            macros: macro_names(program),
+            scopes: vec![Scope::new(&program.statements)],
+            returns: vec![],
+            errors: vec![],
+        };
+        let typ = pass.statements(&program.statements);
+        pass.errors.sort_by_key(|error| error.span.start);
+        (typ, pass.errors)
+    }
+}
+
+// 程序顶层或者一个函数体，if 的代码块不产生新的作用域
+// lets 记下整个作用域里每个名字被 let 了几次，defined 是按执行顺序推到目前为止的类型
+struct Scope {
+    lets: HashMap<Rc<str>, usize>,
+    defined: HashMap<Rc<str>, Type>,
+}
+
+impl Scope {
+    fn new(statements: &[Stmt]) -> Self {
+        let mut lets = HashMap::new();
+        count_lets(statements, &mut lets);
+        Scope {
+            lets,
+            defined: HashMap::new(),
+        }
+    }
+}
+
+struct Pass<'a> {
+    globals: &'a HashMap<Rc<str>, Type>,
+    // 绑定到宏的名字，宏调用的参数是代码本身，结果要展开之后才知道
+    macros: HashSet<Rc<str>>,
+    scopes: Vec<Scope>,
+    // 每个正在检查的函数里 return 语句的类型
+    returns: Vec<Vec<Type>>,
+    errors: Vec<TypeError>,
+}
+
+impl Pass<'_> {
+    fn statements(&mut self, statements: &[Stmt]) -> Type {
+        statements
+            .iter()
+            .fold(Type::Null, |_, statement| self.statement(statement))
+    }
+
+    fn statement(&mut self, statement: &Stmt) -> Type {
+        match statement {
+            Stmt::Let(let_statement) => {
+                let mut typ = self.expression(&let_statement.value);
+                if let Some(annotation) = let_statement.type_annotation.as_ref() {
+                    typ = self.annotated(&let_statement.name, annotation, typ);
+                }
+                self.current()
+                    .defined
+                    .insert(Rc::clone(&let_statement.name.value), typ);
+                Type::Any
+            }
+            Stmt::Return(return_statement) => {
+                let typ = self.expression(&return_statement.return_value);
+                if let Some(returns) = self.returns.last_mut() {
+                    returns.push(typ.clone());
+                }
+                typ
+            }
+            Stmt::Expression(expression_statement) => {
+                self.expression(&expression_statement.expression)
+            }
+            Stmt::Block(block) => self.statements(&block.statements),
+        }
+    }
+
+    fn expression(&mut self, expression: &Expr) -> Type {
+        match expression {
+            Expr::Identifier(identifier) => self.lookup(&identifier.value),
+            Expr::Integer(_) => Type::Int,
+            Expr::Boolean(_) => Type::Bool,
+            Expr::String(_) => Type::String,
+            Expr::Prefix(prefix) => {
+                let right = self.expression(&prefix.right);
+                match prefix.operator.as_ref() {
+                    "!" => Type::Bool,
+                    "-" if matches!(right, Type::Int | Type::Any) => Type::Int,
+                    operator => self.error(
+                        MessageId::UnknownPrefixOperator,
+                        &[&operator, &right],
+                        expression.as_node(),
+                    ),
+                }
+            }
+            Expr::Infix(infix) => self.infix(infix, expression.as_node()),
+            Expr::If(if_expression) => self.if_expression(if_expression),
+            Expr::Function(function) => self.function(function),
+            // 被 quote 的代码不会求值
+            Expr::Macro(_) | Expr::Quote(_) => Type::Any,
+            Expr::Unquote(unquote) => {
+                self.expression(&unquote.expression);
+                Type::Any
+            }
+            Expr::UnquoteSplice(splice) => {
+                self.expression(&splice.expression);
+                Type::Any
+            }
+            Expr::Call(call) => self.call(call, expression.as_node()),
+            Expr::Array(array) => {
+                let element = array
+                    .elements
+                    .iter()
+                    .map(|element| self.expression(element))
+                    .reduce(Type::join)
+                    .unwrap_or(Type::Any);
+                Type::Array(Box::new(element))
+            }
+            Expr::Index(index) => self.index(index, expression.as_node()),
+            Expr::Hash(hash) => {
+                for (key, value) in hash.pairs.iter() {
+                    let key_type = self.expression(key);
+                    if !key_type.is_hashable() {
+                        self.error(MessageId::UnusableHashKey, &[&key_type], key.as_node());
+                    }
+                    self.expression(value);
+                }
+                Type::Hash
+            }
+        }
+    }
+
+    fn infix(&mut self, infix: &InfixExpression, node: &dyn Node) -> Type {
+        let left = self.expression(&infix.left);
+        let right = self.expression(&infix.right);
+        let operator = infix.operator.as_ref();
+        if left.is_known() && right.is_known() && !left.same_kind(&right) {
+            return self.error(MessageId::TypeMismatch, &[&left, &operator, &right], node);
+        }
+        // 到这里两边要么类型相同，要么至少有一边是 any
+        let known = if left.is_known() { &left } else { &right };
+        match (operator, known) {
+            ("==" | "!=", _) => Type::Bool,
+            ("<" | ">", Type::Int | Type::Any) => Type::Bool,
+            ("+", Type::String) => Type::String,
+            ("+" | "-" | "*" | "/", Type::Int) => Type::Int,
+            ("+", Type::Any) => Type::Any,
+            ("-" | "*" | "/", Type::Any) => Type::Int,
+            _ if left.is_known() && right.is_known() => self.error(
+                MessageId::UnknownInfixOperator,
+                &[&left, &operator, &right],
+                node,
+            ),
+            _ => Type::Any,
+        }
+    }
+
+    // 两个分支各自从 if 之前的绑定开始推断，结束后按名字合并
+    fn if_expression(&mut self, if_expression: &IfExpression) -> Type {
+        self.expression(&if_expression.condition);
+        let before = self.current().defined.clone();
+        let consequence = self.statements(&if_expression.consequence.statements);
+        let after_consequence = std::mem::replace(&mut self.current().defined, before);
+        let alternative = match if_expression.alternative.as_ref() {
+            Some(alternative) => self.statements(&alternative.statements),
+            None => Type::Null,
+        };
+        let defined = &mut self.current().defined;
+        for (name, typ) in after_consequence {
+            let joined = match defined.remove(&name) {
+                Some(other) => typ.join(other),
+                None => typ,
+            };
+            defined.insert(name, joined);
+        }
+        consequence.join(alternative)
+    }
+
+    // 参数的类型要到调用时才知道，都当作 any
+    fn function(&mut self, function: &FunctionLiteral) -> Type {
+        let mut scope = Scope::new(&function.body.statements);
+        for parameter in function.parameters.iter() {
+            scope.defined.insert(Rc::clone(&parameter.value), Type::Any);
+        }
+        self.scopes.push(scope);
+        self.returns.push(vec![]);
+        let last = self.block(&function.body);
+        let result = self
+            .returns
+            .pop()
+            .expect("returns pushed above")
+            .into_iter()
+            .fold(last, Type::join);
+        self.scopes.pop();
+        Type::Function(vec![Type::Any; function.parameters.len()], Box::new(result))
+    }
+
+    // 认识的标注和推断出的类型对不上时报错，之后按标注的类型推断
+    // 其他的标注只是注释，不做检查
+    fn annotated(&mut self, name: &Identifier, annotation: &Identifier, typ: Type) -> Type {
+        let expected = match annotation.value.as_ref() {
+            "any" => Type::Any,
+            "int" => Type::Int,
+            "bool" => Type::Bool,
+            "string" => Type::String,
+            "null" => Type::Null,
+            "array" => Type::Array(Box::new(Type::Any)),
+            "hash" => Type::Hash,
+            _ => return typ,
+        };
+        if !typ.is_known() {
+            return expected;
+        }
+        if expected.is_known() && !expected.same_kind(&typ) {
+            self.error(
+                MessageId::AnnotationMismatch,
+                &[&name.value, &annotation.value, &typ],
+                annotation,
+            );
+            return expected;
+        }
+        typ
+    }
+
+    fn block(&mut self, block: &BlockStatement) -> Type {
+        self.statements(&block.statements)
+    }
+
+    fn call(&mut self, call: &CallExpression, node: &dyn Node) -> Type {
+        if let Expr::Identifier(identifier) = call.function.as_ref() {
+            // 宏的参数不会被求值
+            if self.macros.contains(&identifier.value) {
+                return Type::Any;
+            }
+        }
+        let function = self.expression(&call.function);
+        let arguments = call
+            .arguments
+            .iter()
+            .map(|argument| self.expression(argument))
+            .collect::<Vec<_>>();
+        match function {
+            Type::Any => Type::Any,
+            Type::Function(parameters, _) if parameters.len() != arguments.len() => self.error(
+                MessageId::WrongNumberOfArguments,
+                &[&arguments.len(), &parameters.len()],
+                node,
+            ),
+            Type::Function(_, result) => *result,
+            Type::Builtin(name, arity) => {
+                let expected = match arity {
+                    Arity::Exact(count) => count == arguments.len(),
+                    Arity::Range(min, max) => (min..=max).contains(&arguments.len()),
+                    Arity::Variadic => true,
+                };
+                if !expected {
+                    return self.error(
+                        MessageId::WrongNumberOfArguments,
+                        &[&arguments.len(), &arity],
+                        node,
+                    );
+                }
+                match builtin_result(&name, &arguments) {
+                    Ok(typ) => typ,
+                    // 需要指明类型的参数都必须是数组
+                    Err((id @ MessageId::ArgumentMustBe, argument)) => {
+                        self.error(id, &[&name, &"array", &argument], node)
+                    }
+                    Err((id, argument)) => self.error(id, &[&name, &argument], node),
+                }
+            }
+            function => self.error(MessageId::NotAFunction, &[&function], node),
+        }
+    }
+
+    fn index(&mut self, index: &IndexExpression, node: &dyn Node) -> Type {
+        let left = self.expression(&index.left);
+        let key = self.expression(&index.index);
+        match left {
+            Type::Any => Type::Any,
+            // 越界时得到 null，和 null 做运算同样会出错，这里按元素类型继续推断
+            Type::Array(element) if matches!(key, Type::Int | Type::Any) => *element,
+            Type::Hash if key.is_hashable() => Type::Any,
+            Type::Hash => self.error(MessageId::UnusableHashKey, &[&key], node),
+            left => self.error(MessageId::IndexNotSupported, &[&left], node),
+        }
+    }
+
+    // 当前作用域里按顺序推断的类型为准
+    // 外层作用域的名字在函数被调用时才查找，只 let 过一次并且已经绑定的名字类型才是确定的
+    fn lookup(&self, name: &str) -> Type {
+        let (current, outer) = self
+            .scopes
+            .split_last()
+            .expect("program scope is always present");
+        if let Some(typ) = current.defined.get(name) {
+            return typ.clone();
+        }
+        for scope in outer.iter().rev() {
+            match scope.lets.get(name) {
+                Some(1) => return scope.defined.get(name).cloned().unwrap_or(Type::Any),
+                Some(_) => return Type::Any,
+                None => {}
+            }
+            if let Some(typ) = scope.defined.get(name) {
+                return typ.clone();
+            }
+        }
+        self.globals.get(name).cloned().unwrap_or(Type::Any)
+    }
+
+    fn current(&mut self) -> &mut Scope {
+        self.scopes
+            .last_mut()
+            .expect("program scope is always present")
+    }
+
+    // 出错的表达式当作 any，免得同一个问题在外层再报一遍
+    fn error(&mut self, id: MessageId, args: &[&dyn Display], node: &dyn Node) -> Type {
+        self.errors
+            .push(Diagnostic::at((), Severity::Error, message(id, args), node));
+        Type::Any
+    }
+}
+
+// 内置函数按参数类型推断返回值，参数类型不对时返回消息和出错的参数类型
+fn builtin_result(name: &str, arguments: &[Type]) -> Result<Type, (MessageId, Type)> {
+    let must_be_array = |argument: &Type| match argument {
+        Type::Array(_) | Type::Any => Ok(()),
+        _ => Err((MessageId::ArgumentMustBe, argument.clone())),
+    };
+    match (name, arguments) {
+        ("len", [Type::String | Type::Array(_) | Type::Any]) => Ok(Type::Int),
+        ("len", [argument]) => Err((MessageId::ArgumentNotSupported, argument.clone())),
+        ("first" | "last", [Type::Array(element)]) => Ok(*element.clone()),
+        ("rest", [Type::Array(element)]) => Ok(Type::Array(element.clone())),
+        ("push", [Type::Array(element), value]) => Ok(Type::Array(Box::new(
+            (**element).clone().join(value.clone()),
+        ))),
+        ("map", [array, function]) => {
+            must_be_array(array)?;
+            match function {
+                Type::Function(_, result) => Ok(Type::Array(result.clone())),
+                _ => Ok(Type::Array(Box::new(Type::Any))),
+            }
+        }
+        ("first" | "last" | "rest" | "push", [argument, ..]) => {
+            must_be_array(argument)?;
+            Ok(Type::Any)
+        }
+        ("puts", _) => Ok(Type::Null),
+        ("format", _) => Ok(Type::String),
+        _ => Ok(Type::Any),
+    }
+}
+
+// 一个作用域里每个名字被 let 的次数，不进入嵌套的函数和宏
+fn count_lets(statements: &[Stmt], lets: &mut HashMap<Rc<str>, usize>) {
+    for statement in statements {
+        match statement {
+            Stmt::Let(let_statement) => {
+                *lets
+                    .entry(Rc::clone(&let_statement.name.value))
+                    .or_default() += 1;
+            }
+            Stmt::Block(block) => count_lets(&block.statements, lets),
+            Stmt::Return(_) | Stmt::Expression(_) => {}
+        }
+        let expression = match statement {
+            Stmt::Let(let_statement) => &let_statement.value,
+            Stmt::Return(return_statement) => &return_statement.return_value,
+            Stmt::Expression(expression_statement) => &expression_statement.expression,
+            Stmt::Block(_) => continue,
+        };
+        let mut pending = vec![expression.as_ref()];
+        while let Some(expression) = pending.pop() {
+            match expression {
+                Expr::If(if_expression) => {
+                    pending.push(&if_expression.condition);
+                    count_lets(&if_expression.consequence.statements, lets);
+                    if let Some(alternative) = if_expression.alternative.as_ref() {
+                        count_lets(&alternative.statements, lets);
+                    }
+                }
+                Expr::Prefix(prefix) => pending.push(&prefix.right),
+                Expr::Quote(quote) => pending.push(&quote.expression),
+                Expr::Unquote(unquote) => pending.push(&unquote.expression),
+                Expr::UnquoteSplice(splice) => pending.push(&splice.expression),
+                Expr::Infix(infix) => pending.extend([infix.left.as_ref(), infix.right.as_ref()]),
+                Expr::Call(call) => {
+                    pending.push(&call.function);
+                    pending.extend(call.arguments.iter());
+                }
+                Expr::Array(array) => pending.extend(array.elements.iter()),
+                Expr::Index(index) => pending.extend([index.left.as_ref(), index.index.as_ref()]),
+                Expr::Hash(hash) => {
+                    pending.extend(hash.pairs.iter().flat_map(|(key, value)| [key, value]))
+                }
+                Expr::Identifier(_)
+                | Expr::Integer(_)
+                | Expr::Boolean(_)
+                | Expr::String(_)
+                | Expr::Function(_)
+                | Expr::Macro(_) => {}
+            }
+        }
+    }
+}
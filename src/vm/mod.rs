@@ -0,0 +1,479 @@
+// 执行 compiler 模块编译出来的字节码的栈式虚拟机
+// 运算、索引和函数调用直接用求值器里的实现，结果和错误信息都和树遍历求值器一致
+// 步数限制和超时按函数调用和向回跳转计算，内存限制计算数组、哈希和运算产生的字符串
+// 观察者、profile 和 trace 只对树遍历求值器生效
+use crate::ast::program::Program;
+use crate::compiler::code::{read_u16, Opcode};
+use crate::compiler::{Bytecode, CompileError, Compiler};
+use crate::errors::{runtime_error, EvalError, EvalResult, MessageId};
+use crate::evaluator::environment::Environment;
+use crate::evaluator::eval::{
+    apply_function, build_hash, eval_index_expression, eval_infix_expression,
+    eval_prefix_expression, is_truthy,
+};
+use crate::evaluator::object::{
+    Array, Boolean, Closure, CompiledFunction, Error, Identity, Null, Value,
+};
+use crate::evaluator::scheduler;
+use crate::sync::{Rc, RefCell};
+
+// 同一个会话里所有闭包共享的常量池和全局变量
+// 常量池只会变长，之前创建的闭包引用的常量一直有效
+#[derive(Default)]
+pub struct Globals {
+    constants: RefCell<Vec<Rc<Value>>>,
+    values: RefCell<Vec<Option<Rc<Value>>>>,
+    names: RefCell<Vec<Rc<str>>>,
+    builtins: RefCell<Vec<Rc<str>>>,
+}
+
+pub struct Vm {
+    globals: Rc<Globals>,
+    env: Rc<RefCell<Environment>>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Vm::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Vm::with_environment(Rc::new(RefCell::new(Environment::new())))
+    }
+
+    // env 提供输出、内置函数这些运行时状态，编译时找不到的名字也到这里查找，比如预先加载的标准库
+    pub fn with_environment(env: Rc<RefCell<Environment>>) -> Self {
+        Vm {
+            globals: Rc::new(Globals::default()),
+            env,
+        }
+    }
+
+    pub fn environment(&self) -> Rc<RefCell<Environment>> {
+        Rc::clone(&self.env)
+    }
+
+    // 全局变量在多次 run 之间保留，bytecode 要来自同一个 Compiler
+    // 和 eval 一样，中断执行的错误还原成 Error 或 Exit 对象返回
+    pub fn run(&mut self, bytecode: &Bytecode) -> Rc<Value> {
+        *self.globals.constants.borrow_mut() = bytecode.constants.clone();
+        *self.globals.names.borrow_mut() = bytecode.globals.clone();
+        *self.globals.builtins.borrow_mut() = bytecode.builtins.clone();
+        self.globals
+            .values
+            .borrow_mut()
+            .resize(bytecode.globals.len(), None);
+        let main = Rc::new(Value::Closure(Closure {
+            function: Rc::clone(&bytecode.main),
+            free: vec![],
+            globals: Rc::clone(&self.globals),
+            identity: Identity::fresh(),
+        }));
+        let result = Machine::new(&self.globals, &self.env).execute(main, &[]);
+        scheduler::finish(result, &self.env).unwrap_or_else(EvalError::into_object)
+    }
+
+    // 已经赋过值的全局变量，按名字排序
+    pub fn globals(&self) -> Vec<(Rc<str>, Rc<Value>)> {
+        let mut globals = self
+            .globals
+            .names
+            .borrow()
+            .iter()
+            .zip(self.globals.values.borrow().iter())
+            .filter_map(|(name, value)| Some((Rc::clone(name), Rc::clone(value.as_ref()?))))
+            .collect::<Vec<_>>();
+        globals.sort_by(|(a, _), (b, _)| a.cmp(b));
+        globals
+    }
+
+    pub fn global(&self, name: &str) -> Option<Rc<Value>> {
+        let index = self
+            .globals
+            .names
+            .borrow()
+            .iter()
+            .position(|global| &**global == name)?;
+        self.globals.values.borrow()[index].clone()
+    }
+}
+
+// 编译并执行一个程序，宏要事先展开
+pub fn run(program: &Program, env: Rc<RefCell<Environment>>) -> Result<Rc<Value>, CompileError> {
+    let bytecode = Compiler::default()
+        .with_builtins(&env.borrow().context().borrow().builtins)
+        .compile(program)?;
+    Ok(Vm::with_environment(env).run(&bytecode))
+}
+
+// 内置函数回调闭包时用一个新的栈执行，共享同一份全局变量
+pub(crate) fn call_closure(
+    closure: &Closure,
+    args: &[Rc<Value>],
+    env: Rc<RefCell<Environment>>,
+) -> EvalResult {
+    let globals = Rc::clone(&closure.globals);
+    Machine::new(&globals, &env).execute(Rc::new(Value::Closure(closure.clone())), args)
+}
+
+struct Frame {
+    // 正在执行的 Value::Closure
+    closure: Rc<Value>,
+    ip: usize,
+    // 第一个参数在栈上的位置，局部变量紧跟在参数后面，被调用的闭包在它前面一格
+    base: usize,
+}
+
+struct Machine<'a> {
+    globals: &'a Rc<Globals>,
+    env: &'a Rc<RefCell<Environment>>,
+    stack: Vec<Rc<Value>>,
+    frames: Vec<Frame>,
+    true_value: Rc<Value>,
+    false_value: Rc<Value>,
+    null_value: Rc<Value>,
+}
+
+impl<'a> Machine<'a> {
+    fn new(globals: &'a Rc<Globals>, env: &'a Rc<RefCell<Environment>>) -> Self {
+        Machine {
+            globals,
+            env,
+            stack: vec![],
+            frames: vec![],
+            true_value: Rc::new(Value::Boolean(Boolean::True)),
+            false_value: Rc::new(Value::Boolean(Boolean::False)),
+            null_value: Rc::new(Value::Null(Null)),
+        }
+    }
+
+    fn execute(&mut self, closure: Rc<Value>, args: &[Rc<Value>]) -> EvalResult {
+        self.stack.push(Rc::clone(&closure));
+        self.stack.extend(args.iter().cloned());
+        self.push_frame(closure, args.len())?;
+        self.run()
+    }
+
+    fn push_frame(&mut self, closure: Rc<Value>, argc: usize) -> Result<(), EvalError> {
+        let Value::Closure(function) = closure.as_ref() else {
+            return Err(EvalError::internal("frame without a closure"));
+        };
+        let function = &function.function;
+        if argc != function.num_parameters {
+            return Err(runtime_error(
+                MessageId::WrongNumberOfArguments,
+                &[&argc, &function.num_parameters],
+            )
+            .into());
+        }
+        let context = self.env.borrow().context();
+        let max_depth = context.borrow().limits.max_depth;
+        if self.frames.len() >= max_depth {
+            return Err(runtime_error(MessageId::RecursionTooDeep, &[&max_depth]).into());
+        }
+        context.borrow_mut().step()?;
+        context.borrow_mut().stats.function_calls += 1;
+        let base = self.stack.len() - argc;
+        self.stack.resize(
+            base + function.num_locals.max(argc),
+            Rc::clone(&self.null_value),
+        );
+        self.frames.push(Frame {
+            closure,
+            ip: 0,
+            base,
+        });
+        Ok(())
+    }
+
+    // 字节码来自 compile 或者通过了 file::decode 的检查时不会出错，出错说明字节码有问题
+    fn pop(&mut self) -> Result<Rc<Value>, EvalError> {
+        self.stack
+            .pop()
+            .ok_or_else(|| EvalError::internal("stack underflow"))
+    }
+
+    fn pop_many(&mut self, count: usize) -> Result<Vec<Rc<Value>>, EvalError> {
+        let start = self
+            .stack
+            .len()
+            .checked_sub(count)
+            .ok_or_else(|| EvalError::internal("stack underflow"))?;
+        Ok(self.stack.split_off(start))
+    }
+
+    fn run(&mut self) -> EvalResult {
+        'frames: loop {
+            let frame = self.frames.last().expect("a frame is always pushed");
+            let closure_value = Rc::clone(&frame.closure);
+            let Value::Closure(closure) = closure_value.as_ref() else {
+                return Err(EvalError::internal("frame without a closure"));
+            };
+            let function = closure.function.as_ref();
+            let code = function.instructions.as_slice();
+            let base = frame.base;
+            let mut ip = frame.ip;
+            while ip < code.len() {
+                let start = ip;
+                let at = |error: EvalError| locate(error, function, start);
+                let op = Opcode::from_byte(code[ip])
+                    .ok_or_else(|| EvalError::internal("unknown opcode"))?;
+                ip += 1;
+                match op {
+                    Opcode::Constant => {
+                        let index = read_u16(code, ip);
+                        ip += 2;
+                        let constant = Rc::clone(&self.globals.constants.borrow()[index]);
+                        self.stack.push(constant);
+                    }
+                    Opcode::Pop => {
+                        self.pop()?;
+                    }
+                    Opcode::Add
+                    | Opcode::Sub
+                    | Opcode::Mul
+                    | Opcode::Div
+                    | Opcode::Equal
+                    | Opcode::NotEqual
+                    | Opcode::GreaterThan
+                    | Opcode::LessThan => {
+                        let right = self.pop()?;
+                        let left = self.pop()?;
+                        let operator = match op {
+                            Opcode::Add => "+",
+                            Opcode::Sub => "-",
+                            Opcode::Mul => "*",
+                            Opcode::Div => "/",
+                            Opcode::Equal => "==",
+                            Opcode::NotEqual => "!=",
+                            Opcode::GreaterThan => ">",
+                            _ => "<",
+                        };
+                        let value = eval_infix_expression(&left, operator, &right).map_err(at)?;
+                        self.allocate(&value).map_err(at)?;
+                        self.stack.push(value);
+                    }
+                    Opcode::Minus | Opcode::Bang => {
+                        let right = self.pop()?;
+                        let operator = if op == Opcode::Minus { "-" } else { "!" };
+                        let value = eval_prefix_expression(operator, &right).map_err(at)?;
+                        self.stack.push(value);
+                    }
+                    Opcode::True => self.stack.push(Rc::clone(&self.true_value)),
+                    Opcode::False => self.stack.push(Rc::clone(&self.false_value)),
+                    Opcode::Null => self.stack.push(Rc::clone(&self.null_value)),
+                    Opcode::JumpNotTruthy => {
+                        let target = read_u16(code, ip);
+                        ip += 2;
+                        if !is_truthy(&*self.pop()?) {
+                            ip = target;
+                        }
+                    }
+                    Opcode::Jump => {
+                        let target = read_u16(code, ip);
+                        // 向回跳转可能一直循环下去，和函数调用一样计入步数
+                        if target <= start {
+                            self.step().map_err(at)?;
+                        }
+                        ip = target;
+                    }
+                    Opcode::GetGlobal => {
+                        let index = read_u16(code, ip);
+                        ip += 2;
+                        let value = self.globals.values.borrow()[index].clone();
+                        // 顶层的 let 还没执行到，和求值器一样按名字查找，可能找到同名的内置函数
+                        let value = match value {
+                            Some(value) => value,
+                            None => {
+                                let name = Rc::clone(&self.globals.names.borrow()[index]);
+                                self.lookup(&name).map_err(at)?
+                            }
+                        };
+                        self.stack.push(value);
+                    }
+                    Opcode::SetGlobal => {
+                        let index = read_u16(code, ip);
+                        ip += 2;
+                        let value = self.pop()?;
+                        self.globals.values.borrow_mut()[index] = Some(value);
+                    }
+                    Opcode::GetLocal => {
+                        let index = usize::from(code[ip]);
+                        ip += 1;
+                        self.stack.push(Rc::clone(&self.stack[base + index]));
+                    }
+                    Opcode::SetLocal => {
+                        let index = usize::from(code[ip]);
+                        ip += 1;
+                        self.stack[base + index] = self.pop()?;
+                    }
+                    Opcode::GetFree => {
+                        let index = usize::from(code[ip]);
+                        ip += 1;
+                        let value = closure
+                            .free
+                            .get(index)
+                            .ok_or_else(|| EvalError::internal("free variable out of range"))?;
+                        self.stack.push(Rc::clone(value));
+                    }
+                    Opcode::GetName => {
+                        let index = read_u16(code, ip);
+                        ip += 2;
+                        let constant = Rc::clone(&self.globals.constants.borrow()[index]);
+                        let Value::String(name) = constant.as_ref() else {
+                            return Err(EvalError::internal("name constant is not a string"));
+                        };
+                        let value = self.lookup(&name.value).map_err(at)?;
+                        self.stack.push(value);
+                    }
+                    Opcode::GetBuiltin => {
+                        let index = read_u16(code, ip);
+                        ip += 2;
+                        let name = Rc::clone(&self.globals.builtins.borrow()[index]);
+                        // `:reload-builtins` 之后可能已经没有这个函数了
+                        let builtin = self.env.borrow().context().borrow().builtins.get(&name);
+                        let value = match builtin {
+                            Some(builtin) => Rc::new(Value::Builtin(builtin)),
+                            None => self.lookup(&name).map_err(at)?,
+                        };
+                        self.stack.push(value);
+                    }
+                    Opcode::Array => {
+                        let count = read_u16(code, ip);
+                        ip += 2;
+                        let elements = self.pop_many(count)?;
+                        let array = Rc::new(Value::Array(Array {
+                            elements,
+                            frozen: false,
+                        }));
+                        self.allocate(&array).map_err(at)?;
+                        self.stack.push(array);
+                    }
+                    Opcode::Hash => {
+                        let count = read_u16(code, ip);
+                        ip += 2;
+                        let mut items = self.pop_many(count)?.into_iter();
+                        let mut pairs = vec![];
+                        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                            pairs.push((key, value));
+                        }
+                        let hash = build_hash(pairs).map_err(at)?;
+                        self.allocate(&hash).map_err(at)?;
+                        self.stack.push(hash);
+                    }
+                    Opcode::Index => {
+                        let index = self.pop()?;
+                        let left = self.pop()?;
+                        let value = eval_index_expression(&left, &index).map_err(at)?;
+                        self.stack.push(value);
+                    }
+                    Opcode::Call => {
+                        let argc = usize::from(code[ip]);
+                        ip += 1;
+                        let callee = self
+                            .stack
+                            .len()
+                            .checked_sub(argc + 1)
+                            .map(|index| Rc::clone(&self.stack[index]))
+                            .ok_or_else(|| EvalError::internal("stack underflow"))?;
+                        if matches!(callee.as_ref(), Value::Closure(_)) {
+                            self.frames.last_mut().expect("current frame").ip = ip;
+                            self.push_frame(callee, argc).map_err(at)?;
+                            continue 'frames;
+                        }
+                        // 树遍历求值器的函数（标准库、import 进来的模块）和内置函数交给求值器调用
+                        let args = self.pop_many(argc)?;
+                        self.pop()?;
+                        let value =
+                            apply_function(&callee, &args, Rc::clone(self.env)).map_err(at)?;
+                        self.stack.push(value);
+                    }
+                    Opcode::ReturnValue => {
+                        let value = self.pop()?;
+                        let frame = self.frames.pop().expect("current frame");
+                        self.stack.truncate(frame.base - 1);
+                        if self.frames.is_empty() {
+                            return Ok(value);
+                        }
+                        self.stack.push(value);
+                        continue 'frames;
+                    }
+                    Opcode::Closure => {
+                        let index = read_u16(code, ip);
+                        let count = usize::from(code[ip + 2]);
+                        ip += 3;
+                        let constant = Rc::clone(&self.globals.constants.borrow()[index]);
+                        let Value::CompiledFunction(function) = constant.as_ref() else {
+                            return Err(EvalError::internal(
+                                "closure over a non-function constant",
+                            ));
+                        };
+                        let free = self.pop_many(count)?;
+                        self.stack.push(Rc::new(Value::Closure(Closure {
+                            function: Rc::clone(function),
+                            free,
+                            globals: Rc::clone(self.globals),
+                            identity: Identity::fresh(),
+                        })));
+                    }
+                    Opcode::CurrentClosure => self.stack.push(Rc::clone(&closure_value)),
+                }
+            }
+            return Err(EvalError::internal("function without a return"));
+        }
+    }
+
+    fn step(&self) -> Result<(), EvalError> {
+        let context = self.env.borrow().context();
+        let result = context.borrow_mut().step();
+        Ok(result?)
+    }
+
+    // 和求值器一样记下新创建的字符串、数组和哈希，超出内存限制时返回错误
+    fn allocate(&self, value: &Value) -> Result<(), EvalError> {
+        let context = self.env.borrow().context();
+        let result = context.borrow_mut().allocate(value, &[]);
+        Ok(result?)
+    }
+
+    // 和 eval_identifier 一样，先找环境里的绑定，再找内置函数
+    fn lookup(&self, name: &str) -> EvalResult {
+        let env = self.env.borrow();
+        env.get(name)
+            .or_else(|| {
+                env.context()
+                    .borrow()
+                    .builtins
+                    .get(name)
+                    .map(|builtin| Rc::new(Value::Builtin(builtin)))
+            })
+            .ok_or_else(|| runtime_error(MessageId::IdentifierNotFound, &[&name]).into())
+    }
+}
+
+// 和求值器一样，错误第一次经过的指令就是出错的地方
+fn locate(error: EvalError, function: &CompiledFunction, offset: usize) -> EvalError {
+    let EvalError::Runtime(error) = error else {
+        return error;
+    };
+    if error.line != 0 {
+        return EvalError::Runtime(error);
+    }
+    match function
+        .locations
+        .binary_search_by_key(&offset, |location| location.offset)
+    {
+        Ok(index) => {
+            let location = function.locations[index];
+            EvalError::Runtime(Error {
+                span: location.span,
+                line: location.line,
+                column: location.column,
+                ..error
+            })
+        }
+        Err(_) => EvalError::Runtime(error),
+    }
+}
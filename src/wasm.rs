@@ -0,0 +1,73 @@
+// 给浏览器里的 playground 用的接口，编译到 wasm32-unknown-unknown 之后由 wasm-bindgen 生成 JS 绑定
+// 代码在沙盒里运行，不能访问宿主的文件、环境变量和标准输入，步数也有上限，避免死循环卡住页面
+use wasm_bindgen::prelude::*;
+
+use crate::evaluator::context::{Limits, OutputBuffer, RuntimeContext};
+use crate::interpreter::Interpreter;
+
+const MAX_STEPS: u64 = 10_000_000;
+
+// 一次求值的结果，value 和 error 只有一个不为空
+#[wasm_bindgen(getter_with_clone)]
+pub struct Evaluation {
+    // puts 写出的内容
+    pub output: String,
+    pub value: Option<String>,
+    pub error: Option<String>,
+}
+
+// 多次求值共用同一个解释器，之前定义的绑定一直可见
+#[wasm_bindgen]
+pub struct Playground {
+    interpreter: Interpreter,
+    output: OutputBuffer,
+}
+
+#[wasm_bindgen]
+impl Playground {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Playground {
+        let output = OutputBuffer::new();
+        let context = RuntimeContext::new()
+            .with_limits(Limits {
+                max_steps: Some(MAX_STEPS),
+                ..Limits::default()
+            })
+            .sandboxed()
+            .with_output(Box::new(output.clone()));
+        Playground {
+            interpreter: Interpreter::with_context(context),
+            output,
+        }
+    }
+
+    #[wasm_bindgen(js_name = evalStr)]
+    pub fn eval_str(&mut self, source: &str) -> Evaluation {
+        let result = self.interpreter.eval_str(source);
+        let output = self.output.take();
+        match result {
+            Ok(value) => Evaluation {
+                output,
+                value: Some(value.inspect()),
+                error: None,
+            },
+            Err(error) => Evaluation {
+                output,
+                value: None,
+                error: Some(error.to_string()),
+            },
+        }
+    }
+}
+
+impl Default for Playground {
+    fn default() -> Self {
+        Playground::new()
+    }
+}
+
+// 不需要保留绑定时直接求值一段源码
+#[wasm_bindgen(js_name = evalStr)]
+pub fn eval_str(source: &str) -> Evaluation {
+    Playground::new().eval_str(source)
+}
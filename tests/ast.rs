@@ -1,253 +1,321 @@
-use std::collections::HashMap;
-
-use by_address::ByAddress;
 use implement_parser::ast::expressions::{
-    ArrayLiteral, FunctionLiteral, HashLiteral, Identifier, IfExpression, IndexExpression,
-    InfixExpression, IntegerLiteral, PrefixExpression,
+    ArrayLiteral, CallExpression, Expr, FunctionLiteral, HashLiteral, Identifier, IfExpression,
+    IndexExpression, InfixExpression, IntegerLiteral, PrefixExpression,
 };
-use implement_parser::ast::modify::modify;
+use implement_parser::ast::ids::{find, NodeId};
+use implement_parser::ast::modify::{modify, modify_program, modify_statement, transform};
 use implement_parser::ast::program::Program;
 use implement_parser::ast::statements::{
-    BlockStatement, ExpressionStatement, LetStatement, ReturnStatement,
+    BlockStatement, ExpressionStatement, LetStatement, ReturnStatement, Stmt,
 };
-use implement_parser::ast::traits::{Expression, Node, Statement};
+use implement_parser::ast::traits::Node;
+use implement_parser::corpus::programs;
+use implement_parser::evaluator::context::Rng;
+use implement_parser::lexer::{Lexer, Span};
+use implement_parser::parser::Parser;
 use implement_parser::token::{Token, TokenType};
 use rstest::rstest;
 
-fn one() -> IntegerLiteral {
-    IntegerLiteral {
-        token: Token {
-            token_type: TokenType::Int,
-            literal: "".to_owned(),
-        },
+fn one() -> Box<Expr> {
+    Box::new(Expr::Integer(IntegerLiteral {
+        token: Token::new(TokenType::Int, "".to_owned()),
         value: 1,
-    }
+    }))
 }
 
-fn two() -> IntegerLiteral {
-    IntegerLiteral {
-        token: Token {
-            token_type: TokenType::Int,
-            literal: "".to_owned(),
-        },
+fn two() -> Box<Expr> {
+    Box::new(Expr::Integer(IntegerLiteral {
+        token: Token::new(TokenType::Int, "".to_owned()),
         value: 2,
-    }
+    }))
 }
 
-fn turn_one_into_two(node: Box<dyn Node>) -> Box<dyn Node> {
-    let cloned = dyn_clone::clone_box(node.as_ref());
-    if let Ok(mut integer) = node.downcast::<IntegerLiteral>() {
-        if integer.value == 1 {
+fn turn_one_into_two(expression: Expr) -> Expr {
+    match expression {
+        Expr::Integer(mut integer) if integer.value == 1 => {
             integer.value = 2;
-            return integer;
+            Expr::Integer(integer)
         }
+        expression => expression,
     }
-    cloned
 }
 
-fn program(expression: Box<dyn Expression>) -> Program {
+fn program(expression: Box<Expr>) -> Program {
     Program {
-        statements: vec![Box::new(ExpressionStatement {
-            token: Token {
-                token_type: TokenType::Int,
-                literal: "".to_owned(),
-            },
+        statements: vec![Stmt::Expression(ExpressionStatement {
+            token: Token::new(TokenType::Int, "".to_owned()),
             expression,
+            closing: Span::default(),
         })],
+        source: None,
     }
 }
 
-fn infix_expression(left: Box<dyn Expression>, right: Box<dyn Expression>) -> InfixExpression {
-    InfixExpression {
-        token: Token {
-            token_type: TokenType::Plus,
-            literal: "+".to_owned(),
-        },
+fn infix_expression(left: Box<Expr>, right: Box<Expr>) -> Expr {
+    Expr::Infix(InfixExpression {
+        token: Token::new(TokenType::Plus, "+".to_owned()),
         left,
-        operator: "+".to_owned(),
+        operator: "+".into(),
         right,
-    }
+    })
 }
 
-fn prefix_expression(right: Box<dyn Expression>) -> PrefixExpression {
-    PrefixExpression {
-        token: Token {
-            token_type: TokenType::Minus,
-            literal: "-".to_owned(),
-        },
-        operator: "-".to_owned(),
+fn prefix_expression(right: Box<Expr>) -> Expr {
+    Expr::Prefix(PrefixExpression {
+        token: Token::new(TokenType::Minus, "-".to_owned()),
+        operator: "-".into(),
         right,
-    }
+    })
 }
 
-fn index_expression(left: Box<dyn Expression>, index: Box<dyn Expression>) -> IndexExpression {
-    IndexExpression {
-        token: Token {
-            token_type: TokenType::LeftBracket,
-            literal: "[".to_owned(),
-        },
+fn index_expression(left: Box<Expr>, index: Box<Expr>) -> Expr {
+    Expr::Index(IndexExpression {
+        token: Token::new(TokenType::LeftBracket, "[".to_owned()),
         left,
         index,
-    }
+        closing: Span::default(),
+    })
 }
 
-fn if_expression(
-    condition: Box<dyn Expression>,
-    consequence: Box<dyn Expression>,
-    alernative: Box<dyn Expression>,
-) -> IfExpression {
-    IfExpression {
-        token: Token {
-            token_type: TokenType::If,
-            literal: "if".to_owned(),
-        },
+fn if_expression(condition: Box<Expr>, consequence: Box<Expr>, alernative: Box<Expr>) -> Expr {
+    Expr::If(IfExpression {
+        token: Token::new(TokenType::If, "if".to_owned()),
         condition,
         consequence: BlockStatement {
-            token: Token {
-                token_type: TokenType::LeftBrace,
-                literal: "{".to_owned(),
-            },
-            statements: vec![Box::new(ExpressionStatement {
-                token: Token {
-                    token_type: TokenType::Int,
-                    literal: "".to_owned(),
-                },
+            token: Token::new(TokenType::LeftBrace, "{".to_owned()),
+            statements: vec![Stmt::Expression(ExpressionStatement {
+                token: Token::new(TokenType::Int, "".to_owned()),
                 expression: consequence,
+                closing: Span::default(),
             })],
+            closing: Span::default(),
         },
         alternative: Some(BlockStatement {
-            token: Token {
-                token_type: TokenType::LeftBrace,
-                literal: "{".to_owned(),
-            },
-            statements: vec![Box::new(ExpressionStatement {
-                token: Token {
-                    token_type: TokenType::Int,
-                    literal: "".to_owned(),
-                },
+            token: Token::new(TokenType::LeftBrace, "{".to_owned()),
+            statements: vec![Stmt::Expression(ExpressionStatement {
+                token: Token::new(TokenType::Int, "".to_owned()),
                 expression: alernative,
+                closing: Span::default(),
             })],
+            closing: Span::default(),
         }),
-    }
+    })
 }
 
-fn return_statement(return_value: Box<dyn Expression>) -> ReturnStatement {
-    ReturnStatement {
-        token: Token {
-            token_type: TokenType::Return,
-            literal: "return".to_owned(),
-        },
+fn return_statement(return_value: Box<Expr>) -> Stmt {
+    Stmt::Return(ReturnStatement {
+        token: Token::new(TokenType::Return, "return".to_owned()),
         return_value,
-    }
+        closing: Span::default(),
+    })
 }
 
-fn let_statement(value: Box<dyn Expression>) -> LetStatement {
-    LetStatement {
-        token: Token {
-            token_type: TokenType::Let,
-            literal: "let".to_owned(),
-        },
+fn let_statement(value: Box<Expr>) -> Stmt {
+    Stmt::Let(LetStatement {
+        token: Token::new(TokenType::Let, "let".to_owned()),
         name: Identifier {
-            token: Token {
-                token_type: TokenType::Ident,
-                literal: "ident".to_owned(),
-            },
-            value: "ident".to_owned(),
+            token: Token::new(TokenType::Ident, "ident".to_owned()),
+            value: "ident".into(),
         },
+        type_annotation: None,
         value,
-    }
+        closing: Span::default(),
+    })
 }
 
-fn function_literal(expression: Box<dyn Expression>) -> FunctionLiteral {
-    FunctionLiteral {
-        token: Token {
-            token_type: TokenType::Function,
-            literal: "fn".to_owned(),
-        },
+fn function_literal(expression: Box<Expr>) -> Expr {
+    Expr::Function(FunctionLiteral {
+        token: Token::new(TokenType::Function, "fn".to_owned()),
         parameters: vec![],
         body: BlockStatement {
-            token: Token {
-                token_type: TokenType::LeftBrace,
-                literal: "{".to_owned(),
-            },
-            statements: vec![Box::new(ExpressionStatement {
-                token: Token {
-                    token_type: TokenType::Int,
-                    literal: "".to_owned(),
-                },
+            token: Token::new(TokenType::LeftBrace, "{".to_owned()),
+            statements: vec![Stmt::Expression(ExpressionStatement {
+                token: Token::new(TokenType::Int, "".to_owned()),
                 expression,
+                closing: Span::default(),
             })],
+            closing: Span::default(),
         },
-    }
+    })
 }
 
-fn array_literal(element1: Box<dyn Expression>, element2: Box<dyn Expression>) -> ArrayLiteral {
-    ArrayLiteral {
-        token: Token {
-            token_type: TokenType::LeftBracket,
-            literal: "[".to_owned(),
-        },
-        elements: vec![element1, element2],
-    }
+fn array_literal(element1: Box<Expr>, element2: Box<Expr>) -> Expr {
+    Expr::Array(ArrayLiteral {
+        token: Token::new(TokenType::LeftBracket, "[".to_owned()),
+        elements: vec![*element1, *element2],
+        closing: Span::default(),
+    })
 }
 
-fn hash_literal(
-    key1: Box<dyn Expression>,
-    value1: Box<dyn Expression>,
-    key2: Box<dyn Expression>,
-    value2: Box<dyn Expression>,
-) -> HashLiteral {
-    HashLiteral {
-        token: Token {
-            token_type: TokenType::LeftBracket,
-            literal: "[".to_owned(),
-        },
-        pairs: HashMap::from([(ByAddress(key1), value1), (ByAddress(key2), value2)]),
-    }
+fn call_expression(function: Box<Expr>, argument: Box<Expr>) -> Expr {
+    Expr::Call(CallExpression {
+        token: Token::new(TokenType::LeftParen, "(".to_owned()),
+        function,
+        arguments: vec![*argument],
+        closing: Span::default(),
+    })
+}
+
+fn hash_literal(key1: Box<Expr>, value1: Box<Expr>, key2: Box<Expr>, value2: Box<Expr>) -> Expr {
+    Expr::Hash(HashLiteral {
+        token: Token::new(TokenType::LeftBracket, "[".to_owned()),
+        pairs: vec![(*key1, *value1), (*key2, *value2)],
+        closing: Span::default(),
+    })
 }
 
 #[test]
 fn test_string() {
     let program = Program {
-        statements: vec![Box::new(LetStatement {
-            token: Token {
-                token_type: TokenType::Let,
-                literal: "let".to_owned(),
-            },
+        statements: vec![Stmt::Let(LetStatement {
+            token: Token::new(TokenType::Let, "let".to_owned()),
             name: Identifier {
-                token: Token {
-                    token_type: TokenType::Ident,
-                    literal: "myVar".to_owned(),
-                },
-                value: "myVar".to_owned(),
+                token: Token::new(TokenType::Ident, "myVar".to_owned()),
+                value: "myVar".into(),
             },
-            value: Box::new(Identifier {
-                token: Token {
-                    token_type: TokenType::Ident,
-                    literal: "anotherVar".to_owned(),
-                },
-                value: "anotherVar".to_owned(),
-            }) as Box<dyn Expression>,
-        }) as Box<dyn Statement>],
+            type_annotation: None,
+            value: Box::new(Expr::Identifier(Identifier {
+                token: Token::new(TokenType::Ident, "anotherVar".to_owned()),
+                value: "anotherVar".into(),
+            })),
+            closing: Span::default(),
+        })],
+        source: None,
     };
 
     assert_eq!(program.string(), "let myVar = anotherVar;");
+    assert_eq!(program.to_string(), "let myVar = anotherVar;");
+    assert_eq!(
+        format!("{}", program.statements[0]),
+        "let myVar = anotherVar;"
+    );
+    assert!(format!("{:?}", program).starts_with("Program { statements: [Let(LetStatement {"));
 }
 
 #[rstest]
-#[case(&mut one(), &two())]
-#[case::program(&mut program(Box::new(one())), &program(Box::new(two())))]
-#[case::infix(&mut infix_expression(Box::new(one()), Box::new(two())), &infix_expression(Box::new(two()), Box::new(two())))]
-#[case::infix(&mut infix_expression(Box::new(two()), Box::new(two())), &infix_expression(Box::new(two()), Box::new(two())))]
-#[case::prefix(&mut prefix_expression(Box::new(one())), &prefix_expression(Box::new(two())))]
-#[case::index(&mut index_expression(Box::new(one()), Box::new(one())), &index_expression(Box::new(two()), Box::new(two())))]
-#[case::if_exp(&mut if_expression(Box::new(one()), Box::new(one()), Box::new(one())), &if_expression(Box::new(two()), Box::new(two()), Box::new(two())))]
-#[case::return_state(&mut return_statement(Box::new(one())), &return_statement(Box::new(two())))]
-#[case::let_state(&mut let_statement(Box::new(one())), &let_statement(Box::new(two())))]
-#[case::func(&mut function_literal(Box::new(one())), &function_literal(Box::new(two())))]
-#[case::array(&mut array_literal(Box::new(one()), Box::new(one())), &array_literal(Box::new(two()), Box::new(two())))]
-#[case::hash(&mut hash_literal(Box::new(one()), Box::new(one()), Box::new(one()), Box::new(one())), &hash_literal(Box::new(two()), Box::new(two()), Box::new(two()), Box::new(two())))]
-fn test_modify(#[case] input: &mut dyn Node, #[case] expected: &dyn Node) {
-    modify(input, &turn_one_into_two);
-    assert_eq!(input.string(), expected.string());
+#[case(*one(), *two())]
+#[case::infix(infix_expression(one(), two()), infix_expression(two(), two()))]
+#[case::infix(infix_expression(two(), two()), infix_expression(two(), two()))]
+#[case::prefix(prefix_expression(one()), prefix_expression(two()))]
+#[case::index(index_expression(one(), one()), index_expression(two(), two()))]
+#[case::if_exp(if_expression(one(), one(), one()), if_expression(two(), two(), two()))]
+#[case::func(function_literal(one()), function_literal(two()))]
+#[case::array(array_literal(one(), one()), array_literal(two(), two()))]
+#[case::call(call_expression(one(), one()), call_expression(two(), two()))]
+#[case::hash(
+    hash_literal(one(), one(), one(), one()),
+    hash_literal(two(), two(), two(), two())
+)]
+fn test_modify(#[case] input: Expr, #[case] expected: Expr) {
+    let modified = modify(input, &turn_one_into_two);
+    assert_eq!(modified.string(), expected.string());
+}
+
+#[rstest]
+#[case::return_state(return_statement(one()), return_statement(two()))]
+#[case::let_state(let_statement(one()), let_statement(two()))]
+fn test_modify_statement(#[case] input: Stmt, #[case] expected: Stmt) {
+    let modified = modify_statement(input, &turn_one_into_two);
+    assert_eq!(modified.string(), expected.string());
+}
+
+#[test]
+fn test_modify_program() {
+    let mut input = program(one());
+    modify_program(&mut input, &turn_one_into_two);
+    assert_eq!(input.string(), program(two()).string());
+}
+
+#[rstest]
+#[case::expression(*one())]
+#[case::if_exp(if_expression(one(), one(), two()))]
+#[case::call(call_expression(one(), two()))]
+#[case::hash(hash_literal(one(), two(), two(), one()))]
+fn test_transform_keeps_input(#[case] input: Expr) {
+    let before = input.string();
+    let transformed = transform(&input, &turn_one_into_two);
+    assert_eq!(input.string(), before);
+    assert_eq!(transformed.string(), before.replace('1', "2"));
+}
+
+// 对语料库里的程序随机挑选要替换的整数，没有被替换的节点连同区间都应该和原来一样
+#[test]
+fn test_transform_only_rewrites_replaced_nodes() {
+    let mut rng = Rng::new(1108);
+    let mut replaced = 0;
+    for corpus_program in programs() {
+        let program = corpus_program.parse().unwrap();
+        let before = program.to_sexpr();
+        assert_eq!(
+            transform(&program, &|expression| expression).to_sexpr(),
+            before
+        );
+
+        let seed = rng.next_below(u64::MAX);
+        let transformed = transform(&program, &|expression| match expression {
+            Expr::Integer(mut integer) if (integer.value as u64 ^ seed).is_multiple_of(2) => {
+                integer.value = -1;
+                Expr::Integer(integer)
+            }
+            expression => expression,
+        });
+        assert_eq!(program.to_sexpr(), before);
+        let after = transformed.to_sexpr();
+        assert_eq!(after.lines().count(), before.lines().count());
+        for (old, new) in before.lines().zip(after.lines()) {
+            if old != new {
+                replaced += 1;
+                assert!(old.trim_start().starts_with("(IntegerLiteral"), "{}", old);
+                assert!(
+                    new.trim_start().starts_with("(IntegerLiteral -1 "),
+                    "{}",
+                    new
+                );
+            }
+        }
+    }
+    assert!(replaced > 0);
+}
+
+#[test]
+fn test_node_table() {
+    let source = "let a = 1 + x;\nf(a)";
+    let program = Parser::new(Lexer::new(source)).parse().unwrap();
+    let table = program.node_table();
+    let kinds = table.iter().map(|(_, info)| info.kind).collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            "Program",
+            "LetStatement",
+            "Identifier",
+            "InfixExpression",
+            "IntegerLiteral",
+            "Identifier",
+            "ExpressionStatement",
+            "CallExpression",
+            "Identifier",
+            "Identifier",
+        ]
+    );
+    let x = NodeId(5);
+    let info = table.get(x).unwrap();
+    assert_eq!(info.span.slice(source), Some("x"));
+    assert_eq!(info.parent, Some(NodeId(3)));
+    assert_eq!(table.get(NodeId(3)).unwrap().children, [NodeId(4), x]);
+    assert_eq!(
+        table.ancestors(x).collect::<Vec<_>>(),
+        [NodeId(3), NodeId(1), NodeId(0)]
+    );
+    assert_eq!(find(&program, x).unwrap().string(), "x");
+    assert_eq!(find(&program, NodeId(7)).unwrap().string(), "f(a)");
+    assert!(find(&program, NodeId(10)).is_none());
+    assert!(table.get(NodeId(10)).is_none());
+    // 光标在 `+` 上时是整个中缀表达式，在 `f` 上时是函数名
+    assert_eq!(table.node_at(10), Some(NodeId(3)));
+    assert_eq!(table.node_at(15), Some(NodeId(8)));
+    assert_eq!(table.node_at(100), None);
+    // 同样的源码再解析一次，编号不变
+    let again = Parser::new(Lexer::new(source)).parse().unwrap();
+    assert_eq!(again.node_table().get(x).unwrap().span, info.span);
 }
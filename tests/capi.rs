@@ -0,0 +1,56 @@
+#![cfg(feature = "capi")]
+
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use implement_parser::capi::{
+    monkey_eval, monkey_free, monkey_new, monkey_result_free, monkey_result_is_error,
+    monkey_result_to_string, MonkeyInterpreter, MonkeyResult,
+};
+
+unsafe fn eval(interpreter: *mut MonkeyInterpreter, source: &str) -> (bool, String) {
+    let source = CString::new(source).unwrap();
+    let result: *mut MonkeyResult = monkey_eval(interpreter, source.as_ptr());
+    let text = CStr::from_ptr(monkey_result_to_string(result))
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let is_error = monkey_result_is_error(result);
+    monkey_result_free(result);
+    (is_error, text)
+}
+
+#[test]
+fn test_eval_through_c_abi() {
+    unsafe {
+        let interpreter = monkey_new();
+        assert_eq!(eval(interpreter, "let x = 20;"), (false, "null".to_owned()));
+        assert_eq!(eval(interpreter, "x + 1"), (false, "21".to_owned()));
+        assert_eq!(
+            eval(interpreter, "x + true"),
+            (
+                true,
+                "Error: type mismatch: Integer + Boolean at line 1, col 3".to_owned()
+            )
+        );
+        assert_eq!(eval(interpreter, "\"a\\0b\""), (false, "a\\0b".to_owned()));
+        monkey_free(interpreter);
+    }
+}
+
+#[test]
+fn test_null_pointers() {
+    unsafe {
+        assert!(monkey_eval(ptr::null_mut(), c"1".as_ptr()).is_null());
+        assert!(monkey_result_is_error(ptr::null()));
+        assert!(monkey_result_to_string(ptr::null()).is_null());
+        monkey_result_free(ptr::null_mut());
+        monkey_free(ptr::null_mut());
+
+        let interpreter = monkey_new();
+        let result = monkey_eval(interpreter, ptr::null());
+        assert!(monkey_result_is_error(result));
+        monkey_result_free(result);
+        monkey_free(interpreter);
+    }
+}
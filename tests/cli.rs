@@ -0,0 +1,148 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use rstest::rstest;
+
+fn monkey(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_implement-parser"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_run_script_file() {
+    let directory = std::env::temp_dir().join(format!("monkey-cli-{}", std::process::id()));
+    fs::create_dir_all(directory.join("lib")).unwrap();
+    fs::write(
+        directory.join("lib/math.mky"),
+        "let double = fn(x) { x * 2 };",
+    )
+    .unwrap();
+    let script = directory.join("main.mky");
+    fs::write(
+        &script,
+        "let math = import(\"lib/math\");
+let unless = macro(c, a, b) { quote(if (!(unquote(c))) { unquote(a) } else { unquote(b) }) };
+let value = unless(false, math[\"double\"](21), 0);
+puts(value);
+let inline = macro() { quote(unquote(import(\"lib/math\")[\"double\"](4))) };
+puts(inline());
+let argc = macro() { quote(unquote(len(args()))) };
+puts(argc());",
+    )
+    .unwrap();
+
+    // 两个后端都在脚本的上下文里展开宏，宏体里的 import 和 args() 和运行时一样
+    let script = script.to_str().unwrap();
+    let outputs = [
+        monkey(&[script, "x"], ""),
+        monkey(&["run", "--vm", script, "x"], ""),
+    ];
+    fs::remove_dir_all(&directory).unwrap();
+    for output in outputs {
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "42\n8\n1\n");
+    }
+}
+
+#[rstest]
+#[case("puts(1 + 2);", 0, "3\n", "")]
+#[case(
+    "puts(1);\n1 + true;\nputs(2);",
+    1,
+    "1\n",
+    "Error: type mismatch: Integer + Boolean at line 2, col 3\n"
+)]
+#[case("exit(3);", 3, "", "")]
+#[case(
+    "exit(256);",
+    1,
+    "",
+    "Error: exit code out of range: 256, must be 0 to 255 at line 1, col 5\n"
+)]
+#[case(
+    "let = 1;",
+    1,
+    "",
+    "expected next token to be Ident, got Assign instead at line 1, col 5\n"
+)]
+fn test_run_stdin(
+    #[case] source: &str,
+    #[case] code: i32,
+    #[case] stdout: &str,
+    #[case] stderr: &str,
+) {
+    let output = monkey(&["-"], source);
+    assert_eq!(output.status.code(), Some(code));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), stdout);
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), stderr);
+}
+
+#[test]
+fn test_missing_script_file() {
+    let output = monkey(&["does-not-exist.mky"], "");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_fmt_write_keeps_comments() {
+    let path = std::env::temp_dir().join(format!("monkey-fmt-{}.mky", std::process::id()));
+    fs::write(&path, "// double it\nlet f=fn(x){x*2}; // trailing\n").unwrap();
+    let output = monkey(&["fmt", "-w", path.to_str().unwrap()], "");
+    let formatted = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        formatted,
+        "// double it\nlet f = fn(x) {\n    x * 2\n}; // trailing\n"
+    );
+}
+
+#[rstest]
+#[case(&["--eval", "1 + 2"], 0, "3\n")]
+#[case(&["-e", "puts(\"hi\")"], 0, "hi\n")]
+#[case(&["--vm", "-e", "len([1, 2])"], 0, "2\n")]
+#[case(&["-e", "exit(4)"], 4, "")]
+#[case(&["-e", "1 + true"], 1, "")]
+fn test_eval_option(#[case] args: &[&str], #[case] code: i32, #[case] stdout: &str) {
+    let output = monkey(args, "");
+    assert_eq!(output.status.code(), Some(code));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), stdout);
+}
+
+#[rstest]
+#[case(&["fmt"], "let f=fn(x){x*2};f(1)", 0, "let f = fn(x) {\n    x * 2\n};\n\nf(1);\n")]
+#[case(&["fmt", "--check"], "let a = 1;\n", 0, "")]
+#[case(&["fmt", "--check", "-"], "let a=1;", 1, "")]
+#[case(&["tokens"], "let a = 1;", 0, "1:1\tLet\tlet\n1:5\tIdent\ta\n1:7\tAssign\t=\n1:9\tInt\t1\n1:10\tSemicolon\t;\n")]
+#[case(&["ast"], "-a", 0, "(Program @0..2\n  (ExpressionStatement @0..2\n    (PrefixExpression - @0..2\n      (Identifier a @1..2))))\n")]
+#[case(&["run", "-"], "puts(len(\"abc\"))", 0, "3\n")]
+#[case(&["run", "--vm", "-"], "puts(len(\"abc\"))", 0, "3\n")]
+#[case(&["-", "a", "-b"], "puts(args())", 0, "[a, -b]\n")]
+#[case(&["run", "--vm", "-", "1"], "puts(args())", 0, "[1]\n")]
+#[case(&["run", "-", "--", "-y"], "puts(args())", 0, "[-y]\n")]
+#[case(&["run", "-", "x", "--", "-y"], "puts(args())", 0, "[x, --, -y]\n")]
+#[case(&["-", "--", "x", "--"], "puts(args())", 0, "[x, --]\n")]
+#[case(&["repl", "extra"], "", 2, "")]
+fn test_subcommands(
+    #[case] args: &[&str],
+    #[case] stdin: &str,
+    #[case] code: i32,
+    #[case] stdout: &str,
+) {
+    let output = monkey(args, stdin);
+    assert_eq!(output.status.code(), Some(code));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), stdout);
+}
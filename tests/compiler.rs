@@ -0,0 +1,384 @@
+use implement_parser::compiler::code::{disassemble, make, read_operands, Opcode};
+use implement_parser::compiler::disasm::disassemble_bytecode;
+use implement_parser::compiler::symbol_table::{SymbolScope, SymbolTable};
+use implement_parser::compiler::{compile, file, Bytecode, Compiler};
+use implement_parser::corpus::programs;
+use implement_parser::evaluator::builtins::BuiltinRegistry;
+use implement_parser::evaluator::context::EvalContext;
+use implement_parser::evaluator::environment::Environment;
+use implement_parser::evaluator::macro_expansion::{define_macros, expand_macro};
+use implement_parser::evaluator::object::{Null, Value};
+use implement_parser::lexer::Lexer;
+use implement_parser::parser::Parser;
+use implement_parser::sync::{Rc, RefCell};
+use implement_parser::vm::Vm;
+use rstest::rstest;
+
+fn compile_source(input: &str) -> String {
+    let program = Parser::new(Lexer::new(input.to_owned())).parse().unwrap();
+    disassemble(&compile(&program).unwrap().main.instructions)
+}
+
+#[rstest]
+#[case(Opcode::Constant, &[65534], &[Opcode::Constant as u8, 255, 254])]
+#[case(Opcode::GetLocal, &[255], &[Opcode::GetLocal as u8, 255])]
+#[case(Opcode::Closure, &[65534, 255], &[Opcode::Closure as u8, 255, 254, 255])]
+#[case(Opcode::Add, &[], &[Opcode::Add as u8])]
+fn test_make(#[case] op: Opcode, #[case] operands: &[usize], #[case] expected: &[u8]) {
+    let instruction = make(op, operands).unwrap();
+    assert_eq!(instruction, expected);
+    assert_eq!(instruction.len(), op.width());
+    let (read, width) = read_operands(op, &instruction[1..]);
+    assert_eq!(read, operands);
+    assert_eq!(width, op.width() - 1);
+}
+
+#[test]
+fn test_make_rejects_large_operands() {
+    assert!(make(Opcode::Constant, &[65536]).is_none());
+    assert!(make(Opcode::Call, &[256]).is_none());
+}
+
+#[test]
+fn test_disassemble_malformed_instructions() {
+    let mut instructions = vec![255];
+    instructions.extend(make(Opcode::True, &[]).unwrap());
+    instructions.extend(&make(Opcode::Constant, &[1]).unwrap()[..2]);
+    assert_eq!(
+        disassemble(&instructions),
+        "0000 unknown opcode 255\n0001 True\n0002 Constant truncated operand\n"
+    );
+}
+
+#[rstest]
+#[case(
+    "1 + 2",
+    "0000 Constant 0\n0003 Constant 1\n0006 Add\n0007 ReturnValue\n"
+)]
+#[case(
+    "if (true) { 10 }; 3",
+    "0000 True\n0001 JumpNotTruthy 10\n0004 Constant 0\n0007 Jump 11\n0010 Null\n0011 Pop\n0012 Constant 1\n0015 ReturnValue\n"
+)]
+#[case(
+    "let one = 1; one",
+    "0000 Constant 0\n0003 SetGlobal 0\n0006 GetGlobal 0\n0009 ReturnValue\n"
+)]
+#[case(
+    "len([])",
+    "0000 GetBuiltin 22\n0003 Array 0\n0006 Call 1\n0008 ReturnValue\n"
+)]
+#[case(
+    "reverse([])",
+    "0000 GetName 0\n0003 Array 0\n0006 Call 1\n0008 ReturnValue\n"
+)]
+#[case(
+    "let len = 1; len",
+    "0000 Constant 0\n0003 SetGlobal 0\n0006 GetGlobal 0\n0009 ReturnValue\n"
+)]
+#[case("", "0000 Null\n0001 ReturnValue\n")]
+fn test_compile(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(compile_source(input), expected);
+}
+
+#[test]
+fn test_compile_closures() {
+    let program = Parser::new(Lexer::new(
+        "fn(a) { let b = 1; fn(c) { a + b + c } }".to_owned(),
+    ))
+    .parse()
+    .unwrap();
+    let bytecode = compile(&program).unwrap();
+    let functions = bytecode
+        .constants
+        .iter()
+        .filter_map(|constant| match constant.as_ref() {
+            Value::CompiledFunction(function) => Some(function),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let [inner, outer] = functions.as_slice() else {
+        panic!("expected two functions, got {}", functions.len());
+    };
+    assert_eq!(
+        disassemble(&inner.instructions),
+        "0000 GetFree 0\n0002 GetFree 1\n0004 Add\n0005 GetLocal 0\n0007 Add\n0008 ReturnValue\n"
+    );
+    assert_eq!((inner.num_parameters, inner.num_locals), (1, 1));
+    assert_eq!(
+        disassemble(&outer.instructions),
+        "0000 Constant 0\n0003 SetLocal 1\n0005 GetLocal 0\n0007 GetLocal 1\n0009 Closure 1 2\n0013 ReturnValue\n"
+    );
+    assert_eq!((outer.num_parameters, outer.num_locals), (1, 2));
+}
+
+#[test]
+fn test_compile_recursive_function() {
+    let program = Parser::new(Lexer::new(
+        "let f = fn() { let g = fn() { g() }; g }".to_owned(),
+    ))
+    .parse()
+    .unwrap();
+    let bytecode = compile(&program).unwrap();
+    let Value::CompiledFunction(inner) = bytecode.constants[0].as_ref() else {
+        panic!("expected a function");
+    };
+    assert_eq!(
+        disassemble(&inner.instructions),
+        "0000 CurrentClosure\n0001 Call 0\n0003 ReturnValue\n"
+    );
+}
+
+#[rstest]
+#[case(
+    "let m = 1; macro(x) { x }",
+    "macro is not supported by the bytecode compiler at line 1, col 12"
+)]
+#[case(
+    "quote(unquote(1))",
+    "unquote is not supported by the bytecode compiler at line 1, col 7"
+)]
+#[case(
+    "unquote(1)",
+    "`unquote` can only be used inside `quote` at line 1, col 1"
+)]
+fn test_compile_errors(#[case] input: &str, #[case] expected: &str) {
+    let program = Parser::new(Lexer::new(input.to_owned())).parse().unwrap();
+    assert_eq!(compile(&program).unwrap_err().to_string(), expected);
+}
+
+#[test]
+fn test_symbol_table() {
+    let mut global = SymbolTable::new();
+    let a = global.define(&"a".into());
+    assert_eq!((a.scope, a.index), (SymbolScope::Global, 0));
+    assert_eq!(global.define(&"a".into()).index, 0);
+
+    let mut local = SymbolTable::new_enclosed(global);
+    local.define(&"b".into());
+    let mut nested = SymbolTable::new_enclosed(local);
+    nested.define(&"c".into());
+    let scopes = ["a", "b", "c"].map(|name| {
+        let symbol = nested.resolve(name).unwrap();
+        (symbol.scope, symbol.index)
+    });
+    assert_eq!(
+        scopes,
+        [
+            (SymbolScope::Global, 0),
+            (SymbolScope::Free, 0),
+            (SymbolScope::Local, 0)
+        ]
+    );
+    assert_eq!(nested.free_symbols()[0].scope, SymbolScope::Local);
+    assert!(nested.resolve("d").is_none());
+    let global = nested.into_outer().unwrap().into_outer().unwrap();
+    assert_eq!(global.global_names(), [Rc::from("a")]);
+}
+
+#[test]
+fn test_symbol_table_builtins() {
+    let mut global = SymbolTable::new();
+    global.define_builtin(3, &"len".into());
+    let mut local = SymbolTable::new_enclosed(global);
+    let len = local.resolve("len").unwrap();
+    assert_eq!((len.scope, len.index), (SymbolScope::Builtin, 3));
+    assert!(local.free_symbols().is_empty());
+
+    // 全局变量覆盖同名的内置函数，占一个新的位置
+    let mut global = local.into_outer().unwrap();
+    let shadow = global.define(&"len".into());
+    assert_eq!((shadow.scope, shadow.index), (SymbolScope::Global, 0));
+    assert_eq!(global.resolve("len").unwrap(), shadow);
+}
+
+#[test]
+fn test_compile_with_host_builtins() {
+    let mut builtins = BuiltinRegistry::new();
+    builtins.register(
+        "host",
+        Rc::new(|_: &EvalContext, _: &[Rc<Value>]| Rc::new(Value::Null(Null))),
+    );
+    let program = Parser::new(Lexer::new("host()".to_owned()))
+        .parse()
+        .unwrap();
+    let default = Compiler::new().compile(&program).unwrap();
+    assert!(disassemble(&default.main.instructions).starts_with("0000 GetName 0\n"));
+
+    let bytecode = Compiler::default()
+        .with_builtins(&builtins)
+        .compile(&program)
+        .unwrap();
+    let index = bytecode
+        .builtins
+        .iter()
+        .position(|name| &**name == "host")
+        .unwrap();
+    assert!(disassemble(&bytecode.main.instructions)
+        .starts_with(&format!("0000 GetBuiltin {}\n", index)));
+}
+
+fn compile_program(input: &str) -> Bytecode {
+    compile(&Parser::new(Lexer::new(input.to_owned())).parse().unwrap()).unwrap()
+}
+
+fn run_bytecode(bytecode: &Bytecode) -> String {
+    Vm::new().run(bytecode).inspect()
+}
+
+#[rstest]
+#[case("1 + 2 * 3", "7")]
+#[case("let f = fn(a) { fn(b) { a + b } }; f(1)(2)", "3")]
+#[case("let r = fn(n) { if (n == 0) { 0 } else { r(n - 1) } }; r(5)", "0")]
+#[case("[\"a\", {\"k\": len(\"xy\")}]", "[a, {k: 2}]")]
+#[case("quote(fn(x) { x + {\"a\": [1]} })", "QUOTE(fn(x) (x + {a: [1]}))")]
+#[case("fn(x, y) { x }", "fn (x, y) {\nx\n}")]
+#[case(
+    "let f = fn() {\n  1 + true\n}; f()",
+    "Error: type mismatch: Integer + Boolean at line 2, col 5"
+)]
+fn test_file_round_trip(#[case] input: &str, #[case] expected: &str) {
+    let bytecode = compile_program(input);
+    let bytes = file::encode(&bytecode).unwrap();
+    assert!(bytes.starts_with(file::MAGIC));
+    let loaded = file::decode(&bytes).unwrap();
+    assert_eq!(loaded.main, bytecode.main);
+    assert_eq!(loaded.globals, bytecode.globals);
+    assert_eq!(loaded.builtins, bytecode.builtins);
+    assert_eq!(run_bytecode(&loaded), expected);
+}
+
+#[test]
+fn test_file_round_trips_corpus() {
+    for program in programs() {
+        let mut parsed = program.parse().unwrap();
+        let macro_env = Rc::new(RefCell::new(Environment::new()));
+        define_macros(&mut parsed, Rc::clone(&macro_env));
+        expand_macro(&mut parsed, macro_env).unwrap();
+        let bytes = file::encode(&compile(&parsed).unwrap()).unwrap();
+        let loaded = file::decode(&bytes).unwrap();
+        assert_eq!(run_bytecode(&loaded), program.expected, "{}", program.name);
+    }
+}
+
+#[test]
+fn test_file_rejects_bad_input() {
+    let bytes = file::encode(&compile_program("let a = [1, 2]; a[0]")).unwrap();
+    let decode_error = |bytes: &[u8]| file::decode(bytes).unwrap_err().to_string();
+
+    assert_eq!(
+        decode_error(b"MONKEY"),
+        "invalid bytecode file: missing magic header"
+    );
+    let mut version = bytes.clone();
+    version[5] = 9;
+    assert_eq!(
+        decode_error(&version),
+        "bytecode file version 9 is not supported, expected 1"
+    );
+    assert_eq!(
+        decode_error(&bytes[..bytes.len() - 1]),
+        "invalid bytecode file: unexpected end of file"
+    );
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert_eq!(
+        decode_error(&trailing),
+        "invalid bytecode file: trailing bytes"
+    );
+
+    // 把 SetGlobal 0 改成 SetGlobal 7
+    let mut bytecode = compile_program("let a = [1, 2]; a[0]");
+    let main = Rc::make_mut(&mut bytecode.main);
+    assert_eq!(main.instructions[9], Opcode::SetGlobal as u8);
+    main.instructions[11] = 7;
+    assert_eq!(
+        decode_error(&file::encode(&bytecode).unwrap()),
+        "invalid bytecode file: operand out of range at 0009"
+    );
+
+    // 放不进 u32 的长度不能截断后写出去
+    let mut bytecode = compile_program("1");
+    Rc::make_mut(&mut bytecode.main).num_locals = usize::MAX;
+    assert_eq!(
+        file::encode(&bytecode).unwrap_err().to_string(),
+        format!(
+            "length {} is too large for the bytecode file format",
+            usize::MAX
+        )
+    );
+}
+
+// 操作数都在范围内、但是执行时会弹出不存在的值或者读取不存在的自由变量的代码
+#[rstest]
+#[case(vec![(Opcode::Add, vec![])], "stack underflow at 0000")]
+#[case(
+    vec![(Opcode::True, vec![]), (Opcode::Call, vec![1]), (Opcode::ReturnValue, vec![])],
+    "stack underflow at 0001"
+)]
+#[case(vec![(Opcode::Array, vec![2]), (Opcode::ReturnValue, vec![])], "stack underflow at 0000")]
+// 跳到 0006 时栈上没有值，顺序执行到 0006 时有两个值
+#[case(
+    vec![
+        (Opcode::True, vec![]),
+        (Opcode::JumpNotTruthy, vec![6]),
+        (Opcode::True, vec![]),
+        (Opcode::True, vec![]),
+        (Opcode::True, vec![]),
+        (Opcode::ReturnValue, vec![]),
+    ],
+    "inconsistent stack height at 0006"
+)]
+#[case(vec![(Opcode::GetFree, vec![0]), (Opcode::ReturnValue, vec![])], "free variable out of range at 0000")]
+fn test_file_rejects_unbalanced_stack(
+    #[case] instructions: Vec<(Opcode, Vec<usize>)>,
+    #[case] expected: &str,
+) {
+    let mut bytecode = compile_program("1");
+    let main = Rc::make_mut(&mut bytecode.main);
+    main.instructions = instructions
+        .into_iter()
+        .flat_map(|(op, operands)| make(op, &operands).unwrap())
+        .collect();
+    // 没有经过检查的字节码直接交给虚拟机也不会 panic，出错时报告内部错误
+    run_bytecode(&bytecode);
+    assert_eq!(
+        file::decode(&file::encode(&bytecode).unwrap())
+            .unwrap_err()
+            .to_string(),
+        format!("invalid bytecode file: {}", expected)
+    );
+}
+
+#[test]
+fn test_file_rejects_unsupported_constants() {
+    let mut bytecode = compile_program("1");
+    bytecode.constants[0] = Rc::new(Value::Null(Null));
+    assert_eq!(
+        file::encode(&bytecode).unwrap_err().to_string(),
+        "Null constant is not supported by the bytecode compiler"
+    );
+}
+
+#[test]
+fn test_disassemble_bytecode() {
+    let bytecode = compile_program("let greet = fn(name) { \"hi \" + name }; greet(len(\"ab\"))");
+    assert_eq!(
+        disassemble_bytecode(&bytecode),
+        "== main (globals 1, constants 3) ==
+0000 Closure 1 0          ; function 1, 0 free, line 1, col 13
+0004 SetGlobal 0          ; greet
+0007 GetGlobal 0          ; greet, line 1, col 40
+0010 GetBuiltin 22        ; len, line 1, col 46
+0013 Constant 2           ; \"ab\"
+0016 Call 1               ; line 1, col 49
+0018 Call 1               ; line 1, col 45
+0020 ReturnValue
+
+== function 1 (params 1, locals 1) ==
+0000 Constant 0           ; \"hi \"
+0003 GetLocal 0           ; line 1, col 32
+0005 Add                  ; line 1, col 30
+0006 ReturnValue
+"
+    );
+}
@@ -0,0 +1,37 @@
+use std::thread;
+
+use implement_parser::ast::traits::AsNode;
+use implement_parser::corpus::{by_workload, find, programs, Workload};
+use implement_parser::evaluator::environment::Environment;
+use implement_parser::evaluator::eval::eval;
+use implement_parser::evaluator::macro_expansion::{define_macros, expand_macro};
+use implement_parser::sync::{Rc, RefCell};
+
+// 测试线程默认只有 2 MiB 的栈，debug 构建下递归的语料程序会溢出，这里用和主线程一样大的栈
+const STACK_SIZE: usize = 8 * 1024 * 1024;
+
+#[test]
+fn test_corpus_programs_evaluate() {
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(|| {
+            for program in programs() {
+                let mut parsed = program.parse().unwrap();
+                let macro_env = Rc::new(RefCell::new(Environment::new()));
+                define_macros(&mut parsed, Rc::clone(&macro_env));
+                expand_macro(&mut parsed, macro_env).unwrap();
+                let evaluated = eval(parsed.as_node(), Rc::new(RefCell::new(Environment::new())));
+                assert_eq!(evaluated.inspect(), program.expected, "{}", program.name);
+            }
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn test_corpus_lookup() {
+    assert_eq!(find("recursion").unwrap().workload, Workload::Recursion);
+    assert!(find("missing").is_none());
+    assert_eq!(by_workload(Workload::Macros).count(), 1);
+}
@@ -0,0 +1,57 @@
+use implement_parser::ast::traits::AsNode;
+use implement_parser::dot::{ast_to_dot, environment_to_dot};
+use implement_parser::evaluator::environment::Environment;
+use implement_parser::evaluator::eval::eval;
+use implement_parser::lexer::Lexer;
+use implement_parser::parser::Parser;
+use implement_parser::sync::{Rc, RefCell};
+
+#[test]
+fn test_ast_to_dot() {
+    let mut parser = Parser::new(Lexer::new("let x = -a * \"b\";".to_owned()));
+    let program = parser.parse_program();
+    assert!(parser.error_messages.is_empty());
+
+    let expected = r#"digraph ast {
+    node [shape=box, fontname="monospace"];
+    n0 [label="Program"];
+    n1 [label="LetStatement\nx"];
+    n2 [label="Identifier\nx"];
+    n1 -> n2;
+    n3 [label="InfixExpression\n*"];
+    n4 [label="PrefixExpression\n-"];
+    n5 [label="Identifier\na"];
+    n4 -> n5;
+    n3 -> n4;
+    n6 [label="StringLiteral\n\"b\""];
+    n3 -> n6;
+    n1 -> n3;
+    n0 -> n1;
+}
+"#;
+    assert_eq!(ast_to_dot(program.as_node()), expected);
+}
+
+#[test]
+fn test_environment_to_dot() {
+    let input = "
+    let base = 10;
+    let makeAdder = fn(x) { fn(y) { x + y + base } };
+    let addTwo = makeAdder(2);
+    ";
+    let mut parser = Parser::new(Lexer::new(input.to_owned()));
+    let program = parser.parse_program();
+    let env = Rc::new(RefCell::new(Environment::new()));
+    eval(program.as_node(), Rc::clone(&env));
+
+    let expected = r#"digraph environment {
+    node [shape=box, fontname="monospace"];
+    env0 [label="env0\naddTwo = fn(y)\nbase = 10\nmakeAdder = fn(x)"];
+    env0 -> env1 [label="addTwo", style=dashed];
+    env0 -> env0 [label="makeAdder", style=dashed];
+    env1 [label="env1\nx = 2"];
+    env1 -> env0 [label="outer"];
+}
+"#;
+    assert_eq!(environment_to_dot(&env), expected);
+}
@@ -0,0 +1,63 @@
+use implement_parser::ast::traits::AsNode;
+use implement_parser::errors::{message, set_catalog, Chinese, MessageCatalog, MessageId};
+use implement_parser::evaluator::environment::Environment;
+use implement_parser::evaluator::eval::eval;
+use implement_parser::evaluator::object::{Error, Value};
+use implement_parser::lexer::Lexer;
+use implement_parser::parser::Parser;
+use implement_parser::sync::{Rc, RefCell};
+
+// 只翻译了一条模板，其余退回英文
+struct Partial;
+
+impl MessageCatalog for Partial {
+    fn template(&self, id: MessageId) -> Option<&str> {
+        match id {
+            MessageId::TypeMismatch => Some("{2} and {0} do not mix with {1}"),
+            _ => None,
+        }
+    }
+}
+
+fn test_eval(input: &str) -> Rc<Value> {
+    let mut parser = Parser::new(Lexer::new(input.to_owned()));
+    let program = parser.parse_program();
+    eval(program.as_node(), Rc::new(RefCell::new(Environment::new())))
+}
+
+#[test]
+fn test_default_catalog_is_english() {
+    assert_eq!(
+        message(MessageId::WrongNumberOfArguments, &[&2, &1]),
+        "wrong number of arguments: got=2, want=1"
+    );
+}
+
+#[test]
+fn test_chinese_catalog() {
+    set_catalog(Box::new(Chinese));
+
+    let evaluated = test_eval("foobar");
+    let error = evaluated.downcast_ref::<Error>().unwrap();
+    assert_eq!(error.message, "找不到标识符：foobar");
+
+    let mut parser = Parser::new(Lexer::new("let = 5;".to_owned()));
+    parser.parse_program();
+    assert_eq!(
+        parser.error_messages[0],
+        "下一个词法单元应该是 Ident，实际是 Assign"
+    );
+}
+
+#[test]
+fn test_partial_catalog_falls_back_to_english() {
+    set_catalog(Box::new(Partial));
+
+    let evaluated = test_eval("5 + true");
+    let error = evaluated.downcast_ref::<Error>().unwrap();
+    assert_eq!(error.message, "Boolean and Integer do not mix with +");
+
+    let evaluated = test_eval("-true");
+    let error = evaluated.downcast_ref::<Error>().unwrap();
+    assert_eq!(error.message, "unknown operator: -Boolean");
+}
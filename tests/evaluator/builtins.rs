@@ -0,0 +1,654 @@
+use std::io::{Cursor, Write};
+use std::time::{Duration, Instant};
+
+use super::context::{test_eval_with_context, SharedBuffer};
+use super::eval::test_eval;
+use implement_parser::evaluator::builtins::{Arity, BuiltinRegistry};
+use implement_parser::evaluator::context::{EvalContext, Limits, Rng, RuntimeContext};
+use implement_parser::evaluator::environment::Environment;
+use implement_parser::evaluator::object::{Array, Error, Exit, Integer, Null, StringObject, Value};
+use implement_parser::sync::{Rc, RefCell};
+use rstest::rstest;
+
+#[test]
+fn test_read_line() {
+    let context =
+        RuntimeContext::new().with_input(Box::new(Cursor::new("first line\r\nsecond line\nlast")));
+    let context = Rc::new(RefCell::new(context));
+    let evaluated = test_eval_with_context(
+        "[read_line(), read_line(), read_line()]",
+        Rc::clone(&context),
+    );
+    let array = evaluated.downcast_ref::<Array>().unwrap();
+    let lines = array
+        .elements
+        .iter()
+        .map(|element| {
+            element
+                .downcast_ref::<StringObject>()
+                .unwrap()
+                .value
+                .clone()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(lines, ["first line", "second line", "last"]);
+
+    let evaluated = test_eval_with_context("read_line()", context);
+    assert!(evaluated.downcast_ref::<Null>().is_some());
+}
+
+#[test]
+fn test_read_all() {
+    let context = RuntimeContext::new().with_input(Box::new(Cursor::new("1\n2\n3\n")));
+    let evaluated =
+        test_eval_with_context("read_line(); read_all()", Rc::new(RefCell::new(context)));
+    let string = evaluated.downcast_ref::<StringObject>().unwrap();
+    assert_eq!(string.value, "2\n3\n");
+}
+
+#[test]
+fn test_puts_writes_to_context_output() {
+    let output = SharedBuffer::default();
+    let context = RuntimeContext::new().with_output(Box::new(output.clone()));
+    test_eval_with_context(r#"puts("hello", 1 + 2)"#, Rc::new(RefCell::new(context)));
+    assert_eq!(output.contents(), "hello\n3\n");
+}
+
+#[test]
+fn test_time_and_clock() {
+    let evaluated = test_eval("time()".to_owned());
+    assert!(evaluated.downcast_ref::<Integer>().unwrap().value > 1_600_000_000);
+
+    let evaluated = test_eval("let start = clock_ms(); sleep(20); clock_ms() - start".to_owned());
+    assert!(evaluated.downcast_ref::<Integer>().unwrap().value >= 20);
+}
+
+#[test]
+fn test_sleep_errors() {
+    let evaluated = test_eval("sleep(-1)".to_owned());
+    let error = evaluated.downcast_ref::<Error>().unwrap();
+    assert_eq!(
+        error.message,
+        "argument to `sleep` must be non-negative, got -1"
+    );
+
+    let context = RuntimeContext::new().with_limits(Limits {
+        sandbox: true,
+        ..Limits::default()
+    });
+    let evaluated = test_eval_with_context("sleep(10)", Rc::new(RefCell::new(context)));
+    let error = evaluated.downcast_ref::<Error>().unwrap();
+    assert_eq!(error.message, "`sleep` is disabled in sandbox mode");
+}
+
+#[test]
+fn test_sleep_stops_at_timeout() {
+    let context = RuntimeContext::new().with_limits(Limits {
+        timeout: Some(Duration::from_millis(50)),
+        ..Limits::default()
+    });
+    let start = Instant::now();
+    let evaluated = test_eval_with_context("sleep(10000)", Rc::new(RefCell::new(context)));
+    assert!(start.elapsed() < Duration::from_secs(2));
+    let error = evaluated.downcast_ref::<Error>().unwrap();
+    assert_eq!(error.message, "evaluation timed out after 50 ms");
+}
+
+#[test]
+fn test_args_and_getenv() {
+    let context = RuntimeContext::new().with_args(vec!["input.txt".to_owned(), "-v".to_owned()]);
+    let evaluated = test_eval_with_context("args()", Rc::new(RefCell::new(context)));
+    assert_eq!(evaluated.inspect(), "[input.txt, -v]");
+    assert_eq!(test_eval("args()".to_owned()).inspect(), "[]");
+
+    let path = std::env::var("PATH").unwrap();
+    assert_eq!(test_eval("getenv(\"PATH\")".to_owned()).inspect(), path);
+    assert_eq!(
+        test_eval("getenv(\"MONKEY_SURELY_UNSET_VARIABLE\")".to_owned()).inspect(),
+        "null"
+    );
+    let evaluated = test_eval("getenv(1)".to_owned());
+    let error = evaluated.downcast_ref::<Error>().unwrap();
+    assert_eq!(
+        error.message,
+        "argument to `getenv` must be String, got Integer"
+    );
+
+    let context = RuntimeContext::new().with_limits(Limits {
+        sandbox: true,
+        ..Limits::default()
+    });
+    let evaluated = test_eval_with_context("getenv(\"PATH\")", Rc::new(RefCell::new(context)));
+    let error = evaluated.downcast_ref::<Error>().unwrap();
+    assert_eq!(error.message, "`getenv` is disabled in sandbox mode");
+}
+
+#[test]
+fn test_seed_random_is_reproducible() {
+    let input = "seed_random(42); [random(), random_int(1, 6), random_int(-3, 3)]";
+    let first = test_eval(input.to_owned()).inspect();
+    let second = test_eval(input.to_owned()).inspect();
+    assert_eq!(first, second);
+}
+
+#[test]
+fn test_random_int_range() {
+    let context = RuntimeContext::new().with_rng(Rng::new(7));
+    let context = Rc::new(RefCell::new(context));
+    for _ in 0..100 {
+        let evaluated = test_eval_with_context("random_int(-2, 2)", Rc::clone(&context));
+        let value = evaluated.downcast_ref::<Integer>().unwrap().value;
+        assert!((-2..=2).contains(&value));
+    }
+
+    let evaluated = test_eval("random_int(3, 1)".to_owned());
+    let error = evaluated.downcast_ref::<Error>().unwrap();
+    assert_eq!(error.message, "invalid range for `random_int`: 3 > 1");
+}
+
+#[rstest]
+#[case("assert(true)", None)]
+#[case("assert(1 < 2, \"math works\")", None)]
+#[case("assert(false)", Some("assertion failed"))]
+#[case(
+    "assert(1 > 2, \"math is broken\")",
+    Some("assertion failed: math is broken")
+)]
+#[case("assert_eq([1, 2], [1, 1 + 1])", None)]
+#[case(r#"assert_eq({"a": 1, "b": 2}, {"b": 2, "a": 1})"#, None)]
+#[case("assert_eq(1, 2)", Some("assertion failed: left=1, right=2"))]
+#[case("assert_eq(\"1\", 1)", Some("assertion failed: left=1, right=1"))]
+#[case(
+    "assert_eq(len(\"ab\"), 3, \"length\")",
+    Some("assertion failed: length")
+)]
+#[case("assert()", Some("wrong number of arguments: got=0, want=1 or 2"))]
+fn test_assert(#[case] input: &str, #[case] expected: Option<&str>) {
+    let evaluated = test_eval(input.to_owned());
+    match expected {
+        Some(message) => assert_eq!(evaluated.downcast_ref::<Error>().unwrap().message, message),
+        None => assert!(evaluated.downcast_ref::<Null>().is_some()),
+    }
+}
+
+#[test]
+fn test_register_builtin() {
+    let context = Rc::new(RefCell::new(RuntimeContext::new()));
+    let calls = Rc::new(RefCell::new(0));
+    let counter = Rc::clone(&calls);
+    context.borrow_mut().builtins.register(
+        "answer",
+        Rc::new(move |_: &EvalContext, _: &[Rc<Value>]| {
+            *counter.borrow_mut() += 1;
+            Rc::new(Value::Integer(Integer { value: 42 }))
+        }),
+    );
+    let evaluated = test_eval_with_context("answer() + answer()", Rc::clone(&context));
+    assert_eq!(evaluated.downcast_ref::<Integer>().unwrap().value, 84);
+    assert_eq!(*calls.borrow(), 2);
+
+    // 替换已有的内置函数，reset 之后恢复默认实现
+    let replaced = context.borrow_mut().builtins.register(
+        "len",
+        Rc::new(|_: &EvalContext, _: &[Rc<Value>]| Rc::new(Value::Integer(Integer { value: -1 }))),
+    );
+    assert!(replaced.is_some());
+    let evaluated = test_eval_with_context("len(\"abc\")", Rc::clone(&context));
+    assert_eq!(evaluated.downcast_ref::<Integer>().unwrap().value, -1);
+
+    context.borrow_mut().builtins.reset();
+    let evaluated = test_eval_with_context("len(\"abc\")", Rc::clone(&context));
+    assert_eq!(evaluated.downcast_ref::<Integer>().unwrap().value, 3);
+    let evaluated = test_eval_with_context("answer()", context);
+    assert_eq!(
+        evaluated.downcast_ref::<Error>().unwrap().message,
+        "identifier not found: answer"
+    );
+}
+
+#[rstest]
+#[case("exit(); 1", 0)]
+#[case("let f = fn() { exit(3); 1 }; [f(), 2]", 3)]
+#[case(
+    "let f = fn(x) { if (x > 2) { exit(x) } else { f(x + 1) } }; f(0) + 1",
+    3
+)]
+#[case("{\"key\": exit(7)}; 5", 7)]
+fn test_exit_stops_evaluation(#[case] input: &str, #[case] code: i64) {
+    let evaluated = test_eval(input.to_owned());
+    assert_eq!(evaluated.downcast_ref::<Exit>().unwrap().code, code);
+}
+
+#[rstest]
+#[case("exit(256); 1", "exit code out of range: 256, must be 0 to 255")]
+#[case(
+    "exit(4294967297)",
+    "exit code out of range: 4294967297, must be 0 to 255"
+)]
+#[case("exit(-1)", "exit code out of range: -1, must be 0 to 255")]
+fn test_exit_code_out_of_range(#[case] input: &str, #[case] expected: &str) {
+    let evaluated = test_eval(input.to_owned());
+    assert_eq!(evaluated.downcast_ref::<Error>().unwrap().message, expected);
+}
+
+#[test]
+fn test_builtin_listing_is_sorted() {
+    let mut registry = BuiltinRegistry::new();
+    registry.register(
+        "answer",
+        Rc::new(|_: &EvalContext, _: &[Rc<Value>]| Rc::new(Value::Integer(Integer { value: 42 }))),
+    );
+    let names = registry
+        .list()
+        .map(|info| info.name.as_str())
+        .collect::<Vec<_>>();
+    let mut sorted = names.clone();
+    sorted.sort();
+    assert_eq!(names, sorted);
+    assert!(names.contains(&"answer"));
+
+    let len = registry.info("len").unwrap();
+    assert_eq!(len.arity, Arity::Exact(1));
+    assert_eq!(len.description, "Returns the length of a string or array");
+    assert_eq!(registry.info("answer").unwrap().arity, Arity::Variadic);
+    assert_eq!(registry.info("assert").unwrap().arity.to_string(), "1-2");
+}
+
+#[rstest]
+#[case(r#"format("{} + {} = {}", 1, 2, 3)"#, Ok("1 + 2 = 3"))]
+#[case(r#"format("{}: {}", "list", [1, "two"])"#, Ok("list: [1, two]"))]
+#[case(r#"format("{{}} {}", true)"#, Ok("{} true"))]
+#[case(r#"format("plain")"#, Ok("plain"))]
+#[case(
+    r#"format("{} {}", 1)"#,
+    Err("format string has 2 placeholders, got 1 arguments")
+)]
+#[case(
+    r#"format("{}", 1, 2)"#,
+    Err("format string has 1 placeholders, got 2 arguments")
+)]
+#[case(r#"format("{x}", 1)"#, Err("unmatched `{` in format string"))]
+#[case("format(1)", Err("argument to `format` must be String, got Integer"))]
+fn test_format(#[case] input: &str, #[case] expected: Result<&str, &str>) {
+    let evaluated = test_eval(input.to_owned());
+    match expected {
+        Ok(value) => assert_eq!(
+            evaluated.downcast_ref::<StringObject>().unwrap().value,
+            value
+        ),
+        Err(message) => assert_eq!(evaluated.downcast_ref::<Error>().unwrap().message, message),
+    }
+}
+
+#[rstest]
+#[case("let a = freeze([1, 2]); a", Ok("[1, 2]"))]
+#[case(
+    "let a = freeze([1, 2]); push(a, 3)",
+    Err("`push` cannot modify a frozen Array")
+)]
+#[case(
+    "let a = freeze([[1], 2]); push(a[0], 3)",
+    Err("`push` cannot modify a frozen Array")
+)]
+#[case(
+    "let h = freeze({\"list\": [1]}); push(h[\"list\"], 2)",
+    Err("`push` cannot modify a frozen Array")
+)]
+#[case("let a = freeze([1, 2]); push(deep_copy(a), 3)", Ok("[1, 2, 3]"))]
+#[case("let a = [1, 2]; let b = freeze(a); push(a, 3)", Ok("[1, 2, 3]"))]
+#[case(
+    "let a = freeze([1, 2, 3]); push(rest(a), 4)",
+    Err("`push` cannot modify a frozen Array")
+)]
+#[case("deep_copy({\"a\": [1, 2]})[\"a\"]", Ok("[1, 2]"))]
+#[case("freeze(5)", Ok("5"))]
+fn test_deep_copy_and_freeze(#[case] input: &str, #[case] expected: Result<&str, &str>) {
+    let evaluated = test_eval(input.to_owned());
+    match expected {
+        Ok(value) => assert_eq!(evaluated.inspect(), value),
+        Err(message) => assert_eq!(evaluated.downcast_ref::<Error>().unwrap().message, message),
+    }
+}
+
+#[test]
+fn test_register_builtin_from_host() {
+    let context = RuntimeContext::new().with_builtin(
+        "double",
+        Arity::Exact(1),
+        "Doubles an integer",
+        |_: &EvalContext, objects: &[Rc<Value>]| -> Rc<Value> {
+            match objects.first().map(Rc::as_ref) {
+                Some(Value::Integer(integer)) => Rc::new(Value::Integer(Integer {
+                    value: integer.value * 2,
+                })),
+                _ => Rc::new(Value::Null(Null)),
+            }
+        },
+    );
+    let context = Rc::new(RefCell::new(context));
+    assert_eq!(
+        context
+            .borrow()
+            .builtins
+            .info("double")
+            .unwrap()
+            .description,
+        "Doubles an integer"
+    );
+
+    let env = Environment::with_context(Rc::clone(&context));
+    let greeting = String::from("hello");
+    let replaced = env.register_builtin(
+        "greet",
+        move |_: &EvalContext, _: &[Rc<Value>]| -> Rc<Value> {
+            Rc::new(Value::String(StringObject {
+                value: greeting.clone(),
+            }))
+        },
+    );
+    assert!(replaced.is_none());
+
+    let evaluated = test_eval_with_context("[double(21), greet()]", context);
+    assert_eq!(evaluated.inspect(), "[42, hello]");
+}
+
+#[rstest]
+#[case(RuntimeContext::new().sandboxed(), "eval(\"1\")", Err("`eval` is disabled in sandbox mode"))]
+#[case(RuntimeContext::new().sandboxed(), "read_line()", Err("`read_line` is disabled in sandbox mode"))]
+#[case(RuntimeContext::new().sandboxed(), "len(\"abc\")", Ok("3"))]
+#[case(
+    RuntimeContext::new().with_disabled_builtins(&["len"]),
+    "len(\"abc\")",
+    Err("`len` is disabled")
+)]
+#[case(
+    RuntimeContext::new().with_allowed_builtins(&["len", "push"]),
+    "len(push([1], 2))",
+    Ok("2")
+)]
+#[case(
+    RuntimeContext::new().with_allowed_builtins(&["len", "push"]),
+    "first([1])",
+    Err("`first` is disabled")
+)]
+#[case(
+    RuntimeContext::new().sandboxed().with_disabled_builtins(&["len"]),
+    "len(\"abc\")",
+    Err("`len` is disabled in sandbox mode")
+)]
+fn test_disabled_builtins(
+    #[case] context: RuntimeContext,
+    #[case] input: &str,
+    #[case] expected: Result<&str, &str>,
+) {
+    let evaluated = test_eval_with_context(input, Rc::new(RefCell::new(context)));
+    match expected {
+        Ok(expected) => assert_eq!(evaluated.inspect(), expected),
+        Err(message) => assert_eq!(evaluated.downcast_ref::<Error>().unwrap().message, message),
+    }
+}
+
+#[test]
+fn test_disable_keeps_builtin_info() {
+    let mut registry = BuiltinRegistry::new();
+    assert!(registry.disable("eval"));
+    assert!(!registry.disable("missing"));
+    assert_eq!(registry.info("eval").unwrap().arity, Arity::Range(1, 2));
+    // 之后注册的函数不受名单影响
+    registry.allow_only(&[]);
+    registry.register_fn("answer", "", || 42);
+    assert_eq!(
+        registry.list().filter(|info| info.name == "answer").count(),
+        1
+    );
+}
+
+#[rstest]
+#[case("map([1, 2, 3], fn(x) { x * 2 })", Ok("[2, 4, 6]"))]
+#[case("map([], fn(x) { x })", Ok("[]"))]
+#[case("map([\"a\", [1]], len)", Ok("[1, 1]"))]
+#[case("let k = 10; map([1, 2], fn(x) { x + k })", Ok("[11, 12]"))]
+#[case("map([1, true], fn(x) { -x })", Err("unknown operator: -Boolean"))]
+#[case("map(1, len)", Err("argument to `map` must be Array, got Integer"))]
+fn test_map(#[case] input: &str, #[case] expected: Result<&str, &str>) {
+    let evaluated = test_eval(input.to_owned());
+    match expected {
+        Ok(value) => assert_eq!(evaluated.inspect(), value),
+        Err(message) => assert_eq!(evaluated.downcast_ref::<Error>().unwrap().message, message),
+    }
+}
+
+#[test]
+fn test_host_builtin_calls_back_and_writes_output() {
+    let output = SharedBuffer::default();
+    let context = RuntimeContext::new()
+        .with_output(Box::new(output.clone()))
+        .with_builtin(
+            "twice",
+            Arity::Exact(2),
+            "Calls f twice and prints each result",
+            |context: &EvalContext, objects: &[Rc<Value>]| -> Rc<Value> {
+                let mut last: Rc<Value> = Rc::new(Value::Null(Null));
+                for _ in 0..2 {
+                    last = context.call(objects[0].as_ref(), &[Rc::clone(&objects[1])]);
+                    writeln!(context.runtime().output, "{}", last.inspect()).unwrap();
+                }
+                last
+            },
+        );
+    let evaluated = test_eval_with_context(
+        "let inc = fn(x) { puts(x); x + 1 }; twice(inc, 1)",
+        Rc::new(RefCell::new(context)),
+    );
+    assert_eq!(evaluated.downcast_ref::<Integer>().unwrap().value, 2);
+    assert_eq!(output.contents(), "1\n2\n1\n2\n");
+}
+
+#[rstest]
+#[case("env()", "[]")]
+#[case(
+    "let b = \"x\"; let a = [1, 2]; env()",
+    "[{name: a, type: Array, value: [1, 2]}, {name: b, type: String, value: x}]"
+)]
+#[case(
+    "let a = 1; let f = fn(a, c) { env() }; f(true, 2)",
+    "[{name: a, type: Boolean, value: true}, {name: c, type: Integer, value: 2}, {name: f, type: Function, value: fn(a, c)}]"
+)]
+#[case(
+    "let s = \"0123456789012345678901234567890123456789ab\"; env()[0][\"value\"]",
+    "0123456789012345678901234567890123456789..."
+)]
+#[case(
+    "env(1)",
+    "Error: wrong number of arguments: got=1, want=0 at line 1, col 4"
+)]
+fn test_env(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(test_eval(input.to_owned()).inspect(), expected);
+}
+
+#[test]
+fn test_environment_introspection() {
+    let outer = Rc::new(RefCell::new(Environment::new()));
+    outer
+        .borrow_mut()
+        .set("b".into(), Rc::new(Value::Null(Null)));
+    outer
+        .borrow_mut()
+        .set("a".into(), Rc::new(Value::Null(Null)));
+    let mut inner = Environment::new_enclosed(Rc::clone(&outer));
+    inner.set("a".into(), Rc::new(Value::Integer(Integer { value: 1 })));
+    inner.set("c".into(), Rc::new(Value::Integer(Integer { value: 2 })));
+
+    assert_eq!(inner.names(), [Rc::from("a"), Rc::from("b"), Rc::from("c")]);
+    let visible = inner
+        .visible()
+        .into_iter()
+        .map(|(name, value)| format!("{}={}", name, value.inspect()))
+        .collect::<Vec<_>>();
+    assert_eq!(visible, ["a=1", "b=null", "c=2"]);
+    let mut own = inner
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect::<Vec<_>>();
+    own.sort();
+    assert_eq!(own, ["a", "c"]);
+    assert_eq!((inner.depth(), outer.borrow().depth()), (1, 0));
+}
+
+#[rstest]
+#[case(
+    "let it = iter([1, 2]); [next(it), next(it), next(it), next(it)]",
+    "[1, 2, null, null]"
+)]
+#[case("collect(take(generate(fn(i) { i * i }), 4))", "[0, 1, 4, 9]")]
+#[case("collect(generate(fn(i) { if (i < 3) { i } }))", "[0, 1, 2]")]
+#[case("collect(lazy_map([1, 2, 3], fn(x) { x * 10 }))", "[10, 20, 30]")]
+#[case(
+    "[take([1, 2, 3], 2), take([1], 5), take([1], 0)]",
+    "[[1, 2], [1], []]"
+)]
+#[case("let it = iter([1, 2, 3]); next(it); collect(it)", "[2, 3]")]
+#[case("let it = iter([1]); it == iter([1])", "false")]
+#[case("iter([])", "iterator")]
+#[case(
+    "next(lazy_map([1], fn(x) { x + true }))",
+    "Error: type mismatch: Integer + Boolean at line 1, col 30"
+)]
+#[case(
+    "next([1])",
+    "Error: argument to `next` must be Iterator, got Array at line 1, col 5"
+)]
+#[case(
+    "take(1, 2)",
+    "Error: argument to `take` must be Iterator, got Integer at line 1, col 5"
+)]
+#[case(
+    "take([1], -1)",
+    "Error: argument to `take` must be non-negative, got -1 at line 1, col 5"
+)]
+#[case(
+    "take(iter([1]), -1)",
+    "Error: argument to `take` must be non-negative, got -1 at line 1, col 5"
+)]
+#[case(
+    "take([1], true)",
+    "Error: argument to `take` must be Integer, got Boolean at line 1, col 5"
+)]
+fn test_iterators(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(test_eval(input.to_owned()).inspect(), expected);
+}
+
+#[test]
+fn test_lazy_map_only_runs_for_taken_values() {
+    let output = SharedBuffer::default();
+    let context = Rc::new(RefCell::new(
+        RuntimeContext::new().with_output(Box::new(output.clone())),
+    ));
+    // 无限序列上的 lazy_map 只计算取出的值
+    let evaluated = test_eval_with_context(
+        "let it = lazy_map(generate(fn(i) { i }), fn(x) { puts(x); x + 1 }); collect(take(it, 2))",
+        context,
+    );
+    assert_eq!(evaluated.inspect(), "[1, 2]");
+    assert_eq!(output.contents(), "0\n1\n");
+}
+
+#[rstest]
+#[case(
+    "let fib = memoize(fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }); fib(80)",
+    "23416728348467685"
+)]
+#[case(
+    "let first_of = memoize(first); [first_of([1]), first_of([2])]",
+    "[1, 2]"
+)]
+#[case(
+    "let add = memoize(fn(a, b) { a + b }); [add(1, 2), add(\"a\", \"b\"), add(1, 2)]",
+    "[3, ab, 3]"
+)]
+#[case(
+    "memoize(1)",
+    "Error: argument to `memoize` must be Function, got Integer at line 1, col 8"
+)]
+fn test_memoize(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(test_eval(input.to_owned()).inspect(), expected);
+}
+
+#[test]
+fn test_memoize_caches_by_arguments() {
+    let output = SharedBuffer::default();
+    let context = Rc::new(RefCell::new(
+        RuntimeContext::new().with_output(Box::new(output.clone())),
+    ));
+    let evaluated = test_eval_with_context(
+        "let square = memoize(fn(x) { puts(x); x * x }); [square(3), square(4), square(3), square(true)]",
+        context,
+    );
+    assert_eq!(
+        evaluated.inspect(),
+        "Error: unknown operator: Boolean * Boolean at line 1, col 41"
+    );
+    // 第二次用 3 调用时直接返回缓存的结果
+    assert_eq!(output.contents(), "3\n4\ntrue\n");
+}
+
+#[rstest]
+#[case("bytes(\"hi\")", "bytes_from_hex(\"6869\")")]
+#[case("bytes([0, 255, 16])", "bytes_from_hex(\"00ff10\")")]
+#[case(
+    "let b = bytes_from_hex(\"CAFE\"); [b[0], b[1], b[2], len(b)]",
+    "[202, 254, null, 2]"
+)]
+#[case("bytes(\"ab\") == bytes([97, 98])", "true")]
+#[case("len(bytes(\"\"))", "0")]
+#[case("bytes([256])", "Error: byte out of range: 256 at line 1, col 6")]
+#[case(
+    "bytes([\"a\"])",
+    "Error: argument to `bytes` must be Integer, got String at line 1, col 6"
+)]
+#[case(
+    "bytes_from_hex(\"abc\")",
+    "Error: invalid hex string: abc at line 1, col 15"
+)]
+#[case(
+    "bytes_from_hex(\"+1\")",
+    "Error: invalid hex string: +1 at line 1, col 15"
+)]
+#[case(
+    "bytes(1)",
+    "Error: argument to `bytes` not supported, got Integer at line 1, col 6"
+)]
+fn test_bytes(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(test_eval(input.to_owned()).inspect(), expected);
+}
+
+#[test]
+fn test_read_and_write_bytes() {
+    let path = std::env::temp_dir().join(format!("monkey-bytes-{}.bin", std::process::id()));
+    let path = path.to_str().unwrap().replace('\\', "/");
+    let evaluated = test_eval(format!(
+        "write_bytes(\"{0}\", bytes([0, 1, 254])); let data = read_bytes(\"{0}\"); [len(data), data[2]]",
+        path
+    ));
+    assert_eq!(evaluated.inspect(), "[3, 254]");
+    let evaluated = test_eval(format!(
+        "write_bytes(\"{0}\", \"ok\"); read_bytes(\"{0}\")",
+        path
+    ));
+    assert_eq!(evaluated.inspect(), "bytes_from_hex(\"6f6b\")");
+    std::fs::remove_file(&path).unwrap();
+
+    let evaluated = test_eval(format!("read_bytes(\"{}\")", path));
+    assert!(evaluated
+        .inspect()
+        .starts_with(&format!("Error: failed to read `{}`", path)));
+
+    let context = RuntimeContext::new().sandboxed();
+    let evaluated = test_eval_with_context(
+        &format!("write_bytes(\"{}\", \"x\")", path),
+        Rc::new(RefCell::new(context)),
+    );
+    assert!(evaluated
+        .inspect()
+        .contains("`write_bytes` is disabled in sandbox mode"));
+}
@@ -0,0 +1,263 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use super::eval::parse_program_from;
+use implement_parser::ast::traits::Node;
+use implement_parser::evaluator::context::{EvalOptions, Limits, Observer, RuntimeContext};
+use implement_parser::evaluator::environment::Environment;
+use implement_parser::evaluator::eval::eval;
+use implement_parser::evaluator::object::{Error, Integer, Value};
+use implement_parser::evaluator::trace::TraceStep;
+use implement_parser::sync::{Rc, RefCell};
+use rstest::rstest;
+
+// 可以在测试里读回内容的输出
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn test_eval_with_context(input: &str, context: Rc<RefCell<RuntimeContext>>) -> Rc<Value> {
+    let program = parse_program_from(input.to_owned());
+    let env = Environment::with_context(context);
+    eval(&program, Rc::new(RefCell::new(env)))
+}
+
+#[test]
+fn test_stats_are_collected() {
+    let context = Rc::new(RefCell::new(RuntimeContext::new()));
+    let evaluated = test_eval_with_context(
+        "let add = fn(x, y) { x + y }; add(1, len(\"ab\"));",
+        Rc::clone(&context),
+    );
+    assert_eq!(evaluated.downcast_ref::<Integer>().unwrap().value, 3);
+
+    let stats = context.borrow().stats.clone();
+    assert_eq!(stats.function_calls, 1);
+    assert_eq!(stats.builtin_calls, 1);
+    assert!(stats.steps > 0);
+    assert!(stats.max_depth > 1);
+}
+
+#[test]
+fn test_step_limit() {
+    let context = RuntimeContext::new().with_limits(Limits {
+        max_steps: Some(20),
+        ..Limits::default()
+    });
+    let evaluated = test_eval_with_context(
+        "let f = fn(x) { f(x + 1) }; f(0);",
+        Rc::new(RefCell::new(context)),
+    );
+    let error = evaluated.downcast_ref::<Error>().unwrap();
+    assert_eq!(error.message, "evaluation budget exceeded: 20 steps");
+}
+
+#[test]
+fn test_observer_sees_every_node() {
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl Observer for Recorder {
+        fn on_exit(&mut self, node: &dyn Node, result: &Value, depth: usize) {
+            self.0.borrow_mut().push(format!(
+                "{} {} => {}",
+                depth,
+                node.string(),
+                result.inspect()
+            ));
+        }
+    }
+
+    let records = Rc::new(RefCell::new(vec![]));
+    let context = RuntimeContext::new().with_observer(Box::new(Recorder(Rc::clone(&records))));
+    test_eval_with_context("1 + 2", Rc::new(RefCell::new(context)));
+    assert_eq!(
+        *records.borrow(),
+        [
+            "4 1 => 1",
+            "4 2 => 2",
+            "3 (1 + 2) => 3",
+            "2 (1 + 2) => 3",
+            "1 (1 + 2) => 3",
+        ]
+    );
+}
+
+#[test]
+fn test_profile_counts_nodes_and_functions() {
+    let context = Rc::new(RefCell::new(RuntimeContext::new().with_options(
+        EvalOptions {
+            profile: true,
+            ..EvalOptions::default()
+        },
+    )));
+    test_eval_with_context(
+        "let double = fn(x) { x * 2 };\nlet id = fn(x) { x };\ndouble(1) + double(2) + id(3)",
+        Rc::clone(&context),
+    );
+    let context = context.borrow();
+    let profile = &context.profile;
+    let count = |kind: &str| profile.nodes[kind].count;
+    assert_eq!(count("Program"), 1);
+    assert_eq!(count("LetStatement"), 2);
+    assert_eq!(count("CallExpression"), 3);
+    assert_eq!(count("FunctionLiteral"), 2);
+    assert_eq!(
+        profile
+            .functions
+            .iter()
+            .map(|(label, entry)| (label.as_str(), entry.count))
+            .collect::<Vec<_>>(),
+        [
+            ("fn(x) at line 1, col 20", 2),
+            ("fn(x) at line 2, col 16", 1)
+        ]
+    );
+    let report = profile.report();
+    assert!(report.starts_with("nodes:\n"));
+    assert!(report.contains("\n  CallExpression "));
+    assert!(report.contains("\nfunctions:\n"));
+}
+
+#[test]
+fn test_profile_is_off_by_default() {
+    let context = Rc::new(RefCell::new(RuntimeContext::new()));
+    test_eval_with_context("let f = fn() { 1 }; f()", Rc::clone(&context));
+    assert!(context.borrow().profile.is_empty());
+
+    // 中途打开也只统计之后开始求值的节点
+    context.borrow_mut().options.profile = true;
+    test_eval_with_context("1 + 2", Rc::clone(&context));
+    assert_eq!(context.borrow().profile.nodes["InfixExpression"].count, 1);
+    context.borrow_mut().profile.clear();
+    assert!(context.borrow().profile.is_empty());
+}
+
+#[test]
+fn test_trace_writes_steps_to_output() {
+    let buffer = SharedBuffer::default();
+    let context = RuntimeContext::new()
+        .with_output(Box::new(buffer.clone()))
+        .with_options(EvalOptions {
+            trace: true,
+            ..EvalOptions::default()
+        });
+    test_eval_with_context("let f = fn(x) { -x }; f(1)", Rc::new(RefCell::new(context)));
+    let contents = buffer.contents();
+    let lines = contents.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "-> Program let f = fn(x) (-x);f(1) [env 0]");
+    assert!(lines.contains(&"  -> PrefixExpression (-x) [env 1]"));
+    assert!(lines.contains(&"  <- PrefixExpression = -1"));
+    assert_eq!(lines.last(), Some(&"<- Program = -1"));
+}
+
+#[test]
+fn test_trace_callback() {
+    let steps = Rc::new(RefCell::new(vec![]));
+    let recorded = Rc::clone(&steps);
+    let context = RuntimeContext::new()
+        .with_options(EvalOptions {
+            trace: true,
+            ..EvalOptions::default()
+        })
+        .with_tracer(move |step: &TraceStep| {
+            recorded.borrow_mut().push((
+                step.node.string(),
+                step.call_depth,
+                step.env_depth,
+                step.result.map(|result| result.inspect()),
+            ));
+        });
+    test_eval_with_context("let g = fn() { 7 }; g()", Rc::new(RefCell::new(context)));
+    let steps = steps.borrow();
+    assert!(steps.contains(&("7".to_owned(), 1, 1, None)));
+    assert!(steps.contains(&("7".to_owned(), 1, 1, Some("7".to_owned()))));
+    assert!(steps.contains(&("g()".to_owned(), 0, 0, Some("7".to_owned()))));
+    assert_eq!(steps.len() % 2, 0);
+}
+
+#[test]
+fn test_refuel_restarts_step_limit() {
+    let context = Rc::new(RefCell::new(RuntimeContext::new().with_limits(Limits {
+        max_steps: Some(30),
+        ..Limits::default()
+    })));
+    let env = Rc::new(RefCell::new(Environment::with_context(Rc::clone(&context))));
+    let input = parse_program_from("let add = fn(x, y) { x + y }; add(1, add(2, 3));".to_owned());
+    assert_eq!(eval(&input, Rc::clone(&env)).inspect(), "6");
+
+    let evaluated = eval(&input, Rc::clone(&env));
+    let error = evaluated.downcast_ref::<Error>().unwrap();
+    assert_eq!(error.message, "evaluation budget exceeded: 30 steps");
+
+    context.borrow_mut().refuel();
+    assert_eq!(eval(&input, Rc::clone(&env)).inspect(), "6");
+    assert!(context.borrow().stats.steps > 30);
+}
+
+#[test]
+fn test_timeout() {
+    let context = RuntimeContext::new().with_limits(Limits {
+        timeout: Some(Duration::from_millis(20)),
+        ..Limits::default()
+    });
+    let started = Instant::now();
+    // 递归不深但是步数呈指数增长，不限制的话要跑很久
+    let evaluated = test_eval_with_context(
+        "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(40);",
+        Rc::new(RefCell::new(context)),
+    );
+    let error = evaluated.downcast_ref::<Error>().unwrap();
+    assert_eq!(error.message, "evaluation timed out after 20 ms");
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[rstest]
+#[case("grow([], 10); len(grow([], 10))", "10")]
+#[case(
+    "grow([], 1000)",
+    "Error: out of memory: allocated more than 10000 bytes at line 1, col 61"
+)]
+// 每次 push 只算新的元素：字符串字面量 16 + 10 字节，加上新数组的 16 字节和多出来的一个 16 字节的槽位
+#[case("len(grow([], 170))", "170")]
+fn test_max_heap(#[case] input: &str, #[case] expected: &str) {
+    let context = RuntimeContext::new().with_limits(Limits {
+        max_heap: Some(10000),
+        ..Limits::default()
+    });
+    let source = format!(
+        r#"let grow = fn(a, n) {{ if (n == 0) {{ a }} else {{ grow(push(a, "0123456789"), n - 1) }} }};
+{input}"#
+    );
+    let evaluated = test_eval_with_context(&source, Rc::new(RefCell::new(context)));
+    assert_eq!(evaluated.inspect(), expected);
+}
+
+// 默认限制下 f(n) 嵌套调用 n + 1 层，测试线程只有 2 MiB 的栈也能递归到限制为止
+#[rstest]
+#[case("f(9999)", "9999")]
+#[case(
+    "f(10000)",
+    "Error: maximum recursion depth exceeded: 10000 nested calls at line 1, col 47"
+)]
+fn test_default_recursion_depth(#[case] input: &str, #[case] expected: &str) {
+    let source =
+        format!("let f = fn(n) {{ if (n == 0) {{ 0 }} else {{ 1 + f(n - 1) }} }}; {input}");
+    let evaluated = test_eval_with_context(&source, Rc::new(RefCell::new(RuntimeContext::new())));
+    assert_eq!(evaluated.inspect(), expected);
+}
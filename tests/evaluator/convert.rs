@@ -0,0 +1,92 @@
+use std::collections::{BTreeMap, HashMap};
+
+use implement_parser::evaluator::convert::ConversionError;
+use implement_parser::evaluator::object::{Null, ObjectType, Value};
+use indexmap::IndexMap;
+use rstest::rstest;
+
+use super::eval::test_eval;
+
+#[rstest]
+#[case(Value::from(42), "42")]
+#[case(Value::from(true), "true")]
+#[case(Value::from(()), "null")]
+#[case(Value::from("monkey"), "monkey")]
+#[case(Value::from(String::from("monkey")), "monkey")]
+#[case(Value::from(Some(1)), "1")]
+#[case(Value::from(None::<i64>), "null")]
+#[case(Value::from(vec![1, 2, 3]), "[1, 2, 3]")]
+#[case(Value::from(vec![vec!["a"], vec![]]), "[[a], []]")]
+#[case(Value::from(BTreeMap::from([("b", 2), ("a", 1)])), "{a: 1, b: 2}")]
+#[case(Value::from(IndexMap::from([(2, vec![true]), (1, vec![])])), "{2: [true], 1: []}")]
+#[case(Value::from(HashMap::from([(true, "yes")])), "{true: yes}")]
+fn test_into_value(#[case] value: Value, #[case] expected: &str) {
+    assert_eq!(value.inspect(), expected);
+}
+
+#[test]
+fn test_into_value_equals_evaluated() {
+    let value = Value::from(BTreeMap::from([
+        ("name".to_owned(), Value::from("monkey")),
+        ("tags".to_owned(), Value::from(vec![1, 2])),
+    ]));
+    assert_eq!(
+        &value,
+        test_eval("{\"name\": \"monkey\", \"tags\": [1, 2]}".to_owned()).as_ref()
+    );
+}
+
+#[test]
+fn test_try_from_value() {
+    assert_eq!(i64::try_from(&Value::from(7)), Ok(7));
+    assert_eq!(bool::try_from(&Value::from(false)), Ok(false));
+    assert_eq!(String::try_from(&Value::from("x")), Ok("x".to_owned()));
+    assert_eq!(<()>::try_from(&Value::Null(Null)), Ok(()));
+    assert_eq!(Option::<i64>::try_from(&Value::Null(Null)), Ok(None));
+    assert_eq!(Option::<i64>::try_from(&Value::from(3)), Ok(Some(3)));
+
+    let array = test_eval("[[1, 2], [3]]".to_owned());
+    assert_eq!(
+        Vec::<Vec<i64>>::try_from(array.as_ref()),
+        Ok(vec![vec![1, 2], vec![3]])
+    );
+    let hash = test_eval("{\"b\": [true], \"a\": []}".to_owned());
+    assert_eq!(
+        BTreeMap::<String, Vec<bool>>::try_from(hash.as_ref()),
+        Ok(BTreeMap::from([
+            ("a".to_owned(), vec![]),
+            ("b".to_owned(), vec![true])
+        ]))
+    );
+    let hash = IndexMap::<String, Vec<bool>>::try_from(hash.as_ref()).unwrap();
+    assert_eq!(hash.keys().collect::<Vec<_>>(), ["b", "a"]);
+    assert_eq!(
+        HashMap::<i64, String>::try_from(test_eval("{1: \"one\"}".to_owned()).as_ref()),
+        Ok(HashMap::from([(1, "one".to_owned())]))
+    );
+}
+
+#[rstest]
+#[case(i64::try_from(&Value::from("1")).unwrap_err(), "Integer", ObjectType::String)]
+#[case(String::try_from(&Value::from(1)).unwrap_err(), "String", ObjectType::Integer)]
+#[case(Vec::<i64>::try_from(&Value::from(1)).unwrap_err(), "Array", ObjectType::Integer)]
+#[case(Vec::<i64>::try_from(&Value::from(vec![Value::from(1), Value::from(true)])).unwrap_err(), "Integer", ObjectType::Boolean)]
+#[case(HashMap::<String, i64>::try_from(&Value::from(vec![1])).unwrap_err(), "Hash", ObjectType::Array)]
+#[case(HashMap::<String, i64>::try_from(&Value::from(BTreeMap::from([(1, 1)]))).unwrap_err(), "String", ObjectType::Integer)]
+fn test_try_from_value_errors(
+    #[case] error: ConversionError,
+    #[case] expected: &'static str,
+    #[case] got: ObjectType,
+) {
+    assert_eq!(
+        error,
+        ConversionError {
+            expected,
+            got: got.clone()
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        format!("expected {}, got {}", expected, got)
+    );
+}
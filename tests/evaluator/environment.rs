@@ -0,0 +1,114 @@
+use std::fs;
+
+use implement_parser::evaluator::environment::Environment;
+use implement_parser::evaluator::eval::eval;
+use implement_parser::evaluator::object::{Integer, StringObject, Value};
+use implement_parser::stdlib::load_prelude;
+use implement_parser::sync::{Rc, RefCell};
+
+use super::eval::parse_program_from;
+
+fn run(input: &str, env: &Rc<RefCell<Environment>>) -> Rc<Value> {
+    eval(&parse_program_from(input.to_owned()), Rc::clone(env))
+}
+
+#[test]
+fn test_save_and_load() {
+    let path = std::env::temp_dir().join(format!("monkey-env-{}.mky", std::process::id()));
+    let env = Rc::new(RefCell::new(Environment::new()));
+    run(
+        r#"let n = 1; let s = "名字"; let data = [true, {"a": [1, 2]}, if (false) { 1 }, bytes_from_hex("ff")];
+        let scale = 10; let f = fn(x) { x * scale }; let make = fn() { fn() { 1 } }; let inner = make();
+        let l = len;"#,
+        &env,
+    );
+    let mut scope = env.borrow_mut();
+    scope.set(
+        Rc::from("not an identifier"),
+        Rc::new(Value::Integer(Integer { value: 1 })),
+    );
+    // 词法分析器不支持转义，带双引号的字符串写不成源码
+    scope.set(
+        Rc::from("quoted"),
+        Rc::new(Value::String(StringObject {
+            value: "say \"hi\"".to_owned(),
+        })),
+    );
+    drop(scope);
+    let skipped = Environment::save(&env, &path).unwrap();
+    assert_eq!(
+        skipped.iter().map(|name| name.as_ref()).collect::<Vec<_>>(),
+        ["inner", "l", "not an identifier", "quoted"]
+    );
+    let saved = fs::read_to_string(&path).unwrap();
+    assert!(saved.starts_with("let data = [true, {\"a\": [1, 2]}, "));
+    assert!(saved.contains("let n = 1;\nlet s = \"名字\";\n"));
+
+    let loaded = Rc::new(RefCell::new(Environment::new()));
+    Environment::load(&loaded, &path).unwrap();
+    fs::remove_file(&path).unwrap();
+    for (input, expected) in [
+        ("n", "1"),
+        ("s", "名字"),
+        ("data", "[true, {a: [1, 2]}, null, bytes_from_hex(\"ff\")]"),
+        // 函数从源码重新求值，捕获的是加载它的环境
+        ("f(2)", "20"),
+        ("make()()", "1"),
+    ] {
+        assert_eq!(run(input, &loaded).inspect(), expected);
+    }
+    assert!(loaded.borrow().get("inner").is_none());
+}
+
+// i64::MIN 的绝对值写不成整数字面量，保存之后也要能加载回来
+#[test]
+fn test_save_and_load_integer_bounds() {
+    let path = std::env::temp_dir().join(format!("monkey-env-bounds-{}.mky", std::process::id()));
+    let env = Rc::new(RefCell::new(Environment::new()));
+    for (name, value) in [("min", i64::MIN), ("max", i64::MAX)] {
+        env.borrow_mut()
+            .set(Rc::from(name), Rc::new(Value::Integer(Integer { value })));
+    }
+    run("let both = [min, max];", &env);
+    assert!(Environment::save(&env, &path).unwrap().is_empty());
+
+    let loaded = Rc::new(RefCell::new(Environment::new()));
+    Environment::load(&loaded, &path).unwrap();
+    fs::remove_file(&path).unwrap();
+    for name in ["min", "max", "both"] {
+        assert_eq!(loaded.borrow().get(name), env.borrow().get(name));
+    }
+}
+
+#[test]
+fn test_save_skips_prelude() {
+    let path = std::env::temp_dir().join(format!("monkey-env-prelude-{}.mky", std::process::id()));
+    let env = Rc::new(RefCell::new(Environment::new()));
+    load_prelude(&env);
+    run("let x = 1;", &env);
+    assert!(Environment::save(&env, &path).unwrap().is_empty());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "let x = 1;\n");
+
+    // 重新绑定了的标准库名字属于用户
+    run("let sum = 2;", &env);
+    Environment::save(&env, &path).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "let sum = 2;\nlet x = 1;\n"
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_load_errors() {
+    let path = std::env::temp_dir().join(format!("monkey-env-bad-{}.mky", std::process::id()));
+    let env = Rc::new(RefCell::new(Environment::new()));
+    fs::write(&path, "let = 1;").unwrap();
+    let error = Environment::load(&env, &path).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    fs::write(&path, "let a = 1; missing").unwrap();
+    let error = Environment::load(&env, &path).unwrap_err();
+    assert_eq!(error.to_string(), "identifier not found: missing");
+    fs::remove_file(&path).unwrap();
+    assert!(Environment::load(&env, &path).is_err());
+}
@@ -1,17 +1,17 @@
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::rc::Rc;
 
 use implement_parser::ast::program::Program;
 use implement_parser::ast::traits::Node;
+use implement_parser::errors::EvalError;
 use implement_parser::evaluator::environment::Environment;
-use implement_parser::evaluator::eval::eval;
+use implement_parser::evaluator::eval::{eval, eval_expression_in, eval_node};
 use implement_parser::evaluator::object::{
-    self, Array, Boolean, Error, Function, HashKey, Hashable, Integer, Null, Object, ObjectType,
-    StringObject,
+    self, Array, Boolean, Error, Function, HashKey, Hashable, Integer, Null, ObjectType,
+    StringObject, Value,
 };
 use implement_parser::lexer::Lexer;
 use implement_parser::parser::Parser;
+use implement_parser::sync::{Rc, RefCell};
 use rstest::rstest;
 
 // TODO：我本来想要把 parser 里面的 helpers 拿出来放在 tests 目录下，然后在这里引用。但试了下好像引用不到。目前我感觉好像 tests 中的各个文件都是一个单独的 crate 不能交叉引用。但不是很确定
@@ -25,7 +25,7 @@ pub fn parse_program_from(input: String) -> Program {
     program
 }
 
-pub fn test_eval(input: String) -> Box<dyn Object> {
+pub fn test_eval(input: String) -> Rc<Value> {
     let program = parse_program_from(input);
     let env = Environment::new();
     eval(&program, Rc::new(RefCell::new(env)))
@@ -73,12 +73,34 @@ fn test_eval_integer_expression(#[case] input: String, #[case] expected: i64) {
 #[case::infix("(1 < 2) == false".to_owned(), false)]
 #[case::infix("(1 > 2) == true".to_owned(), false)]
 #[case::infix("(1 > 2) == false".to_owned(), true)]
+#[case::equality(r#""a" + "b" == "ab""#.to_owned(), true)]
+#[case::equality(r#""a" != "b""#.to_owned(), true)]
+#[case::equality("[1, [2, 3]] == [1, [2, 1 + 2]]".to_owned(), true)]
+#[case::equality("[1, 2] == [2, 1]".to_owned(), false)]
+#[case::equality(r#"{"a": 1, "b": [2]} == {"b": [2], "a": 1}"#.to_owned(), true)]
+#[case::equality(r#"{"a": 1} != {"a": 2}"#.to_owned(), true)]
+#[case::equality("freeze([1]) == [1]".to_owned(), true)]
+#[case::equality("if (false) { 1 } == if (false) { 2 }".to_owned(), true)]
+#[case::equality("let f = fn(x) { x }; f == f".to_owned(), true)]
+#[case::equality("fn(x) { x } == fn(x) { x }".to_owned(), false)]
+#[case::equality("len == len".to_owned(), true)]
 fn test_eval_boolean_expression(#[case] input: String, #[case] expected: bool) {
     let object = test_eval(input);
     let boolean = object.downcast_ref::<Boolean>().unwrap();
     assert_eq!(boolean.value(), expected);
 }
 
+// 身份跟着值走，复制出来的函数和宏仍然和原来的相等，同一个字面量再求值一次就不相等
+#[rstest]
+#[case("fn(x) { x }")]
+#[case("macro(x) { x }")]
+fn test_function_identity_survives_clone(#[case] input: &str) {
+    let value = test_eval(input.to_owned());
+    let copy = Box::new(value.as_ref().clone());
+    assert_eq!(*copy, *value);
+    assert_ne!(*test_eval(input.to_owned()), *value);
+}
+
 #[rstest]
 #[case("if (true) { 10 }".to_owned(), Some(10))]
 #[case("if (false) { 10 }".to_owned(), None)]
@@ -127,6 +149,7 @@ fn test_return_statement(#[case] input: String, #[case] expected: i64) {
 #[case("let a = 5 * 5; a;".to_owned(), 25)]
 #[case("let a = 5; let b = a; b;".to_owned(), 5)]
 #[case("let a = 5; let b = a; let c = a + b + 5; c;".to_owned(), 15)]
+#[case("let a: string = 5; a;".to_owned(), 5)]
 fn test_let_statements(#[case] input: String, #[case] expected: i64) {
     let object = test_eval(input);
     let integer = object.downcast_ref::<Integer>().unwrap();
@@ -143,6 +166,11 @@ fn test_let_statements(#[case] input: String, #[case] expected: i64) {
 #[case("if (10 > 1) { if (10 > 1) { return true + false; } return 1; }".to_owned(), "unknown operator: Boolean + Boolean".to_owned())]
 #[case("foobar".to_owned(), "identifier not found: foobar".to_owned())]
 #[case("\"Hello\" - \"World!\"".to_owned(), "unknown operator: String - String".to_owned())]
+#[case("[1] == 1".to_owned(), "type mismatch: Array == Integer".to_owned())]
+#[case("fn(x, y) { x + y }(1)".to_owned(), "wrong number of arguments: got=1, want=2".to_owned())]
+#[case("let f = fn() { 1 }; f(1, 2)".to_owned(), "wrong number of arguments: got=2, want=0".to_owned())]
+#[case("unquote(1)".to_owned(), "`unquote` can only be used inside `quote`".to_owned())]
+#[case("unquote_splice([1])".to_owned(), "`unquote_splice` can only be used inside `quote`".to_owned())]
 fn test_error_handling(#[case] input: String, #[case] expected_message: String) {
     let object = test_eval(input);
     let error = object.downcast_ref::<Error>().unwrap();
@@ -157,6 +185,11 @@ fn test_function_object() {
     assert_eq!(function.parameters.len(), 1);
     assert_eq!(function.parameters[0].string(), "x");
     assert_eq!(function.body.string(), "(x + 2)");
+
+    // Debug 不展开闭包环境
+    let debug = format!("{:?}", function);
+    assert!(debug.starts_with("Function { parameters: [Identifier {"));
+    assert!(debug.ends_with(", .. }"));
 }
 
 #[rstest]
@@ -166,6 +199,7 @@ fn test_function_object() {
 #[case("let add = fn(x, y) { x + y; }; add(5, 5);".to_owned(), 10)]
 #[case("let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));".to_owned(), 20)]
 #[case("fn(x) { x; }(5)".to_owned(), 5)]
+#[case("let f = fn(x) { return x; }; f(1) + f(2);".to_owned(), 3)]
 fn test_function_application(#[case] input: String, #[case] expected: i64) {
     let evaluated = test_eval(input);
     let integer = evaluated.downcast_ref::<Integer>().unwrap();
@@ -187,6 +221,31 @@ fn test_closures() {
     assert_eq!(integer.value, 4);
 }
 
+// 闭包离开定义它的函数之后，仍然能访问整条环境链上的绑定
+#[rstest]
+#[case(
+    "let adder = fn(a) { fn(b) { fn(c) { a + b + c } } }; adder(1)(2)(3)",
+    6
+)]
+#[case(
+    "let make = fn() { let step = 5; let next = fn(n) { n + step }; next }; make()(1)",
+    6
+)]
+#[case(
+    "let make = fn() { let down = fn(n) { if (n == 0) { 0 } else { down(n - 1) + 1 } }; down }; make()(5)",
+    5
+)]
+#[case("let f = fn(n) { let g = fn(x) { x * n }; g(2) }; f(3) + f(4)", 14)]
+#[case("let make = fn() { let k = 10; [fn() { k }] }; make()[0]()", 10)]
+#[case(
+    "let make = fn() { let k = 7; let h = {\"get\": fn() { k }}; h }; make()[\"get\"]()",
+    7
+)]
+fn test_escaping_closures(#[case] input: &str, #[case] expected: i64) {
+    let evaluated = test_eval(input.to_owned());
+    assert_eq!(evaluated.inspect(), expected.to_string());
+}
+
 #[test]
 fn test_string_literal() {
     let input = "\"Hello World!".to_owned();
@@ -209,6 +268,7 @@ fn test_string_concatenation() {
 #[case(r#"len("hello world")"#.to_owned(), "11".to_owned())]
 #[case(r#"len(1)"#.to_owned(), "argument to `len` not supported, got Integer".to_owned())]
 #[case(r#"len("one", "one")"#.to_owned(), "wrong number of arguments: got=2, want=1".to_owned())]
+#[case(r#"rest(1)"#.to_owned(), "argument to `rest` must be Array, got Integer".to_owned())]
 fn test_builtin_functions(#[case] input: String, #[case] expected: String) {
     let evaluated = test_eval(input);
     match evaluated.object_type() {
@@ -275,8 +335,8 @@ fn test_hash_literals() {
         }
         "#
     .to_owned();
-    let mut evaluated = test_eval(input);
-    let hash = evaluated.downcast_mut::<object::Hash>().unwrap();
+    let evaluated = test_eval(input);
+    let hash = evaluated.downcast_ref::<object::Hash>().unwrap();
     let expected: HashMap<HashKey, i64> = HashMap::from([
         (
             StringObject {
@@ -305,12 +365,21 @@ fn test_hash_literals() {
     ]);
     assert_eq!(hash.pairs.len(), expected.len());
     for (expected_key, expected_value) in expected {
-        let pair = hash.pairs.remove(&expected_key).unwrap();
+        let pair = hash.pairs.get(&expected_key).unwrap();
         let integer = pair.value.downcast_ref::<Integer>().unwrap();
         assert_eq!(integer.value, expected_value);
     }
 }
 
+#[rstest]
+#[case(r#"{"b": 1, "a": 2, "c": 3}"#, "{b: 1, a: 2, c: 3}")]
+#[case(r#"{3: "x", true: "y", "1": "z"}"#, "{3: x, true: y, 1: z}")]
+#[case(r#"{"a": 1, "b": 2, "a": 3}"#, "{a: 3, b: 2}")]
+#[case(r#"freeze({"z": [1], "y": {"x": 0}})"#, "{z: [1], y: {x: 0}}")]
+fn test_hash_preserves_insertion_order(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(test_eval(input.to_owned()).inspect(), expected);
+}
+
 #[rstest]
 #[case(r#"{"foo": 5}["foo"]"#.to_owned(), Some(5))]
 #[case(r#"{"foo": 5}["bar"]"#.to_owned(), None)]
@@ -328,3 +397,184 @@ fn test_hash_index_expression(#[case] input: String, #[case] expected: Option<i6
         assert!(evaluated.downcast_ref::<object::Null>().is_some());
     }
 }
+
+#[rstest]
+#[case("{[1, 2]: \"x\"}[[1, 2]]", "x")]
+#[case("{[1, [\"a\", true]]: 1}[[1, [\"a\", true]]]", "1")]
+#[case("let key = [1, 2]; {key: 5}[push([1], 2)]", "5")]
+#[case("{freeze([1]): 1}[[1]]", "1")]
+#[case("{[]: 1}[[]]", "1")]
+#[case("{[1, 2]: 1}[[2, 1]]", "null")]
+#[case("{[1]: 1}[1]", "null")]
+#[case("{[1]: 1, [1]: 2}", "{[1]: 2}")]
+#[case("{[{}]: 1}", "Error: unusable as hash key: Array at line 1, col 1")]
+#[case("{}[[fn() {}]]", "Error: unusable as hash key: Array at line 1, col 3")]
+fn test_array_hash_keys(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(test_eval(input.to_owned()).inspect(), expected);
+}
+
+#[rstest]
+#[case("{-1: \"a\", 1: \"b\"}[-1]", "a")]
+#[case("{-9223372036854775807 - 1: \"min\"}[-9223372036854775807 - 1]", "min")]
+#[case(
+    "{1: \"i\", true: \"b\", \"1\": \"s\", [1]: \"a\"}",
+    "{1: i, true: b, 1: s, [1]: a}"
+)]
+#[case("{0: \"i\", false: \"b\", \"\": \"s\", []: \"a\"}[false]", "b")]
+#[case("{[\"1\"]: 1}[[1]]", "null")]
+fn test_hash_keys_do_not_collide(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(test_eval(input.to_owned()).inspect(), expected);
+}
+
+#[test]
+fn test_hash_key_keeps_the_value() {
+    assert_eq!(Integer { value: -1 }.hash_key(), HashKey::Integer(-1));
+    assert_ne!(
+        Integer { value: -1 }.hash_key(),
+        Integer { value: i64::MAX }.hash_key()
+    );
+    assert_ne!(Integer { value: 1 }.hash_key(), Boolean::True.hash_key());
+    // 字符串的键就是字符串本身，不会因为散列值相同而冲突
+    let key = StringObject {
+        value: "1".to_owned(),
+    }
+    .hash_key();
+    assert_eq!(key, HashKey::String("1".to_owned()));
+    let array = Array {
+        elements: vec![Rc::new(Value::Integer(Integer { value: 1 }))],
+        frozen: false,
+    };
+    assert_eq!(
+        array.hash_key(),
+        Some(HashKey::Array(vec![HashKey::Integer(1)]))
+    );
+}
+
+#[rstest]
+#[case(r#"eval("1 + 2")"#.to_owned(), 3)]
+#[case(r#"let x = 10; eval("x * 2")"#.to_owned(), 20)]
+#[case(r#"eval("let y = 5;"); y"#.to_owned(), 5)]
+#[case(r#"let f = fn(x) { eval("x + 1") }; f(41)"#.to_owned(), 42)]
+#[case(r#"let y = 1; eval("let y = 2; y", true) + y"#.to_owned(), 3)]
+#[case(r#"let eval = fn(x) { 7 }; eval("1")"#.to_owned(), 7)]
+fn test_eval_builtin(#[case] input: String, #[case] expected: i64) {
+    let evaluated = test_eval(input);
+    assert_eq!(evaluated.downcast_ref::<Integer>().unwrap().value, expected);
+}
+
+#[rstest]
+#[case(
+    r#"eval("1 +")"#.to_owned(),
+    "failed to parse source passed to `eval`: No prefix parse function for EOF found"
+)]
+#[case(r#"eval("let z = 1;", true); z"#.to_owned(), "identifier not found: z")]
+#[case("eval(1)".to_owned(), "argument to `eval` must be String, got Integer")]
+fn test_eval_builtin_errors(#[case] input: String, #[case] expected_message: &str) {
+    let evaluated = test_eval(input);
+    assert_eq!(
+        evaluated.downcast_ref::<Error>().unwrap().message,
+        expected_message
+    );
+}
+
+#[rstest]
+#[case("x + y", Ok("30"))]
+#[case("add(x, 1)", Ok("11"))]
+#[case("fn(a) { let x = a; x }(5)", Ok("5"))]
+#[case("let x = 1", Err("expected a single expression, got `let x = 1`"))]
+#[case("x; y", Err("expected a single expression, got `x; y`"))]
+#[case(
+    "x +",
+    Err("failed to parse expression: No prefix parse function for EOF found")
+)]
+#[case("missing", Err("identifier not found: missing"))]
+fn test_eval_expression_in(#[case] input: &str, #[case] expected: Result<&str, &str>) {
+    let env = Rc::new(RefCell::new(Environment::new()));
+    eval(
+        &parse_program_from("let x = 10; let y = 20; let add = fn(a, b) { a + b };".to_owned()),
+        Rc::clone(&env),
+    );
+
+    let evaluated = eval_expression_in(input, &env);
+    match expected {
+        Ok(value) => assert_eq!(evaluated.inspect(), value),
+        Err(message) => assert_eq!(evaluated.downcast_ref::<Error>().unwrap().message, message),
+    }
+    // 不会留下新的绑定，也不会改动已有的绑定
+    assert!(env.borrow().get("a").is_none());
+    assert_eq!(env.borrow().get("x").unwrap().inspect(), "10");
+}
+
+#[rstest]
+#[case("5 + true; 10", Err("type mismatch: Integer + Boolean"))]
+#[case("if (10 > 1) { return foobar; }", Err("identifier not found: foobar"))]
+#[case("let f = fn() { return 7; }; f() + 1", Ok("8"))]
+fn test_eval_node_result(#[case] input: &str, #[case] expected: Result<&str, &str>) {
+    let program = parse_program_from(input.to_owned());
+    let evaluated = eval_node(&program, Rc::new(RefCell::new(Environment::new())));
+    match (evaluated, expected) {
+        (Ok(value), Ok(expected)) => assert_eq!(value.inspect(), expected),
+        (Err(EvalError::Runtime(error)), Err(expected)) => assert_eq!(error.message, expected),
+        _ => panic!("unexpected result for {}", input),
+    }
+}
+
+#[test]
+fn test_eval_error_round_trip() {
+    let Err(error) = EvalError::from_object(test_eval("exit(3)".to_owned())) else {
+        panic!("exit should become an EvalError");
+    };
+    assert!(matches!(error, EvalError::Exit(3)));
+    let object = error.into_object();
+    assert_eq!(object.object_type(), ObjectType::Exit);
+
+    let error = EvalError::internal("broken");
+    assert_eq!(error.into_object().object_type(), ObjectType::Error);
+    assert!(EvalError::from_object(Rc::new(Value::Null(Null))).is_ok());
+}
+
+#[rstest]
+#[case("5 + true;", 1, 3, "5 + true")]
+#[case("let a = 1;\nlet b = a;\n  -true;", 3, 3, "-true")]
+#[case("let f = fn(x) {\n  x + missing\n};\nf(1);", 2, 7, "missing")]
+#[case("len(1)", 1, 4, "len(1)")]
+fn test_error_location(
+    #[case] input: &str,
+    #[case] line: usize,
+    #[case] column: usize,
+    #[case] snippet: &str,
+) {
+    let program = parse_program_from(input.to_owned());
+    let evaluated = eval(&program, Rc::new(RefCell::new(Environment::new())));
+    let error = evaluated.downcast_ref::<Error>().unwrap();
+    assert_eq!((error.line, error.column), (line, column));
+    assert_eq!(program.source_slice(error.span), Some(snippet));
+    assert_eq!(
+        evaluated.inspect(),
+        format!("Error: {} at line {}, col {}", error.message, line, column)
+    );
+}
+
+#[test]
+fn test_values_are_shared() {
+    let env = Rc::new(RefCell::new(Environment::new()));
+    let program = parse_program_from("let a = [1, [2, 3]]; let b = a;".to_owned());
+    eval(&program, Rc::clone(&env));
+
+    // 读取变量和赋值都不会复制数组
+    let a = env.borrow().get("a").unwrap();
+    let b = env.borrow().get("b").unwrap();
+    assert!(Rc::ptr_eq(&a, &b));
+
+    let program = parse_program_from("let c = a[1]; let d = push(a, 4);".to_owned());
+    eval(&program, Rc::clone(&env));
+    let c = env.borrow().get("c").unwrap();
+    let d = env.borrow().get("d").unwrap();
+    let nested = &a.downcast_ref::<Array>().unwrap().elements[1];
+    assert!(Rc::ptr_eq(nested, &c));
+    assert!(Rc::ptr_eq(
+        nested,
+        &d.downcast_ref::<Array>().unwrap().elements[1]
+    ));
+    assert_eq!(a.inspect(), "[1, [2, 3]]");
+}
@@ -0,0 +1,102 @@
+use std::io;
+
+use implement_parser::errors::ParseError;
+use implement_parser::evaluator::context::{Limits, Rng, RuntimeContext};
+use implement_parser::evaluator::environment::Environment;
+use implement_parser::evaluator::eval::eval;
+use implement_parser::evaluator::macro_expansion::{define_macros, expand_macro};
+use implement_parser::evaluator::object::{Error, Value};
+use implement_parser::lexer::{Lexer, Span};
+use implement_parser::parser::Parser;
+use implement_parser::sync::{Rc, RefCell};
+use rstest::rstest;
+
+// 随机拼接的词法单元，包括非 ASCII 字符、没有闭合的字符串和注释
+const TOKENS: &str = r#"let fn if else return macro true false x y f len first rest push quote
+    unquote 0 1 -1 9223372036854775807 "s" "名字" = + - * / ! < > == != , ; : ( ) { } [ ] é @ " // 名"#;
+
+// 完整地走一遍解析、宏展开和求值，只关心过程中不会 panic
+// 有语法错误时解析出来的部分照样展开和求值，返回第一个语法错误
+fn run(source: String) -> Rc<Value> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let mut program = parser.parse_program();
+    let macro_env = Rc::new(RefCell::new(Environment::new()));
+    define_macros(&mut program, Rc::clone(&macro_env));
+    if let Err(error) = expand_macro(&mut program, macro_env) {
+        return Rc::new(Value::Error(error));
+    }
+
+    let context = RuntimeContext::new()
+        .with_output(Box::new(io::sink()))
+        .with_input(Box::new(io::empty()))
+        .with_limits(Limits {
+            max_steps: Some(500),
+            sandbox: true,
+            // 没有终止条件的递归尽早停下
+            max_depth: 50,
+            ..Limits::default()
+        });
+    let env = Environment::with_context(Rc::new(RefCell::new(context)));
+    let evaluated = eval(&program, Rc::new(RefCell::new(env)));
+    match parser.errors().first() {
+        Some(error) => Rc::new(Value::Error(runtime_error_from(error))),
+        None => evaluated,
+    }
+}
+
+fn runtime_error_from(error: &ParseError) -> Error {
+    Error {
+        message: error.message.clone(),
+        span: Span::default(),
+        line: error.line,
+        column: error.column,
+    }
+}
+
+#[test]
+fn test_random_token_soup_never_panics() {
+    let tokens = TOKENS.split_whitespace().chain(["\n"]).collect::<Vec<_>>();
+    let mut rng = Rng::new(1084);
+    for _ in 0..2000 {
+        let length = rng.next_below(40) as usize;
+        let source = (0..length)
+            .map(|_| tokens[rng.next_below(tokens.len() as u64) as usize])
+            .collect::<Vec<_>>()
+            .join(" ");
+        run(source);
+    }
+}
+
+#[rstest]
+#[case("1 / 0", "division by zero: 1 / 0")]
+#[case("9223372036854775807 + 1", "integer overflow: 9223372036854775807 + 1")]
+#[case(
+    "-9223372036854775807 - 2",
+    "integer overflow: -9223372036854775807 - 2"
+)]
+#[case(
+    "let m = -9223372036854775807 - 1; -m",
+    "integer overflow: -(-9223372036854775808)"
+)]
+#[case(
+    "let f = fn(n) { f(n + 1) }; f(0)",
+    "maximum recursion depth exceeded: 50 nested calls"
+)]
+#[case(
+    &format!("{}1{}", "(".repeat(20000), ")".repeat(20000)),
+    "expression nested more than 256 levels deep"
+)]
+#[case("quote(unquote(len))", "unquote does not support Builtin")]
+#[case("quote(unquote(missing))", "identifier not found: missing")]
+fn test_former_panics_are_errors(#[case] input: &str, #[case] expected: &str) {
+    let evaluated = run(input.to_owned());
+    assert_eq!(evaluated.downcast_ref::<Error>().unwrap().message, expected);
+}
+
+#[rstest]
+#[case(r#"let s = "名字"; s"#, "名字")]
+#[case(r#"quote(unquote("a") + 1)"#, "QUOTE((a + 1))")]
+#[case("let m = macro(a) { a }; m(1, 2)", "1")]
+fn test_former_panics_evaluate(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(run(input.to_owned()).inspect(), expected);
+}
@@ -0,0 +1,86 @@
+use implement_parser::evaluator::object::Value;
+use implement_parser::interpreter::Interpreter;
+use implement_parser::sync::Rc;
+
+const MAKE: &str = "let make = fn(x) { let g = fn() { x }; g };";
+
+fn global(interpreter: &Interpreter, name: &str) -> Rc<Value> {
+    interpreter.get_global(name).unwrap()
+}
+
+#[test]
+fn test_collect_closure_cycles() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str(MAKE).unwrap();
+    interpreter.eval_str("let h = make(5);").unwrap();
+    let env = match global(&interpreter, "h").as_ref() {
+        Value::Function(function) => Rc::downgrade(&function.env),
+        other => panic!("not a function: {}", other.inspect()),
+    };
+    // 还在用的闭包不会被回收
+    assert_eq!(interpreter.collect_garbage(), 0);
+    assert_eq!(interpreter.eval_str("h()").unwrap().inspect(), "5");
+    // 闭包逃出了调用，release 清不掉 g 和调用环境之间的环
+    interpreter.eval_str("let h = 0;").unwrap();
+    assert!(env.upgrade().is_some());
+    assert_eq!(interpreter.collect_garbage(), 1);
+    assert!(env.upgrade().is_none());
+}
+
+#[test]
+fn test_host_references_keep_closures() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str(MAKE).unwrap();
+    interpreter.eval_str("let h = make(5);").unwrap();
+    let h = global(&interpreter, "h");
+    interpreter.eval_str("let h = 0;").unwrap();
+    assert_eq!(interpreter.collect_garbage(), 0);
+    let Value::Function(function) = h.as_ref() else {
+        panic!("not a function: {}", h.inspect());
+    };
+    assert_eq!(
+        function.env.borrow().get("x").map(|value| value.inspect()),
+        Some("5".to_owned())
+    );
+}
+
+#[test]
+fn test_automatic_collection() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str(MAKE).unwrap();
+    interpreter
+        .eval_str("let run = fn(i) { make(i); i }; let kept = make(0); map(range(0, 20), fn(_) { map(range(0, 60), run) });")
+        .unwrap();
+    let context = interpreter.environment().borrow().context();
+    let (tracked, collected) = {
+        let heap = &context.borrow().heap;
+        (heap.len(), heap.collected())
+    };
+    assert!(tracked < 1000, "{} environments still tracked", tracked);
+    assert!(
+        collected >= 900,
+        "only {} environments collected",
+        collected
+    );
+    assert_eq!(interpreter.eval_str("kept()").unwrap().inspect(), "0");
+}
+
+#[test]
+fn test_drop_interpreter() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str("let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }; f(3)")
+        .unwrap();
+    let env = Rc::downgrade(&interpreter.environment());
+    drop(interpreter);
+    assert!(env.upgrade().is_none());
+
+    // 宿主还拿着根环境里的函数时根环境要留着
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str("let f = fn(n) { n * 2 };").unwrap();
+    let f = interpreter.get_global("f").unwrap();
+    let env = Rc::downgrade(&interpreter.environment());
+    drop(interpreter);
+    assert!(env.upgrade().is_some());
+    drop(f);
+}
@@ -1,8 +1,8 @@
-use std::{cell::RefCell, rc::Rc};
-
+use implement_parser::sync::{Rc, RefCell};
 use implement_parser::{
     ast::traits::Node,
     evaluator::{
+        context::{EvalOptions, Limits, RuntimeContext},
         environment::Environment,
         macro_expansion::{define_macros, expand_macro},
         object::Macro,
@@ -48,11 +48,130 @@ if (!(unquote(condition))) {
     unquote(alternative);
 });
 }; unless(10 > 5, puts("not greater"), puts("greater")); "#.to_owned(), r#"if (!(10 > 5)) { puts("not greater") } else { puts("greater") }"#.to_owned())]
+#[case::splice(r#"let apply = macro(f, args) { quote(unquote(f)(unquote_splice(args))); }; apply(add, [1, 2 * 3]);"#.to_owned(), "add(1, 2 * 3)".to_owned())]
 fn test_expand_macro(#[case] input: String, #[case] expected: String) {
     let expected = parse_program_from(expected);
     let mut program = parse_program_from(input);
     let env = Rc::new(RefCell::new(Environment::new()));
     define_macros(&mut program, Rc::clone(&env));
-    let expanded = expand_macro(&mut program, Rc::clone(&env));
-    assert_eq!(expanded.string(), expected.string());
+    expand_macro(&mut program, Rc::clone(&env)).unwrap();
+    assert_eq!(program.string(), expected.string());
+}
+
+const OR_MACRO: &str = "let or = macro(a, b) { quote(fn(tmp) { if (tmp) { tmp } else { unquote(b) } }(unquote(a))); };";
+
+// 打开 hygiene 后宏引入的 tmp 不会遮住调用处的 tmp
+#[rstest]
+#[case(false, "or(false, tmp)", "fn(tmp) if tmp tmpelse tmp(false)")]
+#[case(true, "or(false, tmp)", "fn(tmp_1) if tmp_1 tmp_1else tmp(false)")]
+#[case(
+    true,
+    "or(false, tmp); or(true, 1)",
+    "fn(tmp_1) if tmp_1 tmp_1else tmp(false)fn(tmp_2) if tmp_2 tmp_2else 1(true)"
+)]
+fn test_hygienic_expansion(#[case] hygiene: bool, #[case] input: &str, #[case] expected: &str) {
+    let mut program = parse_program_from(format!("{} {}", OR_MACRO, input));
+    let context = RuntimeContext::new().with_options(EvalOptions {
+        hygiene,
+        ..EvalOptions::default()
+    });
+    let env = Rc::new(RefCell::new(Environment::with_context(Rc::new(
+        RefCell::new(context),
+    ))));
+    define_macros(&mut program, Rc::clone(&env));
+    expand_macro(&mut program, Rc::clone(&env)).unwrap();
+    assert_eq!(program.string(), expected);
+}
+
+#[rstest]
+#[case(
+    "let twice = macro(x) { quote(fn() { let y = unquote(x); y + y }()) }; let y = 2; twice(y)",
+    "let y = 2;fn() let y_1 = y;(y_1 + y_1)()"
+)]
+#[case(
+    "let m = macro(x) { let body = quote(v * unquote(x)); quote(fn(v) { unquote(body) }) }; m(v)",
+    "fn(v_1) (v_1 * v)"
+)]
+fn test_hygiene_renames_bindings_introduced_by_the_macro(
+    #[case] input: &str,
+    #[case] expected: &str,
+) {
+    let mut program = parse_program_from(input.to_owned());
+    let context = RuntimeContext::new().with_options(EvalOptions {
+        hygiene: true,
+        ..EvalOptions::default()
+    });
+    let env = Rc::new(RefCell::new(Environment::with_context(Rc::new(
+        RefCell::new(context),
+    ))));
+    define_macros(&mut program, Rc::clone(&env));
+    expand_macro(&mut program, Rc::clone(&env)).unwrap();
+    assert_eq!(program.string(), expected);
+}
+
+// 代码块里定义的宏只在这个代码块里展开，定义之前的调用也能展开
+#[rstest]
+#[case(
+    "let f = fn(x) { let double = macro(a) { quote(unquote(a) * 2) }; double(x) }; f(3)",
+    "let f = fn(x) (x * 2);f(3)"
+)]
+#[case("fn() { m(); let m = macro() { quote(1) }; }", "fn() 1")]
+#[case("if (true) { let m = macro() { quote(1) }; m() }; m()", "if true 1m()")]
+#[case(
+    "let m = macro() { quote(1) }; fn() { let m = macro() { quote(2) }; m() }; m()",
+    "fn() 21"
+)]
+#[case(
+    "let inc = macro(x) { quote(unquote(x) + 1) }; puts(inc(1), [inc(2)])",
+    "puts((1 + 1), [(2 + 1)])"
+)]
+fn test_scoped_macros(#[case] input: &str, #[case] expected: &str) {
+    let mut program = parse_program_from(input.to_owned());
+    let env = Rc::new(RefCell::new(Environment::new()));
+    define_macros(&mut program, Rc::clone(&env));
+    expand_macro(&mut program, Rc::clone(&env)).unwrap();
+    assert_eq!(program.string(), expected);
+    // 代码块里的宏不会留在外层环境里
+    assert!(env.borrow().get("double").is_none());
+}
+
+// 展开的结果里的宏调用继续展开
+#[test]
+fn test_nested_expansion() {
+    let mut program = parse_program_from(
+        "let inc = macro(x) { quote(unquote(x) + 1) }; let twice = macro(x) { quote(inc(inc(unquote(x)))) }; twice(1)"
+            .to_owned(),
+    );
+    let env = Rc::new(RefCell::new(Environment::new()));
+    define_macros(&mut program, Rc::clone(&env));
+    expand_macro(&mut program, Rc::clone(&env)).unwrap();
+    assert_eq!(program.string(), "((1 + 1) + 1)");
+}
+
+#[rstest]
+#[case(
+    "let m = macro() { quote(m()) }; m()",
+    "macro expansion exceeded 3 levels: m -> m -> m -> m"
+)]
+#[case(
+    "let a = macro(x) { quote(b(unquote(x))) }; let b = macro(x) { quote(1 + a(unquote(x))) }; 1; a(2)",
+    "macro expansion exceeded 3 levels: a -> b -> a -> b"
+)]
+fn test_macro_expansion_depth_limit(#[case] input: &str, #[case] expected: &str) {
+    let mut program = parse_program_from(input.to_owned());
+    let context = RuntimeContext::new().with_limits(Limits {
+        max_macro_depth: 3,
+        ..Limits::default()
+    });
+    let env = Rc::new(RefCell::new(Environment::with_context(Rc::new(
+        RefCell::new(context),
+    ))));
+    define_macros(&mut program, Rc::clone(&env));
+    let error = expand_macro(&mut program, Rc::clone(&env)).unwrap_err();
+    assert_eq!(error.message, expected);
+    // 位置是程序里的宏调用
+    assert_eq!(
+        (error.line, error.column),
+        (1, input.rfind(';').unwrap() + 3)
+    );
 }
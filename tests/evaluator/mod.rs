@@ -1,3 +1,12 @@
+mod builtins;
+mod context;
+mod convert;
+mod environment;
 mod eval;
+mod fuzz;
+mod gc;
 mod macro_expansion;
+mod module;
+mod ordering;
 mod quote;
+mod scheduler;
@@ -0,0 +1,138 @@
+use std::path::PathBuf;
+
+use super::context::{test_eval_with_context, SharedBuffer};
+use implement_parser::evaluator::context::{Limits, RuntimeContext};
+use implement_parser::evaluator::object::{Error, Hash};
+use implement_parser::sync::{Rc, RefCell};
+use rstest::rstest;
+
+fn module_context(output: &SharedBuffer) -> Rc<RefCell<RuntimeContext>> {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/modules");
+    let context = RuntimeContext::new()
+        .with_output(Box::new(output.clone()))
+        .with_module_root(root);
+    Rc::new(RefCell::new(context))
+}
+
+#[rstest]
+#[case(r#"let math = import("math"); math["square"](7)"#, "49")]
+#[case(r#"import("math.mky")["constants"]"#, "[1, 2, 3]")]
+#[case(r#"let shapes = import("geometry/shapes"); shapes["area"](3)"#, "9")]
+#[case(r#"let square = import("math")["square"]; square(square(2))"#, "16")]
+fn test_import(#[case] input: &str, #[case] expected: &str) {
+    let output = SharedBuffer::default();
+    let evaluated = test_eval_with_context(input, module_context(&output));
+    assert_eq!(evaluated.inspect(), expected);
+}
+
+#[test]
+fn test_import_is_cached() {
+    let output = SharedBuffer::default();
+    let evaluated = test_eval_with_context(
+        r#"let a = import("math"); let b = import("geometry/shapes"); import("./math")["square"](2)"#,
+        module_context(&output),
+    );
+    assert_eq!(evaluated.inspect(), "4");
+    assert_eq!(output.contents(), "loading math\n");
+}
+
+// 导入模块不会提前运行导入之前 spawn 的任务
+#[test]
+fn test_import_leaves_tasks_pending() {
+    let output = SharedBuffer::default();
+    let evaluated = test_eval_with_context(
+        r#"spawn(fn() { puts("task") }); import("math"); puts("main"); 1"#,
+        module_context(&output),
+    );
+    assert_eq!(evaluated.inspect(), "1");
+    assert_eq!(output.contents(), "loading math\nmain\ntask\n");
+}
+
+#[rstest]
+#[case(
+    r#"push(import("math")["constants"], 4)"#,
+    "`push` cannot modify a frozen Array"
+)]
+#[case(
+    r#"import("broken")"#,
+    "failed to parse module `broken`: No prefix parse function for Semicolon found"
+)]
+#[case(r#"import("failing")"#, "type mismatch: Integer + Boolean")]
+#[case("import(1)", "argument to `import` must be String, got Integer")]
+fn test_import_errors(#[case] input: &str, #[case] expected: &str) {
+    let output = SharedBuffer::default();
+    let evaluated = test_eval_with_context(input, module_context(&output));
+    assert_eq!(evaluated.downcast_ref::<Error>().unwrap().message, expected);
+}
+
+#[test]
+fn test_import_missing_module() {
+    let output = SharedBuffer::default();
+    let evaluated = test_eval_with_context(r#"import("missing")"#, module_context(&output));
+    let message = &evaluated.downcast_ref::<Error>().unwrap().message;
+    assert!(
+        message.starts_with("cannot import `missing`: "),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn test_import_cycle() {
+    let output = SharedBuffer::default();
+    let evaluated = test_eval_with_context(r#"import("cycle_a")"#, module_context(&output));
+    let message = &evaluated.downcast_ref::<Error>().unwrap().message;
+    assert!(
+        message.starts_with("import cycle detected: "),
+        "{}",
+        message
+    );
+    let files = message
+        .trim_start_matches("import cycle detected: ")
+        .split(" -> ")
+        .map(|path| path.rsplit('/').next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(files, ["cycle_a.mky", "cycle_b.mky", "cycle_a.mky"]);
+}
+
+#[test]
+fn test_import_exports_are_sorted_by_name() {
+    let output = SharedBuffer::default();
+    let evaluated = test_eval_with_context(r#"import("math")"#, module_context(&output));
+    let hash = evaluated.downcast_ref::<Hash>().unwrap();
+    let names = hash
+        .pairs
+        .values()
+        .map(|pair| pair.key.inspect())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["constants", "square"]);
+}
+
+#[test]
+fn test_import_is_disabled_in_sandbox() {
+    let context = RuntimeContext::new().with_limits(Limits {
+        sandbox: true,
+        ..Limits::default()
+    });
+    let evaluated = test_eval_with_context(r#"import("math")"#, Rc::new(RefCell::new(context)));
+    assert_eq!(
+        evaluated.downcast_ref::<Error>().unwrap().message,
+        "`import` is disabled in sandbox mode"
+    );
+}
+
+#[test]
+fn test_module_macros_use_importers_context() {
+    let output = SharedBuffer::default();
+    let evaluated = test_eval_with_context(
+        r#"import("geometry/scaled")["scaled"]"#,
+        module_context(&output),
+    );
+    assert_eq!(evaluated.inspect(), "40");
+
+    let context = module_context(&output);
+    context.borrow_mut().builtins.disable("getenv");
+    let evaluated = test_eval_with_context(r#"import("host")"#, context);
+    let message = &evaluated.downcast_ref::<Error>().unwrap().message;
+    assert!(message.contains("`getenv` is disabled"), "{}", message);
+}
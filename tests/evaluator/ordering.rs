@@ -0,0 +1,82 @@
+use std::cmp::Ordering;
+
+use super::eval::test_eval;
+use implement_parser::evaluator::ordering::compare;
+use rstest::rstest;
+
+#[rstest]
+#[case("1", "2", Ok(Ordering::Less))]
+#[case("-1", "-1", Ok(Ordering::Equal))]
+#[case("\"b\"", "\"abc\"", Ok(Ordering::Greater))]
+#[case("\"Z\"", "\"a\"", Ok(Ordering::Less))]
+#[case("false", "true", Ok(Ordering::Less))]
+#[case("bytes([1, 2])", "bytes([1])", Ok(Ordering::Greater))]
+#[case("[1, \"b\"]", "[1, \"c\"]", Ok(Ordering::Less))]
+#[case("[1]", "[1, 0]", Ok(Ordering::Less))]
+#[case("[]", "[]", Ok(Ordering::Equal))]
+// 前面的元素已经分出大小时不再比较后面的
+#[case("[1, \"a\"]", "[2, true]", Ok(Ordering::Less))]
+// 类型不同时按类型排：布尔 < 整数 < 字符串 < 字节 < 数组
+#[case("1", "\"1\"", Ok(Ordering::Less))]
+#[case("true", "1", Ok(Ordering::Less))]
+#[case("[1, \"a\"]", "[1, true]", Ok(Ordering::Greater))]
+#[case("bytes([0])", "\"z\"", Ok(Ordering::Greater))]
+#[case("[]", "bytes([])", Ok(Ordering::Greater))]
+#[case("1", "{}", Err("cannot compare Integer with Hash"))]
+#[case("fn(x) { x }", "1", Err("cannot compare Function with Integer"))]
+#[case("{}", "{}", Err("cannot compare Hash with Hash"))]
+#[case(
+    "if (false) { 1 }",
+    "if (false) { 1 }",
+    Err("cannot compare Null with Null")
+)]
+fn test_compare(#[case] left: &str, #[case] right: &str, #[case] expected: Result<Ordering, &str>) {
+    let left = test_eval(left.to_owned());
+    let right = test_eval(right.to_owned());
+    let result = compare(&left, &right).map_err(|error| error.message);
+    assert_eq!(result, expected.map_err(str::to_owned));
+}
+
+#[rstest]
+#[case("sort([3, 1, 2])", "[1, 2, 3]")]
+#[case("sort([\"pear\", \"apple\", \"fig\"])", "[apple, fig, pear]")]
+#[case("sort([true, false])", "[false, true]")]
+#[case("sort([[2, 1], [1, 2], [1]])", "[[1], [1, 2], [2, 1]]")]
+#[case("sort([])", "[]")]
+#[case("sort([\"bb\", \"a\", \"cc\"], len)", "[a, bb, cc]")]
+// 稳定排序，key 相同的元素保持原来的顺序
+#[case(
+    "sort([[1, \"x\"], [0, \"y\"], [1, \"a\"]], first)",
+    "[[0, y], [1, x], [1, a]]"
+)]
+#[case("let a = freeze([2, 1]); [sort(a), a]", "[[1, 2], [2, 1]]")]
+#[case("sort([1, \"a\"])", "[1, a]")]
+#[case(
+    "sort([\"b\", [1], 2, true, \"a\", bytes([1]), 1, false])",
+    "[false, true, 1, 2, a, b, bytes_from_hex(\"01\"), [1]]"
+)]
+#[case("sort([[1, \"a\"], [1, 2], [true]])", "[[true], [1, 2], [1, a]]")]
+#[case("sort([\"10\", 9, \"1\"], fn(x) { x })", "[9, 1, 10]")]
+#[case(
+    "sort([1, {}])",
+    "Error: cannot compare Integer with Hash at line 1, col 5"
+)]
+#[case(
+    "sort([1, 2], fn(x) { x + true })",
+    "Error: type mismatch: Integer + Boolean at line 1, col 24"
+)]
+#[case(
+    "sort([[1], [{}], [2]])",
+    "Error: cannot compare Hash with Integer at line 1, col 5"
+)]
+#[case(
+    "sort([1], len, 3)",
+    "Error: wrong number of arguments: got=3, want=1 or 2 at line 1, col 5"
+)]
+#[case(
+    "sort(1)",
+    "Error: argument to `sort` must be Array, got Integer at line 1, col 5"
+)]
+fn test_sort(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(test_eval(input.to_owned()).inspect(), expected);
+}
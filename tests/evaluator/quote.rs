@@ -1,5 +1,5 @@
 use super::eval::test_eval;
-use implement_parser::evaluator::object::Quote;
+use implement_parser::evaluator::object::{Error, Quote};
 use rstest::rstest;
 
 #[rstest]
@@ -29,3 +29,95 @@ fn test_quote_unquote(#[case] input: String, #[case] expected: String) {
     let quote = evaluated.downcast_ref::<Quote>().unwrap();
     assert_eq!(quote.node.string(), expected);
 }
+
+// 里层 quote 里的 unquote 留到里层 quote 求值时再处理
+#[rstest]
+#[case("quote(quote(unquote(1 + 1)))", "quote(unquote((1 + 1)))")]
+#[case("quote(fn(x) { unquote(1 + 1) })", "fn(x) 2")]
+fn test_nested_quote(#[case] input: &str, #[case] expected: &str) {
+    let evaluated = test_eval(input.to_owned());
+    let quote = evaluated.downcast_ref::<Quote>().unwrap();
+    assert_eq!(quote.node.string(), expected);
+}
+
+#[rstest]
+#[case("quote(f(unquote(1 + 1)))", "f(2)")]
+#[case("quote(f(1, unquote_splice([2, 3]), 4))", "f(1, 2, 3, 4)")]
+#[case("quote(f(unquote_splice([])))", "f()")]
+#[case("quote([unquote_splice([1 + 1]), 3])", "[2, 3]")]
+#[case("quote(f(unquote_splice(quote([a, b + 1]))))", "f(a, (b + 1))")]
+#[case(
+    "let args = [true, \"x\"]; quote(g(0)(unquote_splice(args)))",
+    "g(0)(true, x)"
+)]
+fn test_unquote_splice(#[case] input: &str, #[case] expected: &str) {
+    let evaluated = test_eval(input.to_owned());
+    let quote = evaluated.downcast_ref::<Quote>().unwrap();
+    assert_eq!(quote.node.string(), expected);
+}
+
+#[rstest]
+#[case(
+    "quote(unquote_splice([1]))",
+    "`unquote_splice` can only be used as a call argument or an array element"
+)]
+#[case(
+    "quote(1 + unquote_splice([1]))",
+    "`unquote_splice` can only be used as a call argument or an array element"
+)]
+#[case(
+    "quote(f(unquote_splice(1)))",
+    "`unquote_splice` needs an array or a quoted array literal, got Integer"
+)]
+#[case(
+    "quote([unquote_splice(quote(1 + 2))])",
+    "`unquote_splice` needs an array or a quoted array literal, got Quote"
+)]
+fn test_unquote_splice_errors(#[case] input: &str, #[case] expected: &str) {
+    let evaluated = test_eval(input.to_owned());
+    let error = evaluated.downcast_ref::<Error>().unwrap();
+    assert_eq!(error.message, expected);
+}
+
+#[rstest]
+#[case("[gensym(), gensym(\"tmp\")]", "[QUOTE(g_1), QUOTE(tmp_2)]")]
+#[case("quote(unquote(gensym(\"x\")) + 1)", "QUOTE((x_1 + 1))")]
+#[case(
+    "gensym(1)",
+    "Error: argument to `gensym` must be String, got Integer at line 1, col 7"
+)]
+fn test_gensym(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(test_eval(input.to_owned()).inspect(), expected);
+}
+
+// 求值器直接运行时没有宏环境，代码原样返回
+#[rstest]
+#[case("macroexpand(quote(m(1)))", "QUOTE(m(1))")]
+#[case(
+    "macroexpand(1)",
+    "Error: argument to `macroexpand` must be Quote, got Integer at line 1, col 12"
+)]
+fn test_macroexpand_without_macros(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(test_eval(input.to_owned()).inspect(), expected);
+}
+
+#[rstest]
+#[case("quote(unquote([1, \"a\", [true]]))", "QUOTE([1, a, [true]])")]
+#[case("quote(unquote({\"a\": 1, 2: [3]}))", "QUOTE({a: 1, 2: [3]})")]
+#[case("quote(unquote(if (false) { 1 }))", "QUOTE(if false )")]
+#[case("quote(unquote(fn(x) { x * 2 }))", "QUOTE(fn(x) (x * 2))")]
+#[case(
+    "quote(unquote(bytes_from_hex(\"00ff\")))",
+    "QUOTE(bytes_from_hex(00ff))"
+)]
+#[case(
+    "quote(unquote(len))",
+    "Error: unquote does not support Builtin at line 1, col 1"
+)]
+#[case(
+    "quote(unquote([1, len]))",
+    "Error: unquote does not support Builtin at line 1, col 1"
+)]
+fn test_unquote_values(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(test_eval(input.to_owned()).inspect(), expected);
+}
@@ -0,0 +1,104 @@
+use super::context::{test_eval_with_context, SharedBuffer};
+use super::eval::test_eval;
+use implement_parser::evaluator::context::RuntimeContext;
+use implement_parser::sync::{Rc, RefCell};
+use rstest::rstest;
+
+#[rstest]
+#[case("let c = chan(); send(c, 1); send(c, 2); [recv(c), recv(c)]", "[1, 2]")]
+#[case("let c = chan(); spawn(fn(x) { send(c, x * 2) }, 21); recv(c)", "42")]
+#[case(
+    "let t = spawn(fn(a, b) { a + b }, 1, 2); [wait(t), wait(t)]",
+    "[3, 3]"
+)]
+#[case("spawn(len, \"abc\")", "task")]
+#[case("chan() == chan()", "false")]
+#[case("let c = chan(); c == c", "true")]
+// 任务按创建的顺序运行，recv 只运行到通道里有值为止
+#[case(
+    "let c = chan(); spawn(fn() { send(c, 1) }); spawn(fn() { send(c, 2) }); recv(c) + len([recv(c)])",
+    "2"
+)]
+// 任务之间通过通道接力
+#[case(
+    "let a = chan(); let b = chan();
+     spawn(fn() { send(b, recv(a) + 1) });
+     spawn(fn() { send(a, 10) });
+     recv(b)",
+    "11"
+)]
+#[case(
+    "recv(chan())",
+    "Error: deadlock: `recv` would wait forever at line 1, col 5"
+)]
+#[case(
+    "let t = spawn(fn() { wait(t) }); wait(t)",
+    "Error: deadlock: `wait` would wait forever at line 1, col 26"
+)]
+#[case(
+    "spawn(1)",
+    "Error: argument to `spawn` must be Function, got Integer at line 1, col 6"
+)]
+#[case(
+    "send([], 1)",
+    "Error: argument to `send` must be Channel, got Array at line 1, col 5"
+)]
+#[case(
+    "wait(chan())",
+    "Error: argument to `wait` must be Task, got Channel at line 1, col 5"
+)]
+#[case(
+    "spawn()",
+    "Error: wrong number of arguments: got=0, want=1 at line 1, col 6"
+)]
+// 任务里的错误由 wait 拿到，没有人等待的任务出错时中断整个程序
+#[case(
+    "let t = spawn(fn() { 1 + true }); wait(t); 5",
+    "Error: type mismatch: Integer + Boolean at line 1, col 24"
+)]
+#[case(
+    "spawn(fn() { 1 + true }); 5",
+    "Error: type mismatch: Integer + Boolean at line 1, col 16"
+)]
+#[case("spawn(fn() { exit(3) }); 5", "exit(3)")]
+fn test_tasks_and_channels(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(test_eval(input.to_owned()).inspect(), expected);
+}
+
+#[test]
+fn test_pending_tasks_run_before_eval_returns() {
+    let output = SharedBuffer::default();
+    let context = Rc::new(RefCell::new(
+        RuntimeContext::new().with_output(Box::new(output.clone())),
+    ));
+    let evaluated = test_eval_with_context(
+        "spawn(fn() { puts(\"task\") }); puts(\"main\"); 1",
+        Rc::clone(&context),
+    );
+    assert_eq!(evaluated.inspect(), "1");
+    assert_eq!(output.contents(), "main\ntask\n");
+
+    // 出错时没运行的任务被丢掉，不会在下一次求值时运行
+    let evaluated = test_eval_with_context(
+        "spawn(fn() { puts(\"dropped\") }); 1 + true",
+        Rc::clone(&context),
+    );
+    assert!(evaluated.inspect().starts_with("Error: type mismatch"));
+    test_eval_with_context("2", Rc::clone(&context));
+    assert_eq!(output.contents(), "main\ntask\n");
+}
+
+// eval() 在程序中途求值源码，之前 spawn 的任务仍然等到整个程序结束时才运行
+#[test]
+fn test_nested_eval_leaves_tasks_pending() {
+    let output = SharedBuffer::default();
+    let context = Rc::new(RefCell::new(
+        RuntimeContext::new().with_output(Box::new(output.clone())),
+    ));
+    let evaluated = test_eval_with_context(
+        "spawn(fn() { puts(\"task\") }); eval(\"puts(1)\"); eval(\"puts(2)\", true); puts(\"main\"); 3",
+        context,
+    );
+    assert_eq!(evaluated.inspect(), "3");
+    assert_eq!(output.contents(), "1\n2\nmain\ntask\n");
+}
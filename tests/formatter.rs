@@ -0,0 +1,117 @@
+use implement_parser::ast::statements::Stmt;
+use implement_parser::formatter::{
+    format_expression, format_with_comments, FormatOptions, NewlineStyle,
+};
+use implement_parser::lexer::Lexer;
+use implement_parser::parser::Parser;
+use rstest::rstest;
+
+fn format_source(input: &str, options: &FormatOptions) -> String {
+    let mut parser = Parser::new(Lexer::new(input.to_owned()));
+    let program = parser.parse_program();
+    assert!(
+        parser.error_messages.is_empty(),
+        "{:?}",
+        parser.error_messages
+    );
+    format_with_comments(input, &program, options)
+}
+
+#[rstest]
+#[case("let x=1+2*3;", "let x = 1 + 2 * 3;\n")]
+#[case("let x:int=1;", "let x: int = 1;\n")]
+#[case("(1 + 2) * 3", "(1 + 2) * 3;\n")]
+#[case("1 - (2 - 3); (1 - 2) - 3", "1 - (2 - 3);\n1 - 2 - 3;\n")]
+#[case("-(a + b); !true", "-(a + b);\n!true;\n")]
+#[case("add(1, [2, 3][0])[1]", "add(1, [2, 3][0])[1];\n")]
+#[case("{\"a\": fn() {}}", "{\"a\": fn() {}};\n")]
+#[case("{\"b\":1,\"a\":2,3:[]}", "{\"b\": 1, \"a\": 2, 3: []};\n")]
+fn test_format_expressions(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(format_source(input, &FormatOptions::default()), expected);
+}
+
+#[test]
+fn test_format_blocks() {
+    let input = "let a = 1; let max = fn(x, y) { if (x > y) { return x; } else { y } }; max(a, 2)";
+    let expected = "let a = 1;
+
+let max = fn(x, y) {
+    if (x > y) {
+        return x;
+    } else {
+        y
+    }
+};
+
+max(a, 2);
+";
+    assert_eq!(format_source(input, &FormatOptions::default()), expected);
+}
+
+#[test]
+fn test_format_crlf_source() {
+    let input =
+        "let greeting = \"hello\r\nworld\";\r\n// comment\r\nlet f = fn(x) {\r\n  x\r\n};\r\n";
+    let options = FormatOptions {
+        newline: NewlineStyle::detect(input),
+        ..FormatOptions::default()
+    };
+    assert_eq!(options.newline, NewlineStyle::CrLf);
+    assert_eq!(
+        format_source(input, &options),
+        "let greeting = \"hello\r\nworld\";\r\n\r\n// comment\r\nlet f = fn(x) {\r\n    x\r\n};\r\n"
+    );
+    assert_eq!(NewlineStyle::detect("let x = 1;\nx"), NewlineStyle::Lf);
+}
+
+#[test]
+fn test_format_keeps_comments() {
+    let input = "// header
+let a=1; // one
+let f = fn(x) {
+  // body
+  let y = [x, // inline
+    2];
+  y // value
+  // end
+};
+let empty = fn() { // nothing
+};
+// footer";
+    let expected = "// header
+let a = 1; // one
+
+let f = fn(x) {
+    // body
+    let y = [x, 2]; // inline
+    y // value
+    // end
+};
+
+let empty = fn() {
+    // nothing
+};
+// footer
+";
+    let formatted = format_source(input, &FormatOptions::default());
+    assert_eq!(formatted, expected);
+    // 再格式化一次不会变
+    assert_eq!(
+        format_source(&formatted, &FormatOptions::default()),
+        expected
+    );
+}
+
+#[test]
+fn test_format_single_expression() {
+    let program = Parser::new(Lexer::new("fn(x) { if (x) { [x] } }".to_owned()))
+        .parse()
+        .unwrap();
+    let Stmt::Expression(statement) = &program.statements[0] else {
+        panic!("expected an expression statement");
+    };
+    assert_eq!(
+        format_expression(&statement.expression, &FormatOptions::default()),
+        "fn(x) {\n    if (x) {\n        [x]\n    }\n}"
+    );
+}
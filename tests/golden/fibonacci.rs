@@ -0,0 +1,39 @@
+fn main() {
+    let v_fibonacci = Var::new();
+    let v_map = Var::new();
+    v_fibonacci.set(Value::func({
+        let v_fibonacci = v_fibonacci.clone();
+        move |arguments: Vec<Value>| -> Value {
+            expect_arguments(&arguments, 1);
+            let v_n = Var::bound(arguments[0].clone());
+            if truthy(&lt(v_n.get("n"), Value::Int(2))) {
+                return v_n.get("n")
+            } else { Value::Null };
+            add(call(v_fibonacci.get("fibonacci"), vec![sub(v_n.get("n"), Value::Int(1))]), call(v_fibonacci.get("fibonacci"), vec![sub(v_n.get("n"), Value::Int(2))]))
+        }
+    }));
+    v_map.set(Value::func(move |arguments: Vec<Value>| -> Value {
+        expect_arguments(&arguments, 2);
+        let v_arr = Var::bound(arguments[0].clone());
+        let v_f = Var::bound(arguments[1].clone());
+        let v_iter = Var::new();
+        v_iter.set(Value::func({
+            let v_arr = v_arr.clone();
+            let v_f = v_f.clone();
+            let v_iter = v_iter.clone();
+            move |arguments: Vec<Value>| -> Value {
+                expect_arguments(&arguments, 2);
+                let v_arr = Var::bound(arguments[0].clone());
+                let v_accumulated = Var::bound(arguments[1].clone());
+                if truthy(&eq(call(builtin_len(), vec![v_arr.get("arr")]), Value::Int(0))) {
+                    v_accumulated.get("accumulated")
+                } else {
+                    call(v_iter.get("iter"), vec![call(builtin_rest(), vec![v_arr.get("arr")]), call(builtin_push(), vec![v_accumulated.get("accumulated"), call(v_f.get("f"), vec![call(builtin_first(), vec![v_arr.get("arr")])])])])
+                }
+            }
+        }));
+        call(v_iter.get("iter"), vec![v_arr.get("arr"), Value::array(vec![])])
+    }));
+    call(builtin_puts(), vec![call(v_map.get("map"), vec![Value::array(vec![Value::Int(0), Value::Int(1), Value::Int(2), Value::Int(10), Value::Int(20)]), v_fibonacci.get("fibonacci")])]);
+    let _ = call(builtin_puts(), vec![index(Value::hash(vec![(Value::str("answer"), call(v_fibonacci.get("fibonacci"), vec![Value::Int(15)]))]), Value::str("answer"))]);
+}
@@ -0,0 +1,68 @@
+use implement_parser::highlight::{highlight, to_ansi, Category};
+use rstest::rstest;
+
+fn categories(source: &str) -> Vec<(Category, &str)> {
+    highlight(source)
+        .into_iter()
+        .map(|highlight| (highlight.category, highlight.span.slice(source).unwrap()))
+        .collect()
+}
+
+#[test]
+fn test_highlight() {
+    let source = "// 加一\nlet add = fn(x) { x + 1 }; // 函数\nputs(\"a\", true) @";
+    assert_eq!(
+        categories(source),
+        [
+            (Category::Comment, "// 加一"),
+            (Category::Keyword, "let"),
+            (Category::Identifier, "add"),
+            (Category::Operator, "="),
+            (Category::Keyword, "fn"),
+            (Category::Punctuation, "("),
+            (Category::Identifier, "x"),
+            (Category::Punctuation, ")"),
+            (Category::Punctuation, "{"),
+            (Category::Identifier, "x"),
+            (Category::Operator, "+"),
+            (Category::Literal, "1"),
+            (Category::Punctuation, "}"),
+            (Category::Punctuation, ";"),
+            (Category::Comment, "// 函数"),
+            (Category::Identifier, "puts"),
+            (Category::Punctuation, "("),
+            (Category::Literal, "\"a\""),
+            (Category::Punctuation, ","),
+            (Category::Literal, "true"),
+            (Category::Punctuation, ")"),
+            (Category::Illegal, "@"),
+        ]
+    );
+}
+
+#[rstest]
+#[case("", &[])]
+#[case("   ", &[])]
+#[case("// 只有注释", &[(Category::Comment, "// 只有注释")])]
+#[case("1 // a\r\n// b", &[(Category::Literal, "1"), (Category::Comment, "// a"), (Category::Comment, "// b")])]
+#[case("a / b", &[(Category::Identifier, "a"), (Category::Operator, "/"), (Category::Identifier, "b")])]
+#[case("quote(unquote_splice(x))", &[
+    (Category::Keyword, "quote"),
+    (Category::Punctuation, "("),
+    (Category::Keyword, "unquote_splice"),
+    (Category::Punctuation, "("),
+    (Category::Identifier, "x"),
+    (Category::Punctuation, ")"),
+    (Category::Punctuation, ")"),
+])]
+fn test_highlight_cases(#[case] source: &str, #[case] expected: &[(Category, &str)]) {
+    assert_eq!(categories(source), expected);
+}
+
+#[test]
+fn test_to_ansi() {
+    assert_eq!(
+        to_ansi("let x = \"a\"; // c\n"),
+        "\x1b[35mlet\x1b[0m x \x1b[36m=\x1b[0m \x1b[32m\"a\"\x1b[0m; \x1b[90m// c\x1b[0m\n"
+    );
+}
@@ -0,0 +1,71 @@
+use implement_parser::incremental::IncrementalParser;
+use implement_parser::lexer::Lexer;
+use implement_parser::parser::Parser;
+use rstest::rstest;
+
+fn full_parse(source: &str) -> Result<String, Vec<String>> {
+    match Parser::new(Lexer::new(source)).parse() {
+        Ok(program) => Ok(program.to_sexpr()),
+        Err(errors) => Err(errors.iter().map(|error| error.to_string()).collect()),
+    }
+}
+
+fn incremental_parse(parser: &mut IncrementalParser, source: &str) -> Result<String, Vec<String>> {
+    match parser.parse(source) {
+        Ok(program) => Ok(program.to_sexpr()),
+        Err(errors) => Err(errors.iter().map(|error| error.to_string()).collect()),
+    }
+}
+
+// 每一步的结果（包括区间和错误的位置）都和整个重新解析一样
+#[rstest]
+#[case(&["let a = 1;\nlet b = a + 1;", "let a = 1;\nlet b = a + 2;", "let a = 10;\nlet b = a + 2;"])]
+#[case(&["let f = fn(x) { x; x };\n// 注释\nf(1)", "let f = fn(x) { x; x };\n// 注释\nf(1); f(2)"])]
+#[case(&["1 2; if (x) { 1 } else { 2 }", "1 2; if (x) { 1 } else { 2 };"])]
+#[case(&["let a = 1;\nlet b = ;", "let a = 1;\nlet b = 2;", "let a = 1;\nlet b = 2;)"])]
+#[case(&["", "   // 只有注释", "名字;"])]
+fn test_incremental_matches_full_parse(#[case] sources: &[&str]) {
+    let mut parser = IncrementalParser::new();
+    for source in sources {
+        assert_eq!(incremental_parse(&mut parser, source), full_parse(source));
+    }
+}
+
+#[test]
+fn test_only_changed_statements_are_reparsed() {
+    let mut parser = IncrementalParser::new();
+    parser
+        .parse("let a = 1;\nlet f = fn(x) { x * 2; };\nf(a)")
+        .unwrap();
+    assert_eq!(parser.reparsed(), 3);
+    parser
+        .parse("let a = 1;\nlet f = fn(x) { x * 2; };\nf(a) + 1")
+        .unwrap();
+    assert_eq!(parser.reparsed(), 1);
+    parser
+        .parse("let a = 1;\nlet f = fn(x) { x * 2; };\nf(a) + 1")
+        .unwrap();
+    assert_eq!(parser.reparsed(), 0);
+    // 前面的语句变长之后，后面的语句位置也变了
+    let program = parser
+        .parse("let a = 12;\nlet f = fn(x) { x * 2; };\nf(a) + 1")
+        .unwrap();
+    assert_eq!(parser.reparsed(), 3);
+    assert_eq!(program.statements.len(), 3);
+}
+
+#[test]
+fn test_errors_fall_back_to_full_parse() {
+    let mut parser = IncrementalParser::new();
+    parser.parse("let a = 1;\nlet b = 2;").unwrap();
+    let errors = parser
+        .parse("let a = 1;\nlet b = 2;\nlet = 3;")
+        .unwrap_err();
+    assert_eq!(
+        errors[0].to_string(),
+        "expected next token to be Ident, got Assign instead at line 3, col 5"
+    );
+    // 出错之后缓存清空
+    parser.parse("let a = 1;\nlet b = 2;").unwrap();
+    assert_eq!(parser.reparsed(), 2);
+}
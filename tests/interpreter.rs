@@ -0,0 +1,392 @@
+use implement_parser::evaluator::builtins::{Arity, UNSAFE_BUILTINS};
+use implement_parser::evaluator::context::{EvalOptions, Limits, RuntimeContext};
+use implement_parser::evaluator::object::{Integer, Value};
+use implement_parser::interpreter::{Interpreter, InterpreterError};
+use implement_parser::sync::Rc;
+use std::time::Duration;
+
+#[test]
+fn test_eval_str_keeps_bindings_and_macros() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str("let a = 20;").unwrap();
+    interpreter
+        .eval_str("let unless = macro(c, a, b) { quote(if (!(unquote(c))) { unquote(a) } else { unquote(b) }) };")
+        .unwrap();
+    let value = interpreter.eval_str("unless(false, a + 1, 0)").unwrap();
+    assert_eq!(value.inspect(), "21");
+    // 标准库已经加载
+    let value = interpreter.eval_str("sum(range(0, 4))").unwrap();
+    assert_eq!(value.inspect(), "6");
+}
+
+#[test]
+fn test_globals() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("name", "monkey");
+    interpreter.set_global("count", 2);
+    let value = interpreter
+        .eval_str("let greeting = name + \"!\"; count * 3")
+        .unwrap();
+    assert_eq!(value.inspect(), "6");
+    assert_eq!(
+        interpreter
+            .get_global("greeting")
+            .map(|value| value.inspect()),
+        Some("monkey!".to_owned())
+    );
+    assert!(interpreter.get_global("len").is_none());
+    assert!(interpreter.get_global("missing").is_none());
+}
+
+#[test]
+fn test_eval_str_errors() {
+    let mut interpreter = Interpreter::new();
+    let error = interpreter.eval_str("let = 1;").unwrap_err();
+    assert!(matches!(&error, InterpreterError::Parse(errors) if errors.len() == 1));
+    assert_eq!(
+        error.to_string(),
+        "expected next token to be Ident, got Assign instead at line 1, col 5"
+    );
+
+    let error = interpreter.eval_str("1 + true").unwrap_err();
+    assert!(matches!(&error, InterpreterError::Runtime(_)));
+    assert_eq!(
+        error.to_string(),
+        "Error: type mismatch: Integer + Boolean at line 1, col 3"
+    );
+
+    let error = interpreter.eval_str("exit(3)").unwrap_err();
+    assert_eq!(error, InterpreterError::Exit(3));
+    assert_eq!(error.to_string(), "exited with code 3");
+}
+
+#[test]
+fn test_with_context_and_host_builtins() {
+    let context = RuntimeContext::new().with_limits(Limits {
+        max_steps: Some(50),
+        ..Limits::default()
+    });
+    let mut interpreter = Interpreter::with_context(context);
+    interpreter.register_builtins(|builtins| {
+        builtins.register_with_info(
+            "answer",
+            Arity::Exact(0),
+            "Returns 42",
+            Rc::new(|_, _| Rc::new(Value::Integer(Integer { value: 42 }))),
+        );
+    });
+    assert_eq!(interpreter.eval_str("answer()").unwrap().inspect(), "42");
+    assert!(interpreter
+        .eval_str("let f = fn(n) { f(n) }; f(1)")
+        .is_err());
+    // 步数限制对每次调用分别计算
+    assert_eq!(
+        interpreter.eval_str("answer() + 1").unwrap().inspect(),
+        "43"
+    );
+}
+
+#[test]
+fn test_limits_apply_to_macro_expansion() {
+    let context = RuntimeContext::new().with_limits(Limits {
+        max_steps: Some(200),
+        ..Limits::default()
+    });
+    let mut interpreter = Interpreter::with_context(context);
+    let error = interpreter
+        .eval_str("let m = macro() { let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }; quote(unquote(f(100))) }; m()")
+        .unwrap_err();
+    assert!(
+        error.to_string().contains("evaluation budget exceeded"),
+        "{}",
+        error
+    );
+    // 宏展开的步数也对每次调用分别计算
+    let value = interpreter
+        .eval_str("let n = macro() { quote(unquote(1 + 1)) }; n()")
+        .unwrap();
+    assert_eq!(value.inspect(), "2");
+}
+
+#[test]
+fn test_limits_apply_to_macros_expanded_by_eval() {
+    let cases = [
+        (
+            Limits {
+                max_steps: Some(1000),
+                ..Limits::default()
+            },
+            "let m = macro() { let f = fn(n) { f(n + 1) }; quote(unquote(f(0))) }; m()",
+            "evaluation budget exceeded: 1000 steps",
+        ),
+        (
+            Limits {
+                timeout: Some(Duration::from_millis(10)),
+                ..Limits::default()
+            },
+            "let m = macro() { let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) + f(n - 1) } }; quote(unquote(f(30))) }; m()",
+            "evaluation timed out after 10 ms",
+        ),
+        (
+            Limits {
+                max_heap: Some(1000),
+                ..Limits::default()
+            },
+            "let m = macro() { let f = fn(a) { f(push(a, a)) }; quote(unquote(f([1, 2]))) }; m()",
+            "out of memory: allocated more than 1000 bytes",
+        ),
+        (
+            Limits {
+                max_macro_depth: 8,
+                ..Limits::default()
+            },
+            "let m = macro() { quote(m()) }; m()",
+            "macro expansion exceeded 8 levels",
+        ),
+    ];
+    for (limits, source, expected) in cases {
+        let context = RuntimeContext::new().with_limits(limits);
+        let mut interpreter = Interpreter::with_context(context);
+        let error = interpreter
+            .eval_str(&format!("eval({:?})", source))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains(expected), "{}", error);
+    }
+}
+
+#[test]
+fn test_register_fn() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register_fn("add", |a: i64, b: i64| a + b);
+    interpreter.register_fn("shout", |s: String| s.to_uppercase());
+    interpreter.register_fn("total", |values: Vec<i64>| values.iter().sum::<i64>());
+    interpreter.register_fn("half", |n: i64| {
+        if n % 2 == 0 {
+            Ok(n / 2)
+        } else {
+            Err(format!("{} is odd", n))
+        }
+    });
+    let value = interpreter
+        .eval_str("[add(1, 2), shout(\"hi\"), total([1, 2, 3]), half(8)]")
+        .unwrap();
+    assert_eq!(value.inspect(), "[3, HI, 6, 4]");
+
+    let error = |interpreter: &mut Interpreter, source| {
+        interpreter.eval_str(source).unwrap_err().to_string()
+    };
+    assert_eq!(
+        error(&mut interpreter, "add(1)"),
+        "Error: wrong number of arguments: got=1, want=2 at line 1, col 4"
+    );
+    assert_eq!(
+        error(&mut interpreter, "add(1, \"2\")"),
+        "Error: argument to `add` must be Integer, got String at line 1, col 4"
+    );
+    assert_eq!(
+        error(&mut interpreter, "total([1, true])"),
+        "Error: argument to `total` must be Integer, got Boolean at line 1, col 6"
+    );
+    assert_eq!(
+        error(&mut interpreter, "half(3)"),
+        "Error: `half` failed: 3 is odd at line 1, col 5"
+    );
+}
+
+#[test]
+fn test_sandboxed() {
+    let mut interpreter = Interpreter::sandboxed();
+    let value = interpreter
+        .eval_str("let list = import(\"std/list\"); list[\"sum\"]([1, 2, 3])")
+        .unwrap();
+    assert_eq!(value.inspect(), "6");
+    for (source, name) in [
+        ("eval(\"1\")", "eval"),
+        ("getenv(\"HOME\")", "getenv"),
+        ("import(\"secrets.monkey\")", "import"),
+    ] {
+        let error = interpreter.eval_str(source).unwrap_err().to_string();
+        assert!(
+            error.contains(&format!("`{}` is disabled in sandbox mode", name)),
+            "{}",
+            error
+        );
+    }
+}
+
+#[test]
+fn test_sandbox_applies_to_macro_bodies() {
+    let mut interpreter = Interpreter::sandboxed();
+    for name in UNSAFE_BUILTINS {
+        let source = format!("let m = macro() {{ quote(unquote({}())) }}; m()", name);
+        let error = interpreter.eval_str(&source).unwrap_err().to_string();
+        assert!(
+            error.contains(&format!("`{}` is disabled in sandbox mode", name)),
+            "{}",
+            error
+        );
+    }
+    let error = interpreter
+        .eval_str("let m = macro() { quote(unquote(import(\"secrets.monkey\"))) }; m()")
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("`import` is disabled in sandbox mode"),
+        "{}",
+        error
+    );
+
+    // 之后停用的函数在宏体里也不能用
+    let mut interpreter = Interpreter::new();
+    interpreter.register_builtins(|builtins| {
+        builtins.disable("len");
+    });
+    let error = interpreter
+        .eval_str("let m = macro() { quote(unquote(len([]))) }; m()")
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("`len` is disabled"), "{}", error);
+
+    // macroexpand 运行的宏体也一样
+    let error = interpreter
+        .eval_str("let n = macro() { quote(unquote(len([]))) }; macroexpand(quote(n()))")
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("`len` is disabled"), "{}", error);
+}
+
+#[test]
+fn test_eval_expands_macros_in_callers_context() {
+    let context = RuntimeContext::new().with_disabled_builtins(&["getenv"]);
+    let mut interpreter = Interpreter::with_context(context);
+    let error = interpreter
+        .eval_str("eval(\"let m = macro() { quote(unquote(getenv(1))) }; m()\")")
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("`getenv` is disabled"), "{}", error);
+
+    // 程序里定义的宏在 eval 的代码里也能用，eval 里定义的宏不会留下来
+    let value = interpreter
+        .eval_str("let twice = macro(x) { quote(unquote(x) * 2) }; eval(\"let m = macro() { quote(1) }; twice(m())\")")
+        .unwrap();
+    assert_eq!(value.inspect(), "2");
+    let error = interpreter.eval_str("m()").unwrap_err().to_string();
+    assert!(error.contains("identifier not found: m"), "{}", error);
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_interpreter_moves_across_threads() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register_fn("double", |n: i64| n * 2);
+    interpreter.eval_str("let base = 20;").unwrap();
+    let (interpreter, value) = std::thread::spawn(move || {
+        let value = interpreter.eval_str("double(base) + 2").unwrap();
+        (interpreter, value)
+    })
+    .join()
+    .unwrap();
+    assert_eq!(value.inspect(), "42");
+    assert_eq!(interpreter.get_global("base").unwrap().inspect(), "20");
+}
+
+#[test]
+fn test_hygienic_macros() {
+    let source = "let or = macro(a, b) { quote(fn(tmp) { if (tmp) { tmp } else { unquote(b) } }(unquote(a))); }; let tmp = 5; or(false, tmp)";
+    let value = Interpreter::new().eval_str(source).unwrap();
+    assert_eq!(value.inspect(), "false");
+    let context = RuntimeContext::new().with_options(EvalOptions {
+        hygiene: true,
+        ..EvalOptions::default()
+    });
+    let value = Interpreter::with_context(context).eval_str(source).unwrap();
+    assert_eq!(value.inspect(), "5");
+}
+
+#[test]
+fn test_recursive_macro_is_reported() {
+    let error = Interpreter::new()
+        .eval_str("let m = macro() { quote(m()) };\nm()")
+        .unwrap_err();
+    let InterpreterError::Runtime(error) = error else {
+        panic!("expected a runtime error, got {:?}", error);
+    };
+    assert!(error
+        .message
+        .starts_with("macro expansion exceeded 64 levels: m -> m -> "));
+    assert_eq!((error.line, error.column), (2, 1));
+}
+
+#[test]
+fn test_macroexpand() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str("let unless = macro(c, a, b) { quote(if (!(unquote(c))) { unquote(a) } else { unquote(b) }) };")
+        .unwrap();
+    let value = interpreter
+        .eval_str("macroexpand(quote(unless(x > 1, puts(x), 2)))")
+        .unwrap();
+    assert_eq!(value.inspect(), "QUOTE(if (!(x > 1)) puts(x)else 2)");
+    // 只展开，不求值
+    let value = interpreter.eval_str("macroexpand(quote(1 + y))").unwrap();
+    assert_eq!(value.inspect(), "QUOTE((1 + y))");
+}
+
+// unquote 出来的值放回语法树后求值，结果和原来的值一样
+#[test]
+fn test_unquoted_values_round_trip() {
+    let mut interpreter = Interpreter::new();
+    for (input, expected) in [
+        ("[1, [2, \"a\"]]", "[1, [2, a]]"),
+        ("{\"a\": [1]}", "{a: [1]}"),
+        ("if (false) { 1 }", "null"),
+        ("bytes_from_hex(\"00ff\")", "bytes_from_hex(\"00ff\")"),
+    ] {
+        let source = format!("let m = macro() {{ quote(unquote({})) }}; m()", input);
+        assert_eq!(interpreter.eval_str(&source).unwrap().inspect(), expected);
+    }
+    let value = interpreter
+        .eval_str(
+            "let double = macro() { quote(unquote(fn(x) { x * 2 })) }; let f = double(); f(21)",
+        )
+        .unwrap();
+    assert_eq!(value.inspect(), "42");
+}
+
+#[test]
+fn test_macros_in_nested_scopes() {
+    let mut interpreter = Interpreter::new();
+    let value = interpreter
+        .eval_str(
+            "let f = fn(x) { let double = macro(a) { quote(unquote(a) * 2) }; double(x) }; f(3)",
+        )
+        .unwrap();
+    assert_eq!(value.inspect(), "6");
+    // 函数体里的宏在外面用不了
+    assert!(interpreter.eval_str("double(1)").is_err());
+}
+
+#[test]
+fn test_checkpoint_and_restore() {
+    let mut interpreter = Interpreter::new();
+    interpreter
+        .eval_str("let a = 1; let add = fn(x) { x + a }; let m = macro() { quote(1) };")
+        .unwrap();
+    let checkpoint = interpreter.checkpoint();
+    interpreter
+        .eval_str("let a = 10; let b = 2; let m = macro() { quote(2) };")
+        .unwrap();
+    assert_eq!(
+        interpreter.eval_str("add(1) + m()").unwrap().inspect(),
+        "13"
+    );
+    interpreter.restore(&checkpoint);
+    // 闭包看到的也是恢复之后的绑定
+    assert_eq!(interpreter.eval_str("add(1) + m()").unwrap().inspect(), "3");
+    assert!(interpreter.get_global("b").is_none());
+    // 同一个检查点可以恢复多次
+    interpreter.eval_str("let a = 100;").unwrap();
+    interpreter.restore(&checkpoint);
+    assert_eq!(interpreter.eval_str("a").unwrap().inspect(), "1");
+}
@@ -1,4 +1,8 @@
-use implement_parser::lexer::Lexer;
+use std::io::{self, Cursor, Read};
+
+use implement_parser::interner::Interner;
+use implement_parser::lexer::{Lexer, Span};
+use implement_parser::sync::Rc;
 use implement_parser::token::TokenType;
 
 #[test]
@@ -21,7 +25,7 @@ fn test_simple_input_token() {
     for test in tests.iter() {
         let token = lexer.next_token();
         assert_eq!(token.token_type, test.0);
-        assert_eq!(token.literal, test.1);
+        assert_eq!(&*token.literal, test.1);
     }
 }
 
@@ -50,7 +54,8 @@ fn test_source_code_token() {
         "foo bar"
         [1, 2];
         {"foo": "bar"}
-        macro(x, y) { x + y;};"#;
+        macro(x, y) { x + y;};
+        quote(unquote(x));"#;
 
     let tests = [
         (TokenType::Let, "let"),
@@ -151,6 +156,50 @@ fn test_source_code_token() {
         (TokenType::Semicolon, ";"),
         (TokenType::RightBrace, "}"),
         (TokenType::Semicolon, ";"),
+        (TokenType::Quote, "quote"),
+        (TokenType::LeftParen, "("),
+        (TokenType::Unquote, "unquote"),
+        (TokenType::LeftParen, "("),
+        (TokenType::Ident, "x"),
+        (TokenType::RightParen, ")"),
+        (TokenType::RightParen, ")"),
+        (TokenType::Semicolon, ";"),
+        (TokenType::EOF, ""),
+    ];
+
+    let mut lexer = Lexer::new(input.to_owned());
+    for test in tests.iter() {
+        let token = lexer.next_token();
+        assert_eq!(token.token_type, test.0);
+        assert_eq!(&*token.literal, test.1);
+    }
+}
+
+#[test]
+fn test_span_slice() {
+    let lexer = Lexer::new("let five = 5;".to_owned());
+    assert_eq!(lexer.slice(Span::new(4, 8)), Some("five"));
+    assert_eq!(lexer.slice(Span::new(0, 3)), Some("let"));
+    assert_eq!(lexer.slice(Span::new(10, 100)), None);
+
+    let merged = Span::new(0, 3).merge(Span::new(4, 8));
+    assert_eq!(merged, Span::new(0, 8));
+    assert_eq!(merged.len(), 8);
+    assert!(Span::new(3, 3).is_empty());
+}
+
+#[test]
+fn test_line_comments() {
+    let input = "// leading comment\nlet x = 10 / 2; // trailing\n// last line";
+
+    let tests = [
+        (TokenType::Let, "let"),
+        (TokenType::Ident, "x"),
+        (TokenType::Assign, "="),
+        (TokenType::Int, "10"),
+        (TokenType::Slash, "/"),
+        (TokenType::Int, "2"),
+        (TokenType::Semicolon, ";"),
         (TokenType::EOF, ""),
     ];
 
@@ -158,6 +207,215 @@ fn test_source_code_token() {
     for test in tests.iter() {
         let token = lexer.next_token();
         assert_eq!(token.token_type, test.0);
-        assert_eq!(token.literal, test.1);
+        assert_eq!(&*token.literal, test.1);
     }
 }
+
+#[test]
+fn test_crlf_source() {
+    let input = "let s = \"a\r\nb\";\r\nlet t = 1; // note\r\nt";
+
+    let tests = [
+        (TokenType::Let, "let"),
+        (TokenType::Ident, "s"),
+        (TokenType::Assign, "="),
+        (TokenType::String, "a\nb"),
+        (TokenType::Semicolon, ";"),
+        (TokenType::Let, "let"),
+        (TokenType::Ident, "t"),
+        (TokenType::Assign, "="),
+        (TokenType::Int, "1"),
+        (TokenType::Semicolon, ";"),
+        (TokenType::Ident, "t"),
+        (TokenType::EOF, ""),
+    ];
+
+    let mut lexer = Lexer::new(input.to_owned());
+    for test in tests.iter() {
+        let token = lexer.next_token();
+        assert_eq!(token.token_type, test.0);
+        assert_eq!(&*token.literal, test.1);
+    }
+}
+
+#[test]
+fn test_token_positions() {
+    let input = "let x = 5;\n  x == \"ab\"\n// c\n!y";
+
+    let tests = [
+        ("let", 1, 1, Span::new(0, 3)),
+        ("x", 1, 5, Span::new(4, 5)),
+        ("=", 1, 7, Span::new(6, 7)),
+        ("5", 1, 9, Span::new(8, 9)),
+        (";", 1, 10, Span::new(9, 10)),
+        ("x", 2, 3, Span::new(13, 14)),
+        ("==", 2, 5, Span::new(15, 17)),
+        ("ab", 2, 8, Span::new(18, 22)),
+        ("!", 4, 1, Span::new(28, 29)),
+        ("y", 4, 2, Span::new(29, 30)),
+        ("", 4, 3, Span::new(30, 30)),
+    ];
+
+    let mut lexer = Lexer::new(input.to_owned());
+    for (literal, line, column, span) in tests {
+        let token = lexer.next_token();
+        assert_eq!(&*token.literal, literal);
+        assert_eq!((token.line, token.column), (line, column), "{}", literal);
+        assert_eq!(token.span, span, "{}", literal);
+    }
+}
+
+#[test]
+fn test_interner() {
+    let mut interner = Interner::new();
+    let first = interner.intern("counter");
+    let second = interner.intern("counter");
+    let other = interner.intern("total");
+    assert!(Rc::ptr_eq(&first, &second));
+    assert!(!Rc::ptr_eq(&first, &other));
+    assert_eq!(interner.len(), 2);
+}
+
+#[test]
+fn test_repeated_identifiers_share_literal() {
+    let mut lexer = Lexer::new("let x = x + x;".to_owned());
+    let tokens = (0..6).map(|_| lexer.next_token()).collect::<Vec<_>>();
+    assert_eq!(&*tokens[1].literal, "x");
+    assert!(Rc::ptr_eq(&tokens[1].literal, &tokens[3].literal));
+    assert!(Rc::ptr_eq(&tokens[3].literal, &tokens[5].literal));
+}
+
+#[test]
+fn test_lexer_shares_source() {
+    let source = Rc::<str>::from("let 名字 = \"值\"; x");
+    let mut lexer = Lexer::new(Rc::clone(&source));
+    assert!(Rc::ptr_eq(&lexer.source().unwrap(), &source));
+
+    let tokens = (0..6).map(|_| lexer.next_token()).collect::<Vec<_>>();
+    assert_eq!(tokens[1].token_type, TokenType::Illegal);
+    assert_eq!(&*tokens[1].literal, "名");
+    assert_eq!(&*tokens[4].literal, "值");
+    assert_eq!((tokens[4].line, tokens[4].column), (1, 10));
+    assert_eq!(lexer.next_token().column, 15);
+}
+
+#[test]
+fn test_lexer_iterator() {
+    let types = Lexer::new("let x = [1, \"a\"];")
+        .map(|token| token.token_type)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        [
+            TokenType::Let,
+            TokenType::Ident,
+            TokenType::Assign,
+            TokenType::LeftBracket,
+            TokenType::Int,
+            TokenType::Comma,
+            TokenType::String,
+            TokenType::RightBracket,
+            TokenType::Semicolon,
+        ]
+    );
+
+    let mut lexer = Lexer::new("x");
+    assert_eq!(lexer.next().map(|token| token.literal), Some("x".into()));
+    assert!(lexer.next().is_none());
+    assert!(lexer.next().is_none());
+}
+
+#[test]
+fn test_tokenize() {
+    let literals = Lexer::tokenize("a != b // done")
+        .into_iter()
+        .map(|token| token.literal.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(literals, ["a", "!=", "b"]);
+    assert!(Lexer::tokenize("  // only a comment").is_empty());
+}
+
+// 每次只给出一个字节，多字节字符会被切断在两次读取之间
+struct OneByte<R>(R);
+
+impl<R: Read> Read for OneByte<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(1);
+        self.0.read(&mut buf[..len])
+    }
+}
+
+struct Failing;
+
+impl Read for Failing {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::other("connection reset"))
+    }
+}
+
+#[test]
+fn test_lexer_from_reader() {
+    let input = "let s = \"值\\r\\n\";\r\nlet t = s == 10; // 注释\nt";
+    let expected = Lexer::tokenize(input)
+        .into_iter()
+        .map(|token| (token.token_type, token.literal, token.span, token.column))
+        .collect::<Vec<_>>();
+    for lexer in [
+        Lexer::from_reader(Cursor::new(input.to_owned())),
+        Lexer::from_reader(OneByte(Cursor::new(input.to_owned()))),
+    ] {
+        let tokens = lexer
+            .map(|token| (token.token_type, token.literal, token.span, token.column))
+            .collect::<Vec<_>>();
+        assert_eq!(tokens, expected);
+    }
+
+    let mut lexer = Lexer::from_reader(OneByte(Cursor::new(b"x \xff y".to_vec())));
+    let literals = lexer
+        .by_ref()
+        .map(|token| token.literal.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(literals, ["x", "\u{fffd}", "y"]);
+    assert!(lexer.source().is_none());
+    assert!(lexer.read_error().is_none());
+
+    let mut lexer = Lexer::from_reader(Failing);
+    assert!(lexer.next().is_none());
+    assert_eq!(lexer.read_error().unwrap().to_string(), "connection reset");
+}
+
+// 已经扫描过的源码会被丢掉，只有还没扫描完的部分留在内存里
+#[test]
+fn test_lexer_from_reader_drops_consumed_input() {
+    let line = "let value = 12345; // 注释\n";
+    let input = line.repeat(10_000);
+    let mut lexer = Lexer::from_reader(Cursor::new(input.clone()));
+    let first = lexer.next().unwrap();
+    assert_eq!(lexer.slice(first.span), Some("let"));
+    let mut last = first.clone();
+    let mut count = 1;
+    for token in lexer.by_ref() {
+        last = token;
+        count += 1;
+    }
+    assert_eq!(count, 5 * 10_000);
+    assert_eq!(
+        last.span.start,
+        input.len() - line.len() + "let value = 12345".len()
+    );
+    assert_eq!(lexer.slice(last.span), Some(";"));
+    assert_eq!(lexer.slice(first.span), None);
+}
+
+#[test]
+fn test_lexer_with_range() {
+    let source = "let a = 1;\nlet b = 2;\nlet c = 3;";
+    let tokens = Lexer::with_range(source, Span::new(11, 21), 2, 1).collect::<Vec<_>>();
+    let literals = tokens
+        .iter()
+        .map(|token| &*token.literal)
+        .collect::<Vec<_>>();
+    assert_eq!(literals, ["let", "b", "=", "2", ";"]);
+    assert_eq!((tokens[1].line, tokens[1].column), (2, 5));
+    assert_eq!(tokens[1].span, Span::new(15, 16));
+}
@@ -0,0 +1,68 @@
+use crate::parser::helpers::parse_program_from;
+
+use implement_parser::errors::{set_catalog, Chinese, English};
+use implement_parser::lint::{lint, WarningKind};
+use rstest::rstest;
+
+#[rstest]
+#[case("let f = fn() { let x = 1; 2 };", &["unused binding `x`"])]
+#[case("let f = fn() { let x = 1; x };", &[])]
+#[case("let f = fn() { let _x = 1; 2 };", &[])]
+#[case("let f = fn(unused) { 1 };", &[])]
+#[case("let x = 1;", &[])]
+#[case("let f = fn() { let x = 1; let x = 2; x };", &["unused binding `x`"])]
+#[case("let f = fn() { let x = 1; let x = x + 1; x };", &[])]
+#[case("let f = fn() { let x = 1; fn() { x } };", &[])]
+#[case("let f = fn() { let x = 1; fn(x) { x } };", &["unused binding `x`"])]
+#[case("let f = fn() { let g = fn(n) { g(n - 1) }; 1 };", &["unused binding `g`"])]
+#[case(
+    "let f = fn() { return 1; 2; 3 };",
+    &["unreachable code after `return`"]
+)]
+#[case("return 1; puts(2);", &["unreachable code after `return`"])]
+#[case("if (x) { return 1; } 2", &[])]
+#[case("if (true) { 1 }", &["condition is always true"])]
+#[case("if (false) { 1 } else { 2 }", &["condition is always false"])]
+#[case("if (\"\") { 1 }", &["condition is always true"])]
+#[case("if (!x) { 1 }", &[])]
+fn test_lint(#[case] input: &str, #[case] expected: &[&str]) {
+    let messages = lint(&parse_program_from(input.to_owned()))
+        .into_iter()
+        .map(|warning| warning.message)
+        .collect::<Vec<_>>();
+    assert_eq!(messages, expected);
+}
+
+#[test]
+fn test_warnings_are_ordered_and_located() {
+    let warnings = lint(&parse_program_from(
+        "let f = fn() {\n  let a = 1;\n  if (false) { 2 }\n  return 3;\n  4\n};".to_owned(),
+    ));
+    let kinds = warnings
+        .iter()
+        .map(|warning| warning.kind)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            WarningKind::UnusedBinding,
+            WarningKind::ConstantCondition,
+            WarningKind::UnreachableCode
+        ]
+    );
+    assert_eq!((warnings[0].line, warnings[0].column), (2, 7));
+    assert_eq!(
+        warnings[2].to_string(),
+        "warning: unreachable code after `return` at line 5, col 3"
+    );
+}
+
+#[test]
+fn test_lint_messages_use_catalog() {
+    set_catalog(Box::new(Chinese));
+    let warnings = lint(&parse_program_from(
+        "let f = fn() { let a = 1; 2 };".to_owned(),
+    ));
+    set_catalog(Box::new(English));
+    assert_eq!(warnings[0].message, "绑定 `a` 没有被用到");
+}
@@ -1,5 +1,26 @@
 mod ast;
+mod capi;
+mod cli;
+mod compiler;
+mod corpus;
+mod dot;
+mod errors;
 mod evaluator;
+mod formatter;
+mod highlight;
+mod incremental;
+mod interpreter;
 mod lexer;
+mod lint;
 mod object;
 mod parser;
+mod pool;
+mod repl;
+mod resolver;
+mod serialize;
+mod sexpr;
+mod stdlib;
+mod transpile;
+mod typecheck;
+mod vm;
+mod wasm;
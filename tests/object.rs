@@ -1,5 +1,6 @@
 use implement_parser::evaluator::object;
 use implement_parser::evaluator::object::Hashable;
+use implement_parser::sync::Rc;
 
 #[test]
 fn test_string_hash_key() {
@@ -20,3 +21,37 @@ fn test_string_hash_key() {
     assert_eq!(diff1.hash_key(), diff2.hash_key());
     assert_ne!(hello1.hash_key(), diff1.hash_key());
 }
+
+#[test]
+fn test_value_matches_and_derefs() {
+    let value = object::Value::from(object::Integer { value: 5 });
+    assert!(matches!(
+        value,
+        object::Value::Integer(object::Integer { value: 5 })
+    ));
+    assert_eq!(value.inspect(), "5");
+    assert_eq!(value.object_type(), object::ObjectType::Integer);
+    assert_eq!(value.downcast_ref::<object::Integer>().unwrap().value, 5);
+    assert!(value.downcast_ref::<object::StringObject>().is_none());
+}
+
+#[test]
+fn test_value_display_and_debug() {
+    let array = object::Value::from(object::Array {
+        elements: vec![
+            Rc::new(object::Value::from(object::Integer { value: 1 })),
+            Rc::new(object::Value::from(object::StringObject {
+                value: "two".to_owned(),
+            })),
+        ],
+        frozen: false,
+    });
+    assert_eq!(array.to_string(), "[1, two]");
+    assert_eq!(format!("{}", object::Null), "null");
+    assert_eq!(
+        format!("{:?}", object::Integer { value: 5 }),
+        "Integer { value: 5 }"
+    );
+    assert!(format!("{:?}", array)
+        .starts_with("Array(Array { elements: [Integer(Integer { value: 1 })"));
+}
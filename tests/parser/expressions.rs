@@ -8,11 +8,11 @@ use crate::parser::helpers::{
 use implement_parser::ast::expressions::{
     ArrayLiteral, Boolean, CallExpression, FunctionLiteral, HashLiteral, Identifier, IfExpression,
     IndexExpression, InfixExpression, IntegerLiteral, MacroLiteral, PrefixExpression,
-    StringLiteral,
+    QuoteExpression, StringLiteral, UnquoteExpression, UnquoteSpliceExpression,
 };
 use implement_parser::ast::program::Program;
 use implement_parser::ast::statements::ExpressionStatement;
-use implement_parser::ast::traits::{Expression, Node};
+use implement_parser::ast::traits::Node;
 
 use rstest::rstest;
 
@@ -25,7 +25,7 @@ fn test_indentifier_expression() {
 
     let expression = get_first_expression::<Identifier>(&program);
 
-    assert_eq!(expression.value, "foobar");
+    assert_eq!(&*expression.value, "foobar");
     assert_eq!(expression.token_literal(), "foobar");
 }
 
@@ -72,8 +72,8 @@ fn test_prefix_expression() {
 
         fn test_expression(&self, program: &Program) {
             let expression = get_first_expression::<PrefixExpression>(program);
-            assert_eq!(expression.operator, self.operator);
-            test_integer_literal(expression.right.as_ref(), self.integer_value);
+            assert_eq!(&*expression.operator, self.operator);
+            test_integer_literal(expression.right.as_node(), self.integer_value);
         }
     }
 
@@ -90,8 +90,8 @@ fn test_prefix_expression() {
 
         fn test_expression(&self, program: &Program) {
             let expression = get_first_expression::<PrefixExpression>(program);
-            assert_eq!(expression.operator, self.operator);
-            test_boolean_literal(expression.right.as_ref(), self.boolean_value);
+            assert_eq!(&*expression.operator, self.operator);
+            test_boolean_literal(expression.right.as_node(), self.boolean_value);
         }
     }
 
@@ -264,14 +264,14 @@ fn test_if_expression() {
     assert_eq!(program.statements.len(), 1);
 
     let if_expression = get_first_expression::<IfExpression>(&program);
-    test_string_infix_expression(if_expression.condition.as_ref(), "x", "<", "y");
+    test_string_infix_expression(if_expression.condition.as_node(), "x", "<", "y");
     let consequence = if_expression
         .consequence
         .statements
         .first()
         .and_then(|statement| statement.downcast_ref::<ExpressionStatement>())
         .unwrap();
-    test_identifier(consequence.expression.as_ref(), "x".to_owned());
+    test_identifier(consequence.expression.as_node(), "x".to_owned());
     assert!(if_expression.alternative.is_none());
 }
 
@@ -282,7 +282,7 @@ fn test_if_else_expression() {
     assert_eq!(program.statements.len(), 1);
 
     let if_expression = get_first_expression::<IfExpression>(&program);
-    test_string_infix_expression(if_expression.condition.as_ref(), "x", "<", "y");
+    test_string_infix_expression(if_expression.condition.as_node(), "x", "<", "y");
 
     let consequence = if_expression
         .consequence
@@ -290,14 +290,14 @@ fn test_if_else_expression() {
         .first()
         .and_then(|statement| statement.downcast_ref::<ExpressionStatement>())
         .unwrap();
-    test_identifier(consequence.expression.as_ref(), "x".to_owned());
+    test_identifier(consequence.expression.as_node(), "x".to_owned());
     let alternative = if_expression
         .alternative
         .as_ref()
         .and_then(|alt| alt.statements.first())
         .and_then(|statement| statement.downcast_ref::<ExpressionStatement>())
         .unwrap();
-    test_identifier(alternative.expression.as_ref(), "y".to_owned());
+    test_identifier(alternative.expression.as_node(), "y".to_owned());
 }
 
 #[test]
@@ -345,11 +345,11 @@ fn test_call_expression_parsing() {
 
     let call_expression = get_first_expression::<CallExpression>(&program);
 
-    test_identifier(call_expression.function.as_ref(), "add".to_owned());
+    test_identifier(call_expression.function.as_node(), "add".to_owned());
     assert_eq!(call_expression.arguments.len(), 3);
-    test_integer_literal(call_expression.arguments[0].as_ref(), 1);
-    test_integer_infix_expression(call_expression.arguments[1].as_ref(), 2, "*", 3);
-    test_integer_infix_expression(call_expression.arguments[2].as_ref(), 4, "+", 5);
+    test_integer_literal(call_expression.arguments[0].as_node(), 1);
+    test_integer_infix_expression(call_expression.arguments[1].as_node(), 2, "*", 3);
+    test_integer_infix_expression(call_expression.arguments[2].as_node(), 4, "+", 5);
 }
 
 #[test]
@@ -368,9 +368,9 @@ fn test_parsing_array_literal() {
     let array = get_first_expression::<ArrayLiteral>(&program);
     assert_eq!(array.elements.len(), 3);
 
-    test_integer_literal(array.elements[0].as_ref(), 1);
-    test_integer_infix_expression(array.elements[1].as_ref(), 2, "*", 2);
-    test_integer_infix_expression(array.elements[2].as_ref(), 3, "+", 3);
+    test_integer_literal(array.elements[0].as_node(), 1);
+    test_integer_infix_expression(array.elements[1].as_node(), 2, "*", 2);
+    test_integer_infix_expression(array.elements[2].as_node(), 3, "+", 3);
 }
 
 #[test]
@@ -378,8 +378,8 @@ fn test_parsing_index_expression() {
     let input = "myArray[1 + 1]".to_owned();
     let program = parse_program_from(input);
     let index_expression = get_first_expression::<IndexExpression>(&program);
-    test_identifier(index_expression.left.as_ref(), "myArray".to_owned());
-    test_integer_infix_expression(index_expression.index.as_ref(), 1, "+", 1);
+    test_identifier(index_expression.left.as_node(), "myArray".to_owned());
+    test_integer_infix_expression(index_expression.index.as_node(), 1, "+", 1);
 }
 
 #[test]
@@ -388,12 +388,10 @@ fn test_parsing_hash_literals_string_keys() {
     let program = parse_program_from(input);
     let hash_literal = get_first_expression::<HashLiteral>(&program);
     assert_eq!(hash_literal.pairs.len(), 3);
-    let expected: HashMap<&str, i64> = HashMap::from([("one", 1), ("two", 2), ("three", 3)]);
-    for (key, value) in hash_literal.pairs.iter() {
-        test_integer_literal(
-            value.as_ref(),
-            *expected.get(&key.string() as &str).unwrap(),
-        );
+    let expected = [("one", 1), ("two", 2), ("three", 3)];
+    for ((key, value), (expected_key, expected_value)) in hash_literal.pairs.iter().zip(expected) {
+        assert_eq!(key.string(), expected_key);
+        test_integer_literal(value.as_node(), expected_value);
     }
 }
 
@@ -411,12 +409,12 @@ fn test_parsing_hash_literals_with_expressions() {
     let program = parse_program_from(input);
     let hash_literal = get_first_expression::<HashLiteral>(&program);
     assert_eq!(hash_literal.pairs.len(), 3);
-    type TestMapType<'a> = HashMap<&'a str, Box<dyn Fn(&dyn Expression)>>;
+    type TestMapType<'a> = HashMap<&'a str, Box<dyn Fn(&dyn Node)>>;
     let tests: TestMapType = HashMap::from([
         (
             "one",
-            Box::new(|e: &dyn Expression| test_integer_infix_expression(e, 0, "+", 1))
-                as Box<dyn Fn(&dyn Expression)>,
+            Box::new(|e: &dyn Node| test_integer_infix_expression(e, 0, "+", 1))
+                as Box<dyn Fn(&dyn Node)>,
         ),
         (
             "two",
@@ -429,7 +427,7 @@ fn test_parsing_hash_literals_with_expressions() {
     ]);
     for (key, value) in hash_literal.pairs.iter() {
         let test_func = tests.get(&key.string() as &str).unwrap();
-        test_func(value.as_ref());
+        test_func(value.as_node());
     }
 }
 
@@ -446,5 +444,27 @@ fn test_macro_literal_parsing() {
     let statement = macro_literal.body.statements[0]
         .downcast_ref::<ExpressionStatement>()
         .unwrap();
-    test_string_infix_expression(statement.expression.as_ref(), "x", "+", "y");
+    test_string_infix_expression(statement.expression.as_node(), "x", "+", "y");
+}
+
+#[test]
+fn test_quote_expression_parsing() {
+    let program = parse_program_from("quote(1 + unquote(x))".to_owned());
+    let quote = get_first_expression::<QuoteExpression>(&program);
+    let infix = quote.expression.downcast_ref::<InfixExpression>().unwrap();
+    test_integer_literal(infix.left.as_node(), 1);
+    let unquote = infix.right.downcast_ref::<UnquoteExpression>().unwrap();
+    test_identifier(unquote.expression.as_node(), "x".to_owned());
+    assert_eq!(program.string(), "quote((1 + unquote(x)))");
+}
+
+#[test]
+fn test_unquote_splice_parsing() {
+    let program = parse_program_from("quote(f(unquote_splice(xs)))".to_owned());
+    let quote = get_first_expression::<QuoteExpression>(&program);
+    let call = quote.expression.downcast_ref::<CallExpression>().unwrap();
+    let splice = call.arguments[0]
+        .downcast_ref::<UnquoteSpliceExpression>()
+        .unwrap();
+    test_identifier(splice.expression.as_node(), "xs".to_owned());
 }
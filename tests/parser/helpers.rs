@@ -1,7 +1,7 @@
 use implement_parser::ast::expressions::{Boolean, Identifier, InfixExpression, IntegerLiteral};
 use implement_parser::ast::program::Program;
 use implement_parser::ast::statements::ExpressionStatement;
-use implement_parser::ast::traits::{Expression, Node};
+use implement_parser::ast::traits::Node;
 use implement_parser::lexer::Lexer;
 use implement_parser::parser::Parser;
 
@@ -15,9 +15,17 @@ pub fn parse_program_from(input: String) -> Program {
     program
 }
 
+// 解析 input，交给 check 做静态检查，把每条结果转成文字，typecheck、resolver 等的测试用
+pub fn messages<T: ToString>(input: &str, check: impl FnOnce(&Program) -> Vec<T>) -> Vec<String> {
+    check(&parse_program_from(input.to_owned()))
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+}
+
 pub fn get_first_expression<T>(program: &Program) -> &T
 where
-    T: 'static + Expression, // TODO: 去掉这个会有问题，好像是类型也需要一个生命周期（https://stackoverflow.com/questions/29740488/parameter-type-may-not-live-long-enough）
+    T: 'static + Node, // TODO: 去掉这个会有问题，好像是类型也需要一个生命周期（https://stackoverflow.com/questions/29740488/parameter-type-may-not-live-long-enough）
 {
     program
         .statements
@@ -27,54 +35,49 @@ where
         .unwrap()
 }
 
-pub fn test_integer_literal(expression: &dyn Expression, value: i64) {
+pub fn test_integer_literal(expression: &dyn Node, value: i64) {
     let integer_literal = expression.downcast_ref::<IntegerLiteral>().unwrap();
     assert_eq!(integer_literal.value, value);
 }
 
-pub fn test_identifier(expression: &dyn Expression, value: String) {
+pub fn test_identifier(expression: &dyn Node, value: String) {
     let identifier = expression.downcast_ref::<Identifier>().unwrap();
-    assert_eq!(identifier.value, value);
+    assert_eq!(&*identifier.value, value);
     assert_eq!(identifier.token_literal(), value);
 }
 
-pub fn test_boolean_literal(expression: &dyn Expression, value: bool) {
+pub fn test_boolean_literal(expression: &dyn Node, value: bool) {
     let boolean_literal = expression.downcast_ref::<Boolean>().unwrap();
     assert_eq!(boolean_literal.value, value);
 }
 
-pub fn test_integer_infix_expression(
-    expression: &dyn Expression,
-    left: i64,
-    operator: &str,
-    right: i64,
-) {
+pub fn test_integer_infix_expression(expression: &dyn Node, left: i64, operator: &str, right: i64) {
     let infix_expression = expression.downcast_ref::<InfixExpression>().unwrap();
-    test_integer_literal(infix_expression.left.as_ref(), left);
-    assert_eq!(infix_expression.operator, operator);
-    test_integer_literal(infix_expression.right.as_ref(), right);
+    test_integer_literal(infix_expression.left.as_node(), left);
+    assert_eq!(&*infix_expression.operator, operator);
+    test_integer_literal(infix_expression.right.as_node(), right);
 }
 
 pub fn test_boolean_infix_expression(
-    expression: &dyn Expression,
+    expression: &dyn Node,
     left: bool,
     operator: &str,
     right: bool,
 ) {
     let infix_expression = expression.downcast_ref::<InfixExpression>().unwrap();
-    test_boolean_literal(infix_expression.left.as_ref(), left);
-    assert_eq!(infix_expression.operator, operator);
-    test_boolean_literal(infix_expression.right.as_ref(), right);
+    test_boolean_literal(infix_expression.left.as_node(), left);
+    assert_eq!(&*infix_expression.operator, operator);
+    test_boolean_literal(infix_expression.right.as_node(), right);
 }
 
 pub fn test_string_infix_expression(
-    expression: &dyn Expression,
+    expression: &dyn Node,
     left: &str,
     operator: &str,
     right: &str,
 ) {
     let infix_expression = expression.downcast_ref::<InfixExpression>().unwrap();
     assert_eq!(infix_expression.left.string(), left);
-    assert_eq!(infix_expression.operator, operator);
+    assert_eq!(&*infix_expression.operator, operator);
     assert_eq!(infix_expression.right.string(), right);
 }
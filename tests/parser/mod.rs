@@ -1,4 +1,4 @@
 mod expressions;
-mod helpers;
+pub mod helpers;
 mod precedence;
 mod statements;
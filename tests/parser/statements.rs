@@ -1,6 +1,9 @@
 use crate::parser::helpers;
 use implement_parser::ast::statements::{LetStatement, ReturnStatement};
 use implement_parser::ast::traits::Node;
+use implement_parser::lexer::{Lexer, Span};
+use implement_parser::parser::Parser;
+use implement_parser::token::TokenType;
 
 use rstest::rstest;
 
@@ -42,3 +45,189 @@ fn test_let_statements(
     assert_eq!(statement.name.string(), expected_identifier);
     assert_eq!(statement.value.string(), expected_value);
 }
+
+#[rstest]
+#[case("let x: int = 5;", Some("int"), "let x: int = 5;")]
+#[case(
+    "let name: string = \"monkey\"",
+    Some("string"),
+    "let name: string = monkey;"
+)]
+#[case("let y = 5;", None, "let y = 5;")]
+fn test_let_type_annotations(
+    #[case] input: &str,
+    #[case] expected_annotation: Option<&str>,
+    #[case] expected_string: &str,
+) {
+    let program = helpers::parse_program_from(input.to_owned());
+    let statement = program
+        .statements
+        .first()
+        .and_then(|statement| statement.downcast_ref::<LetStatement>())
+        .unwrap();
+    assert_eq!(
+        statement
+            .type_annotation
+            .as_ref()
+            .map(|annotation| &*annotation.value),
+        expected_annotation
+    );
+    assert_eq!(statement.string(), expected_string);
+}
+
+#[test]
+fn test_program_retains_source() {
+    let input = "let x = 5;\nreturn x;".to_owned();
+    let program = helpers::parse_program_from(input.clone());
+    assert_eq!(program.source(), Some(input.as_str()));
+    assert_eq!(program.source_slice(Span::new(11, 17)), Some("return"));
+    assert_eq!(program.source_slice(Span::new(11, 1000)), None);
+}
+
+#[rstest]
+#[case("", true)]
+#[case("   \n\t  ", true)]
+#[case("// only a comment", true)]
+#[case("// comment\n5;", false)]
+fn test_program_is_empty(#[case] input: String, #[case] expected: bool) {
+    let program = helpers::parse_program_from(input);
+    assert_eq!(program.is_empty(), expected);
+}
+
+#[rstest]
+#[case("let = 5;", Some(TokenType::Ident), Some(TokenType::Assign), 4, (1, 5))]
+#[case(
+    "let x = 5;\nlet y 10;",
+    Some(TokenType::Assign),
+    Some(TokenType::Int),
+    17,
+    (2, 7)
+)]
+#[case("1 + ;", None, Some(TokenType::Semicolon), 4, (1, 5))]
+#[case("let quote = 1;", Some(TokenType::Ident), Some(TokenType::Quote), 4, (1, 5))]
+#[case("quote(1, 2)", Some(TokenType::RightParen), Some(TokenType::Comma), 7, (1, 8))]
+#[case("unquote;", Some(TokenType::LeftParen), Some(TokenType::Semicolon), 7, (1, 8))]
+fn test_parse_errors(
+    #[case] input: &str,
+    #[case] expected: Option<TokenType>,
+    #[case] got: Option<TokenType>,
+    #[case] position: usize,
+    #[case] line_column: (usize, usize),
+) {
+    let mut parser = Parser::new(Lexer::new(input.to_owned()));
+    let errors = parser.parse().err().unwrap();
+    assert_eq!(errors[0].expected, expected);
+    assert_eq!(errors[0].got, got);
+    assert_eq!(errors[0].position, position);
+    assert_eq!((errors[0].line, errors[0].column), line_column);
+    assert_eq!(
+        errors[0].to_string(),
+        format!(
+            "{} at line {}, col {}",
+            errors[0].message, line_column.0, line_column.1
+        )
+    );
+    // 旧的字段里仍然是同样的文字
+    assert_eq!(parser.error_messages[0], errors[0].message);
+}
+
+#[test]
+fn test_parse_ok() {
+    let program = Parser::new(Lexer::new("let x = 5; x;".to_owned()))
+        .parse()
+        .ok()
+        .unwrap();
+    assert_eq!(program.statements.len(), 2);
+}
+
+// 区间包括结尾的分号和右括号
+#[rstest]
+#[case("let x = 1 + 2;", "let x = 1 + 2;")]
+#[case("  add(1, [2, 3][0])", "add(1, [2, 3][0])")]
+#[case("if (x) { y } else { !z }", "if (x) { y } else { !z }")]
+#[case("fn(a) { return a * 2; }", "fn(a) { return a * 2; }")]
+#[case(r#"quote({"a": [1]});"#, r#"quote({"a": [1]});"#)]
+fn test_node_spans(#[case] input: &str, #[case] expected: &str) {
+    let program = helpers::parse_program_from(input.to_owned());
+    let statement = program.statements.first().unwrap();
+    assert_eq!(program.source_slice(statement.span()), Some(expected));
+    assert_eq!(program.span(), statement.span());
+}
+
+#[rstest]
+#[case("let = 1; let y = 2; let 3;", &["Ident", "Ident"], 1)]
+#[case("let x = 1 +; x * ;  let z = 3;", &["Semicolon", "Semicolon"], 1)]
+#[case("fn() { let = 1; 2 } ; let a 5;", &["Ident", "Assign"], 1)]
+#[case("if (x) { 1 + } else { 2 }; 3", &["RightBrace"], 2)]
+fn test_parse_error_recovery(
+    #[case] input: &str,
+    #[case] reported: &[&str],
+    #[case] statements: usize,
+) {
+    let mut parser = Parser::new(Lexer::new(input.to_owned()));
+    let program = parser.parse_program();
+    let got = parser
+        .errors()
+        .iter()
+        .map(|error| format!("{:?}", error.expected.or(error.got).unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(got, reported);
+    assert_eq!(program.statements.len(), statements);
+}
+
+#[rstest]
+#[case("let x = 5 @ 3;", &["illegal character '@' at line 1, col 11"])]
+#[case(
+    "let a = 1;\n  é;",
+    &[
+        "illegal character 'é' at line 2, col 3",
+        "No prefix parse function for Semicolon found at line 2, col 4",
+    ]
+)]
+#[case(
+    "let 名 = 1;",
+    &[
+        "illegal character '名' at line 1, col 5",
+        "expected next token to be Ident, got Assign instead at line 1, col 7",
+    ]
+)]
+fn test_illegal_characters(#[case] input: &str, #[case] expected: &[&str]) {
+    let mut parser = Parser::new(Lexer::new(input.to_owned()));
+    let errors = parser.parse().err().unwrap();
+    let got = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(got, expected);
+}
+
+#[rstest]
+#[case(
+    "retrun x;",
+    "unexpected Ident after identifier `retrun`, did you mean `return`?"
+)]
+#[case(
+    "lett x = 5;",
+    "unexpected Ident after identifier `lett`, did you mean `let`?"
+)]
+#[case(
+    "let f = fnn(x) { x };",
+    "expected next token to be Colon, got RightBrace instead, did you mean `fn`?"
+)]
+#[case(
+    "iff (x > 1) { 2 }",
+    "expected next token to be Colon, got RightBrace instead, did you mean `if`?"
+)]
+#[case(
+    "let y = 1; let = 2;",
+    "expected next token to be Ident, got Assign instead"
+)]
+fn test_keyword_typo_suggestions(#[case] input: &str, #[case] expected: &str) {
+    let mut parser = Parser::new(Lexer::new(input.to_owned()));
+    let errors = parser.parse().err().unwrap();
+    assert_eq!(errors[0].message, expected);
+}
+
+#[rstest]
+#[case("retrun\nx")]
+#[case("let f = 1; f(2); it + 1")]
+fn test_keyword_like_identifiers_still_parse(#[case] input: &str) {
+    assert!(Parser::new(Lexer::new(input.to_owned())).parse().is_ok());
+}
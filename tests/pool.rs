@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use implement_parser::evaluator::context::{Limits, RuntimeContext};
+use implement_parser::interpreter::InterpreterError;
+use implement_parser::pool::EvaluatorPool;
+
+#[test]
+fn test_pool_keeps_script_order() {
+    let scripts = (0..20)
+        .map(|i| format!("puts({}); {} * 2", i, i))
+        .collect::<Vec<_>>();
+    let results = EvaluatorPool::new().with_workers(4).run(&scripts);
+    assert_eq!(results.len(), 20);
+    for (i, result) in results.iter().enumerate() {
+        assert_eq!(result.output, format!("{}\n", i));
+        assert_eq!(result.value, Ok((i * 2).to_string()));
+    }
+}
+
+#[test]
+fn test_pool_isolates_scripts() {
+    // 每个脚本有自己的解释器，互相看不到对方的绑定
+    let results = EvaluatorPool::new()
+        .with_workers(1)
+        .run(&["let a = 1; a", "a", "exit(3)"]);
+    assert_eq!(results[0].value, Ok("1".to_owned()));
+    assert!(matches!(
+        results[1].value,
+        Err(InterpreterError::Runtime(_))
+    ));
+    assert_eq!(results[2].value, Err(InterpreterError::Exit(3)));
+}
+
+#[test]
+fn test_pool_timeout_per_job() {
+    let results = EvaluatorPool::new()
+        .with_workers(2)
+        .with_timeout(Duration::from_millis(50))
+        .run(&[
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(40);",
+            "1 + 2",
+        ]);
+    let error = results[0].value.as_ref().unwrap_err().to_string();
+    // 超时发生在哪个节点不确定，只检查开头
+    assert!(
+        error.starts_with("Error: evaluation timed out after 50 ms"),
+        "{}",
+        error
+    );
+    assert_eq!(results[1].value, Ok("3".to_owned()));
+}
+
+#[test]
+fn test_pool_with_context() {
+    let results = EvaluatorPool::new()
+        .with_context(|| {
+            RuntimeContext::new().sandboxed().with_limits(Limits {
+                max_steps: Some(100),
+                ..Limits::default()
+            })
+        })
+        .run(&["getenv(\"HOME\")", "len(\"abc\")"]);
+    assert!(results[0].value.is_err());
+    assert_eq!(results[1].value, Ok("3".to_owned()));
+}
+
+#[test]
+fn test_pool_without_scripts() {
+    let scripts: [&str; 0] = [];
+    assert!(EvaluatorPool::new().run(&scripts).is_empty());
+}
@@ -0,0 +1,217 @@
+use implement_parser::evaluator::builtins::BuiltinRegistry;
+use implement_parser::repl::{
+    is_incomplete, start_with_backend, start_with_builtins, start_with_options, Backend,
+    ColorChoice, Command, Completion, Options,
+};
+use implement_parser::token::keywords;
+use rstest::rstest;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Cursor;
+
+#[rstest]
+#[case(":help", Some(Command::Help))]
+#[case("  :help builtins\n", Some(Command::HelpBuiltins))]
+#[case(":quit\n", Some(Command::Quit))]
+#[case(":vars", Some(Command::Vars))]
+#[case(":reset", Some(Command::Reset))]
+#[case(":undo", Some(Command::Undo))]
+#[case(":load lib/util.mky", Some(Command::Load("lib/util.mky".to_owned())))]
+#[case(":load", Some(Command::Load("".to_owned())))]
+#[case(":save session.mky", Some(Command::Save("session.mky".to_owned())))]
+#[case(":type  len(\"ab\") ", Some(Command::Type("len(\"ab\")".to_owned())))]
+#[case(":expand m(1)", Some(Command::Expand("m(1)".to_owned())))]
+#[case(":budget 100", Some(Command::Budget("100".to_owned())))]
+#[case(":trace on", Some(Command::Trace("on".to_owned())))]
+#[case(":profile", Some(Command::Profile("".to_owned())))]
+#[case(":envgraph", Some(Command::EnvGraph))]
+#[case(":reload-builtins", Some(Command::ReloadBuiltins))]
+#[case(":quit now", Some(Command::Unknown(":quit now".to_owned())))]
+#[case(":frobnicate", Some(Command::Unknown(":frobnicate".to_owned())))]
+#[case("let a = 1;", None)]
+#[case("", None)]
+fn test_parse_command(#[case] line: &str, #[case] expected: Option<Command>) {
+    assert_eq!(Command::parse(line), expected);
+}
+
+#[rstest]
+#[case("let f = fn(x) {", true)]
+#[case("let f = fn(x) {\n  x * 2\n};", false)]
+#[case("puts(", true)]
+#[case("let a = [1,\n2", true)]
+#[case("let h = {\"a\": [1, 2]}", false)]
+#[case("\"{\"", false)]
+#[case("let a = 1; // {", false)]
+#[case("if (x) { [", true)]
+#[case(")(", false)]
+#[case("", false)]
+fn test_is_incomplete(#[case] source: &str, #[case] expected: bool) {
+    assert_eq!(is_incomplete(source), expected);
+}
+
+#[rstest]
+#[case("le", 2, 0, &["len", "let"])]
+#[case("let x = fi", 10, 8, &["filter", "first"])]
+#[case("puts(my_v", 9, 5, &["my_value"])]
+#[case("fn", 2, 0, &["fn"])]
+#[case("le", 1, 0, &["last", "len", "let"])]
+#[case("1 + 2", 5, 5, &[])]
+#[case("\"le", 3, 3, &[])]
+#[case(":re", 3, 0, &[":reload-builtins", ":reset"])]
+#[case("  :help b", 9, 2, &[":help builtins"])]
+#[case(":x", 2, 0, &[])]
+#[case("\"中\" + le", 10, 8, &["len", "let"])]
+#[case("é le", 5, 3, &["len", "let"])]
+#[case("😀fi", 6, 4, &["filter", "first"])]
+fn test_completion(
+    #[case] line: &str,
+    #[case] pos: usize,
+    #[case] start: usize,
+    #[case] expected: &[&str],
+) {
+    let mut names = keywords()
+        .into_iter()
+        .map(str::to_owned)
+        .collect::<BTreeSet<_>>();
+    names.extend(["len", "last", "filter", "first", "my_value"].map(str::to_owned));
+    let completion = Completion {
+        names,
+        color: false,
+    };
+    assert_eq!(
+        completion.candidates(line, pos),
+        (
+            start,
+            expected.iter().map(|name| name.to_string()).collect()
+        )
+    );
+}
+
+fn run_repl(input: &str, backend: Backend) -> (String, i64) {
+    let mut output = vec![];
+    let code = start_with_backend(Cursor::new(input), &mut output, backend).unwrap();
+    (String::from_utf8(output).unwrap(), code)
+}
+
+#[rstest]
+#[case(Backend::TreeWalker)]
+#[case(Backend::Vm)]
+fn test_repl_session(#[case] backend: Backend) {
+    let (output, code) = run_repl(
+        "let double = fn(x) {\n  x * 2\n};\ndouble(21)\n:type double\nnope\n",
+        backend,
+    );
+    assert_eq!(code, 0);
+    assert_eq!(
+        output,
+        ">> .. .. null\n>> 42\n>> double: Function\n>> Error: identifier not found: nope at line 1, col 1\n>> \n"
+    );
+}
+
+#[rstest]
+#[case("exit(7)\n1\n", 7, ">> ")]
+#[case(":quit\n1\n", 0, ">> ")]
+#[case(
+    "let a = 1;\n:reset\na\n",
+    0,
+    ">> null\n>> session reset\n>> Error: identifier not found: a at line 1, col 1\n>> \n"
+)]
+#[case("let = 1;\n", 0, ">> Woops! We ran into some monkey bussiness here!\n parser errors:\nexpected next token to be Ident, got Assign instead at line 1, col 5\n>> \n")]
+#[case(":budget 5\nlet f = fn(n) { f(n) }; f(1)\n", 0, ">> budget set to 5 steps per input\n>> Error: evaluation budget exceeded: 5 steps at line 1, col 26\n>> \n")]
+#[case(
+    "let twice = macro(x) { quote(unquote(x) * 2) };\n:expand twice(1 + 2)\n",
+    0,
+    ">> null\n>> (1 + 2) * 2;\n>> \n"
+)]
+#[case(
+    "let a = 1;\nlet a = 2; let twice = macro(x) { quote(unquote(x) * 2) };\n:undo\na\ntwice(a)\n:undo\n:undo\n:undo\n:undo\n",
+    0,
+    ">> null\n>> 1\n>> undid the last input\n>> 1\n>> Error: identifier not found: twice at line 1, col 1\n>> undid the last input\n>> undid the last input\n>> undid the last input\n>> nothing to undo\n>> \n"
+)]
+// 展开失败的输入不留下撤销记录，:undo 直接撤销前一次输入
+#[case(
+    "let a = 1;\nlet m = macro() { quote(unquote(1 + true)) }; m()\n:undo\na\n",
+    0,
+    ">> null\n>> Error: type mismatch: Integer + Boolean at line 1, col 35\n>> undid the last input\n>> Error: identifier not found: a at line 1, col 1\n>> \n"
+)]
+#[case(
+    "let t = macro() { quote(1) }; let m = macro() { quote(unquote(1 + true)) }; m()\nt()\n",
+    0,
+    ">> Error: type mismatch: Integer + Boolean at line 1, col 65\n>> Error: identifier not found: t at line 1, col 1\n>> \n"
+)]
+#[case(
+    ":expand let m = macro(x) { quote(-unquote(x)) }; m(3)\nm(3)\n:expand\n",
+    0,
+    ">> -3;\n>> Error: identifier not found: m at line 1, col 1\n>> usage: :expand <code>\n>> \n"
+)]
+#[case(
+    ":budget 50\nlet m = macro() { let f = fn(n) { f(n) }; quote(unquote(f(1))) };\nm()\n",
+    0,
+    ">> budget set to 50 steps per input\n>> null\n>> Error: evaluation budget exceeded: 50 steps at line 1, col 35\n>> \n"
+)]
+fn test_repl_control(#[case] input: &str, #[case] code: i64, #[case] expected: &str) {
+    assert_eq!(
+        run_repl(input, Backend::TreeWalker),
+        (expected.to_owned(), code)
+    );
+}
+
+#[test]
+fn test_repl_save_and_load() {
+    let path = std::env::temp_dir().join(format!("monkey-repl-{}.mky", std::process::id()));
+    let path = path.to_str().unwrap();
+    let (output, _) = run_repl(
+        &format!("let a = 20;\nlet b = a + 1;\nc\n:save {}\n", path),
+        Backend::TreeWalker,
+    );
+    assert!(output.contains(&format!("saved 2 inputs to {}", path)));
+    let (output, _) = run_repl(&format!(":load {}\na + b\n", path), Backend::Vm);
+    fs::remove_file(path).unwrap();
+    assert_eq!(output, ">> null\n>> 41\n>> \n");
+}
+
+fn run_colored(input: &str, color: ColorChoice) -> String {
+    let mut output = vec![];
+    let options = Options {
+        color,
+        ..Options::default()
+    };
+    start_with_options(Cursor::new(input), &mut output, options).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_repl_colors() {
+    let output = run_colored(
+        "[1, true]\n\"a\"\nnope\nif (true) { 1 }\n",
+        ColorChoice::Always,
+    );
+    assert_eq!(
+        output,
+        "\x1b[1;34m>> \x1b[0m[\x1b[32m1\x1b[0m, \x1b[32mtrue\x1b[0m]\n\
+         \x1b[1;34m>> \x1b[0m\x1b[32ma\x1b[0m\n\
+         \x1b[1;34m>> \x1b[0m\x1b[31mError: identifier not found: nope at line 1, col 1\x1b[0m\n\
+         \x1b[1;34m>> \x1b[0m\x1b[33mwarning: condition is always true at line 1, col 5\x1b[0m\n\
+         \x1b[32m1\x1b[0m\n\
+         \x1b[1;34m>> \x1b[0m\n"
+    );
+    // 从管道读取输入时 Auto 不上色
+    assert_eq!(run_colored("1\n", ColorChoice::Auto), ">> 1\n>> \n");
+    assert_eq!(run_colored("1\n", ColorChoice::Never), ">> 1\n>> \n");
+}
+
+#[test]
+fn test_repl_macro_bodies_use_session_builtins() {
+    let mut output = vec![];
+    let register = |builtins: &mut BuiltinRegistry| {
+        builtins.disable("len");
+    };
+    start_with_builtins(
+        Cursor::new("let m = macro() { quote(unquote(len([]))) };\nm()\n"),
+        &mut output,
+        &register,
+    )
+    .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("`len` is disabled"), "{}", output);
+}
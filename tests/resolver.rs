@@ -0,0 +1,68 @@
+use crate::parser::helpers::{messages, parse_program_from};
+
+use implement_parser::evaluator::environment::Environment;
+use implement_parser::evaluator::eval::eval;
+use implement_parser::resolver::{resolve, DiagnosticKind, Resolver};
+use implement_parser::sync::{Rc, RefCell};
+use rstest::rstest;
+
+#[rstest]
+#[case("let a = 1; a + len([])", &[])]
+#[case("a; let a = 1;", &["error: identifier not found: a at line 1, col 1"])]
+#[case("let a = a;", &["error: identifier not found: a at line 1, col 9"])]
+#[case("let f = fn() { g() }; let g = fn() { 1 }; f()", &[])]
+#[case("let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } };", &[])]
+#[case("let f = fn() { x; let x = 1; };", &["error: identifier not found: x at line 1, col 16"])]
+#[case("if (true) { let b = 1; } b", &[])]
+#[case("let f = fn(x, y, x) { x };", &["error: duplicate parameter `x` at line 1, col 18"])]
+#[case("let x = 1; let f = fn(x) { x };", &["warning: `x` shadows an outer binding at line 1, col 23"])]
+#[case("let f = fn() { let len = 1; len };", &["warning: `len` shadows an outer binding at line 1, col 20"])]
+#[case("let f = fn(a) { let a = 2; a };", &[])]
+#[case("let x = 1; let x = 2;", &[])]
+#[case("quote(a + unquote(1 + 2))", &[])]
+#[case("quote(unquote(b))", &["error: identifier not found: b at line 1, col 15"])]
+#[case("let m = macro(a) { quote(unquote(a) * 2) }; m(anything)", &[])]
+#[case(
+    "fn() { let m = macro(a) { quote(unquote(a) * 2) }; m(anything) }",
+    &[]
+)]
+fn test_resolve(#[case] input: &str, #[case] expected: &[&str]) {
+    assert_eq!(messages(input, resolve), expected);
+}
+
+#[test]
+fn test_diagnostic_kinds() {
+    let diagnostics = resolve(&parse_program_from(
+        "let f = fn(p, p) { let len = q; };".to_owned(),
+    ));
+    let kinds = diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.kind, diagnostic.kind.is_error()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            (DiagnosticKind::DuplicateParameter, true),
+            (DiagnosticKind::Shadowing, false),
+            (DiagnosticKind::UndefinedIdentifier, true),
+        ]
+    );
+}
+
+#[test]
+fn test_resolver_globals() {
+    let program = parse_program_from("answer + len([])".to_owned());
+    assert_eq!(Resolver::default().resolve(&program).len(), 2);
+    assert!(Resolver::new()
+        .with_globals(["answer"])
+        .resolve(&program)
+        .is_empty());
+
+    // REPL 里之前输入的绑定对新的一行可见
+    let env = Rc::new(RefCell::new(Environment::new()));
+    eval(
+        &parse_program_from("let answer = 42;".to_owned()),
+        Rc::clone(&env),
+    );
+    assert!(Resolver::for_environment(&env).resolve(&program).is_empty());
+}
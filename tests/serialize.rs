@@ -0,0 +1,75 @@
+#![cfg(feature = "serde")]
+
+use crate::parser::helpers::parse_program_from;
+
+use implement_parser::ast::program::Program;
+use implement_parser::ast::traits::Node;
+use implement_parser::evaluator::environment::Environment;
+use implement_parser::evaluator::eval::eval;
+use implement_parser::evaluator::object::{Hash, Value};
+use implement_parser::sync::{Rc, RefCell};
+use rstest::rstest;
+
+fn test_eval(input: &str) -> Rc<Value> {
+    let program = parse_program_from(input.to_owned());
+    eval(&program, Rc::new(RefCell::new(Environment::new())))
+}
+
+#[rstest]
+#[case("let x: int = -a * \"b\";")]
+#[case("if (x > 1) { return [1, 2][0]; } else { {\"k\": fn(y) { y }} }")]
+#[case("let m = macro(a) { quote(unquote(a) + 1) }; m(2)")]
+fn test_program_round_trip(#[case] input: &str) {
+    let program = parse_program_from(input.to_owned());
+    let json = serde_json::to_string(&program).unwrap();
+    let restored: Program = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored.string(), program.string());
+    assert_eq!(restored.source(), Some(input));
+    assert_eq!(restored.statements[0].span(), program.statements[0].span());
+}
+
+#[test]
+fn test_program_json_shape() {
+    let program = parse_program_from("x".to_owned());
+    let json = serde_json::to_value(&program).unwrap();
+    let identifier = &json["statements"][0]["Expression"]["expression"]["Identifier"];
+    assert_eq!(identifier["value"], "x");
+    assert_eq!(identifier["token"]["token_type"], "Ident");
+    assert_eq!(identifier["token"]["line"], 1);
+}
+
+#[rstest]
+#[case("[1, \"two\", true, if (false) { 1 }]")]
+#[case("{\"b\": [1], 2: {true: \"c\"}}")]
+#[case("quote(1 + x)")]
+#[case("{[1, \"a\"]: 2, [[true]]: 3}")]
+fn test_value_round_trip(#[case] input: &str) {
+    let value = test_eval(input);
+    let json = serde_json::to_string(&value).unwrap();
+    let restored: Rc<Value> = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored, value);
+    assert_eq!(restored.inspect(), value.inspect());
+}
+
+#[test]
+fn test_hash_serializes_as_pairs() {
+    let value = test_eval("{\"a\": 1}");
+    let hash = value.downcast_ref::<Hash>().unwrap();
+    let json = serde_json::to_value(hash).unwrap();
+    assert_eq!(
+        json["pairs"][0]["key"],
+        serde_json::json!({"String": {"value": "a"}})
+    );
+    let invalid =
+        r#"{"pairs": [{"key": {"Null": null}, "value": {"Null": null}}], "frozen": false}"#;
+    let error = serde_json::from_str::<Hash>(invalid).unwrap_err();
+    assert!(error.to_string().starts_with("unusable as hash key: Null"));
+}
+
+#[rstest]
+#[case("fn(x) { x }")]
+#[case("len")]
+fn test_closures_are_not_serializable(#[case] input: &str) {
+    let value = test_eval(input);
+    assert!(serde_json::to_string(&value).is_err());
+}
@@ -0,0 +1,58 @@
+use crate::parser::helpers::parse_program_from;
+
+use implement_parser::sexpr::to_sexpr;
+use rstest::rstest;
+
+#[test]
+fn test_program_to_sexpr() {
+    let program = parse_program_from("let x = 1 + 2 * -a; f(x)[0]".to_owned());
+    let expected = "(Program @0..27
+  (LetStatement x @0..19
+    (Identifier x @4..5)
+    (InfixExpression + @8..18
+      (IntegerLiteral 1 @8..9)
+      (InfixExpression * @12..18
+        (IntegerLiteral 2 @12..13)
+        (PrefixExpression - @16..18
+          (Identifier a @17..18)))))
+  (ExpressionStatement @20..27
+    (IndexExpression @20..27
+      (CallExpression @20..24
+        (Identifier f @20..21)
+        (Identifier x @22..23))
+      (IntegerLiteral 0 @25..26))))
+";
+    assert_eq!(program.to_sexpr(), expected);
+}
+
+// 只看结合方式，去掉区间后和加好括号的 string() 对照
+#[rstest]
+#[case(
+    "a + b * c",
+    "(InfixExpression + (Identifier a) (InfixExpression * (Identifier b) (Identifier c)))"
+)]
+#[case(
+    "(a + b) * c",
+    "(InfixExpression * (InfixExpression + (Identifier a) (Identifier b)) (Identifier c))"
+)]
+#[case("!-a", "(PrefixExpression ! (PrefixExpression - (Identifier a)))")]
+#[case(
+    "a - b - c",
+    "(InfixExpression - (InfixExpression - (Identifier a) (Identifier b)) (Identifier c))"
+)]
+fn test_sexpr_shows_precedence(#[case] input: &str, #[case] expected: &str) {
+    let program = parse_program_from(input.to_owned());
+    let sexpr = to_sexpr(program.statements[0].as_node());
+    let compact = sexpr
+        .split_whitespace()
+        .map(|part| match part.strip_prefix('@') {
+            Some(span) => span.trim_start_matches(|c: char| c != ')'),
+            None => part,
+        })
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace(" )", ")");
+    let compact = compact.trim_start_matches("(ExpressionStatement ");
+    assert_eq!(compact, format!("{})", expected));
+}
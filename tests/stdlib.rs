@@ -0,0 +1,85 @@
+use implement_parser::ast::traits::AsNode;
+use implement_parser::evaluator::context::{Limits, RuntimeContext};
+use implement_parser::evaluator::environment::Environment;
+use implement_parser::evaluator::eval::eval;
+use implement_parser::evaluator::object::Null;
+use implement_parser::lexer::Lexer;
+use implement_parser::parser::Parser;
+use implement_parser::stdlib::{find, load_prelude, modules};
+use implement_parser::sync::{Rc, RefCell};
+use rstest::rstest;
+
+fn eval_in(input: &str, env: &Rc<RefCell<Environment>>) -> String {
+    let mut parser = Parser::new(Lexer::new(input.to_owned()));
+    let program = parser.parse_program();
+    assert!(
+        parser.error_messages.is_empty(),
+        "{:?}",
+        parser.error_messages
+    );
+    eval(program.as_node(), Rc::clone(env)).inspect()
+}
+
+#[test]
+fn test_modules_parse() {
+    for module in modules() {
+        let mut parser = Parser::new(Lexer::new(module.source.to_owned()));
+        parser.parse_program();
+        assert!(parser.error_messages.is_empty(), "{}", module.name);
+    }
+    assert!(find("std/list").is_some());
+    assert!(find("list").is_none());
+}
+
+#[rstest]
+#[case("range(0, 5)", "[0, 1, 2, 3, 4]")]
+#[case("filter(range(0, 10), fn(x) { x / 2 * 2 == x })", "[0, 2, 4, 6, 8]")]
+#[case("reduce([1, 2, 3], 10, fn(a, b) { a * b })", "60")]
+#[case("sum(range(1, 101))", "5050")]
+#[case("reverse([1, 2, 3])", "[3, 2, 1]")]
+#[case("concat([1], [2, 3])", "[1, 2, 3]")]
+#[case(
+    "[take([1, 2, 3], 2), take([1], 5), drop([1, 2, 3], 2), drop([1], 5)]",
+    "[[1, 2], [1], [3], []]"
+)]
+// 默认限制下能处理上千个元素
+#[case("[len(range(0, 1000)), sum(range(0, 1000))]", "[1000, 499500]")]
+#[case(
+    "let a = reverse(range(0, 1000)); [len(a), first(a), last(a)]",
+    "[1000, 999, 0]"
+)]
+#[case("len(concat(range(0, 1000), range(0, 1000)))", "2000")]
+#[case(r#"repeat("ab", 3)"#, "ababab")]
+#[case(r#"join([1, "two", true], ", ")"#, "1, two, true")]
+#[case(r#"join([], "-")"#, "")]
+#[case(r#"[pad_left("7", 3, "0"), pad_right("ab", 4, ".")]"#, "[007, ab..]")]
+#[case("compose(fn(x) { x + 1 }, fn(x) { x * 2 })(5)", "11")]
+#[case(
+    "[identity(4), constant(1)(2), flip(fn(a, b) { a - b })(1, 3)]",
+    "[4, 1, 2]"
+)]
+#[case("iterate(fn(x) { x * 2 }, 10, 1)", "1024")]
+fn test_prelude(#[case] input: &str, #[case] expected: &str) {
+    let env = Rc::new(RefCell::new(Environment::new()));
+    let loaded = load_prelude(&env);
+    assert!(loaded.downcast_ref::<Null>().is_some());
+    assert_eq!(eval_in(input, &env), expected);
+}
+
+#[test]
+fn test_import_std_module() {
+    // 嵌入的模块不访问文件系统，沙盒模式下也能导入
+    let context = RuntimeContext::new().with_limits(Limits {
+        sandbox: true,
+        ..Limits::default()
+    });
+    let env = Rc::new(RefCell::new(Environment::with_context(Rc::new(
+        RefCell::new(context),
+    ))));
+    let evaluated = eval_in(
+        r#"let list = import("std/list"); let string = import("std/string"); string["join"](list["range"](1, 4), "+")"#,
+        &env,
+    );
+    assert_eq!(evaluated, "1+2+3");
+    assert!(env.borrow().get("range").is_none());
+}
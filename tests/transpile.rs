@@ -0,0 +1,64 @@
+use std::fs;
+use std::process::Command;
+
+use implement_parser::lexer::Lexer;
+use implement_parser::parser::Parser;
+use implement_parser::transpile::{transpile, transpile_program};
+use rstest::rstest;
+
+fn transpile_source(input: &str) -> Result<String, String> {
+    let mut parser = Parser::new(Lexer::new(input.to_owned()));
+    let program = parser.parse_program();
+    assert!(parser.error_messages.is_empty());
+    transpile_program(&program)
+}
+
+#[test]
+fn test_transpile_golden_fibonacci() {
+    let source = include_str!("golden/fibonacci.mky");
+    let main = transpile_source(source).unwrap();
+    assert_eq!(main, include_str!("golden/fibonacci.rs"));
+}
+
+#[test]
+fn test_transpiled_fibonacci_runs() {
+    let source = include_str!("golden/fibonacci.mky");
+    let mut parser = Parser::new(Lexer::new(source.to_owned()));
+    let program = parser.parse_program();
+    let rust_source = transpile(&program).unwrap();
+
+    let directory = std::env::temp_dir().join(format!("monkey-transpile-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let source_path = directory.join("fibonacci.rs");
+    let binary_path = directory.join("fibonacci");
+    fs::write(&source_path, rust_source).unwrap();
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    let status = Command::new(rustc)
+        .arg("--edition=2021")
+        .arg("-o")
+        .arg(&binary_path)
+        .arg(&source_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = Command::new(&binary_path).output().unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "[0, 1, 1, 55, 6765]\n610\n"
+    );
+}
+
+#[rstest]
+#[case("foobar;", "identifier not found: foobar")]
+#[case("quote(1 + 2);", "`quote` is not supported by the transpiler")]
+#[case(
+    "let m = macro(x) { x };",
+    "macros must be expanded before transpiling"
+)]
+fn test_transpile_errors(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(transpile_source(input).unwrap_err(), expected);
+}
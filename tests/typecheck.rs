@@ -0,0 +1,106 @@
+use crate::parser::helpers::{messages, parse_program_from};
+
+use implement_parser::corpus::programs;
+use implement_parser::evaluator::builtins::{Arity, BuiltinRegistry};
+use implement_parser::typecheck::{check, Type, TypeChecker};
+use rstest::rstest;
+
+#[rstest]
+#[case("5 + true", &["error: type mismatch: int + bool at line 1, col 3"])]
+#[case("\"a\" - \"b\"", &["error: unknown operator: string - string at line 1, col 5"])]
+#[case("true > false", &["error: unknown operator: bool > bool at line 1, col 6"])]
+#[case("-\"a\"", &["error: unknown operator: -string at line 1, col 1"])]
+#[case("[1] == 1", &["error: type mismatch: array<int> == int at line 1, col 5"])]
+#[case("let f = fn(a, b) { a + b }; f(1)", &["error: wrong number of arguments: got=1, want=2 at line 1, col 30"])]
+#[case("len(1, 2)", &["error: wrong number of arguments: got=2, want=1 at line 1, col 4"])]
+#[case("len(1)", &["error: argument to `len` not supported, got int at line 1, col 4"])]
+#[case("push(1, 2)", &["error: argument to `push` must be array, got int at line 1, col 5"])]
+#[case("5()", &["error: not a function: int at line 1, col 2"])]
+#[case("[1][\"a\"]", &["error: index operator not supported: array<int> at line 1, col 4"])]
+#[case("{[{}]: 2}", &["error: unusable as hash key: array<hash> at line 1, col 2"])]
+#[case("{[1, 2]: 2}[[3]]", &[])]
+#[case("{}[fn() {}]", &["error: unusable as hash key: fn() -> null at line 1, col 3"])]
+#[case("let x = 1; x + \"a\"", &["error: type mismatch: int + string at line 1, col 14"])]
+#[case("first([1, 2]) + true", &["error: type mismatch: int + bool at line 1, col 15"])]
+// 出错的表达式不会在外层再报一遍
+#[case("(1 + true) + \"a\"", &["error: type mismatch: int + bool at line 1, col 4"])]
+#[case("let f = fn(x) { x + 1 }; f(true)", &[])]
+#[case("let x = 1; if (true) { let x = \"a\"; } x + 1", &[])]
+#[case("let f = fn() { x + 1 }; let x = \"a\";", &[])]
+#[case("let x = 1; let f = fn() { x + 1 }; let x = \"a\";", &[])]
+#[case("let f = fn() { len(1) }; let len = fn(x) { x };", &[])]
+#[case("let len = fn(x) { x }; len(1)", &[])]
+#[case("let f = fn(x) { if (x) { return 1; } \"a\" }; f(true) + 1", &[])]
+#[case("let m = macro(a) { quote(unquote(a) + 1) }; m(true + 1)", &[])]
+#[case(
+    "fn() { let m = macro(a) { quote(unquote(a) + 1) }; m(true + 1) }",
+    &[]
+)]
+#[case("quote(1 + true)", &[])]
+#[case("let x: string = 1;", &["error: `x` is annotated as string, got int at line 1, col 8"])]
+#[case("let f = fn(x) { x }; let n: int = f(1); n + \"a\"", &["error: type mismatch: int + string at line 1, col 43"])]
+#[case("let a: array = [1]; let b: point = 1; let c: any = 1;", &[])]
+fn test_check(#[case] input: &str, #[case] expected: &[&str]) {
+    assert_eq!(messages(input, check), expected);
+}
+
+#[rstest]
+#[case("1 + 2 * 3", "int")]
+#[case("1 < 2", "bool")]
+#[case("\"a\" + \"b\"", "string")]
+#[case("[1, 2]", "array<int>")]
+#[case("[1, \"a\"]", "array<any>")]
+#[case("[[1], []]", "array<array<any>>")]
+#[case("push([1], 2)", "array<int>")]
+#[case("map([1], fn(x) { x > 0 })", "array<bool>")]
+#[case("{\"a\": 1}", "hash")]
+#[case("fn(x, y) { x }", "fn(any, any) -> any")]
+#[case("let f = fn() { 1 }; f()", "int")]
+#[case("if (true) { 1 } else { 2 }", "int")]
+#[case("if (true) { 1 }", "any")]
+#[case("puts(1)", "null")]
+#[case("len", "builtin len")]
+fn test_infer(#[case] input: &str, #[case] expected: &str) {
+    let typ = TypeChecker::new()
+        .infer(&parse_program_from(input.to_owned()))
+        .unwrap();
+    assert_eq!(typ.to_string(), expected);
+}
+
+#[test]
+fn test_join() {
+    assert_eq!(Type::Int.join(Type::Int), Type::Int);
+    assert_eq!(Type::Int.join(Type::Bool), Type::Any);
+    assert_eq!(
+        Type::Array(Box::new(Type::Int)).join(Type::Array(Box::new(Type::String))),
+        Type::Array(Box::new(Type::Any))
+    );
+}
+
+#[test]
+fn test_checker_globals() {
+    let program = parse_program_from("answer + 1; len(\"a\", 2)".to_owned());
+    assert!(TypeChecker::default().check(&program).is_empty());
+
+    let checker = TypeChecker::default()
+        .with_builtins(&BuiltinRegistry::new())
+        .with_global("answer", Type::String);
+    let errors = checker.check(&program);
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].message, "type mismatch: string + int");
+
+    let checker = TypeChecker::default()
+        .with_global("answer", Type::Builtin("answer".into(), Arity::Exact(0)));
+    assert_eq!(
+        checker.check(&program)[0].message,
+        "type mismatch: builtin answer + int"
+    );
+}
+
+// 能正常运行的程序不应该报类型错误
+#[test]
+fn test_corpus_has_no_type_errors() {
+    for program in programs() {
+        assert_eq!(check(&program.parse().unwrap()), [], "{}", program.name);
+    }
+}
@@ -0,0 +1,251 @@
+use std::time::Duration;
+
+use crate::parser::helpers::parse_program_from;
+
+use implement_parser::ast::program::Program;
+use implement_parser::ast::traits::AsNode;
+use implement_parser::compiler::Compiler;
+use implement_parser::corpus::programs;
+use implement_parser::evaluator::context::{Limits, RuntimeContext};
+use implement_parser::evaluator::environment::Environment;
+use implement_parser::evaluator::eval::eval;
+use implement_parser::evaluator::macro_expansion::{define_macros, expand_macro};
+use implement_parser::evaluator::object::Value;
+use implement_parser::stdlib::load_prelude;
+use implement_parser::sync::{Rc, RefCell};
+use implement_parser::vm::{run, Vm};
+use rstest::rstest;
+
+// 解析并展开宏
+fn parse_and_expand(input: &str) -> Program {
+    let mut program = parse_program_from(input.to_owned());
+    let macro_env = Rc::new(RefCell::new(Environment::new()));
+    define_macros(&mut program, Rc::clone(&macro_env));
+    expand_macro(&mut program, macro_env).unwrap();
+    program
+}
+
+fn run_vm(input: &str) -> Rc<Value> {
+    run(
+        &parse_and_expand(input),
+        Rc::new(RefCell::new(Environment::new())),
+    )
+    .unwrap()
+}
+
+fn run_tree_walker(input: &str) -> Rc<Value> {
+    eval(
+        parse_and_expand(input).as_node(),
+        Rc::new(RefCell::new(Environment::new())),
+    )
+}
+
+// 两个后端的结果和错误信息（包括位置）都一样
+#[rstest]
+#[case("1 + 2 * 3 - 4 / 2", "5")]
+#[case("-5 + 10", "5")]
+#[case("!true == false", "true")]
+#[case("1 < 2 == 2 > 1", "true")]
+#[case("\"mon\" + \"key\"", "monkey")]
+#[case("if (1 > 2) { 10 }", "null")]
+#[case("if (false) { 10 } else { 20 }", "20")]
+#[case("if (1) { 10 }", "10")]
+#[case("let a = 5; let b = a * 2; b + a", "15")]
+#[case("let a = 1; let a = a + 1; a", "2")]
+#[case("[1, 2 * 2, 3 + 3][1]", "4")]
+#[case("[1, 2][5]", "null")]
+#[case("{\"a\": 1, 2: true, false: \"x\"}[2]", "true")]
+#[case("{\"b\": 1, \"a\": 2}", "{b: 1, a: 2}")]
+#[case("[1, [2]] == [1, [2]]", "true")]
+#[case("let add = fn(a, b) { a + b }; add(1, add(2, 3))", "6")]
+#[case("fn() { }()", "null")]
+#[case("fn() { let x = 1; }()", "null")]
+#[case("let f = fn(x) { return x * 2; 100 }; f(4)", "8")]
+#[case(
+    "let f = fn(x) { if (x > 1) { return 1; } return 0; }; [f(2), f(0)]",
+    "[1, 0]"
+)]
+#[case("return 7; 8", "7")]
+#[case("let adder = fn(a) { fn(b) { a + b } }; adder(2)(3)", "5")]
+#[case("let f = fn(a) { fn(b) { fn(c) { a + b + c } } }; f(1)(2)(3)", "6")]
+#[case(
+    "let counter = fn(n) { if (n == 0) { 0 } else { 1 + counter(n - 1) } }; counter(50)",
+    "50"
+)]
+#[case(
+    "let outer = fn() { let inner = fn(n) { if (n == 0) { 0 } else { inner(n - 1) } }; inner(3) }; outer()",
+    "0"
+)]
+#[case("let f = fn() { g() }; let g = fn() { 42 }; f()", "42")]
+#[case("let x = 1; let f = fn() { x }; let x = 2; f()", "2")]
+#[case("len(\"four\") + len([1, 2])", "6")]
+#[case("rest(push([1, 2], 3))", "[2, 3]")]
+#[case("map([1, 2, 3], fn(x) { x * x })", "[1, 4, 9]")]
+#[case("let k = 10; map([1, 2], fn(x) { x + k })", "[11, 12]")]
+#[case("let len = fn(x) { 0 }; len([1])", "0")]
+#[case("let f = fn(xs) { fn() { len(xs) } }; f([1, 2])()", "2")]
+#[case("let a = len([1]); let len = fn(x) { 10 }; a + len([1])", "11")]
+#[case(
+    "let f = fn() { let len = fn(x) { 5 }; len([]) }; [f(), len([])]",
+    "[5, 0]"
+)]
+#[case("fn(x) { x + 1 }", "fn (x) {\n(x + 1)\n}")]
+#[case(
+    "let f = fn() { 1 }; [f == f, [f] == [f], fn(x) { x }(f) == f]",
+    "[true, true, true]"
+)]
+#[case(
+    "let mk = fn() { fn() { 1 } }; [mk() == mk(), mk() != mk()]",
+    "[false, true]"
+)]
+#[case(
+    "let mk = fn(x) { fn() { x } }; let h = mk(1); [mk(1) == mk(1), h == h]",
+    "[false, true]"
+)]
+#[case("fn() { 1 } == fn() { 1 }", "false")]
+#[case("quote(1 + 2)", "QUOTE((1 + 2))")]
+#[case("let m = macro(a) { quote(unquote(a) * 2) }; m(3 + 4)", "14")]
+#[case("5 + true", "Error: type mismatch: Integer + Boolean at line 1, col 3")]
+#[case(
+    "let f = fn() {\n  -true\n}; f()",
+    "Error: unknown operator: -Boolean at line 2, col 3"
+)]
+#[case("foo", "Error: identifier not found: foo at line 1, col 1")]
+#[case("x; let x = 1;", "Error: identifier not found: x at line 1, col 1")]
+#[case(
+    "fn(a) { a }(1, 2)",
+    "Error: wrong number of arguments: got=2, want=1 at line 1, col 12"
+)]
+#[case("1(2)", "Error: not a function: Integer at line 1, col 2")]
+#[case("{[{}]: 2}", "Error: unusable as hash key: Array at line 1, col 1")]
+#[case("{[1, [2]]: \"x\"}[[1, [2]]]", "x")]
+#[case("1 / 0", "Error: division by zero: 1 / 0 at line 1, col 3")]
+#[case(
+    "len(1)",
+    "Error: argument to `len` not supported, got Integer at line 1, col 4"
+)]
+#[case(
+    "map([1], fn(x) { x + true })",
+    "Error: type mismatch: Integer + Boolean at line 1, col 20"
+)]
+#[case("exit(3); 1", "exit(3)")]
+#[case(
+    "let c = chan(); let t = spawn(fn(x) { send(c, x); x * 2 }, 4); [recv(c), wait(t)]",
+    "[4, 8]"
+)]
+#[case(
+    "spawn(fn() { 1 + true }); 5",
+    "Error: type mismatch: Integer + Boolean at line 1, col 16"
+)]
+fn test_vm_matches_tree_walker(#[case] input: &str, #[case] expected: &str) {
+    assert_eq!(run_tree_walker(input).inspect(), expected);
+    assert_eq!(run_vm(input).inspect(), expected);
+}
+
+#[test]
+fn test_vm_runs_corpus() {
+    for program in programs() {
+        let mut parsed = program.parse().unwrap();
+        let macro_env = Rc::new(RefCell::new(Environment::new()));
+        define_macros(&mut parsed, Rc::clone(&macro_env));
+        expand_macro(&mut parsed, macro_env).unwrap();
+        let value = run(&parsed, Rc::new(RefCell::new(Environment::new()))).unwrap();
+        assert_eq!(value.inspect(), program.expected, "{}", program.name);
+    }
+}
+
+// REPL 的每一行用同一个编译器和虚拟机，之前定义的全局变量一直可见
+#[test]
+fn test_vm_closure_identity_survives_clone() {
+    let closure = run_vm("let mk = fn(x) { fn() { x } }; mk(1)");
+    let copy = Box::new(closure.as_ref().clone());
+    assert_eq!(*copy, *closure);
+    assert_ne!(*run_vm("let mk = fn(x) { fn() { x } }; mk(1)"), *closure);
+}
+
+#[test]
+fn test_vm_keeps_globals_between_runs() {
+    let mut compiler = Compiler::new();
+    let mut vm = Vm::new();
+    let lines = [
+        ("let a = 2;", "null"),
+        ("let double = fn(x) { x * a };", "null"),
+        ("double(5)", "10"),
+        ("let a = 3; double(5)", "15"),
+    ];
+    for (line, expected) in lines {
+        let bytecode = compiler.compile(&parse_and_expand(line)).unwrap();
+        assert_eq!(vm.run(&bytecode).inspect(), expected, "{}", line);
+    }
+    assert_eq!(vm.global("a").unwrap().inspect(), "3");
+    assert!(vm.global("missing").is_none());
+}
+
+// 编译时找不到的名字到虚拟机的环境里找，标准库的函数可以直接调用
+#[test]
+fn test_vm_uses_environment_bindings() {
+    let env = Rc::new(RefCell::new(Environment::new()));
+    load_prelude(&env);
+    let value = run(&parse_and_expand("reverse([1, 2, 3])"), Rc::clone(&env)).unwrap();
+    assert_eq!(value.inspect(), "[3, 2, 1]");
+    let stats = env.borrow().context().borrow().stats.clone();
+    assert!(stats.function_calls > 0);
+}
+
+#[test]
+fn test_vm_writes_to_context_output() {
+    let env = Rc::new(RefCell::new(Environment::new()));
+    let output = Rc::new(RefCell::new(Vec::new()));
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+    impl std::io::Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    env.borrow().context().borrow_mut().output = Box::new(Shared(Rc::clone(&output)));
+    run(&parse_and_expand("puts(\"hi\", 1)"), env).unwrap();
+    assert_eq!(
+        String::from_utf8(output.borrow().clone()).unwrap(),
+        "hi\n1\n"
+    );
+}
+
+#[test]
+fn test_vm_recursion_depth_is_limited() {
+    let value = run_vm("let f = fn(n) { f(n + 1) }; f(0)");
+    assert!(
+        value
+            .inspect()
+            .contains("maximum recursion depth exceeded: 10000 nested calls"),
+        "{}",
+        value.inspect()
+    );
+}
+
+// 步数按函数调用计算，超时和内存限制和求值器一样生效
+#[rstest]
+#[case(
+    Limits { max_steps: Some(100), ..Limits::default() },
+    "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+    "Error: evaluation budget exceeded: 100 steps at line 1, col 59"
+)]
+#[case(
+    Limits { timeout: Some(Duration::from_millis(20)), ..Limits::default() },
+    "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(40)",
+    // 超时的时候正在执行哪一次调用不确定，只比较前面的部分
+    "Error: evaluation timed out after 20 ms at line 1, col "
+)]
+#[case(
+    Limits { max_heap: Some(1000), ..Limits::default() },
+    r#"let grow = fn(s, n) { if (n == 0) { s } else { grow(s + "0123456789", n - 1) } }; grow("", 100)"#,
+    "Error: out of memory: allocated more than 1000 bytes at line 1, col 55"
+)]
+fn test_vm_limits(#[case] limits: Limits, #[case] input: &str, #[case] expected: &str) {
+    let context = RuntimeContext::new().with_limits(limits);
+    let env = Environment::with_context(Rc::new(RefCell::new(context)));
+    let value = run(&parse_and_expand(input), Rc::new(RefCell::new(env))).unwrap();
+    assert!(value.inspect().starts_with(expected), "{}", value.inspect());
+}
@@ -0,0 +1,32 @@
+#![cfg(feature = "wasm")]
+
+use implement_parser::wasm::{eval_str, Playground};
+
+#[test]
+fn test_playground_keeps_bindings_and_captures_output() {
+    let mut playground = Playground::new();
+    let evaluation = playground.eval_str("let x = 20; puts(\"hi\"); x");
+    assert_eq!(evaluation.output, "hi\n");
+    assert_eq!(evaluation.value.as_deref(), Some("20"));
+    assert_eq!(evaluation.error, None);
+
+    let evaluation = playground.eval_str("x + 1");
+    assert_eq!(evaluation.output, "");
+    assert_eq!(evaluation.value.as_deref(), Some("21"));
+}
+
+#[test]
+fn test_eval_str_reports_errors() {
+    let evaluation = eval_str("1 + true");
+    assert_eq!(evaluation.value, None);
+    assert_eq!(
+        evaluation.error.as_deref(),
+        Some("Error: type mismatch: Integer + Boolean at line 1, col 3")
+    );
+
+    let evaluation = eval_str("getenv(\"HOME\")");
+    assert!(evaluation
+        .error
+        .unwrap()
+        .contains("`getenv` is disabled in sandbox mode"));
+}